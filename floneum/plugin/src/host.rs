@@ -8,7 +8,6 @@ use kalosm::language::DynamicNodeId;
 use kalosm::language::*;
 use once_cell::sync::Lazy;
 
-use reqwest::header::{HeaderName, HeaderValue};
 use slab::Slab;
 use std::collections::HashMap;
 use std::path::Path;
@@ -101,29 +100,16 @@ impl main::types::Host for State {
         url: String,
         headers: Vec<main::types::Header>,
     ) -> std::result::Result<String, wasmtime::Error> {
-        let mut headers = headers
-            .into_iter()
-            .map(|header| {
-                Ok((
-                    HeaderName::try_from(header.key)?,
-                    HeaderValue::from_str(&header.value)?,
-                ))
-            })
-            .collect::<wasmtime::Result<Vec<_>>>()?;
-        headers.push((
-            HeaderName::from_static("user-agent"),
-            HeaderValue::from_static("floneum"),
-        ));
-        let res = reqwest::Client::new()
-            .get(&url)
-            .headers(reqwest::header::HeaderMap::from_iter(headers))
-            .send()
-            .await
-            .unwrap()
-            .text()
-            .await
-            .unwrap();
-        Ok(res)
+        let url = Url::parse(&url)?;
+        let headers = headers
+            .iter()
+            .map(|header| (header.key.as_str(), header.value.as_str()))
+            .chain(std::iter::once(("user-agent", "floneum")))
+            .collect::<Vec<_>>();
+        let res = HttpClient::global()
+            .get_with_headers(&url, &headers)
+            .await?;
+        Ok(res.into_text())
     }
 }
 
@@ -6,8 +6,8 @@ use std::{
     sync::{Arc, Mutex},
 };
 
+use crate::template::{ChatFormat, ChatTemplate};
 use anyhow::Result;
-use kalosm_language_model::ChatMarkers;
 use kalosm_language_model::Session;
 use kalosm_language_model::{GenerationParameters, Model, ModelExt, SyncModel, SyncModelExt};
 use kalosm_sample::{ArcParser, CreateParserState, ParserExt};
@@ -44,6 +44,7 @@ pub enum MessageType {
 }
 
 /// A single item in the chat history.
+#[derive(Clone, Debug, PartialEq)]
 pub struct ChatHistoryItem {
     ty: MessageType,
     contents: String,
@@ -71,10 +72,7 @@ impl ChatHistoryItem {
 
 /// The history of a chat session.
 struct ChatSession<Session, Model: SyncModel<Session = Session>> {
-    user_marker: String,
-    end_user_marker: String,
-    assistant_marker: String,
-    end_assistant_marker: String,
+    format: ChatFormat,
     history: Vec<ChatHistoryItem>,
     session: Session,
     unfed_text: String,
@@ -89,12 +87,7 @@ impl<Session, Model: SyncModel<Session = Session>> ChatSession<Session, Model> {
     /// Creates a new chat history.
     pub(crate) fn new(
         model: &mut Model,
-        system_prompt_marker: String,
-        end_system_prompt_marker: String,
-        user_marker: String,
-        end_user_marker: String,
-        assistant_marker: String,
-        end_assistant_marker: String,
+        format: ChatFormat,
         system_prompt: String,
         map_user_message_prompt: Option<UserMessageMapping<Model>>,
         bot_constraints: Option<ResponseConstraintGenerator<Model>>,
@@ -106,11 +99,7 @@ impl<Session, Model: SyncModel<Session = Session>> ChatSession<Session, Model> {
         let feed_initial_messages = session.is_none();
         let session = session.unwrap_or_else(|| model.new_session().unwrap());
         let unfed_text = if feed_initial_messages {
-            let mut unfed_text = String::new();
-            unfed_text += &system_prompt_marker;
-            unfed_text += &system_prompt;
-            unfed_text += &end_system_prompt_marker;
-            unfed_text
+            format.system(&system_prompt)
         } else {
             String::new()
         };
@@ -120,10 +109,7 @@ impl<Session, Model: SyncModel<Session = Session>> ChatSession<Session, Model> {
         }];
 
         let mut myself = Self {
-            user_marker,
-            end_user_marker,
-            assistant_marker,
-            end_assistant_marker,
+            format,
             session,
             unfed_text,
             history,
@@ -161,7 +147,7 @@ impl<Session, Model: SyncModel<Session = Session>> ChatSession<Session, Model> {
     ) -> Result<()> {
         self.add_user_message(message, model);
         let mut bot_response = String::new();
-        self.unfed_text += &self.assistant_marker;
+        self.unfed_text += self.format.assistant_prefix();
         let prompt = std::mem::take(&mut self.unfed_text);
         let bot_constraints = &self.bot_constraints;
         match &self.filter_map_bot_response {
@@ -177,7 +163,7 @@ impl<Session, Model: SyncModel<Session = Session>> ChatSession<Session, Model> {
                             let state = constraints.create_parser_state();
                             let on_token = |tok: String| {
                                 let tok = tok
-                                    .strip_suffix(&self.end_assistant_marker)
+                                    .strip_suffix(self.format.stop_on())
                                     .unwrap_or(&tok)
                                     .to_string();
                                 bot_response += &tok;
@@ -195,7 +181,7 @@ impl<Session, Model: SyncModel<Session = Session>> ChatSession<Session, Model> {
                         None => {
                             let on_token = |tok: String| {
                                 let tok = tok
-                                    .strip_suffix(&self.end_assistant_marker)
+                                    .strip_suffix(self.format.stop_on())
                                     .unwrap_or(&tok)
                                     .to_string();
                                 bot_response += &tok;
@@ -205,15 +191,18 @@ impl<Session, Model: SyncModel<Session = Session>> ChatSession<Session, Model> {
                                 &mut self.session,
                                 &prompt,
                                 None,
-                                Some(&self.end_assistant_marker),
+                                Some(self.format.stop_on()),
                                 self.sampler.clone(),
                                 on_token,
                             )?;
                         }
                     }
 
-                    if let Some(bot_response) = filter(&bot_response, model) {
-                        stream.send(bot_response.to_string())?;
+                    if let Some(filtered) = filter(&bot_response, model) {
+                        // The history records the response the user saw, not the raw output of the model
+                        let filtered = filtered.to_string();
+                        stream.send(filtered.clone())?;
+                        bot_response = filtered;
                         break;
                     } else {
                         tracing::trace!("Filtered out: {}", bot_response);
@@ -227,7 +216,7 @@ impl<Session, Model: SyncModel<Session = Session>> ChatSession<Session, Model> {
                     let state = constraints.create_parser_state();
                     let on_token = |tok: String| {
                         let tok = tok
-                            .strip_suffix(&self.end_assistant_marker)
+                            .strip_suffix(self.format.stop_on())
                             .unwrap_or(&tok)
                             .to_string();
                         bot_response += &tok;
@@ -246,7 +235,7 @@ impl<Session, Model: SyncModel<Session = Session>> ChatSession<Session, Model> {
                 None => {
                     let on_token = |tok: String| {
                         let tok = tok
-                            .strip_suffix(&self.end_assistant_marker)
+                            .strip_suffix(self.format.stop_on())
                             .unwrap_or(&tok)
                             .to_string();
                         bot_response += &tok;
@@ -257,13 +246,17 @@ impl<Session, Model: SyncModel<Session = Session>> ChatSession<Session, Model> {
                         &mut self.session,
                         &prompt,
                         None,
-                        Some(&self.end_assistant_marker),
+                        Some(self.format.stop_on()),
                         self.sampler.clone(),
                         on_token,
                     )?;
                 }
             },
         }
+        self.history.push(ChatHistoryItem {
+            ty: MessageType::ModelAnswer,
+            contents: bot_response,
+        });
 
         Ok(())
     }
@@ -273,14 +266,10 @@ impl<Session, Model: SyncModel<Session = Session>> ChatSession<Session, Model> {
             Some(map) => {
                 let mut map = map.lock().unwrap();
                 let message = map(&message, model);
-                self.unfed_text += &self.user_marker;
-                self.unfed_text += &message;
-                self.unfed_text += &self.end_user_marker;
+                self.unfed_text += &self.format.user(&message);
             }
             None => {
-                self.unfed_text += &self.user_marker;
-                self.unfed_text += &message;
-                self.unfed_text += &self.end_user_marker;
+                self.unfed_text += &self.format.user(&message);
             }
         };
         self.history.push(ChatHistoryItem {
@@ -290,9 +279,7 @@ impl<Session, Model: SyncModel<Session = Session>> ChatSession<Session, Model> {
     }
 
     fn add_bot_message(&mut self, message: String) {
-        self.unfed_text += &self.assistant_marker;
-        self.unfed_text += &message;
-        self.unfed_text += &self.end_assistant_marker;
+        self.unfed_text += &self.format.assistant(&message);
         self.history.push(ChatHistoryItem {
             ty: MessageType::ModelAnswer,
             contents: message,
@@ -303,7 +290,7 @@ impl<Session, Model: SyncModel<Session = Session>> ChatSession<Session, Model> {
 /// A builder for [`Chat`].
 pub struct ChatBuilder<'a, M: Model> {
     model: &'a mut M,
-    chat_markers: ChatMarkers,
+    format: ChatFormat,
     session: Option<<M::SyncModel as kalosm_language_model::SyncModel>::Session>,
     system_prompt: String,
     sampler: Arc<Mutex<dyn Sampler + Send + Sync>>,
@@ -315,11 +302,17 @@ pub struct ChatBuilder<'a, M: Model> {
 
 impl<'a, M: Model> ChatBuilder<'a, M> {
     fn new(model: &'a mut M) -> ChatBuilder<M> {
-        let chat_markers = model.chat_markers().expect("Model does not support chat");
+        let chat_markers = model.chat_markers();
+        if chat_markers.is_none() {
+            tracing::warn!("The model does not have chat markers, so the chat will use a markdown format that the model was not trained on. Set a chat template for the model with ChatBuilder::with_chat_template");
+        }
+        let format = ChatTemplate::default()
+            .render(chat_markers.as_ref())
+            .expect("The default chat template is valid");
 
         ChatBuilder {
             model,
-            chat_markers,
+            format,
             session: None,
             system_prompt: "Always assist with care, respect, and truth. Respond with utmost utility yet securely. Avoid harmful, unethical, prejudiced, or negative content. Ensure replies promote fairness and positivity.".into(),
             sampler: Arc::new(Mutex::new(GenerationParameters::default().sampler())),
@@ -343,6 +336,14 @@ impl<'a, M: Model> ChatBuilder<'a, M> {
         self
     }
 
+    /// Sets the [`ChatTemplate`] used to format the messages in the chat. This can be used to chat with models that use a different format than the chat markers kalosm provides for them.
+    ///
+    /// Returns an error if the template cannot be rendered with the model's chat markers.
+    pub fn with_chat_template(mut self, chat_template: ChatTemplate) -> anyhow::Result<Self> {
+        self.format = chat_template.render(self.model.chat_markers().as_ref())?;
+        Ok(self)
+    }
+
     /// Filters out bot responses that do not match the given filter.
     ///
     /// > **Note**: This setting will disable streaming responses.
@@ -395,6 +396,8 @@ impl<'a, M: Model> ChatBuilder<'a, M> {
 
     /// Filters out bot responses that do not match the given filter, and maps the bot response before it is sent to the stream.
     ///
+    /// The mapped response is recorded in the chat history, so the history passed to [`Self::constrain_response`] contains the responses the user saw.
+    ///
     /// > **Note**: This setting will disable streaming responses.
     pub fn filter_map_bot_response(
         mut self,
@@ -457,7 +460,7 @@ impl<'a, M: Model> ChatBuilder<'a, M> {
     {
         let Self {
             model,
-            chat_markers,
+            format,
             system_prompt,
             sampler,
             map_user_message_prompt,
@@ -466,12 +469,6 @@ impl<'a, M: Model> ChatBuilder<'a, M> {
             session,
             initial_history,
         } = self;
-        let system_prompt_marker = chat_markers.system_prompt_marker.to_string();
-        let end_system_prompt_marker = chat_markers.end_system_prompt_marker.to_string();
-        let user_marker = chat_markers.user_marker.to_string();
-        let end_user_marker = chat_markers.end_user_marker.to_string();
-        let assistant_marker = chat_markers.assistant_marker.to_string();
-        let end_assistant_marker = chat_markers.end_assistant_marker.to_string();
         let (sender_tx, mut sender_rx) = unbounded_channel();
         let (result_tx, result_rx) = unbounded_channel();
         model
@@ -479,12 +476,7 @@ impl<'a, M: Model> ChatBuilder<'a, M> {
                 Box::pin(async move {
                     let mut session = ChatSession::new(
                         model,
-                        system_prompt_marker,
-                        end_system_prompt_marker,
-                        user_marker,
-                        end_user_marker,
-                        assistant_marker,
-                        end_assistant_marker,
+                        format,
                         system_prompt,
                         map_user_message_prompt,
                         bot_constraints,
@@ -505,6 +497,11 @@ impl<'a, M: Model> ChatBuilder<'a, M> {
                                 session.session.save_to(path).unwrap();
                                 result_tx.send(Response::SaveSession).unwrap();
                             }
+                            Message::History => {
+                                result_tx
+                                    .send(Response::History(session.history.clone()))
+                                    .unwrap();
+                            }
                         }
                     }
                 })
@@ -521,11 +518,13 @@ impl<'a, M: Model> ChatBuilder<'a, M> {
 enum Message {
     AddMessage(String),
     SaveSession(PathBuf),
+    History,
 }
 
 enum Response {
     AddMessage(ChannelTextStream<String>),
     SaveSession,
+    History(Vec<ChatHistoryItem>),
 }
 
 /// A chat session.
@@ -563,6 +562,21 @@ impl Chat {
             .ok_or(anyhow::anyhow!("Model stopped"))
     }
 
+    /// Get the messages in the chat so far, starting with the system prompt. If the model is still responding to a message, this waits until the response is finished.
+    pub async fn history(&mut self) -> Result<Vec<ChatHistoryItem>> {
+        self.sender
+            .send(Message::History)
+            .map_err(|_| anyhow::anyhow!("Model stopped"))?;
+        self.channel
+            .recv()
+            .await
+            .map(|c| match c {
+                Response::History(history) => history,
+                _ => unreachable!(),
+            })
+            .ok_or(anyhow::anyhow!("Model stopped"))
+    }
+
     /// Saves the session to the given path.
     pub async fn save_session(&mut self, path: impl AsRef<std::path::Path>) -> Result<()> {
         self.sender
@@ -1,6 +1,12 @@
+use std::fmt::Display;
+use std::ops::Range;
+use std::path::PathBuf;
+
 use url::Url;
 pub use whatlang::Lang;
 
+use crate::vector_db::{EmbeddingMetadata, MetadataValue};
+
 /// A document is a piece of text with a title.
 #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct Document {
@@ -9,6 +15,14 @@ pub struct Document {
     summary: Option<String>,
     created_at: Option<chrono::DateTime<chrono::Utc>>,
     updated_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(default)]
+    source: Option<DocumentSource>,
+    #[serde(default)]
+    metadata: EmbeddingMetadata,
+    #[serde(default)]
+    sections: Vec<DocumentSection>,
+    #[serde(default)]
+    pages: Vec<Range<usize>>,
 }
 
 impl Document {
@@ -30,6 +44,10 @@ impl Document {
             summary: None,
             created_at: None,
             updated_at: None,
+            source: None,
+            metadata: EmbeddingMetadata::new(),
+            sections: Vec::new(),
+            pages: Vec::new(),
         }
     }
 
@@ -57,6 +75,206 @@ impl Document {
     pub fn body(&self) -> &str {
         &self.body
     }
+
+    /// Set the file or URL the document was loaded from.
+    pub fn set_source(&mut self, source: impl Into<DocumentSource>) {
+        self.source = Some(source.into());
+    }
+
+    /// Get the file or URL the document was loaded from.
+    pub fn source(&self) -> Option<&DocumentSource> {
+        self.source.as_ref()
+    }
+
+    /// Insert a value into the metadata of the document, like the author or publish date.
+    pub fn insert_metadata(&mut self, key: impl ToString, value: impl Into<MetadataValue>) {
+        self.metadata.insert(key, value);
+    }
+
+    /// Get the metadata of the document. The metadata can be passed directly to [`crate::vector_db::VectorDB::add_embedding_with_metadata`] to filter searches by document.
+    pub fn metadata(&self) -> &EmbeddingMetadata {
+        &self.metadata
+    }
+
+    /// Set the sections of the document. The byte ranges of the sections are ranges in the body of the document.
+    pub fn set_sections(&mut self, sections: Vec<DocumentSection>) {
+        self.sections = sections;
+    }
+
+    /// Get the top level sections of the document.
+    pub fn sections(&self) -> &[DocumentSection] {
+        &self.sections
+    }
+
+    /// Set the byte range in the body of each page in the document. The first range is page 1.
+    pub fn set_pages(&mut self, pages: Vec<Range<usize>>) {
+        self.pages = pages;
+    }
+
+    /// Get the byte range in the body of each page in the document. The first range is page 1.
+    pub fn pages(&self) -> &[Range<usize>] {
+        &self.pages
+    }
+
+    /// Get the page number (starting at 1) that contains a byte offset in the body.
+    pub fn page_at(&self, byte_offset: usize) -> Option<usize> {
+        self.pages
+            .iter()
+            .position(|page| page.contains(&byte_offset))
+            .map(|index| index + 1)
+    }
+
+    /// Get the path of nested sections that contain a byte offset in the body, starting with the top level section.
+    pub fn section_path_at(&self, byte_offset: usize) -> Vec<&DocumentSection> {
+        let mut path = Vec::new();
+        let mut sections = &self.sections;
+        while let Some(section) = sections
+            .iter()
+            .find(|section| section.byte_range.contains(&byte_offset))
+        {
+            path.push(section);
+            sections = &section.children;
+        }
+        path
+    }
+
+    /// Find where a byte offset in the body is in the original document. This can be used to cite the source of a chunk.
+    pub fn locate(&self, byte_offset: usize) -> DocumentLocation {
+        DocumentLocation {
+            source: self.source.clone(),
+            page: self.page_at(byte_offset),
+            section_path: self
+                .section_path_at(byte_offset)
+                .into_iter()
+                .map(|section| section.title.clone())
+                .collect(),
+        }
+    }
+}
+
+/// The file or URL a [`Document`] was loaded from.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum DocumentSource {
+    /// A file on the local file system.
+    File(PathBuf),
+    /// A web page or feed.
+    Url(Url),
+}
+
+impl From<PathBuf> for DocumentSource {
+    fn from(path: PathBuf) -> Self {
+        Self::File(path)
+    }
+}
+
+impl From<Url> for DocumentSource {
+    fn from(url: Url) -> Self {
+        Self::Url(url)
+    }
+}
+
+impl Display for DocumentSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::File(path) => write!(f, "{}", path.display()),
+            Self::Url(url) => write!(f, "{}", url),
+        }
+    }
+}
+
+/// A (possibly nested) section of a [`Document`], like a chapter or a heading in a web page.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct DocumentSection {
+    /// The title of the section.
+    pub title: String,
+    /// The byte range of the section in the body of the document, including the title and all subsections.
+    pub byte_range: Range<usize>,
+    /// The subsections of the section.
+    pub children: Vec<DocumentSection>,
+}
+
+impl DocumentSection {
+    /// Build a section tree from a list of headings in the order they appear in the body. Each heading is a level (1 for the top level) and a title.
+    ///
+    /// Each heading is found by searching the body for the title after the previous heading. Headings that are not in the body are skipped. Each section runs until the next heading of the same or a higher level.
+    pub fn from_headings(
+        body: &str,
+        headings: impl IntoIterator<Item = (usize, String)>,
+    ) -> Vec<Self> {
+        let mut cursor = 0;
+        let mut located = Vec::new();
+        for (level, title) in headings {
+            let title = title.trim();
+            if title.is_empty() {
+                continue;
+            }
+            if let Some(offset) = body[cursor..].find(title) {
+                let start = cursor + offset;
+                cursor = start + title.len();
+                located.push((level, title.to_string(), start));
+            }
+        }
+
+        // The stack holds the sections that are still open along with their level
+        let mut roots = Vec::new();
+        let mut stack: Vec<(usize, Self)> = Vec::new();
+        fn close(
+            roots: &mut Vec<DocumentSection>,
+            stack: &mut Vec<(usize, DocumentSection)>,
+            end: usize,
+        ) {
+            let (_, mut section) = stack.pop().unwrap();
+            section.byte_range.end = end;
+            match stack.last_mut() {
+                Some((_, parent)) => parent.children.push(section),
+                None => roots.push(section),
+            }
+        }
+        for (level, title, start) in located {
+            while stack.last().is_some_and(|(open, _)| *open >= level) {
+                close(&mut roots, &mut stack, start);
+            }
+            stack.push((
+                level,
+                Self {
+                    title,
+                    byte_range: start..body.len(),
+                    children: Vec::new(),
+                },
+            ));
+        }
+        while !stack.is_empty() {
+            close(&mut roots, &mut stack, body.len());
+        }
+        roots
+    }
+}
+
+/// Where a piece of a [`Document`] came from. The display implementation formats the location as a citation like `report.pdf, page 12, section Results > Accuracy`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocumentLocation {
+    /// The file or URL of the document.
+    pub source: Option<DocumentSource>,
+    /// The page number, starting at 1.
+    pub page: Option<usize>,
+    /// The titles of the nested sections, starting with the top level section.
+    pub section_path: Vec<String>,
+}
+
+impl Display for DocumentLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(source) = &self.source {
+            parts.push(source.to_string());
+        }
+        if let Some(page) = self.page {
+            parts.push(format!("page {}", page));
+        }
+        if !self.section_path.is_empty() {
+            parts.push(format!("section {}", self.section_path.join(" > ")));
+        }
+        write!(f, "{}", parts.join(", "))
+    }
 }
 
 impl std::fmt::Display for Document {
@@ -103,7 +321,7 @@ impl IntoDocument for Document {
 #[async_trait::async_trait]
 impl IntoDocument for Url {
     async fn into_document(self) -> anyhow::Result<Document> {
-        super::page::get_article(self).await
+        super::page::UrlDocument::new(self).into_document().await
     }
 }
 
@@ -128,3 +346,34 @@ where
         Ok(documents)
     }
 }
+
+#[test]
+fn test_document_location() {
+    let body =
+        "Intro\nResults\nThe model is fast.\nAccuracy\nThe model is accurate.\nSummary\nDone.";
+    let mut document = Document::from_parts("Report", body);
+    document.set_source(PathBuf::from("report.pdf"));
+    document.set_pages(vec![0..32, 32..body.len()]);
+    document.set_sections(DocumentSection::from_headings(
+        body,
+        [
+            (1, "Results".to_string()),
+            (2, "Accuracy".to_string()),
+            (1, "Summary".to_string()),
+        ],
+    ));
+
+    assert_eq!(document.sections().len(), 2);
+    assert_eq!(document.sections()[0].children[0].title, "Accuracy");
+    assert_eq!(
+        &body[document.sections()[1].byte_range.clone()],
+        "Summary\nDone."
+    );
+
+    let accurate = body.find("accurate").unwrap();
+    assert_eq!(
+        document.locate(accurate).to_string(),
+        "report.pdf, page 2, section Results > Accuracy"
+    );
+    assert_eq!(document.locate(0).to_string(), "report.pdf, page 1");
+}
@@ -0,0 +1,57 @@
+use std::hash::Hasher;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+/// A response saved in the on-disk cache.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct CachedResponse {
+    /// The method and URL of the request, used to detect hash collisions.
+    pub(crate) request: String,
+    pub(crate) status: u16,
+    /// The URL of the response after following redirects.
+    pub(crate) url: String,
+    pub(crate) etag: Option<String>,
+    pub(crate) last_modified: Option<String>,
+    pub(crate) body: String,
+}
+
+/// An on-disk cache of http responses with one json file per request.
+#[derive(Debug, Clone)]
+pub(crate) struct HttpCache {
+    dir: PathBuf,
+}
+
+impl HttpCache {
+    pub(crate) fn new(dir: impl AsRef<Path>) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Get the key that identifies a request. The body is part of the key so post requests with different bodies are cached separately.
+    pub(crate) fn request_key(method: &str, url: &str, body: Option<&str>) -> String {
+        match body {
+            Some(body) => format!("{} {}\n{}", method, url, body),
+            None => format!("{} {}", method, url),
+        }
+    }
+
+    fn path(&self, request: &str) -> PathBuf {
+        let mut hasher = rustc_hash::FxHasher::default();
+        hasher.write(request.as_bytes());
+        self.dir.join(format!("{:016x}.json", hasher.finish()))
+    }
+
+    pub(crate) async fn load(&self, request: &str) -> Option<CachedResponse> {
+        let json = tokio::fs::read_to_string(self.path(request)).await.ok()?;
+        let cached: CachedResponse = serde_json::from_str(&json).ok()?;
+        (cached.request == request).then_some(cached)
+    }
+
+    pub(crate) async fn store(&self, response: &CachedResponse) -> anyhow::Result<()> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+        let path = self.path(&response.request);
+        super::write_atomically(&path, &serde_json::to_string(response)?).await
+    }
+}
@@ -0,0 +1,427 @@
+//! A shared http client with an on-disk cache and record/replay support.
+
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use once_cell::sync::OnceCell;
+use reqwest::header::{CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::{Method, StatusCode};
+use url::Url;
+
+mod cache;
+use cache::{CachedResponse, HttpCache};
+
+/// How an [`HttpClient`] uses its cache directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FetchMode {
+    /// Always fetch from the network. The cache is never read or written.
+    Network,
+    /// Cache successful get requests and revalidate them with the `ETag` and `Last-Modified` headers. If the network is unavailable, the cached response is used.
+    #[default]
+    Cache,
+    /// Always fetch from the network and record every response in the cache.
+    Record,
+    /// Only use responses that were recorded in the cache. Requests without a recorded response fail without accessing the network.
+    Replay,
+}
+
+impl FromStr for FetchMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "network" => Ok(Self::Network),
+            "cache" => Ok(Self::Cache),
+            "record" => Ok(Self::Record),
+            "replay" => Ok(Self::Replay),
+            _ => Err(anyhow::anyhow!(
+                "Unknown fetch mode {}, expected network, cache, record or replay",
+                s
+            )),
+        }
+    }
+}
+
+/// A response from an [`HttpClient`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpResponse {
+    status: u16,
+    url: Url,
+    body: String,
+}
+
+impl HttpResponse {
+    fn from_cached(cached: CachedResponse, request_url: &Url) -> Self {
+        Self {
+            status: cached.status,
+            url: Url::parse(&cached.url).unwrap_or_else(|_| request_url.clone()),
+            body: cached.body,
+        }
+    }
+
+    /// Get the status code of the response.
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// Get the URL of the response after following redirects.
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    /// Check if the status code of the response is in the 200 range.
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    /// Return an error if the status code of the response is not in the 200 range.
+    pub fn error_for_status(self) -> anyhow::Result<Self> {
+        if self.is_success() {
+            Ok(self)
+        } else {
+            Err(anyhow::anyhow!(
+                "Request to {} failed with status {}",
+                self.url,
+                self.status
+            ))
+        }
+    }
+
+    /// Get the body of the response.
+    pub fn text(&self) -> &str {
+        &self.body
+    }
+
+    /// Get the body of the response as an owned string.
+    pub fn into_text(self) -> String {
+        self.body
+    }
+
+    /// Parse the body of the response as json.
+    pub fn json<T: serde::de::DeserializeOwned>(&self) -> anyhow::Result<T> {
+        Ok(serde_json::from_str(&self.body)?)
+    }
+}
+
+/// A builder for an [`HttpClient`].
+#[derive(Debug, Clone)]
+pub struct HttpClientBuilder {
+    user_agent: String,
+    timeout: Duration,
+    cache_dir: Option<PathBuf>,
+    mode: FetchMode,
+}
+
+impl Default for HttpClientBuilder {
+    fn default() -> Self {
+        Self {
+            user_agent: concat!("kalosm-language/", env!("CARGO_PKG_VERSION")).to_string(),
+            timeout: Duration::from_secs(30),
+            cache_dir: None,
+            mode: FetchMode::default(),
+        }
+    }
+}
+
+impl HttpClientBuilder {
+    /// Set the user agent that is sent with every request.
+    pub fn with_user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = user_agent.into();
+        self
+    }
+
+    /// Set the timeout for each request. Defaults to 30 seconds.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set the directory responses are cached in. Without a cache directory, every request is fetched from the network.
+    pub fn with_cache_dir(mut self, cache_dir: impl AsRef<Path>) -> Self {
+        self.cache_dir = Some(cache_dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Set how the cache is used. Defaults to [`FetchMode::Cache`].
+    pub fn with_mode(mut self, mode: FetchMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Build the http client.
+    pub fn build(self) -> anyhow::Result<HttpClient> {
+        if self.mode == FetchMode::Replay && self.cache_dir.is_none() {
+            return Err(anyhow::anyhow!("Replay mode requires a cache directory"));
+        }
+        let client = reqwest::Client::builder()
+            .user_agent(self.user_agent)
+            .timeout(self.timeout)
+            .build()?;
+        Ok(HttpClient {
+            client,
+            cache: self.cache_dir.map(HttpCache::new),
+            mode: self.mode,
+        })
+    }
+}
+
+/// An http client with an on-disk cache that is shared by the pages, feeds, crawlers and search providers in kalosm.
+///
+/// The [global client](HttpClient::global) is configured with the `KALOSM_HTTP_CACHE` (the cache directory) and `KALOSM_HTTP_MODE` (`network`, `cache`, `record` or `replay`) environment variables, or with [`HttpClient::set_global`]. Recording a run of an example with `KALOSM_HTTP_MODE=record` and then running it again with `KALOSM_HTTP_MODE=replay` makes the example deterministic and offline.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm_language::prelude::*;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let client = HttpClient::builder()
+///         .with_cache_dir("./http-cache")
+///         .with_mode(FetchMode::Record)
+///         .build()
+///         .unwrap();
+///     HttpClient::set_global(client).unwrap();
+///
+///     let url = Url::parse("https://floneum.com").unwrap();
+///     let html = HttpClient::global().get_text(&url).await.unwrap();
+///     println!("{}", html);
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct HttpClient {
+    client: reqwest::Client,
+    cache: Option<HttpCache>,
+    mode: FetchMode,
+}
+
+impl Default for HttpClient {
+    fn default() -> Self {
+        HttpClientBuilder::default()
+            .build()
+            .expect("Failed to create http client")
+    }
+}
+
+static GLOBAL_CLIENT: OnceCell<HttpClient> = OnceCell::new();
+
+impl HttpClient {
+    /// Create a new http client without a cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a builder for an http client.
+    pub fn builder() -> HttpClientBuilder {
+        HttpClientBuilder::default()
+    }
+
+    /// Create an http client configured with the `KALOSM_HTTP_CACHE` and `KALOSM_HTTP_MODE` environment variables.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let mut builder = Self::builder();
+        if let Ok(cache_dir) = std::env::var("KALOSM_HTTP_CACHE") {
+            builder = builder.with_cache_dir(cache_dir);
+        }
+        if let Ok(mode) = std::env::var("KALOSM_HTTP_MODE") {
+            builder = builder.with_mode(mode.parse()?);
+        }
+        builder.build()
+    }
+
+    /// Get the global http client. If no client was set with [`HttpClient::set_global`], the client is created from the environment with [`HttpClient::from_env`].
+    pub fn global() -> &'static Self {
+        GLOBAL_CLIENT.get_or_init(|| {
+            Self::from_env().unwrap_or_else(|err| {
+                tracing::error!("Failed to configure the http client: {}", err);
+                Self::default()
+            })
+        })
+    }
+
+    /// Set the global http client. This fails if the global client was already used.
+    pub fn set_global(client: Self) -> anyhow::Result<()> {
+        GLOBAL_CLIENT
+            .set(client)
+            .map_err(|_| anyhow::anyhow!("The global http client was already initialized"))
+    }
+
+    /// Get the fetch mode of the client.
+    pub fn mode(&self) -> FetchMode {
+        self.mode
+    }
+
+    /// Send a get request.
+    pub async fn get(&self, url: &Url) -> anyhow::Result<HttpResponse> {
+        self.get_with_headers(url, &[]).await
+    }
+
+    /// Send a get request and return the body, failing if the response was not successful.
+    pub async fn get_text(&self, url: &Url) -> anyhow::Result<String> {
+        Ok(self.get(url).await?.error_for_status()?.into_text())
+    }
+
+    /// Send a get request with extra headers. Headers are not part of the cache key, so api keys are never written to the cache.
+    pub async fn get_with_headers(
+        &self,
+        url: &Url,
+        headers: &[(&str, &str)],
+    ) -> anyhow::Result<HttpResponse> {
+        self.execute(Method::GET, url, headers, None).await
+    }
+
+    /// Send a post request with a json body. Post requests are only cached in [`FetchMode::Record`] and [`FetchMode::Replay`].
+    pub async fn post_json(
+        &self,
+        url: &Url,
+        headers: &[(&str, &str)],
+        body: &serde_json::Value,
+    ) -> anyhow::Result<HttpResponse> {
+        self.execute(Method::POST, url, headers, Some(body.to_string()))
+            .await
+    }
+
+    async fn execute(
+        &self,
+        method: Method,
+        url: &Url,
+        headers: &[(&str, &str)],
+        body: Option<String>,
+    ) -> anyhow::Result<HttpResponse> {
+        let request_key = HttpCache::request_key(method.as_str(), url.as_str(), body.as_deref());
+        let cache = match self.mode {
+            FetchMode::Network => None,
+            FetchMode::Cache if method != Method::GET => None,
+            _ => self.cache.as_ref(),
+        };
+
+        if self.mode == FetchMode::Replay {
+            let cache = cache.ok_or_else(|| anyhow::anyhow!("Replay mode requires a cache"))?;
+            return match cache.load(&request_key).await {
+                Some(cached) => Ok(HttpResponse::from_cached(cached, url)),
+                None => Err(anyhow::anyhow!(
+                    "No recorded response for {} {}",
+                    method,
+                    url
+                )),
+            };
+        }
+
+        let cached = match (cache, self.mode) {
+            (Some(cache), FetchMode::Cache) => cache.load(&request_key).await,
+            _ => None,
+        };
+
+        let mut request = self.client.request(method.clone(), url.clone());
+        for (key, value) in headers {
+            request = request.header(*key, *value);
+        }
+        if let Some(body) = body {
+            request = request.header(CONTENT_TYPE, "application/json").body(body);
+        }
+        if let Some(cached) = &cached {
+            if let Some(etag) = &cached.etag {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &cached.last_modified {
+                request = request.header(IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+
+        let response = match request.send().await {
+            Ok(response) => response,
+            Err(err) => {
+                return match cached {
+                    Some(cached) => {
+                        tracing::warn!("Using cached response for {}: {}", url, err);
+                        Ok(HttpResponse::from_cached(cached, url))
+                    }
+                    None => Err(err.into()),
+                }
+            }
+        };
+        if response.status() == StatusCode::NOT_MODIFIED {
+            if let Some(cached) = cached {
+                return Ok(HttpResponse::from_cached(cached, url));
+            }
+        }
+
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        let etag = header(ETAG);
+        let last_modified = header(LAST_MODIFIED);
+        let status = response.status().as_u16();
+        let response_url = response.url().to_string();
+        let body = response.text().await?;
+        let entry = CachedResponse {
+            request: request_key,
+            status,
+            url: response_url,
+            etag,
+            last_modified,
+            body,
+        };
+
+        if let Some(cache) = cache {
+            // Failed responses are only saved when recording so a replay sees the same failures
+            if self.mode == FetchMode::Record || (200..300).contains(&status) {
+                if let Err(err) = cache.store(&entry).await {
+                    tracing::warn!("Failed to cache response for {}: {}", url, err);
+                }
+            }
+        }
+
+        Ok(HttpResponse::from_cached(entry, url))
+    }
+}
+
+/// Write a file through a temporary file next to it, so a crash never leaves a partially written file.
+pub(crate) async fn write_atomically(path: &Path, contents: &str) -> anyhow::Result<()> {
+    let temp = path.with_extension("tmp");
+    tokio::fs::write(&temp, contents).await?;
+    tokio::fs::rename(&temp, path).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn replay_uses_recorded_responses() {
+    let dir = tempfile::tempdir().unwrap();
+    let url = Url::parse("https://example.com/feed.xml").unwrap();
+    HttpCache::new(dir.path())
+        .store(&CachedResponse {
+            request: HttpCache::request_key("GET", url.as_str(), None),
+            status: 200,
+            url: url.to_string(),
+            etag: Some("\"abc\"".to_string()),
+            last_modified: None,
+            body: "<rss></rss>".to_string(),
+        })
+        .await
+        .unwrap();
+
+    let client = HttpClient::builder()
+        .with_cache_dir(dir.path())
+        .with_mode(FetchMode::Replay)
+        .build()
+        .unwrap();
+    assert_eq!(client.get_text(&url).await.unwrap(), "<rss></rss>");
+
+    // Requests that were not recorded fail without touching the network
+    let missing = Url::parse("https://example.com/missing").unwrap();
+    assert!(client.get(&missing).await.is_err());
+    let search = Url::parse("https://example.com/search").unwrap();
+    assert!(client
+        .post_json(&search, &[], &serde_json::json!({ "q": "kalosm" }))
+        .await
+        .is_err());
+
+    assert_eq!("REPLAY".parse::<FetchMode>().unwrap(), FetchMode::Replay);
+    assert!(HttpClient::builder()
+        .with_mode(FetchMode::Replay)
+        .build()
+        .is_err());
+}
@@ -0,0 +1,159 @@
+use std::path::PathBuf;
+
+use tokio::{fs::File, io::AsyncReadExt};
+
+use super::FileType;
+use crate::context::document::{Document, DocumentSection, IntoDocument};
+use crate::search::CodeChunker;
+
+/// Find the programming language of a file from the extension.
+pub(crate) fn language_from_extension(extension: &str) -> Option<&'static str> {
+    let language = match extension {
+        "rs" => "Rust",
+        "py" | "pyi" => "Python",
+        "js" | "mjs" | "cjs" | "jsx" => "JavaScript",
+        "ts" | "tsx" => "TypeScript",
+        "go" => "Go",
+        "java" => "Java",
+        "kt" | "kts" => "Kotlin",
+        "scala" => "Scala",
+        "swift" => "Swift",
+        "c" | "h" => "C",
+        "cc" | "cpp" | "cxx" | "hpp" | "hh" => "C++",
+        "cs" => "C#",
+        "rb" => "Ruby",
+        "php" => "PHP",
+        "lua" => "Lua",
+        "zig" => "Zig",
+        "hs" => "Haskell",
+        "ml" | "mli" => "OCaml",
+        "ex" | "exs" => "Elixir",
+        "erl" => "Erlang",
+        "clj" => "Clojure",
+        "dart" => "Dart",
+        "r" => "R",
+        "jl" => "Julia",
+        "sh" | "bash" | "zsh" => "Shell",
+        "ps1" => "PowerShell",
+        "sql" => "SQL",
+        _ => return None,
+    };
+    Some(language)
+}
+
+/// Find the programming language of a script from the shebang line.
+pub(crate) fn language_from_shebang(line: &str) -> Option<&'static str> {
+    let interpreter = line.strip_prefix("#!")?;
+    // `#!/usr/bin/env python3` runs the first argument, `#!/bin/sh` runs the path itself
+    let mut parts = interpreter.split_whitespace();
+    let mut program = parts.next()?.rsplit('/').next()?;
+    if program == "env" {
+        program = parts.find(|part| !part.starts_with('-'))?;
+    }
+    let program = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    let language = match program {
+        "python" => "Python",
+        "node" | "deno" => "JavaScript",
+        "ruby" => "Ruby",
+        "perl" => "Perl",
+        "php" => "PHP",
+        "lua" => "Lua",
+        "sh" | "bash" | "zsh" | "dash" | "ksh" => "Shell",
+        "pwsh" => "PowerShell",
+        "rust-script" => "Rust",
+        _ => return None,
+    };
+    Some(language)
+}
+
+/// A source code file that can be read from the file system. The document has a section for each top level definition in the file and the language of the file in the `language` metadata field.
+#[derive(Debug, Clone)]
+pub struct CodeDocument {
+    path: PathBuf,
+    language: &'static str,
+}
+
+impl TryFrom<PathBuf> for CodeDocument {
+    type Error = anyhow::Error;
+
+    fn try_from(path: PathBuf) -> Result<Self, Self::Error> {
+        FileType::check(&path, FileType::Code, &[])?;
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase());
+        let language = match extension.as_deref().and_then(language_from_extension) {
+            Some(language) => language,
+            None => {
+                let text = std::fs::read_to_string(&path)?;
+                language_from_shebang(text.lines().next().unwrap_or_default())
+                    .ok_or_else(|| anyhow::anyhow!("Path is not a source code file"))?
+            }
+        };
+        Ok(Self { path, language })
+    }
+}
+
+impl CodeDocument {
+    /// Get the programming language of the file.
+    pub fn language(&self) -> &'static str {
+        self.language
+    }
+}
+
+#[async_trait::async_trait]
+impl IntoDocument for CodeDocument {
+    async fn into_document(self) -> anyhow::Result<Document> {
+        let title = self
+            .path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let file = File::open(&self.path).await?;
+        let mut text = String::new();
+        tokio::io::BufReader::new(file)
+            .read_to_string(&mut text)
+            .await?;
+
+        // The chunker may split long definitions into several ranges, so merge ranges that belong to the same definition
+        let mut sections: Vec<DocumentSection> = Vec::new();
+        for range in CodeChunker::new().chunk_str(&text) {
+            if range.title.is_empty() {
+                continue;
+            }
+            match sections.last_mut() {
+                Some(last) if last.title == range.title => {
+                    last.byte_range.end = range.byte_range.end;
+                }
+                _ => sections.push(DocumentSection {
+                    title: range.title,
+                    byte_range: range.byte_range,
+                    children: Vec::new(),
+                }),
+            }
+        }
+
+        let mut document = Document::from_parts(title, text);
+        document.set_source(self.path);
+        document.set_sections(sections);
+        document.insert_metadata("language", self.language);
+        Ok(document)
+    }
+}
+
+#[test]
+fn test_language_detection() {
+    assert_eq!(language_from_extension("rs"), Some("Rust"));
+    assert_eq!(language_from_extension("tsx"), Some("TypeScript"));
+    assert_eq!(language_from_extension("docx"), None);
+    assert_eq!(
+        language_from_shebang("#!/usr/bin/env python3"),
+        Some("Python")
+    );
+    assert_eq!(
+        language_from_shebang("#!/usr/bin/env -S node"),
+        Some("JavaScript")
+    );
+    assert_eq!(language_from_shebang("#!/bin/bash"), Some("Shell"));
+    assert_eq!(language_from_shebang("# not a shebang"), None);
+}
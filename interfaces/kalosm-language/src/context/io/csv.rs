@@ -0,0 +1,185 @@
+use convert_case::{Case, Casing};
+use std::path::{Path, PathBuf};
+
+use super::FileType;
+use crate::context::document::{Document, IntoDocument, IntoDocuments};
+use crate::vector_db::MetadataValue;
+
+/// How the rows of a [`CsvDocument`] are turned into documents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CsvMode {
+    /// Turn the whole file into one document with a markdown table.
+    #[default]
+    Table,
+    /// Turn each row into a separate document with a `column: value` line for each column. The values are also added to the metadata of the document so searches can be filtered by column.
+    Rows,
+}
+
+/// A csv or tsv document that can be read from the file system.
+///
+/// The delimiter is detected from the extension and the first line of the file unless it is set with [`CsvDocument::with_delimiter`].
+#[derive(Debug, Clone)]
+pub struct CsvDocument {
+    path: PathBuf,
+    mode: CsvMode,
+    delimiter: Option<u8>,
+}
+
+impl TryFrom<PathBuf> for CsvDocument {
+    type Error = anyhow::Error;
+
+    fn try_from(path: PathBuf) -> Result<Self, Self::Error> {
+        FileType::check(&path, FileType::Csv, &["csv", "tsv"])?;
+        Ok(Self {
+            path,
+            mode: CsvMode::default(),
+            delimiter: None,
+        })
+    }
+}
+
+impl CsvDocument {
+    /// Set how rows are turned into documents.
+    pub fn with_mode(mut self, mode: CsvMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Set the delimiter between columns.
+    pub fn with_delimiter(mut self, delimiter: u8) -> Self {
+        self.delimiter = Some(delimiter);
+        self
+    }
+
+    fn title(&self) -> String {
+        self.path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string().to_case(Case::Title))
+            .unwrap_or_default()
+    }
+
+    async fn read(&self) -> anyhow::Result<(Vec<String>, Vec<Vec<String>>)> {
+        let text = tokio::fs::read_to_string(&self.path).await?;
+        let delimiter = self
+            .delimiter
+            .unwrap_or_else(|| detect_delimiter(&self.path, &text));
+        let mut reader = csv::ReaderBuilder::new()
+            .delimiter(delimiter)
+            .flexible(true)
+            .from_reader(text.as_bytes());
+        let headers = reader.headers()?.iter().map(str::to_string).collect();
+        let mut rows = Vec::new();
+        for record in reader.records() {
+            rows.push(record?.iter().map(str::to_string).collect());
+        }
+        Ok((headers, rows))
+    }
+
+    async fn table_document(&self) -> anyhow::Result<Document> {
+        let (headers, rows) = self.read().await?;
+        let mut document = Document::from_parts(self.title(), markdown_table(&headers, &rows));
+        document.set_source(self.path.clone());
+        document.insert_metadata("columns", headers);
+        document.insert_metadata("row_count", rows.len() as f64);
+        Ok(document)
+    }
+
+    async fn row_documents(&self) -> anyhow::Result<Vec<Document>> {
+        let (headers, rows) = self.read().await?;
+        let title = self.title();
+        let mut documents = Vec::new();
+        for (index, row) in rows.into_iter().enumerate() {
+            let body = headers
+                .iter()
+                .zip(&row)
+                .map(|(header, value)| format!("{}: {}", header, value))
+                .collect::<Vec<_>>()
+                .join("\n");
+            let mut document = Document::from_parts(format!("{} row {}", title, index + 1), body);
+            document.set_source(self.path.clone());
+            document.insert_metadata("row", (index + 1) as f64);
+            for (header, value) in headers.iter().zip(row) {
+                let value = match value.trim().parse::<f64>() {
+                    Ok(number) => MetadataValue::Number(number),
+                    Err(_) => MetadataValue::String(value),
+                };
+                document.insert_metadata(header, value);
+            }
+            documents.push(document);
+        }
+        Ok(documents)
+    }
+}
+
+/// Pick the delimiter that appears most often in the first line, preferring tabs for tsv files.
+fn detect_delimiter(path: &Path, text: &str) -> u8 {
+    if path.extension().is_some_and(|extension| extension == "tsv") {
+        return b'\t';
+    }
+    let first_line = text.lines().next().unwrap_or_default();
+    [b',', b'\t', b';', b'|']
+        .into_iter()
+        .max_by_key(|delimiter| first_line.bytes().filter(|b| b == delimiter).count())
+        .filter(|delimiter| first_line.as_bytes().contains(delimiter))
+        .unwrap_or(b',')
+}
+
+fn markdown_table(headers: &[String], rows: &[Vec<String>]) -> String {
+    fn escape(cell: &str) -> String {
+        cell.replace('|', "\\|").replace(['\r', '\n'], " ")
+    }
+    let mut table = String::new();
+    table += &format!(
+        "| {} |\n",
+        headers
+            .iter()
+            .map(|h| escape(h))
+            .collect::<Vec<_>>()
+            .join(" | ")
+    );
+    table += &format!("|{}\n", " --- |".repeat(headers.len()));
+    for row in rows {
+        let cells = (0..headers.len())
+            .map(|index| escape(row.get(index).map(String::as_str).unwrap_or_default()))
+            .collect::<Vec<_>>();
+        table += &format!("| {} |\n", cells.join(" | "));
+    }
+    table
+}
+
+#[async_trait::async_trait]
+impl IntoDocument for CsvDocument {
+    async fn into_document(self) -> anyhow::Result<Document> {
+        self.table_document().await
+    }
+}
+
+#[async_trait::async_trait]
+impl IntoDocuments for CsvDocument {
+    async fn into_documents(self) -> anyhow::Result<Vec<Document>> {
+        match self.mode {
+            CsvMode::Table => Ok(vec![self.table_document().await?]),
+            CsvMode::Rows => self.row_documents().await,
+        }
+    }
+}
+
+#[test]
+fn test_csv_formatting() {
+    assert_eq!(detect_delimiter(Path::new("a.csv"), "a;b;c\n1;2;3"), b';');
+    assert_eq!(detect_delimiter(Path::new("a.csv"), "a,b\n1,2"), b',');
+    assert_eq!(detect_delimiter(Path::new("a.csv"), "single"), b',');
+    assert_eq!(detect_delimiter(Path::new("a.tsv"), "a,b"), b'\t');
+
+    let table = markdown_table(
+        &["name".to_string(), "notes".to_string()],
+        &[
+            vec!["a|b".to_string(), "line\nbreak".to_string()],
+            vec!["short".to_string()],
+        ],
+    );
+    assert_eq!(
+        table,
+        "| name | notes |\n| --- | --- |\n| a\\|b | line break |\n| short |  |\n"
+    );
+}
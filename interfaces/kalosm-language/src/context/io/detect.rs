@@ -0,0 +1,238 @@
+use std::io::Read;
+use std::path::Path;
+
+use super::{language_from_extension, language_from_shebang};
+
+/// The kind of a file on the file system, detected from the contents of the file and the extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum FileType {
+    Csv,
+    Code,
+    Docx,
+    Email,
+    Epub,
+    Html,
+    Json,
+    Md,
+    Pdf,
+    Txt,
+}
+
+impl FileType {
+    /// Detect the type of a file. Magic bytes like the pdf header take priority over the extension. If `sniff_text` is true, files with an unknown extension are sniffed for json, email, scripts and plain text. Otherwise they are not supported.
+    pub(crate) fn detect(path: &Path, sniff_text: bool) -> anyhow::Result<Self> {
+        if !path.is_file() {
+            return Err(anyhow::anyhow!("Path is not a file"));
+        }
+        let mut header = Vec::with_capacity(1024);
+        std::fs::File::open(path)?
+            .take(1024)
+            .read_to_end(&mut header)?;
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase())
+            .unwrap_or_default();
+
+        if let Some(file_type) = Self::sniff_binary(&header, &extension) {
+            return Ok(file_type);
+        }
+        if let Some(file_type) = Self::from_extension(&extension) {
+            return Ok(file_type);
+        }
+        if !sniff_text || !is_text(&header) {
+            return Err(anyhow::anyhow!(
+                "{} is not a supported file type",
+                path.display()
+            ));
+        }
+        Ok(Self::sniff_text(&String::from_utf8_lossy(&header)))
+    }
+
+    /// Check that a file is of the expected type, either by the extension or by the contents of the file. The file was explicitly opened as the expected type, so files with an unknown extension are sniffed.
+    pub(crate) fn check(path: &Path, expected: Self, extensions: &[&str]) -> anyhow::Result<()> {
+        if !path.is_file() {
+            return Err(anyhow::anyhow!("Path is not a file"));
+        }
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase());
+        if extension.is_some_and(|extension| extensions.contains(&extension.as_str())) {
+            return Ok(());
+        }
+        let detected = Self::detect(path, true)?;
+        if detected != expected {
+            return Err(anyhow::anyhow!(
+                "Expected a {:?} file, but {} looks like a {:?} file",
+                expected,
+                path.display(),
+                detected
+            ));
+        }
+        Ok(())
+    }
+
+    fn sniff_binary(header: &[u8], extension: &str) -> Option<Self> {
+        if header.starts_with(b"%PDF-") {
+            return Some(Self::Pdf);
+        }
+        if header.starts_with(b"PK\x03\x04") {
+            if contains(header, b"application/epub+zip") {
+                return Some(Self::Epub);
+            }
+            if contains(header, b"word/") || extension == "docx" {
+                return Some(Self::Docx);
+            }
+            return None;
+        }
+        let text = String::from_utf8_lossy(&header[..header.len().min(64)]).to_ascii_lowercase();
+        let text = text.trim_start();
+        if text.starts_with("<!doctype html") || text.starts_with("<html") {
+            return Some(Self::Html);
+        }
+        None
+    }
+
+    fn from_extension(extension: &str) -> Option<Self> {
+        match extension {
+            "csv" | "tsv" => Some(Self::Csv),
+            "docx" => Some(Self::Docx),
+            "eml" | "mbox" => Some(Self::Email),
+            "epub" => Some(Self::Epub),
+            "html" | "htm" => Some(Self::Html),
+            "json" | "jsonl" | "ndjson" => Some(Self::Json),
+            "md" | "markdown" => Some(Self::Md),
+            "pdf" => Some(Self::Pdf),
+            "txt" => Some(Self::Txt),
+            _ if language_from_extension(extension).is_some() => Some(Self::Code),
+            _ => None,
+        }
+    }
+
+    fn sniff_text(text: &str) -> Self {
+        let trimmed = text.trim_start();
+        if looks_like_mbox(trimmed) || looks_like_email_headers(trimmed) {
+            Self::Email
+        } else if trimmed.starts_with('{') || trimmed.starts_with('[') {
+            Self::Json
+        } else if language_from_shebang(trimmed.lines().next().unwrap_or_default()).is_some() {
+            Self::Code
+        } else {
+            Self::Txt
+        }
+    }
+}
+
+/// Check if the start of a file is valid utf8. The header may cut a multi-byte character in half, so an incomplete character at the end is allowed.
+fn is_text(header: &[u8]) -> bool {
+    match std::str::from_utf8(header) {
+        Ok(text) => !text.contains('\0'),
+        Err(err) => err.error_len().is_none(),
+    }
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack
+        .windows(needle.len())
+        .any(|window| window == needle)
+}
+
+/// Check if the text starts with an mbox `From ` separator line followed by email headers.
+fn looks_like_mbox(text: &str) -> bool {
+    let Some((separator, headers)) = text.split_once('\n') else {
+        return false;
+    };
+    // The separator is `From <sender> <date>`, and the date contains a time like `01:05:34`
+    separator.strip_prefix("From ").is_some_and(|rest| {
+        rest.split_whitespace().skip(1).any(|word| {
+            let mut parts = word.split(':');
+            parts.clone().count() >= 2
+                && parts.all(|part| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()))
+        })
+    }) && looks_like_email_headers(headers)
+}
+
+/// Check if the text starts with a block of RFC 822 headers that contains at least two common email headers.
+fn looks_like_email_headers(text: &str) -> bool {
+    const HEADERS: &[&str] = &[
+        "from",
+        "to",
+        "subject",
+        "date",
+        "received",
+        "return-path",
+        "message-id",
+        "mime-version",
+        "delivered-to",
+    ];
+    let mut known = 0;
+    for line in text.lines().take_while(|line| !line.trim().is_empty()) {
+        // Folded header values continue on lines that start with whitespace
+        if line.starts_with(char::is_whitespace) {
+            continue;
+        }
+        // Every other line in the header block must be a `Name: value` field
+        let Some((name, _)) = line.split_once(':') else {
+            return false;
+        };
+        if name.is_empty() || !name.bytes().all(|b| b.is_ascii_graphic()) {
+            return false;
+        }
+        if HEADERS.contains(&name.to_ascii_lowercase().as_str()) {
+            known += 1;
+        }
+    }
+    known >= 2
+}
+
+#[test]
+fn test_sniff_file_type() {
+    assert_eq!(
+        FileType::sniff_binary(b"%PDF-1.7\n", "bin"),
+        Some(FileType::Pdf)
+    );
+    assert_eq!(
+        FileType::sniff_binary(b"PK\x03\x04....mimetypeapplication/epub+zip", ""),
+        Some(FileType::Epub)
+    );
+    assert_eq!(
+        FileType::sniff_binary(b"\n<!DOCTYPE html><html></html>", "txt"),
+        Some(FileType::Html)
+    );
+    assert_eq!(
+        FileType::sniff_text("From: alice@example.com\nSubject: Hi\n\nHello"),
+        FileType::Email
+    );
+    assert_eq!(
+        FileType::sniff_text(
+            "From alice@example.com Sat Jan  3 01:05:34 1996\nFrom: alice@example.com\nSubject: Hi\n\nHello"
+        ),
+        FileType::Email
+    );
+    // Text that only starts like an email is not an email
+    assert_eq!(
+        FileType::sniff_text("From the start of the book\nTo: the reader\nDate: today"),
+        FileType::Txt
+    );
+    assert_eq!(
+        FileType::sniff_text("From: the author\nTo: the reader\nThis is a letter"),
+        FileType::Txt
+    );
+    assert_eq!(FileType::sniff_text("  [{\"text\": 1}]"), FileType::Json);
+    assert_eq!(
+        FileType::sniff_text("#!/usr/bin/env python3\nprint(1)"),
+        FileType::Code
+    );
+    assert_eq!(
+        FileType::sniff_text("Note: this is text\nMore text"),
+        FileType::Txt
+    );
+    assert!(!is_text(b"\x00\x01\x02binary"));
+
+    let folder = tempfile::tempdir().unwrap();
+    let path = folder.path().join("notes.log");
+    std::fs::write(&path, "Plain text").unwrap();
+    assert!(FileType::detect(&path, false).is_err());
+    assert_eq!(FileType::detect(&path, true).unwrap(), FileType::Txt);
+}
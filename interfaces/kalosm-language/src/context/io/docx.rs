@@ -3,7 +3,8 @@ use std::path::PathBuf;
 
 use std::fs::File;
 
-use crate::context::document::{Document, IntoDocument};
+use super::FileType;
+use crate::context::document::{Document, DocumentSection, IntoDocument};
 
 /// A docx document that can be read from the file system.
 #[derive(Debug, Clone)]
@@ -15,12 +16,7 @@ impl TryFrom<PathBuf> for DocxDocument {
     type Error = anyhow::Error;
 
     fn try_from(path: PathBuf) -> Result<Self, Self::Error> {
-        if !path.is_file() {
-            return Err(anyhow::anyhow!("Path is not a file"));
-        }
-        if path.extension().unwrap() != "docx" {
-            return Err(anyhow::anyhow!("Path is not a docx file"));
-        }
+        FileType::check(&path, FileType::Docx, &["docx"])?;
         Ok(Self { path })
     }
 }
@@ -28,13 +24,21 @@ impl TryFrom<PathBuf> for DocxDocument {
 #[async_trait::async_trait]
 impl IntoDocument for DocxDocument {
     async fn into_document(self) -> anyhow::Result<Document> {
-        let file = File::open(self.path)?;
+        let file = File::open(&self.path)?;
         let reader = std::io::BufReader::new(file);
         let docx = DocxFile::from_xml(reader)?;
+        let mut title = String::new();
+        let mut headings = Vec::new();
         let mut text = String::new();
         for section in docx.children {
             match section {
                 docx_rs::DocumentChild::Paragraph(paragraph) => {
+                    let style = paragraph
+                        .property
+                        .style
+                        .as_ref()
+                        .map(|style| style.val.clone());
+                    let paragraph_start = text.len();
                     for child in paragraph.children {
                         match child {
                             docx_rs::ParagraphChild::Run(run) => {
@@ -68,6 +72,22 @@ impl IntoDocument for DocxDocument {
                             docx_rs::ParagraphChild::StructuredDataTag(_) => {}
                         }
                     }
+                    let paragraph_text = text[paragraph_start..].trim().to_string();
+                    match style.as_deref() {
+                        Some("Title") if title.is_empty() => title = paragraph_text,
+                        Some(style) => {
+                            if let Some(level) = style
+                                .strip_prefix("Heading")
+                                .and_then(|level| level.parse::<usize>().ok())
+                            {
+                                headings.push((level, paragraph_text));
+                            }
+                        }
+                        None => {}
+                    }
+                    if text.len() > paragraph_start {
+                        text += "\n\n";
+                    }
                 }
                 docx_rs::DocumentChild::Table(_) => {}
                 docx_rs::DocumentChild::BookmarkStart(_) => {}
@@ -78,6 +98,10 @@ impl IntoDocument for DocxDocument {
                 docx_rs::DocumentChild::TableOfContents(_) => {}
             }
         }
-        Ok(Document::from_parts("", text))
+        let sections = DocumentSection::from_headings(&text, headings);
+        let mut document = Document::from_parts(title, text);
+        document.set_source(self.path);
+        document.set_sections(sections);
+        Ok(document)
     }
 }
@@ -0,0 +1,137 @@
+use chrono::TimeZone;
+use mail_parser::MessageParser;
+use std::path::PathBuf;
+
+use super::{combine_documents, FileType};
+use crate::context::document::{Document, IntoDocument, IntoDocuments};
+
+/// An email (`.eml`) or mailbox (`.mbox`) file that can be read from the file system. Each message in a mailbox is read as a separate document or as a section of the mailbox.
+///
+/// The sender, recipients, date and message id of each message are added to the metadata of the document.
+#[derive(Debug, Clone)]
+pub struct EmailDocument {
+    path: PathBuf,
+}
+
+impl TryFrom<PathBuf> for EmailDocument {
+    type Error = anyhow::Error;
+
+    fn try_from(path: PathBuf) -> Result<Self, Self::Error> {
+        FileType::check(&path, FileType::Email, &["eml", "mbox"])?;
+        Ok(Self { path })
+    }
+}
+
+impl EmailDocument {
+    async fn messages(&self) -> anyhow::Result<Vec<Document>> {
+        let bytes = tokio::fs::read(&self.path).await?;
+        let parser = MessageParser::default();
+        let mut documents = Vec::new();
+        for raw in split_mbox(&bytes) {
+            let Some(message) = parser.parse(raw) else {
+                continue;
+            };
+            let subject = message.subject().unwrap_or_default().to_string();
+            let body = message
+                .body_text(0)
+                .map(|body| body.to_string())
+                .unwrap_or_default();
+            let mut document = Document::from_parts(subject, body);
+            document.set_source(self.path.clone());
+            if let Some(from) = message.from().and_then(|from| from.first()) {
+                if let Some(address) = from.address() {
+                    document.insert_metadata("from", address);
+                }
+                if let Some(name) = from.name() {
+                    document.insert_metadata("from_name", name);
+                }
+            }
+            if let Some(to) = message.to() {
+                let recipients = to
+                    .iter()
+                    .filter_map(|address| address.address())
+                    .map(str::to_string)
+                    .collect::<Vec<_>>();
+                document.insert_metadata("to", recipients);
+            }
+            if let Some(date) = message.date() {
+                document.insert_metadata("date", date.to_rfc3339());
+                if let Some(date) = chrono::Utc.timestamp_opt(date.to_timestamp(), 0).single() {
+                    document.set_created_at(date);
+                }
+            }
+            if let Some(message_id) = message.message_id() {
+                document.insert_metadata("message_id", message_id);
+            }
+            documents.push(document);
+        }
+        Ok(documents)
+    }
+}
+
+/// Split a mailbox into raw messages. Each message in a mailbox starts with a `From ` line. Files without a `From ` line are treated as a single message.
+fn split_mbox(bytes: &[u8]) -> Vec<&[u8]> {
+    if !bytes.starts_with(b"From ") {
+        return vec![bytes];
+    }
+    let mut starts = vec![0];
+    starts.extend(
+        bytes
+            .windows(6)
+            .enumerate()
+            .filter(|(_, window)| *window == b"\nFrom ")
+            .map(|(index, _)| index + 1),
+    );
+    starts
+        .iter()
+        .enumerate()
+        .map(|(index, start)| {
+            let end = starts.get(index + 1).copied().unwrap_or(bytes.len());
+            let message = &bytes[*start..end];
+            // Skip the `From ` separator line
+            match message.iter().position(|b| *b == b'\n') {
+                Some(newline) => &message[newline + 1..],
+                None => &[],
+            }
+        })
+        .filter(|message| !message.is_empty())
+        .collect()
+}
+
+#[async_trait::async_trait]
+impl IntoDocument for EmailDocument {
+    async fn into_document(self) -> anyhow::Result<Document> {
+        let mut messages = self.messages().await?;
+        if messages.len() == 1 {
+            return Ok(messages.remove(0));
+        }
+        let title = self
+            .path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        Ok(combine_documents(title, self.path, messages))
+    }
+}
+
+#[async_trait::async_trait]
+impl IntoDocuments for EmailDocument {
+    async fn into_documents(self) -> anyhow::Result<Vec<Document>> {
+        self.messages().await
+    }
+}
+
+#[test]
+fn test_split_mbox() {
+    let single = b"From: a@example.com\nSubject: Hi\n\nHello";
+    assert_eq!(split_mbox(single), vec![&single[..]]);
+
+    let mbox = b"From a@example.com Mon Jan 1 00:00:00 2024\nSubject: One\n\nFirst\n\nFrom b@example.com Tue Jan 2 00:00:00 2024\nSubject: Two\n\nSecond\n";
+    assert_eq!(
+        split_mbox(mbox),
+        vec![
+            &b"Subject: One\n\nFirst\n\n"[..],
+            &b"Subject: Two\n\nSecond\n"[..]
+        ]
+    );
+}
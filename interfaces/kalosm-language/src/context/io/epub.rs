@@ -0,0 +1,81 @@
+use std::path::PathBuf;
+
+use epub::doc::EpubDoc;
+
+use super::{combine_documents, FileType};
+use crate::context::document::{Document, IntoDocument, IntoDocuments};
+use crate::context::page::extract_article;
+
+/// An epub book that can be read from the file system. Each chapter in the book is read as a separate document or as a section of the book.
+#[derive(Debug, Clone)]
+pub struct EpubDocument {
+    path: PathBuf,
+}
+
+impl TryFrom<PathBuf> for EpubDocument {
+    type Error = anyhow::Error;
+
+    fn try_from(path: PathBuf) -> Result<Self, Self::Error> {
+        FileType::check(&path, FileType::Epub, &["epub"])?;
+        Ok(Self { path })
+    }
+}
+
+impl EpubDocument {
+    /// Read the title of the book and a document for each chapter.
+    fn chapters(&self) -> anyhow::Result<(String, Vec<Document>)> {
+        let mut book = EpubDoc::new(&self.path)?;
+        let title = book.mdata("title").unwrap_or_default();
+        let author = book.mdata("creator");
+
+        let mut chapters = Vec::new();
+        loop {
+            if let Some((content, _)) = book.get_current_str() {
+                let article = extract_article(&content)?;
+                if !article.body().trim().is_empty() {
+                    let chapter_title = if article.title().trim().is_empty() {
+                        format!("Chapter {}", chapters.len() + 1)
+                    } else {
+                        article.title().trim().to_string()
+                    };
+                    let mut chapter = Document::from_parts(chapter_title, article.body());
+                    chapter.set_sections(article.sections().to_vec());
+                    chapter.set_source(self.path.clone());
+                    chapter.insert_metadata("book", title.clone());
+                    chapter.insert_metadata("chapter", (chapters.len() + 1) as f64);
+                    if let Some(author) = &author {
+                        chapter.insert_metadata("author", author.clone());
+                    }
+                    chapters.push(chapter);
+                }
+            }
+            if !book.go_next() {
+                break;
+            }
+        }
+
+        Ok((title, chapters))
+    }
+}
+
+#[async_trait::async_trait]
+impl IntoDocument for EpubDocument {
+    async fn into_document(self) -> anyhow::Result<Document> {
+        let (title, chapters) = self.chapters()?;
+        let author = chapters
+            .first()
+            .and_then(|chapter| chapter.metadata().get("author").cloned());
+        let mut document = combine_documents(title, self.path, chapters);
+        if let Some(author) = author {
+            document.insert_metadata("author", author);
+        }
+        Ok(document)
+    }
+}
+
+#[async_trait::async_trait]
+impl IntoDocuments for EpubDocument {
+    async fn into_documents(self) -> anyhow::Result<Vec<Document>> {
+        Ok(self.chapters()?.1)
+    }
+}
@@ -1,40 +1,553 @@
 use std::path::PathBuf;
 
+use scraper::{node::Node, ElementRef, Html, Selector};
 use tokio::{fs::File, io::AsyncReadExt};
+use url::Url;
 
+use super::FileType;
 use crate::context::{
-    document::{Document, IntoDocument},
+    document::{Document, DocumentSection, IntoDocument},
     page::extract_article,
 };
 
+/// How the text of an html page is extracted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HtmlMode {
+    /// Extract the main article of the page as plain text with readability.
+    #[default]
+    Article,
+    /// Convert the page to markdown, keeping headings, lists, tables, links and code blocks.
+    Markdown,
+}
+
 /// An html document that can be read from the file system.
 #[derive(Debug, Clone)]
 pub struct HtmlDocument {
     path: PathBuf,
+    mode: HtmlMode,
+    base_url: Option<Url>,
 }
 
 impl TryFrom<PathBuf> for HtmlDocument {
     type Error = anyhow::Error;
 
     fn try_from(path: PathBuf) -> Result<Self, Self::Error> {
-        if !path.is_file() {
-            return Err(anyhow::anyhow!("Path is not a file"));
-        }
-        if path.extension().unwrap() != "html" {
-            return Err(anyhow::anyhow!("Path is not a html file"));
-        }
-        Ok(Self { path })
+        FileType::check(&path, FileType::Html, &["html", "htm"])?;
+        Ok(Self {
+            path,
+            mode: HtmlMode::default(),
+            base_url: None,
+        })
+    }
+}
+
+impl HtmlDocument {
+    /// Set how the text of the document is extracted.
+    pub fn with_mode(mut self, mode: HtmlMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Set the URL relative links in the document are resolved against. Defaults to the `<base>` element of the document if it has one.
+    pub fn with_base_url(mut self, base_url: Url) -> Self {
+        self.base_url = Some(base_url);
+        self
     }
 }
 
 #[async_trait::async_trait]
 impl IntoDocument for HtmlDocument {
     async fn into_document(self) -> anyhow::Result<Document> {
-        let file = File::open(self.path).await?;
+        let file = File::open(&self.path).await?;
         let mut html = String::new();
         tokio::io::BufReader::new(file)
             .read_to_string(&mut html)
             .await?;
-        extract_article(&html)
+        let mut document = html_document(&html, self.base_url.as_ref(), self.mode)?;
+        document.set_source(self.path);
+        Ok(document)
+    }
+}
+
+/// Extract a document from an html page with the given mode.
+pub(crate) fn html_document(
+    html: &str,
+    base_url: Option<&Url>,
+    mode: HtmlMode,
+) -> anyhow::Result<Document> {
+    match mode {
+        HtmlMode::Article => extract_article(html),
+        HtmlMode::Markdown => Ok(markdown_document(html, base_url)),
+    }
+}
+
+/// Convert html to markdown. Headings, paragraphs, emphasis, links, images, lists, tables, block quotes and code blocks are kept, and scripts, styles and navigation are removed. Relative links are resolved against the base URL or the `<base>` element of the page.
+///
+/// # Example
+/// ```rust
+/// use kalosm_language::prelude::*;
+///
+/// let html = r#"<h1>Title</h1><ul><li>One</li><li><a href="/two">Two</a></li></ul>"#;
+/// let base_url = Url::parse("https://example.com").unwrap();
+/// let markdown = html_to_markdown(html, Some(&base_url));
+/// assert_eq!(markdown, "# Title\n\n- One\n- [Two](https://example.com/two)");
+/// ```
+pub fn html_to_markdown(html: &str, base_url: Option<&Url>) -> String {
+    let html = Html::parse_document(html);
+    MarkdownWriter::new(&html, base_url).write(&html)
+}
+
+fn markdown_document(html: &str, base_url: Option<&Url>) -> Document {
+    let html = Html::parse_document(html);
+    let mut writer = MarkdownWriter::new(&html, base_url);
+    let body = writer.write(&html);
+    let title = Selector::parse("title")
+        .ok()
+        .and_then(|selector| html.select(&selector).next())
+        .map(|title| collapse_whitespace(&title.text().collect::<String>()))
+        .filter(|title| !title.is_empty())
+        .or_else(|| {
+            writer
+                .headings
+                .iter()
+                .find(|(level, _)| *level == 1)
+                .map(|(_, title)| title.clone())
+        })
+        .unwrap_or_default();
+    let sections = DocumentSection::from_headings(&body, writer.headings);
+    let mut document = Document::from_parts(title, body);
+    document.set_sections(sections);
+    document
+}
+
+/// Elements that are removed with their contents.
+const SKIPPED_ELEMENTS: &[&str] = &[
+    "button", "canvas", "form", "head", "iframe", "input", "link", "meta", "nav", "noscript",
+    "script", "select", "style", "svg", "template", "textarea", "title",
+];
+
+/// Elements that start a new block of text.
+const BLOCK_ELEMENTS: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "body",
+    "details",
+    "dialog",
+    "dd",
+    "div",
+    "dl",
+    "dt",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hgroup",
+    "hr",
+    "html",
+    "li",
+    "main",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "summary",
+    "table",
+    "ul",
+];
+
+struct MarkdownWriter {
+    base_url: Option<Url>,
+    /// The level and text of every heading that was written.
+    headings: Vec<(usize, String)>,
+}
+
+impl MarkdownWriter {
+    fn new(html: &Html, base_url: Option<&Url>) -> Self {
+        let base_element = Selector::parse("base[href]")
+            .ok()
+            .and_then(|selector| html.select(&selector).next())
+            .and_then(|base| base.value().attr("href"));
+        let base_url = match (base_url, base_element) {
+            (Some(base_url), Some(href)) => base_url.join(href).ok(),
+            (Some(base_url), None) => Some(base_url.clone()),
+            (None, Some(href)) => Url::parse(href).ok(),
+            (None, None) => None,
+        };
+        Self {
+            base_url,
+            headings: Vec::new(),
+        }
+    }
+
+    fn write(&mut self, html: &Html) -> String {
+        // Prefer the main content of the page if it is marked
+        let root = Selector::parse("main")
+            .ok()
+            .and_then(|selector| html.select(&selector).next())
+            .unwrap_or_else(|| html.root_element());
+        let mut blocks = Vec::new();
+        self.push_children(root, &mut blocks);
+        blocks.join("\n\n")
+    }
+
+    fn resolve(&self, link: &str) -> Option<String> {
+        let link = link.trim();
+        if link.is_empty() || link.starts_with("javascript:") {
+            return None;
+        }
+        match &self.base_url {
+            Some(base_url) => base_url.join(link).ok().map(|url| url.to_string()),
+            None => Some(link.to_string()),
+        }
+    }
+
+    /// Write the children of an element as blocks, grouping inline children into paragraphs.
+    fn push_children(&mut self, element: ElementRef, blocks: &mut Vec<String>) {
+        let mut inline = String::new();
+        for child in element.children() {
+            match child.value() {
+                Node::Text(text) => inline.push_str(&collapse_whitespace_keep_edges(text)),
+                Node::Element(_) => {
+                    let Some(child) = ElementRef::wrap(child) else {
+                        continue;
+                    };
+                    let name = child.value().name();
+                    if SKIPPED_ELEMENTS.contains(&name) {
+                        continue;
+                    }
+                    if BLOCK_ELEMENTS.contains(&name) {
+                        flush_paragraph(&mut inline, blocks);
+                        self.push_block(child, blocks);
+                    } else {
+                        let text = self.inline(child);
+                        inline.push_str(&text);
+                    }
+                }
+                _ => {}
+            }
+        }
+        flush_paragraph(&mut inline, blocks);
+    }
+
+    fn push_block(&mut self, element: ElementRef, blocks: &mut Vec<String>) {
+        let name = element.value().name();
+        match name {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                let level = name[1..].parse().unwrap_or(1);
+                let text = collapse_whitespace(&self.inline_children(element));
+                if !text.is_empty() {
+                    blocks.push(format!("{} {}", "#".repeat(level), text));
+                    self.headings.push((level, text));
+                }
+            }
+            "pre" => {
+                let language = element
+                    .children()
+                    .filter_map(ElementRef::wrap)
+                    .chain(std::iter::once(element))
+                    .filter_map(|element| element.value().attr("class"))
+                    .flat_map(str::split_whitespace)
+                    .find_map(|class| {
+                        class
+                            .strip_prefix("language-")
+                            .or_else(|| class.strip_prefix("lang-"))
+                    })
+                    .unwrap_or_default();
+                let code = element.text().collect::<String>();
+                let code = code.trim_start_matches('\n').trim_end();
+                if !code.is_empty() {
+                    blocks.push(format!("```{}\n{}\n```", language, code));
+                }
+            }
+            "ul" | "ol" => {
+                let list = self.list(element, name == "ol");
+                if !list.is_empty() {
+                    blocks.push(list);
+                }
+            }
+            "blockquote" => {
+                let mut quoted = Vec::new();
+                self.push_children(element, &mut quoted);
+                if !quoted.is_empty() {
+                    let quote = quoted
+                        .join("\n\n")
+                        .lines()
+                        .map(|line| {
+                            if line.is_empty() {
+                                ">".to_string()
+                            } else {
+                                format!("> {}", line)
+                            }
+                        })
+                        .collect::<Vec<_>>()
+                        .join("\n");
+                    blocks.push(quote);
+                }
+            }
+            "table" => {
+                let table = self.table(element);
+                if !table.is_empty() {
+                    blocks.push(table);
+                }
+            }
+            "hr" => blocks.push("---".to_string()),
+            _ => self.push_children(element, blocks),
+        }
+    }
+
+    fn list(&mut self, element: ElementRef, ordered: bool) -> String {
+        let mut number = element
+            .value()
+            .attr("start")
+            .and_then(|start| start.parse::<usize>().ok())
+            .unwrap_or(1);
+        let mut items = Vec::new();
+        for item in element.children().filter_map(ElementRef::wrap) {
+            if item.value().name() != "li" {
+                continue;
+            }
+            let mut blocks = Vec::new();
+            self.push_children(item, &mut blocks);
+            let marker = if ordered {
+                format!("{}. ", number)
+            } else {
+                "- ".to_string()
+            };
+            number += 1;
+            let indent = " ".repeat(marker.len());
+            let text = blocks.join("\n");
+            let mut lines = text.lines();
+            let mut rendered = format!("{}{}", marker, lines.next().unwrap_or_default());
+            for line in lines {
+                rendered.push('\n');
+                if !line.is_empty() {
+                    rendered.push_str(&indent);
+                    rendered.push_str(line);
+                }
+            }
+            items.push(rendered);
+        }
+        items.join("\n")
+    }
+
+    fn table(&mut self, element: ElementRef) -> String {
+        let Ok(row_selector) = Selector::parse("tr") else {
+            return String::new();
+        };
+        let mut rows = Vec::new();
+        for row in element.select(&row_selector) {
+            // Skip the rows of nested tables
+            let table = row
+                .ancestors()
+                .filter_map(ElementRef::wrap)
+                .find(|ancestor| ancestor.value().name() == "table");
+            if table.map(|table| table.id()) != Some(element.id()) {
+                continue;
+            }
+            let cells = row
+                .children()
+                .filter_map(ElementRef::wrap)
+                .filter(|cell| matches!(cell.value().name(), "td" | "th"))
+                .map(|cell| {
+                    collapse_whitespace(&self.inline_children(cell).replace('\n', " "))
+                        .replace('|', "\\|")
+                })
+                .collect::<Vec<_>>();
+            if !cells.is_empty() {
+                rows.push(cells);
+            }
+        }
+        let columns = rows.iter().map(Vec::len).max().unwrap_or_default();
+        if columns == 0 {
+            return String::new();
+        }
+        let format_row = |row: &[String]| {
+            let mut line = String::from("|");
+            for column in 0..columns {
+                line.push(' ');
+                line.push_str(row.get(column).map(String::as_str).unwrap_or_default());
+                line.push_str(" |");
+            }
+            line
+        };
+        let mut lines = vec![
+            format_row(&rows[0]),
+            format_row(&vec!["---".to_string(); columns]),
+        ];
+        lines.extend(rows[1..].iter().map(|row| format_row(row)));
+        lines.join("\n")
+    }
+
+    fn inline_children(&mut self, element: ElementRef) -> String {
+        let mut text = String::new();
+        for child in element.children() {
+            match child.value() {
+                Node::Text(content) => text.push_str(&collapse_whitespace_keep_edges(content)),
+                Node::Element(_) => {
+                    let Some(child) = ElementRef::wrap(child) else {
+                        continue;
+                    };
+                    let name = child.value().name();
+                    if SKIPPED_ELEMENTS.contains(&name) {
+                        continue;
+                    }
+                    if BLOCK_ELEMENTS.contains(&name) {
+                        text.push(' ');
+                        text.push_str(&self.inline_children(child));
+                        text.push(' ');
+                    } else {
+                        let inline = self.inline(child);
+                        text.push_str(&inline);
+                    }
+                }
+                _ => {}
+            }
+        }
+        text
+    }
+
+    fn inline(&mut self, element: ElementRef) -> String {
+        match element.value().name() {
+            "strong" | "b" => self.wrap_inline(element, "**"),
+            "em" | "i" => self.wrap_inline(element, "*"),
+            "del" | "s" => self.wrap_inline(element, "~~"),
+            "code" | "kbd" | "samp" => {
+                let code = element.text().collect::<String>();
+                let code = code.trim();
+                if code.is_empty() {
+                    String::new()
+                } else {
+                    format!("`{}`", code)
+                }
+            }
+            "br" => "\n".to_string(),
+            "a" => {
+                let text = self.inline_children(element);
+                // Links to anchors on the same page are not useful outside of the page
+                let href = element
+                    .value()
+                    .attr("href")
+                    .filter(|href| !href.starts_with('#'))
+                    .and_then(|href| self.resolve(href));
+                match href {
+                    Some(href) if !text.trim().is_empty() => {
+                        with_edges(&text, &format!("[{}]({})", text.trim(), href))
+                    }
+                    _ => text,
+                }
+            }
+            "img" => {
+                let alt = element.value().attr("alt").unwrap_or_default().trim();
+                match element
+                    .value()
+                    .attr("src")
+                    .and_then(|src| self.resolve(src))
+                {
+                    Some(src) => format!("![{}]({})", alt, src),
+                    None => alt.to_string(),
+                }
+            }
+            _ => self.inline_children(element),
+        }
+    }
+
+    fn wrap_inline(&mut self, element: ElementRef, marker: &str) -> String {
+        let text = self.inline_children(element);
+        if text.trim().is_empty() {
+            return text;
+        }
+        with_edges(&text, &format!("{}{}{}", marker, text.trim(), marker))
+    }
+}
+
+/// Keep the whitespace around `text` when replacing it with `formatted` so words stay separated.
+fn with_edges(text: &str, formatted: &str) -> String {
+    let mut output = String::new();
+    if text.starts_with(char::is_whitespace) {
+        output.push(' ');
+    }
+    output.push_str(formatted);
+    if text.ends_with(char::is_whitespace) {
+        output.push(' ');
     }
+    output
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Collapse whitespace in a text node, keeping a single space at the edges so it stays separated from the surrounding nodes.
+fn collapse_whitespace_keep_edges(text: &str) -> String {
+    let collapsed = collapse_whitespace(text);
+    if collapsed.is_empty() {
+        return if text.is_empty() {
+            String::new()
+        } else {
+            " ".to_string()
+        };
+    }
+    with_edges(text, &collapsed)
+}
+
+/// Add the inline text collected so far as a paragraph, keeping line breaks.
+fn flush_paragraph(inline: &mut String, blocks: &mut Vec<String>) {
+    let paragraph = inline
+        .lines()
+        .map(collapse_whitespace)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n");
+    if !paragraph.is_empty() {
+        blocks.push(paragraph);
+    }
+    inline.clear();
+}
+
+#[test]
+fn html_to_markdown_keeps_structure() {
+    let html = r#"<html>
+<head><title>Report</title><style>p { color: red; }</style></head>
+<body>
+<nav><a href="/">Home</a></nav>
+<h1>Results</h1>
+<p>The <strong>new</strong> model is <a href="details.html">faster</a>.<br>See below.</p>
+<ol start="3"><li>First</li><li>Second<ul><li>Nested</li></ul></li></ol>
+<table>
+  <thead><tr><th>Model</th><th>Accuracy</th></tr></thead>
+  <tbody><tr><td>Small</td><td>81%</td></tr><tr><td>Large | XL</td><td>92%</td></tr></tbody>
+</table>
+<pre><code class="language-rust">fn main() {
+    println!("hi");
+}
+</code></pre>
+<blockquote><p>Quoted</p></blockquote>
+<script>alert("hidden")</script>
+</body>
+</html>"#;
+    let base_url = Url::parse("https://example.com/reports/").unwrap();
+    let markdown = html_to_markdown(html, Some(&base_url));
+    assert_eq!(
+        markdown,
+        "# Results\n\n\
+The **new** model is [faster](https://example.com/reports/details.html).\nSee below.\n\n\
+3. First\n4. Second\n   - Nested\n\n\
+| Model | Accuracy |\n| --- | --- |\n| Small | 81% |\n| Large \\| XL | 92% |\n\n\
+```rust\nfn main() {\n    println!(\"hi\");\n}\n```\n\n\
+> Quoted"
+    );
+
+    let document = markdown_document(html, Some(&base_url));
+    assert_eq!(document.title(), "Report");
+    assert_eq!(document.sections().len(), 1);
+    assert_eq!(document.sections()[0].title, "Results");
 }
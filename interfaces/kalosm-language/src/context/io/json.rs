@@ -0,0 +1,176 @@
+use convert_case::{Case, Casing};
+use serde_json::Value;
+use std::path::PathBuf;
+
+use super::{combine_documents, FileType};
+use crate::context::document::{Document, IntoDocument, IntoDocuments};
+use crate::vector_db::MetadataValue;
+
+/// A json or json lines document that can be read from the file system.
+///
+/// A file with a top level array or one json value per line is read as one record per item. By default the whole record is used as the text of the document. If a text field is set with [`JsonDocument::with_text_field`], only that field is used as the text and the other top level fields are added to the metadata of the document.
+#[derive(Debug, Clone)]
+pub struct JsonDocument {
+    path: PathBuf,
+    text_field: Option<String>,
+    title_field: Option<String>,
+}
+
+impl TryFrom<PathBuf> for JsonDocument {
+    type Error = anyhow::Error;
+
+    fn try_from(path: PathBuf) -> Result<Self, Self::Error> {
+        FileType::check(&path, FileType::Json, &["json", "jsonl", "ndjson"])?;
+        Ok(Self {
+            path,
+            text_field: None,
+            title_field: None,
+        })
+    }
+}
+
+impl JsonDocument {
+    /// Set the field that contains the text of each record. Nested fields can be selected with a dot separated path like `content.body`.
+    pub fn with_text_field(mut self, field: impl Into<String>) -> Self {
+        self.text_field = Some(field.into());
+        self
+    }
+
+    /// Set the field that contains the title of each record. Nested fields can be selected with a dot separated path like `meta.title`.
+    pub fn with_title_field(mut self, field: impl Into<String>) -> Self {
+        self.title_field = Some(field.into());
+        self
+    }
+
+    fn title(&self) -> String {
+        self.path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string().to_case(Case::Title))
+            .unwrap_or_default()
+    }
+
+    async fn records(&self) -> anyhow::Result<Vec<Value>> {
+        let text = tokio::fs::read_to_string(&self.path).await?;
+        parse_records(&text)
+    }
+
+    fn record_document(&self, index: usize, record: &Value) -> Document {
+        let text = self
+            .text_field
+            .as_deref()
+            .and_then(|field| lookup(record, field))
+            .map(value_to_text)
+            .unwrap_or_else(|| serde_json::to_string_pretty(record).unwrap_or_default());
+        let title = self
+            .title_field
+            .as_deref()
+            .and_then(|field| lookup(record, field))
+            .map(value_to_text)
+            .unwrap_or_else(|| format!("{} {}", self.title(), index + 1));
+
+        let mut document = Document::from_parts(title, text);
+        document.set_source(self.path.clone());
+        document.insert_metadata("record", (index + 1) as f64);
+        if self.text_field.is_some() {
+            if let Value::Object(fields) = record {
+                for (key, value) in fields {
+                    if Some(key) == self.text_field.as_ref() {
+                        continue;
+                    }
+                    if let Some(value) = metadata_value(value) {
+                        document.insert_metadata(key, value);
+                    }
+                }
+            }
+        }
+        document
+    }
+
+    async fn record_documents(&self) -> anyhow::Result<Vec<Document>> {
+        Ok(self
+            .records()
+            .await?
+            .iter()
+            .enumerate()
+            .map(|(index, record)| self.record_document(index, record))
+            .collect())
+    }
+}
+
+/// Parse a json file into records. Top level arrays are split into one record per item, and files that are not valid json are parsed as json lines.
+fn parse_records(text: &str) -> anyhow::Result<Vec<Value>> {
+    match serde_json::from_str(text) {
+        Ok(Value::Array(items)) => Ok(items),
+        Ok(value) => Ok(vec![value]),
+        Err(err) => text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(serde_json::from_str)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| anyhow::anyhow!("Failed to parse json: {}", err)),
+    }
+}
+
+fn lookup<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.')
+        .try_fold(value, |value, key| value.get(key))
+        .filter(|value| !value.is_null())
+}
+
+fn value_to_text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        _ => value.to_string(),
+    }
+}
+
+fn metadata_value(value: &Value) -> Option<MetadataValue> {
+    match value {
+        Value::Bool(value) => Some(MetadataValue::Bool(*value)),
+        Value::Number(value) => value.as_f64().map(MetadataValue::Number),
+        Value::String(value) => Some(MetadataValue::String(value.clone())),
+        Value::Array(values) => Some(MetadataValue::List(
+            values.iter().filter_map(metadata_value).collect(),
+        )),
+        Value::Null | Value::Object(_) => None,
+    }
+}
+
+#[async_trait::async_trait]
+impl IntoDocument for JsonDocument {
+    async fn into_document(self) -> anyhow::Result<Document> {
+        let documents = self.record_documents().await?;
+        Ok(combine_documents(self.title(), self.path, documents))
+    }
+}
+
+#[async_trait::async_trait]
+impl IntoDocuments for JsonDocument {
+    async fn into_documents(self) -> anyhow::Result<Vec<Document>> {
+        self.record_documents().await
+    }
+}
+
+#[test]
+fn test_json_records() {
+    let records = parse_records("[{\"a\": 1}, {\"a\": 2}]").unwrap();
+    assert_eq!(records.len(), 2);
+    let records = parse_records("{\"a\": 1}\n\n{\"a\": 2}\n{\"a\": 3}\n").unwrap();
+    assert_eq!(records.len(), 3);
+    assert!(parse_records("{\"a\": 1}\nnot json").is_err());
+
+    let record: Value =
+        serde_json::from_str("{\"content\": {\"body\": \"hello\"}, \"tags\": [\"a\", 1]}").unwrap();
+    assert_eq!(
+        lookup(&record, "content.body").map(value_to_text),
+        Some("hello".to_string())
+    );
+    assert_eq!(lookup(&record, "content.missing"), None);
+    assert_eq!(
+        metadata_value(&record["tags"]),
+        Some(MetadataValue::List(vec![
+            MetadataValue::String("a".to_string()),
+            MetadataValue::Number(1.)
+        ]))
+    );
+}
@@ -2,6 +2,7 @@ use std::path::PathBuf;
 
 use tokio::{fs::File, io::AsyncReadExt};
 
+use super::FileType;
 use crate::context::{
     document::{Document, IntoDocument},
     page::extract_article,
@@ -17,12 +18,7 @@ impl TryFrom<PathBuf> for MdDocument {
     type Error = anyhow::Error;
 
     fn try_from(path: PathBuf) -> Result<Self, Self::Error> {
-        if !path.is_file() {
-            return Err(anyhow::anyhow!("Path is not a file"));
-        }
-        if path.extension().unwrap() != "md" {
-            return Err(anyhow::anyhow!("Path is not a md file"));
-        }
+        FileType::check(&path, FileType::Md, &["md", "markdown"])?;
         Ok(Self { path })
     }
 }
@@ -30,7 +26,7 @@ impl TryFrom<PathBuf> for MdDocument {
 #[async_trait::async_trait]
 impl IntoDocument for MdDocument {
     async fn into_document(self) -> anyhow::Result<Document> {
-        let file = File::open(self.path).await?;
+        let file = File::open(&self.path).await?;
         let mut md = String::new();
         tokio::io::BufReader::new(file)
             .read_to_string(&mut md)
@@ -39,6 +35,8 @@ impl IntoDocument for MdDocument {
 
         let mut html_output = String::new();
         pulldown_cmark::html::push_html(&mut html_output, parser);
-        extract_article(&html_output)
+        let mut document = extract_article(&html_output)?;
+        document.set_source(self.path);
+        Ok(document)
     }
 }
@@ -1,21 +1,42 @@
 use crate::context::document::Document;
+use crate::context::document::DocumentSection;
 use crate::context::document::IntoDocument;
 use crate::context::document::IntoDocuments;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use tokio::task::JoinSet;
+mod code;
+pub use code::*;
+mod csv;
+pub use self::csv::*;
+mod detect;
+pub(crate) use detect::FileType;
 mod docx;
 pub use docx::*;
+mod email;
+pub use email::*;
+mod epub;
+pub use self::epub::*;
 mod html;
 pub use html::*;
+mod json;
+pub use json::*;
 mod md;
 pub use md::*;
+#[cfg(feature = "ocr")]
+mod ocr;
 mod pdf;
 pub use self::pdf::*;
 mod txt;
 pub use txt::*;
+mod watch;
+pub use watch::*;
 
 /// A document that can be read from the file system.
 ///
+/// The type of the file is detected from the contents of the file (like the magic bytes of a pdf or epub) and falls back to the extension. Files with an unknown extension are not supported unless they are opened with [`FsDocument::sniff`], which reads them as text, json, email or code depending on their contents.
+///
+/// Some files (json lines, epub chapters and mailboxes) contain more than one document. [`IntoDocument`] reads them as one document with a section for each part, and [`IntoDocuments`] reads them as separate documents. Csv files are read as one document with a markdown table. Open them with [`CsvDocument`] and [`CsvMode::Rows`] to read each row as a separate document.
+///
 /// # Example
 /// ```rust, no_run
 /// use kalosm_language::prelude::*;
@@ -39,22 +60,54 @@ pub enum FsDocument {
     Pdf(PdfDocument),
     /// A text document.
     Txt(TextDocument),
+    /// A csv or tsv document.
+    Csv(CsvDocument),
+    /// A json or json lines document.
+    Json(JsonDocument),
+    /// An epub book.
+    Epub(EpubDocument),
+    /// An email or mailbox.
+    Email(EmailDocument),
+    /// A source code file.
+    Code(CodeDocument),
 }
 
 impl TryFrom<PathBuf> for FsDocument {
     type Error = anyhow::Error;
 
     fn try_from(path: PathBuf) -> Result<Self, Self::Error> {
-        if !path.is_file() {
-            return Err(anyhow::anyhow!("Path is not a file"));
+        let file_type = FileType::detect(&path, false)?;
+        Self::from_file_type(file_type, path)
+    }
+}
+
+impl FsDocument {
+    /// Open a file like [`FsDocument::try_from`], but read files with an unknown extension based on their contents. Files that contain text are read as json, email, code or plain text documents.
+    pub fn sniff(path: PathBuf) -> anyhow::Result<Self> {
+        let file_type = FileType::detect(&path, true)?;
+        Self::from_file_type(file_type, path)
+    }
+
+    /// Set how the text of html files is extracted. Other files are not affected. Defaults to [`HtmlMode::Article`].
+    pub fn with_html_mode(self, mode: HtmlMode) -> Self {
+        match self {
+            Self::Html(html) => Self::Html(html.with_mode(mode)),
+            document => document,
         }
-        match path.extension().unwrap().to_str().unwrap() {
-            "docx" => Ok(Self::Docx(DocxDocument::try_from(path)?)),
-            "html" => Ok(Self::Html(HtmlDocument::try_from(path)?)),
-            "md" => Ok(Self::Md(MdDocument::try_from(path)?)),
-            "pdf" => Ok(Self::Pdf(PdfDocument::try_from(path)?)),
-            "txt" => Ok(Self::Txt(TextDocument::try_from(path)?)),
-            _ => Err(anyhow::anyhow!("Path is not a supported file type")),
+    }
+
+    fn from_file_type(file_type: FileType, path: PathBuf) -> anyhow::Result<Self> {
+        match file_type {
+            FileType::Docx => Ok(Self::Docx(DocxDocument::try_from(path)?)),
+            FileType::Html => Ok(Self::Html(HtmlDocument::try_from(path)?)),
+            FileType::Md => Ok(Self::Md(MdDocument::try_from(path)?)),
+            FileType::Pdf => Ok(Self::Pdf(PdfDocument::try_from(path)?)),
+            FileType::Txt => Ok(Self::Txt(TextDocument::try_from(path)?)),
+            FileType::Csv => Ok(Self::Csv(CsvDocument::try_from(path)?)),
+            FileType::Json => Ok(Self::Json(JsonDocument::try_from(path)?)),
+            FileType::Epub => Ok(Self::Epub(EpubDocument::try_from(path)?)),
+            FileType::Email => Ok(Self::Email(EmailDocument::try_from(path)?)),
+            FileType::Code => Ok(Self::Code(CodeDocument::try_from(path)?)),
         }
     }
 }
@@ -68,8 +121,69 @@ impl IntoDocument for FsDocument {
             Self::Md(md) => md.into_document().await,
             Self::Pdf(pdf) => pdf.into_document().await,
             Self::Txt(txt) => txt.into_document().await,
+            Self::Csv(csv) => csv.into_document().await,
+            Self::Json(json) => json.into_document().await,
+            Self::Epub(epub) => epub.into_document().await,
+            Self::Email(email) => email.into_document().await,
+            Self::Code(code) => code.into_document().await,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl IntoDocuments for FsDocument {
+    async fn into_documents(self) -> anyhow::Result<Vec<Document>> {
+        match self {
+            Self::Csv(csv) => csv.into_documents().await,
+            Self::Json(json) => json.into_documents().await,
+            Self::Epub(epub) => epub.into_documents().await,
+            Self::Email(email) => email.into_documents().await,
+            document => Ok(vec![document.into_document().await?]),
+        }
+    }
+}
+
+/// Combine several documents from the same file into one document with a section for each document.
+pub(crate) fn combine_documents(
+    title: String,
+    source: PathBuf,
+    documents: Vec<Document>,
+) -> Document {
+    let mut body = String::new();
+    let mut sections = Vec::new();
+    for document in &documents {
+        if !body.is_empty() {
+            body += "\n\n";
         }
+        let start = body.len();
+        body += document.body();
+        // Keep the sections of each document as subsections, shifted to the position of the document in the combined body
+        let children = document
+            .sections()
+            .iter()
+            .cloned()
+            .map(|section| offset_section(section, start))
+            .collect();
+        sections.push(DocumentSection {
+            title: document.title().to_string(),
+            byte_range: start..body.len(),
+            children,
+        });
     }
+    let mut combined = Document::from_parts(title, body);
+    combined.set_source(source);
+    combined.set_sections(sections);
+    combined
+}
+
+fn offset_section(mut section: DocumentSection, offset: usize) -> DocumentSection {
+    section.byte_range = section.byte_range.start + offset..section.byte_range.end + offset;
+    section.children = section
+        .children
+        .into_iter()
+        .map(|child| offset_section(child, offset))
+        .collect();
+    section
 }
 
 /// A folder full of documents.
@@ -123,6 +237,8 @@ impl IntoDocument for FsDocument {
 #[derive(Debug, Clone)]
 pub struct DocumentFolder {
     path: PathBuf,
+    sniff_text: bool,
+    html_mode: HtmlMode,
 }
 
 impl TryFrom<PathBuf> for DocumentFolder {
@@ -132,40 +248,138 @@ impl TryFrom<PathBuf> for DocumentFolder {
         if !path.is_dir() {
             return Err(anyhow::anyhow!("Path is not a directory"));
         }
-        Ok(Self { path })
+        Ok(Self {
+            path,
+            sniff_text: false,
+            html_mode: HtmlMode::default(),
+        })
     }
 }
 
 #[async_trait::async_trait]
 impl IntoDocuments for DocumentFolder {
+    /// Read every supported file in the folder. Files that fail to load are logged and skipped.
     async fn into_documents(self) -> anyhow::Result<Vec<Document>> {
         let mut set = JoinSet::new();
         self.start_into_documents(&mut set).await?;
         let mut documents = Vec::new();
         while let Some(join) = set.join_next().await {
-            documents.push(join??);
+            match join? {
+                (_, Ok(file_documents)) => documents.extend(file_documents),
+                (path, Err(err)) => tracing::warn!("Skipping {}: {}", path.display(), err),
+            }
         }
         Ok(documents)
     }
 }
 
 impl DocumentFolder {
+    /// Get the path of the folder.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Read files with an unknown extension based on their contents (see [`FsDocument::sniff`]). Defaults to false, so only files with a known extension or magic bytes are read.
+    pub fn with_text_sniffing(mut self, sniff_text: bool) -> Self {
+        self.sniff_text = sniff_text;
+        self
+    }
+
+    /// Set how the text of html files in the folder is extracted. Defaults to [`HtmlMode::Article`].
+    pub fn with_html_mode(mut self, html_mode: HtmlMode) -> Self {
+        self.html_mode = html_mode;
+        self
+    }
+
+    /// Open a file in the folder with the folder's file type detection and html mode.
+    pub(crate) fn open(&self, path: PathBuf) -> anyhow::Result<FsDocument> {
+        let document = if self.sniff_text {
+            FsDocument::sniff(path)?
+        } else {
+            FsDocument::try_from(path)?
+        };
+        Ok(document.with_html_mode(self.html_mode))
+    }
+
     #[async_recursion::async_recursion]
     async fn start_into_documents(
         &self,
-        set: &mut JoinSet<anyhow::Result<Document>>,
+        set: &mut JoinSet<(PathBuf, anyhow::Result<Vec<Document>>)>,
     ) -> anyhow::Result<()> {
         let mut read_dir = tokio::fs::read_dir(&self.path).await?;
         while let Some(entry) = read_dir.next_entry().await? {
             let path = entry.path();
             if path.is_dir() {
                 if let Ok(folder) = DocumentFolder::try_from(path) {
-                    folder.start_into_documents(set).await?;
+                    folder
+                        .with_text_sniffing(self.sniff_text)
+                        .with_html_mode(self.html_mode)
+                        .start_into_documents(set)
+                        .await?;
+                }
+            } else {
+                match self.open(path.clone()) {
+                    Ok(document) => {
+                        set.spawn(async move { (path, document.into_documents().await) });
+                    }
+                    Err(err) => tracing::warn!("Skipping {}: {}", path.display(), err),
                 }
-            } else if let Ok(document) = FsDocument::try_from(path) {
-                set.spawn(document.into_document());
             }
         }
         Ok(())
     }
 }
+
+#[tokio::test]
+async fn folder_skips_files_that_fail_to_load() {
+    let folder = tempfile::tempdir().unwrap();
+    std::fs::write(folder.path().join("notes.txt"), "Some notes").unwrap();
+    std::fs::write(folder.path().join("broken.json"), "{ not json").unwrap();
+    std::fs::write(folder.path().join("server.log"), "Started the server").unwrap();
+
+    let documents = DocumentFolder::try_from(folder.path().to_path_buf())
+        .unwrap()
+        .into_documents()
+        .await
+        .unwrap();
+    assert_eq!(documents.len(), 1);
+    assert_eq!(documents[0].body(), "Some notes");
+
+    let mut documents = DocumentFolder::try_from(folder.path().to_path_buf())
+        .unwrap()
+        .with_text_sniffing(true)
+        .into_documents()
+        .await
+        .unwrap();
+    documents.sort_by(|a, b| a.body().cmp(b.body()));
+    assert_eq!(documents.len(), 2);
+    assert_eq!(documents[1].body(), "Started the server");
+}
+
+#[tokio::test]
+async fn folder_reads_html_with_the_html_mode() {
+    let folder = tempfile::tempdir().unwrap();
+    std::fs::create_dir(folder.path().join("guides")).unwrap();
+    std::fs::write(
+        folder.path().join("guides").join("install.html"),
+        "<html><body><h1>Install</h1><ul><li>Download</li><li>Run</li></ul></body></html>",
+    )
+    .unwrap();
+
+    let documents = DocumentFolder::try_from(folder.path().to_path_buf())
+        .unwrap()
+        .with_html_mode(HtmlMode::Markdown)
+        .into_documents()
+        .await
+        .unwrap();
+    assert_eq!(documents.len(), 1);
+    assert_eq!(documents[0].body(), "# Install\n\n- Download\n- Run");
+
+    let document = FsDocument::try_from(folder.path().join("guides").join("install.html"))
+        .unwrap()
+        .with_html_mode(HtmlMode::Markdown)
+        .into_document()
+        .await
+        .unwrap();
+    assert_eq!(document.body(), "# Install\n\n- Download\n- Run");
+}
@@ -0,0 +1,149 @@
+use std::sync::{Arc, Mutex};
+
+use image::{DynamicImage, GrayImage};
+use kalosm_ocr::{Ocr, OcrInferenceSettings, OcrSource};
+
+/// The OCR model is only loaded the first time a scanned page is read.
+static OCR: tokio::sync::OnceCell<Arc<Mutex<Ocr>>> = tokio::sync::OnceCell::const_new();
+
+async fn ocr_model() -> anyhow::Result<Arc<Mutex<Ocr>>> {
+    let model = OCR
+        .get_or_try_init(|| async {
+            let model = tokio::task::spawn_blocking(|| {
+                Ocr::builder()
+                    .with_source(OcrSource::base_printed())
+                    .build()
+            })
+            .await??;
+            anyhow::Ok(Arc::new(Mutex::new(model)))
+        })
+        .await?;
+    Ok(model.clone())
+}
+
+/// Recognize the text in an image of a page. The OCR model only reads a single line of text at a time, so the page is split into lines first.
+pub(crate) async fn recognize_page(image: DynamicImage) -> anyhow::Result<String> {
+    let model = ocr_model().await?;
+    tokio::task::spawn_blocking(move || {
+        let mut model = model
+            .lock()
+            .map_err(|_| anyhow::anyhow!("The OCR model panicked while reading another page"))?;
+        let mut lines = Vec::new();
+        for (x, y, width, height) in line_bounds(&image.to_luma8()) {
+            let line = image.crop_imm(x, y, width, height).to_rgba8();
+            let text = model.recognize_text(OcrInferenceSettings::new(line)?)?;
+            let text = text.trim();
+            if !text.is_empty() {
+                lines.push(text.to_string());
+            }
+        }
+        Ok(lines.join("\n"))
+    })
+    .await?
+}
+
+/// Find a threshold that separates dark ink from the light background with Otsu's method.
+fn ink_threshold(image: &GrayImage) -> u8 {
+    let mut histogram = [0usize; 256];
+    for pixel in image.pixels() {
+        histogram[pixel.0[0] as usize] += 1;
+    }
+    let total = image.width() as f64 * image.height() as f64;
+    let sum = histogram
+        .iter()
+        .enumerate()
+        .map(|(value, count)| value as f64 * *count as f64)
+        .sum::<f64>();
+    let mut dark_sum = 0.;
+    let mut dark_weight = 0.;
+    let mut best = (0., 127);
+    for (value, count) in histogram.iter().enumerate() {
+        dark_weight += *count as f64;
+        if dark_weight == 0. {
+            continue;
+        }
+        let light_weight = total - dark_weight;
+        if light_weight == 0. {
+            break;
+        }
+        dark_sum += value as f64 * *count as f64;
+        let dark_mean = dark_sum / dark_weight;
+        let light_mean = (sum - dark_sum) / light_weight;
+        let variance = dark_weight * light_weight * (dark_mean - light_mean).powi(2);
+        if variance > best.0 {
+            best = (variance, value as u8);
+        }
+    }
+    best.1
+}
+
+/// Find the bounding boxes `(x, y, width, height)` of the lines of text in a page from the number of dark pixels in each row.
+pub(crate) fn line_bounds(image: &GrayImage) -> Vec<(u32, u32, u32, u32)> {
+    const MIN_LINE_HEIGHT: u32 = 4;
+    const MAX_ROW_GAP: u32 = 1;
+    const PADDING: u32 = 4;
+
+    let (width, height) = image.dimensions();
+    if width == 0 || height == 0 {
+        return Vec::new();
+    }
+    let threshold = ink_threshold(image);
+    let is_ink = |x, y| image.get_pixel(x, y).0[0] <= threshold;
+    // Ignore specks of noise in otherwise empty rows
+    let min_row_ink = (width / 200).max(1);
+    let has_ink = (0..height)
+        .map(|y| (0..width).filter(|x| is_ink(*x, y)).count() as u32 >= min_row_ink)
+        .collect::<Vec<_>>();
+
+    let mut rows = Vec::new();
+    let mut y = 0;
+    while y < height {
+        if !has_ink[y as usize] {
+            y += 1;
+            continue;
+        }
+        let start = y;
+        let mut end = y;
+        while y < height {
+            if has_ink[y as usize] {
+                end = y;
+            } else if y - end > MAX_ROW_GAP {
+                break;
+            }
+            y += 1;
+        }
+        if end + 1 - start >= MIN_LINE_HEIGHT {
+            rows.push(start..end + 1);
+        }
+    }
+
+    rows.into_iter()
+        .filter_map(|rows| {
+            let columns = (0..width)
+                .filter(|x| rows.clone().any(|y| is_ink(*x, y)))
+                .collect::<Vec<_>>();
+            let left = columns.first()?.saturating_sub(PADDING);
+            let right = (columns.last()? + PADDING + 1).min(width);
+            let top = rows.start.saturating_sub(PADDING);
+            let bottom = (rows.end + PADDING).min(height);
+            Some((left, top, right - left, bottom - top))
+        })
+        .collect()
+}
+
+#[test]
+fn finds_lines_of_text() {
+    let mut page = GrayImage::from_pixel(200, 100, image::Luma([255]));
+    // Two lines of "text" and a speck of noise
+    for x in 20..180 {
+        for y in (10..20).chain(50..62) {
+            if x % 7 != 0 {
+                page.put_pixel(x, y, image::Luma([0]));
+            }
+        }
+    }
+    page.put_pixel(100, 90, image::Luma([0]));
+
+    let lines = line_bounds(&page);
+    assert_eq!(lines, vec![(16, 6, 168, 18), (16, 46, 168, 20)]);
+}
@@ -1,59 +1,277 @@
+#[cfg(feature = "ocr")]
+use super::ocr::recognize_page;
+use super::FileType;
 use crate::context::document::Document;
 use crate::context::document::IntoDocument;
+use image::DynamicImage;
 use itertools::Itertools;
 use std::fmt::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
+use pdf::enc::StreamFilter;
 use pdf::file::FileOptions;
+use pdf::object::{ImageXObject, Page, Resolve, XObject};
+
+/// Images smaller than this many pixels (like logos and icons) are not read with OCR.
+const MIN_OCR_PIXELS: u64 = 100 * 100;
 
 /// A pdf document that can be read from the file system.
+///
+/// With the `ocr` feature, pages without any text (like scanned pages) are read with OCR from the images on the page. The OCR model is downloaded the first time a scanned page is read. OCR can be disabled with [`PdfDocument::with_ocr`]. Images that can't be decoded are skipped with a warning, and pages that can't be parsed are read as empty pages so the page numbers of the rest of the document stay the same.
 #[derive(Debug, Clone)]
 pub struct PdfDocument {
     path: PathBuf,
+    ocr: bool,
 }
 
 impl TryFrom<PathBuf> for PdfDocument {
     type Error = anyhow::Error;
 
     fn try_from(path: PathBuf) -> Result<Self, Self::Error> {
-        if !path.is_file() {
-            return Err(anyhow::anyhow!("Path is not a file"));
-        }
-        if path.extension().unwrap() != "pdf" {
-            return Err(anyhow::anyhow!("Path is not a pdf file"));
-        }
-        Ok(Self { path })
+        FileType::check(&path, FileType::Pdf, &["pdf"])?;
+        Ok(Self {
+            path,
+            ocr: cfg!(feature = "ocr"),
+        })
+    }
+}
+
+#[cfg(feature = "ocr")]
+impl PdfDocument {
+    /// Set whether pages without any text are read with OCR. Defaults to true.
+    pub fn with_ocr(mut self, ocr: bool) -> Self {
+        self.ocr = ocr;
+        self
     }
 }
 
+/// The contents of a page in a pdf.
+enum PageContent {
+    Text(String),
+    /// A page without text and the images on the page.
+    Scanned(Vec<DynamicImage>),
+}
+
+struct PdfContents {
+    title: String,
+    author: Option<String>,
+    pages: Vec<PageContent>,
+}
+
 #[async_trait::async_trait]
 impl IntoDocument for PdfDocument {
     async fn into_document(self) -> anyhow::Result<Document> {
-        let file = FileOptions::cached().open(self.path).unwrap();
-        let resolver = file.resolver();
-        let mut title = String::new();
-        let mut text = String::new();
+        let path = self.path.clone();
+        let ocr = self.ocr;
+        let contents = tokio::task::spawn_blocking(move || read_pdf(&path, ocr)).await??;
 
-        if let Some(info) = &file.trailer.info_dict {
-            if let Some(pdf_title) = info.title.as_ref().map(|p| p.to_string_lossy()) {
-                title = pdf_title;
+        let mut text = String::new();
+        let mut pages = Vec::new();
+        let mut ocr_pages = 0usize;
+        for (index, page) in contents.pages.into_iter().enumerate() {
+            let page_start = text.len();
+            match page {
+                PageContent::Text(page_text) => text += &page_text,
+                PageContent::Scanned(images) => {
+                    text += &recognize_images(images).await.map_err(|err| {
+                        anyhow::anyhow!(
+                            "Failed to read scanned page {} of {}: {}",
+                            index + 1,
+                            self.path.display(),
+                            err
+                        )
+                    })?;
+                    ocr_pages += 1;
+                }
             }
+            pages.push(page_start..text.len());
         }
 
-        for page in file.pages().flatten() {
-            if let Ok(flow) = pdf_text::run(&file, &page, &resolver) {
-                for run in flow.runs {
-                    for line in run.lines {
-                        let _ = text.write_fmt(format_args!(
-                            "{}",
-                            line.words.iter().map(|w| &w.text).format(" ")
-                        ));
-                        text += "\n\n";
-                    }
+        let page_count = pages.len();
+        let mut document = Document::from_parts(contents.title, text);
+        document.set_source(self.path);
+        document.set_pages(pages);
+        document.insert_metadata("page_count", page_count as f64);
+        if ocr_pages > 0 {
+            document.insert_metadata("ocr_pages", ocr_pages as f64);
+        }
+        if let Some(author) = contents.author {
+            document.insert_metadata("author", author);
+        }
+        Ok(document)
+    }
+}
+
+fn read_pdf(path: &Path, ocr: bool) -> anyhow::Result<PdfContents> {
+    let file = FileOptions::cached()
+        .open(path)
+        .map_err(|err| anyhow::anyhow!("Failed to open pdf {}: {}", path.display(), err))?;
+    let resolver = file.resolver();
+    let mut title = String::new();
+    let mut author = None;
+    let mut pages = Vec::new();
+
+    if let Some(info) = &file.trailer.info_dict {
+        if let Some(pdf_title) = info.title.as_ref().map(|p| p.to_string_lossy()) {
+            title = pdf_title;
+        }
+        author = info.author.as_ref().map(|p| p.to_string_lossy());
+    }
+
+    for (index, page) in file.pages().enumerate() {
+        let page = match page {
+            Ok(page) => page,
+            Err(err) => {
+                tracing::warn!(
+                    "Failed to parse page {} of {}: {}",
+                    index + 1,
+                    path.display(),
+                    err
+                );
+                pages.push(PageContent::Text(String::new()));
+                continue;
+            }
+        };
+        let mut text = String::new();
+        if let Ok(flow) = pdf_text::run(&file, &page, &resolver) {
+            for run in flow.runs {
+                for line in run.lines {
+                    let _ = text.write_fmt(format_args!(
+                        "{}",
+                        line.words.iter().map(|w| &w.text).format(" ")
+                    ));
+                    text += "\n\n";
                 }
             }
         }
+        let images = if ocr && text.trim().is_empty() {
+            page_images(&page, &resolver, |err| {
+                tracing::warn!(
+                    "Skipping an image on page {} of {} that could not be decoded: {}",
+                    index + 1,
+                    path.display(),
+                    err
+                )
+            })
+        } else {
+            Vec::new()
+        };
+        if images.is_empty() {
+            pages.push(PageContent::Text(text));
+        } else {
+            pages.push(PageContent::Scanned(images));
+        }
+    }
 
-        Ok(Document::from_parts(title, text))
+    Ok(PdfContents {
+        title,
+        author,
+        pages,
+    })
+}
+
+/// Recognize the text in the images of a scanned page.
+#[cfg(feature = "ocr")]
+async fn recognize_images(images: Vec<DynamicImage>) -> anyhow::Result<String> {
+    let mut text = String::new();
+    for image in images {
+        let image_text = recognize_page(image).await?;
+        if !image_text.is_empty() {
+            text += &image_text;
+            text += "\n\n";
+        }
     }
+    Ok(text)
+}
+
+/// Pages are never read as scanned pages without the `ocr` feature.
+#[cfg(not(feature = "ocr"))]
+async fn recognize_images(_: Vec<DynamicImage>) -> anyhow::Result<String> {
+    anyhow::bail!("Reading scanned pages requires the ocr feature")
+}
+
+/// Decode the images on a page that are large enough to contain text. Images that fail to decode are passed to `on_error` and skipped.
+fn page_images(
+    page: &Page,
+    resolver: &impl Resolve,
+    mut on_error: impl FnMut(anyhow::Error),
+) -> Vec<DynamicImage> {
+    let Ok(resources) = page.resources() else {
+        return Vec::new();
+    };
+    let mut images = Vec::new();
+    for xobject in resources.xobjects.values() {
+        let Ok(xobject) = resolver.get(*xobject) else {
+            continue;
+        };
+        if let XObject::Image(image) = &*xobject {
+            if (image.width as u64) * (image.height as u64) < MIN_OCR_PIXELS {
+                continue;
+            }
+            match decode_image(image, resolver) {
+                Ok(image) => images.push(image),
+                Err(err) => on_error(err),
+            }
+        }
+    }
+    images
+}
+
+fn decode_image(image: &ImageXObject, resolver: &impl Resolve) -> anyhow::Result<DynamicImage> {
+    let (width, height) = (image.width, image.height);
+    let (data, filter) = image
+        .raw_image_data(resolver)
+        .map_err(|err| anyhow::anyhow!("Failed to read image data: {}", err))?;
+    // Scans are usually stored as jpegs, which are decoded with the image crate
+    if let Some(StreamFilter::DCTDecode(_)) = filter {
+        return Ok(image::load_from_memory_with_format(
+            &data,
+            image::ImageFormat::Jpeg,
+        )?);
+    }
+    let data = image
+        .image_data(resolver)
+        .map_err(|err| anyhow::anyhow!("Failed to decode image data with {:?}: {}", filter, err))?;
+    let pixels = width as usize * height as usize;
+    let row_bytes = (width as usize).div_ceil(8);
+    let image = if data.len() == pixels {
+        image::GrayImage::from_raw(width, height, data.to_vec()).map(DynamicImage::ImageLuma8)
+    } else if data.len() == pixels * 3 {
+        image::RgbImage::from_raw(width, height, data.to_vec()).map(DynamicImage::ImageRgb8)
+    } else if data.len() == pixels * 4 {
+        // Four components are CMYK
+        let rgb = data
+            .chunks_exact(4)
+            .flat_map(|cmyk| {
+                let k = 255 - cmyk[3] as u16;
+                [0, 1, 2].map(|i| ((255 - cmyk[i] as u16) * k / 255) as u8)
+            })
+            .collect();
+        image::RgbImage::from_raw(width, height, rgb).map(DynamicImage::ImageRgb8)
+    } else if data.len() == row_bytes * height as usize {
+        // One bit per pixel where 0 is black
+        let gray = data
+            .chunks_exact(row_bytes)
+            .flat_map(|row| {
+                (0..width as usize).map(|x| {
+                    if row[x / 8] & (0x80 >> (x % 8)) == 0 {
+                        0
+                    } else {
+                        255
+                    }
+                })
+            })
+            .collect();
+        image::GrayImage::from_raw(width, height, gray).map(DynamicImage::ImageLuma8)
+    } else {
+        None
+    };
+    image.ok_or_else(|| {
+        anyhow::anyhow!(
+            "Unsupported {}x{} image with {} bytes of data",
+            width,
+            height,
+            data.len()
+        )
+    })
 }
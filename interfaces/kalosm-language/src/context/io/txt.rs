@@ -3,6 +3,7 @@ use std::path::PathBuf;
 
 use tokio::{fs::File, io::AsyncReadExt};
 
+use super::FileType;
 use crate::context::document::{Document, IntoDocument};
 
 /// A text document that can be read from the file system.
@@ -15,12 +16,7 @@ impl TryFrom<PathBuf> for TextDocument {
     type Error = anyhow::Error;
 
     fn try_from(path: PathBuf) -> Result<Self, Self::Error> {
-        if !path.is_file() {
-            return Err(anyhow::anyhow!("Path is not a file"));
-        }
-        if path.extension().unwrap() != "txt" {
-            return Err(anyhow::anyhow!("Path is not a txt file"));
-        }
+        FileType::check(&path, FileType::Txt, &["txt"])?;
         Ok(Self { path })
     }
 }
@@ -34,11 +30,13 @@ impl IntoDocument for TextDocument {
             .to_string_lossy()
             .to_string()
             .to_case(Case::Title);
-        let file = File::open(self.path).await?;
+        let file = File::open(&self.path).await?;
         let mut text = String::new();
         tokio::io::BufReader::new(file)
             .read_to_string(&mut text)
             .await?;
-        Ok(Document::from_parts(title, text))
+        let mut document = Document::from_parts(title, text);
+        document.set_source(self.path);
+        Ok(document)
     }
 }
@@ -0,0 +1,366 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::Hasher;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use futures_util::Stream;
+
+use super::DocumentFolder;
+use crate::context::document::{Document, IntoDocuments};
+
+/// A change to a file in a watched [`DocumentFolder`].
+#[derive(Debug, Clone)]
+pub enum DocumentChange {
+    /// A file was added to the folder. All files in the folder that were not passed to [`DocumentFolder::watch_from`] are reported as added when the watch starts.
+    Added {
+        /// The absolute path of the file.
+        path: PathBuf,
+        /// The documents in the file.
+        documents: Vec<Document>,
+        /// The state of the file when it was read.
+        file: WatchedFile,
+    },
+    /// The contents of a file in the folder changed.
+    Modified {
+        /// The absolute path of the file.
+        path: PathBuf,
+        /// The new documents in the file.
+        documents: Vec<Document>,
+        /// The state of the file when it was read.
+        file: WatchedFile,
+    },
+    /// A file was removed from the folder.
+    Removed {
+        /// The absolute path of the file.
+        path: PathBuf,
+    },
+}
+
+/// The state of a file the last time a watch read it. Pass the state of the files you already indexed to [`DocumentFolder::watch_from`] to resume a watch without reading them again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchedFile {
+    /// A hash of the contents of the file.
+    pub hash: u64,
+    /// The length of the file in bytes.
+    pub len: u64,
+    /// When the file was last modified, if the platform records it.
+    pub modified: Option<SystemTime>,
+}
+
+impl WatchedFile {
+    /// Check if the file still has the same length and modification time.
+    fn is_unchanged(&self, len: u64, modified: Option<SystemTime>) -> bool {
+        self.modified.is_some() && self.modified == modified && self.len == len
+    }
+}
+
+impl DocumentChange {
+    /// Get the path of the file that changed.
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::Added { path, .. } | Self::Modified { path, .. } | Self::Removed { path } => path,
+        }
+    }
+}
+
+impl DocumentFolder {
+    /// Watch the folder for changes. The folder is scanned every two seconds. Files are only hashed again when their length or modification time changes, and only read into documents again when the hash of their contents changes.
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// use kalosm_language::prelude::*;
+    /// use std::path::PathBuf;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let folder = DocumentFolder::try_from(PathBuf::from("./documents")).unwrap();
+    ///     let mut changes = std::pin::pin!(folder.watch());
+    ///     while let Some(change) = changes.next().await {
+    ///         println!("{:?}", change.unwrap().path());
+    ///     }
+    /// }
+    /// ```
+    pub fn watch(&self) -> impl Stream<Item = anyhow::Result<DocumentChange>> + Send + 'static {
+        self.watch_with_interval(Duration::from_secs(2))
+    }
+
+    /// Watch the folder for changes, scanning the folder at the given interval.
+    pub fn watch_with_interval(
+        &self,
+        interval: Duration,
+    ) -> impl Stream<Item = anyhow::Result<DocumentChange>> + Send + 'static {
+        self.watch_from([], interval)
+    }
+
+    /// Resume a watch of the folder from the files that were already indexed, scanning the folder at the given interval. The paths must be the absolute paths reported by an earlier watch.
+    ///
+    /// Known files that are unchanged are not read again, known files that changed are reported as modified, and known files that are no longer in the folder are reported as removed in the first scan. This keeps an index in sync with files that changed while nothing was watching the folder.
+    pub fn watch_from(
+        &self,
+        known: impl IntoIterator<Item = (PathBuf, WatchedFile)>,
+        interval: Duration,
+    ) -> impl Stream<Item = anyhow::Result<DocumentChange>> + Send + 'static {
+        let watcher = FolderWatcher {
+            folder: self.clone(),
+            interval,
+            files: known
+                .into_iter()
+                .map(|(path, file)| {
+                    (
+                        path,
+                        TrackedFile {
+                            file,
+                            indexed: true,
+                        },
+                    )
+                })
+                .collect(),
+            pending: VecDeque::new(),
+            scanned: false,
+        };
+        futures_util::stream::unfold(watcher, |mut watcher| async move {
+            loop {
+                if let Some(change) = watcher.pending.pop_front() {
+                    return Some((change, watcher));
+                }
+                if watcher.scanned {
+                    tokio::time::sleep(watcher.interval).await;
+                }
+                watcher.scanned = true;
+                watcher.scan().await;
+            }
+        })
+    }
+}
+
+struct TrackedFile {
+    file: WatchedFile,
+    /// If the file was read into documents. Files that are not supported or failed to load are still tracked so they are not read again until they change.
+    indexed: bool,
+}
+
+struct FolderWatcher {
+    folder: DocumentFolder,
+    interval: Duration,
+    files: HashMap<PathBuf, TrackedFile>,
+    pending: VecDeque<anyhow::Result<DocumentChange>>,
+    scanned: bool,
+}
+
+impl FolderWatcher {
+    async fn scan(&mut self) {
+        // Paths are reported relative to the canonical folder so they don't depend on the working directory
+        let paths = match tokio::fs::canonicalize(&self.folder.path).await {
+            Ok(root) => list_files(&root).await,
+            Err(err) => Err(err.into()),
+        };
+        let paths = match paths {
+            Ok(paths) => paths,
+            Err(err) => {
+                self.pending.push_back(Err(err));
+                return;
+            }
+        };
+
+        let mut seen = HashSet::new();
+        for path in paths {
+            seen.insert(path.clone());
+            // The file may be removed between listing and reading it. It will be reported as removed in the next scan
+            let Ok(metadata) = tokio::fs::metadata(&path).await else {
+                continue;
+            };
+            let len = metadata.len();
+            let modified = metadata.modified().ok();
+            let previous = self.files.get_mut(&path);
+            if previous
+                .as_ref()
+                .is_some_and(|previous| previous.file.is_unchanged(len, modified))
+            {
+                continue;
+            }
+            let Ok(contents) = tokio::fs::read(&path).await else {
+                continue;
+            };
+            let file = WatchedFile {
+                hash: hash_contents(&contents),
+                len,
+                modified,
+            };
+            if let Some(previous) = previous {
+                if previous.file.hash == file.hash {
+                    previous.file = file;
+                    continue;
+                }
+            }
+            let was_indexed = self.files.get(&path).is_some_and(|file| file.indexed);
+
+            let documents = match self.folder.open(path.clone()) {
+                Ok(document) => document.into_documents().await,
+                Err(_) => {
+                    // Unsupported files are ignored. If the file used to be supported, it is no longer indexed
+                    self.files.insert(
+                        path.clone(),
+                        TrackedFile {
+                            file,
+                            indexed: false,
+                        },
+                    );
+                    if was_indexed {
+                        self.pending.push_back(Ok(DocumentChange::Removed { path }));
+                    }
+                    continue;
+                }
+            };
+            let documents = match documents {
+                Ok(documents) => documents,
+                Err(err) => {
+                    self.files.insert(
+                        path.clone(),
+                        TrackedFile {
+                            file,
+                            indexed: was_indexed,
+                        },
+                    );
+                    self.pending.push_back(Err(anyhow::anyhow!(
+                        "Failed to read {}: {}",
+                        path.display(),
+                        err
+                    )));
+                    continue;
+                }
+            };
+
+            self.files.insert(
+                path.clone(),
+                TrackedFile {
+                    file,
+                    indexed: true,
+                },
+            );
+            let change = if was_indexed {
+                DocumentChange::Modified {
+                    path,
+                    documents,
+                    file,
+                }
+            } else {
+                DocumentChange::Added {
+                    path,
+                    documents,
+                    file,
+                }
+            };
+            self.pending.push_back(Ok(change));
+        }
+
+        let removed = self
+            .files
+            .keys()
+            .filter(|path| !seen.contains(*path))
+            .cloned()
+            .collect::<Vec<_>>();
+        for path in removed {
+            if let Some(file) = self.files.remove(&path) {
+                if file.indexed {
+                    self.pending.push_back(Ok(DocumentChange::Removed { path }));
+                }
+            }
+        }
+    }
+}
+
+/// Recursively list all files in a folder.
+async fn list_files(root: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut folders = vec![root.to_path_buf()];
+    while let Some(folder) = folders.pop() {
+        let mut read_dir = tokio::fs::read_dir(&folder).await?;
+        while let Some(entry) = read_dir.next_entry().await? {
+            let path = entry.path();
+            if path.is_dir() {
+                folders.push(path);
+            } else if path.is_file() {
+                files.push(path);
+            }
+        }
+    }
+    Ok(files)
+}
+
+fn hash_contents(contents: &[u8]) -> u64 {
+    let mut hasher = rustc_hash::FxHasher::default();
+    hasher.write(contents);
+    hasher.finish()
+}
+
+#[tokio::test]
+async fn watch_reports_file_changes() {
+    use futures_util::StreamExt;
+
+    let folder = tempfile::tempdir().unwrap();
+    let path = folder.path().canonicalize().unwrap().join("notes.txt");
+    std::fs::write(&path, "first version").unwrap();
+
+    let watched = DocumentFolder::try_from(folder.path().to_path_buf()).unwrap();
+    let mut changes = std::pin::pin!(watched.watch_with_interval(Duration::from_millis(10)));
+
+    let change = changes.next().await.unwrap().unwrap();
+    assert!(
+        matches!(&change, DocumentChange::Added { documents, .. } if documents[0].body() == "first version")
+    );
+
+    std::fs::write(&path, "second version").unwrap();
+    let change = changes.next().await.unwrap().unwrap();
+    assert!(
+        matches!(&change, DocumentChange::Modified { documents, .. } if documents[0].body() == "second version")
+    );
+
+    std::fs::remove_file(&path).unwrap();
+    let change = changes.next().await.unwrap().unwrap();
+    assert!(matches!(change, DocumentChange::Removed { path: removed } if removed == path));
+}
+
+#[tokio::test]
+async fn watch_from_skips_known_files() {
+    use futures_util::StreamExt;
+
+    let folder = tempfile::tempdir().unwrap();
+    let root = folder.path().canonicalize().unwrap();
+    let kept = root.join("kept.txt");
+    let removed = root.join("removed.txt");
+    std::fs::write(&kept, "kept").unwrap();
+    std::fs::write(&removed, "removed").unwrap();
+
+    let watched = DocumentFolder::try_from(folder.path().to_path_buf()).unwrap();
+    let mut known = HashMap::new();
+    {
+        let mut changes = std::pin::pin!(watched.watch_with_interval(Duration::from_millis(10)));
+        for _ in 0..2 {
+            match changes.next().await.unwrap().unwrap() {
+                DocumentChange::Added { path, file, .. } => {
+                    known.insert(path, file);
+                }
+                change => panic!("unexpected change {:?}", change),
+            }
+        }
+    }
+    assert!(known.contains_key(&kept));
+
+    // The file is removed while nothing is watching the folder
+    std::fs::remove_file(&removed).unwrap();
+    let mut changes = std::pin::pin!(watched.watch_from(known, Duration::from_millis(10)));
+    let change = changes.next().await.unwrap().unwrap();
+    assert!(matches!(change, DocumentChange::Removed { path } if path == removed));
+    // The unchanged file is not reported again
+    assert!(
+        tokio::time::timeout(Duration::from_millis(100), changes.next())
+            .await
+            .is_err()
+    );
+
+    std::fs::write(&kept, "kept and changed").unwrap();
+    let change = changes.next().await.unwrap().unwrap();
+    assert!(
+        matches!(&change, DocumentChange::Modified { path, documents, .. } if *path == kept && documents[0].body() == "kept and changed")
+    );
+}
@@ -2,6 +2,8 @@
 
 mod document;
 pub use document::*;
+mod http;
+pub use http::*;
 mod io;
 pub use io::*;
 mod page;
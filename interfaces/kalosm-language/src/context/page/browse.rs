@@ -5,8 +5,8 @@ use scraper::Html;
 use std::sync::Arc;
 use url::Url;
 
-use super::extract_article;
 use crate::context::document::Document;
+use crate::context::io::{html_document, HtmlMode};
 
 static BROWSER: Browser = Browser::new();
 
@@ -124,8 +124,16 @@ impl Tab {
 
     /// Extract the article from the current page.
     pub fn article(&self) -> anyhow::Result<Document> {
+        self.article_with_mode(HtmlMode::Article)
+    }
+
+    /// Extract the text of the current page with the given mode.
+    pub fn article_with_mode(&self, mode: HtmlMode) -> anyhow::Result<Document> {
         let html = self.inner.get_content()?;
-        extract_article(&html)
+        let url = self.url();
+        let mut document = html_document(&html, Some(&url), mode)?;
+        document.set_source(url);
+        Ok(document)
     }
 
     /// Get the title of the current page.
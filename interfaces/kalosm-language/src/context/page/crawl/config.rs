@@ -0,0 +1,162 @@
+use std::path::{Path, PathBuf};
+
+use url::Url;
+
+/// Query parameters that are only used for tracking and never change the content of a page.
+const TRACKING_PARAMS: &[&str] = &[
+    "fbclid", "gclid", "dclid", "msclkid", "yclid", "mc_cid", "mc_eid", "_ga", "_gl", "ref_src",
+];
+
+/// Configuration for a crawl started with [`crate::context::Page::crawl_with_config`].
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm_language::prelude::*;
+///
+/// let config = CrawlConfig::new()
+///     .with_max_depth(3)
+///     .with_max_pages_per_domain(50_000)
+///     .with_sitemap_seeding(true)
+///     .with_frontier("./docs-crawl.jsonl");
+/// ```
+#[derive(Debug, Clone)]
+pub struct CrawlConfig {
+    max_depth: Option<usize>,
+    max_pages_per_domain: Option<usize>,
+    canonicalize: bool,
+    ignored_query_params: Vec<String>,
+    sitemap_seeding: bool,
+    frontier: Option<PathBuf>,
+}
+
+impl Default for CrawlConfig {
+    fn default() -> Self {
+        Self {
+            max_depth: None,
+            max_pages_per_domain: None,
+            canonicalize: true,
+            ignored_query_params: TRACKING_PARAMS.iter().map(|p| p.to_string()).collect(),
+            sitemap_seeding: false,
+            frontier: None,
+        }
+    }
+}
+
+impl CrawlConfig {
+    /// Create a new crawl config with no depth or page limits that canonicalizes URLs.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the maximum number of links to follow from the start page. The start page (and any pages from the sitemap) have a depth of 0.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Set the maximum number of pages to visit for each domain.
+    pub fn with_max_pages_per_domain(mut self, max_pages: usize) -> Self {
+        self.max_pages_per_domain = Some(max_pages);
+        self
+    }
+
+    /// Set if URLs should be canonicalized before checking if they were already visited. Canonicalization removes fragments, tracking query parameters and trailing slashes, and sorts the remaining query parameters. Defaults to true.
+    pub fn with_canonicalization(mut self, canonicalize: bool) -> Self {
+        self.canonicalize = canonicalize;
+        self
+    }
+
+    /// Add a query parameter that is removed when URLs are canonicalized. `utm_*` parameters and common click ids are removed by default.
+    pub fn with_ignored_query_param(mut self, param: impl Into<String>) -> Self {
+        self.ignored_query_params.push(param.into());
+        self
+    }
+
+    /// Set if the crawl should be seeded with the pages from the sitemap of the start page. The sitemaps listed in robots.txt are used if there are any, otherwise `/sitemap.xml` is used. Only sitemaps and pages on the origin of the start page are used, and gzipped (`.xml.gz`) sitemaps are skipped. Defaults to false.
+    pub fn with_sitemap_seeding(mut self, sitemap_seeding: bool) -> Self {
+        self.sitemap_seeding = sitemap_seeding;
+        self
+    }
+
+    /// Save the frontier of the crawl (the visited and queued pages) to a file. If the file already exists, the crawl resumes from the saved frontier instead of visiting pages again.
+    pub fn with_frontier(mut self, path: impl AsRef<Path>) -> Self {
+        self.frontier = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Get the maximum depth of the crawl.
+    pub fn max_depth(&self) -> Option<usize> {
+        self.max_depth
+    }
+
+    /// Get the maximum number of pages to visit for each domain.
+    pub fn max_pages_per_domain(&self) -> Option<usize> {
+        self.max_pages_per_domain
+    }
+
+    /// Check if the crawl is seeded from sitemaps.
+    pub fn sitemap_seeding(&self) -> bool {
+        self.sitemap_seeding
+    }
+
+    /// Get the path of the saved frontier.
+    pub fn frontier(&self) -> Option<&Path> {
+        self.frontier.as_deref()
+    }
+
+    fn is_ignored_query_param(&self, param: &str) -> bool {
+        param.starts_with("utm_") || self.ignored_query_params.iter().any(|p| p == param)
+    }
+
+    /// Get the canonical form of a URL. Two URLs with the same canonical form are treated as the same page.
+    pub fn canonicalize(&self, url: &Url) -> Url {
+        let mut url = url.clone();
+        if !self.canonicalize {
+            return url;
+        }
+        url.set_fragment(None);
+
+        let mut query = url
+            .query_pairs()
+            .filter(|(key, _)| !self.is_ignored_query_param(key))
+            .map(|(key, value)| (key.into_owned(), value.into_owned()))
+            .collect::<Vec<_>>();
+        query.sort();
+        if query.is_empty() {
+            url.set_query(None);
+        } else {
+            url.query_pairs_mut().clear().extend_pairs(query);
+        }
+
+        let path = url.path();
+        if path.len() > 1 && path.ends_with('/') {
+            let trimmed = path.trim_end_matches('/').to_string();
+            url.set_path(&trimmed);
+        }
+
+        url
+    }
+}
+
+#[test]
+fn canonicalize_removes_duplicate_forms() {
+    let config = CrawlConfig::new();
+    let canonical = |url: &str| config.canonicalize(&Url::parse(url).unwrap()).to_string();
+    assert_eq!(
+        canonical("https://Docs.Example.com:443/guide/#install"),
+        "https://docs.example.com/guide"
+    );
+    assert_eq!(
+        canonical("https://example.com/search?q=rust&utm_source=feed&fbclid=abc&a=1"),
+        "https://example.com/search?a=1&q=rust"
+    );
+    assert_eq!(canonical("https://example.com/"), "https://example.com/");
+
+    let config = CrawlConfig::new().with_canonicalization(false);
+    assert_eq!(
+        config
+            .canonicalize(&Url::parse("https://example.com/a/#b").unwrap())
+            .to_string(),
+        "https://example.com/a/#b"
+    );
+}
@@ -0,0 +1,165 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use super::CrawlConfig;
+
+/// An entry in the frontier log.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+enum FrontierEntry {
+    /// A page was added to the queue.
+    Queued { url: Url, depth: usize },
+    /// A page was visited and the links from the page were queued.
+    Visited { url: Url },
+}
+
+/// The state of a crawl loaded from a saved frontier.
+#[derive(Debug, Default)]
+pub(crate) struct FrontierState {
+    /// The canonical URLs of pages that were already visited.
+    pub(crate) visited: Vec<Url>,
+    /// The pages that were queued but not visited, with their depth.
+    pub(crate) pending: Vec<(Url, usize)>,
+}
+
+/// A log of the visited and queued pages of a crawl on disk. Each line is a json entry, so a crawl that is interrupted loses at most the entry that was being written.
+pub(crate) struct Frontier {
+    file: Mutex<File>,
+}
+
+impl Frontier {
+    /// Open a frontier, replaying any entries that are already saved. The log is compacted so it only contains the current state.
+    pub(crate) fn open(path: &Path, config: &CrawlConfig) -> anyhow::Result<(Self, FrontierState)> {
+        let state = if path.exists() {
+            let reader = BufReader::new(File::open(path)?);
+            let entries = reader
+                .lines()
+                .map_while(Result::ok)
+                // A partially written line at the end of the file is ignored
+                .filter_map(|line| serde_json::from_str(&line).ok());
+            replay(entries, config)
+        } else {
+            FrontierState::default()
+        };
+
+        // Write the compacted state to a temporary file and then move it over the old log
+        let mut compacted_path = PathBuf::from(path);
+        compacted_path.set_extension("compacting");
+        {
+            let mut compacted = File::create(&compacted_path)?;
+            for url in &state.visited {
+                write_entry(&mut compacted, &FrontierEntry::Visited { url: url.clone() })?;
+            }
+            for (url, depth) in &state.pending {
+                write_entry(
+                    &mut compacted,
+                    &FrontierEntry::Queued {
+                        url: url.clone(),
+                        depth: *depth,
+                    },
+                )?;
+            }
+            compacted.sync_all()?;
+        }
+        std::fs::rename(&compacted_path, path)?;
+
+        let file = std::fs::OpenOptions::new().append(true).open(path)?;
+        Ok((
+            Self {
+                file: Mutex::new(file),
+            },
+            state,
+        ))
+    }
+
+    /// Record that a page was queued.
+    pub(crate) fn queued(&self, url: &Url, depth: usize) {
+        self.write(&FrontierEntry::Queued {
+            url: url.clone(),
+            depth,
+        });
+    }
+
+    /// Record that a page was visited.
+    pub(crate) fn visited(&self, url: &Url) {
+        self.write(&FrontierEntry::Visited { url: url.clone() });
+    }
+
+    fn write(&self, entry: &FrontierEntry) {
+        let mut file = self.file.lock().unwrap();
+        if let Err(err) = write_entry(&mut file, entry) {
+            tracing::error!("Failed to save crawl frontier: {}", err);
+        }
+    }
+}
+
+fn write_entry(file: &mut File, entry: &FrontierEntry) -> anyhow::Result<()> {
+    let mut line = serde_json::to_string(entry)?;
+    line.push('\n');
+    file.write_all(line.as_bytes())?;
+    Ok(())
+}
+
+/// Replay the entries of a frontier log into the visited pages and the pages that still need to be visited.
+fn replay(entries: impl IntoIterator<Item = FrontierEntry>, config: &CrawlConfig) -> FrontierState {
+    let mut visited = HashSet::new();
+    let mut queued = Vec::new();
+    for entry in entries {
+        match entry {
+            FrontierEntry::Queued { url, depth } => queued.push((url, depth)),
+            FrontierEntry::Visited { url } => {
+                visited.insert(config.canonicalize(&url));
+            }
+        }
+    }
+
+    let mut seen = visited.clone();
+    let pending = queued
+        .into_iter()
+        .filter(|(url, _)| seen.insert(config.canonicalize(url)))
+        .collect();
+    FrontierState {
+        visited: visited.into_iter().collect(),
+        pending,
+    }
+}
+
+#[test]
+fn frontier_resumes_pending_pages() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("frontier.jsonl");
+    let config = CrawlConfig::new();
+    let url = |path: &str| Url::parse(&format!("https://example.com{}", path)).unwrap();
+
+    {
+        let (frontier, state) = Frontier::open(&path, &config).unwrap();
+        assert!(state.visited.is_empty() && state.pending.is_empty());
+        frontier.queued(&url("/"), 0);
+        frontier.visited(&url("/"));
+        frontier.queued(&url("/a"), 1);
+        frontier.queued(&url("/b/"), 1);
+        frontier.queued(&url("/b#section"), 1);
+        frontier.visited(&url("/a"));
+    }
+    // Simulate a crash in the middle of writing an entry
+    std::fs::OpenOptions::new()
+        .append(true)
+        .open(&path)
+        .unwrap()
+        .write_all(b"{\"Visited\":{\"url\":")
+        .unwrap();
+
+    let (_, state) = Frontier::open(&path, &config).unwrap();
+    assert_eq!(state.visited.len(), 2);
+    assert_eq!(state.pending, vec![(url("/b/"), 1)]);
+
+    // The compacted log keeps the same state
+    let (_, state) = Frontier::open(&path, &config).unwrap();
+    assert_eq!(state.visited.len(), 2);
+    assert_eq!(state.pending, vec![(url("/b/"), 1)]);
+}
@@ -1,5 +1,6 @@
 use crate::context::page::BrowserMode;
 use crate::context::page::Page;
+use crate::context::HttpClient;
 use core::task::Context;
 use dashmap::DashMap;
 use once_cell::sync::OnceCell;
@@ -18,6 +19,12 @@ use tokio::time::Instant;
 use url::Origin;
 use url::Url;
 
+mod config;
+pub use config::*;
+mod frontier;
+use frontier::{Frontier, FrontierState};
+mod sitemap;
+
 const COOLDOWN: Duration = Duration::from_secs(5);
 
 /// Feedback that can be given to the crawler after visiting a page.
@@ -200,6 +207,9 @@ pub(crate) struct Crawler<T> {
     active: Arc<ActiveLinks>,
     visit: Arc<T>,
     mode: BrowserMode,
+    config: Arc<CrawlConfig>,
+    frontier: Option<Arc<Frontier>>,
+    resume: Option<FrontierState>,
     queued: Arc<DashMap<url::Origin, DomainQueue<T>>>,
     aborted: Arc<AtomicBool>,
 }
@@ -210,6 +220,9 @@ impl<T> Clone for Crawler<T> {
             active: self.active.clone(),
             visit: self.visit.clone(),
             mode: self.mode,
+            config: self.config.clone(),
+            frontier: self.frontier.clone(),
+            resume: None,
             queued: self.queued.clone(),
             aborted: self.aborted.clone(),
         }
@@ -217,14 +230,24 @@ impl<T> Clone for Crawler<T> {
 }
 
 impl<T: CrawlingCallback> Crawler<T> {
-    pub fn new(mode: BrowserMode, visit: T) -> Self {
-        Self {
+    pub fn new(mode: BrowserMode, config: CrawlConfig, visit: T) -> anyhow::Result<Self> {
+        let (frontier, resume) = match config.frontier() {
+            Some(path) => {
+                let (frontier, state) = Frontier::open(path, &config)?;
+                (Some(Arc::new(frontier)), Some(state))
+            }
+            None => (None, None),
+        };
+        Ok(Self {
             active: Arc::new(ActiveLinks::new()),
             mode,
+            config: Arc::new(config),
+            frontier,
+            resume,
             queued: Default::default(),
             visit: Arc::new(visit),
             aborted: Default::default(),
-        }
+        })
     }
 
     pub fn is_aborted(&self) -> bool {
@@ -244,28 +267,52 @@ impl<T: CrawlingCallback> Crawler<T> {
             return Ok(());
         }
 
-        self.add_urls(vec![url]).await?;
+        // Pages that were visited before the crawl was interrupted are marked as seen so they are not visited again
+        if let Some(resume) = self.resume.take() {
+            for url in resume.visited {
+                self.domain_queue(&url.origin()).await?.mark_visited(&url);
+            }
+            self.add_urls(resume.pending).await?;
+        }
+
+        if self.config.sitemap_seeding() {
+            match sitemap::sitemap_urls(&url.origin()).await {
+                Ok(pages) => {
+                    self.add_urls(pages.into_iter().map(|page| (page, 0)).collect())
+                        .await?
+                }
+                Err(err) => tracing::error!("Error reading sitemap: {}", err),
+            }
+        }
+
+        self.add_urls(vec![(url, 0)]).await?;
 
         self.active.wait().await;
 
         Ok(())
     }
 
-    async fn add_urls(&self, urls: Vec<Url>) -> anyhow::Result<()> {
+    async fn domain_queue(
+        &self,
+        origin: &Origin,
+    ) -> anyhow::Result<dashmap::mapref::one::RefMut<'_, Origin, DomainQueue<T>>> {
+        if let Some(queue) = self.queued.get_mut(origin) {
+            return Ok(queue);
+        }
+        let queue = DomainQueue::new(origin.clone(), self.clone()).await?;
+        Ok(self.queued.entry(origin.clone()).or_insert(queue))
+    }
+
+    async fn add_urls(&self, urls: Vec<(Url, usize)>) -> anyhow::Result<()> {
         if self.is_aborted() {
             return Ok(());
         }
 
-        for url in urls {
-            let origin = url.origin();
-            if let Some(mut queue) = self.queued.get_mut(&origin) {
-                queue.push(url);
+        for (url, depth) in urls {
+            if self.config.max_depth().is_some_and(|max| depth > max) {
                 continue;
             }
-
-            let mut queue = DomainQueue::new(origin.clone(), self.clone()).await?;
-            queue.push(url);
-            self.queued.insert(origin, queue);
+            self.domain_queue(&url.origin()).await?.push(url, depth);
         }
 
         Ok(())
@@ -275,13 +322,8 @@ impl<T: CrawlingCallback> Crawler<T> {
 async fn try_get_robot(origin: &Origin) -> anyhow::Result<Option<Robot>> {
     let robots_txt_url = origin.ascii_serialization() + "/robots.txt";
     let robots_txt_url = Url::parse(&robots_txt_url)?;
-    let robots_txt_content = match reqwest::get(robots_txt_url.clone()).await {
-        Ok(response) => match response.text().await {
-            Ok(text) => text,
-            Err(_) => {
-                return Ok(None);
-            }
-        },
+    let robots_txt_content = match HttpClient::global().get(&robots_txt_url).await {
+        Ok(response) => response.into_text(),
         Err(_) => {
             return Ok(None);
         }
@@ -292,8 +334,9 @@ async fn try_get_robot(origin: &Origin) -> anyhow::Result<Option<Robot>> {
 }
 
 struct DomainQueue<T> {
-    visited: HashSet<Url>,
-    queue: tokio::sync::mpsc::UnboundedSender<Url>,
+    /// The canonical URLs of pages that were queued or visited
+    seen: HashSet<Url>,
+    queue: tokio::sync::mpsc::UnboundedSender<(Url, usize)>,
     crawler: Crawler<T>,
     task: tokio::task::JoinHandle<()>,
 }
@@ -301,7 +344,7 @@ struct DomainQueue<T> {
 impl<T: CrawlingCallback> DomainQueue<T> {
     async fn new(origin: Origin, crawler: Crawler<T>) -> anyhow::Result<Self> {
         let robots_txt = try_get_robot(&origin).await?;
-        let (queue, mut rx) = tokio::sync::mpsc::unbounded_channel::<Url>();
+        let (queue, mut rx) = tokio::sync::mpsc::unbounded_channel::<(Url, usize)>();
 
         let pool = get_local_pool();
         let task = {
@@ -312,15 +355,19 @@ impl<T: CrawlingCallback> DomainQueue<T> {
                     .and_then(|r| r.delay)
                     .map(|delay| Duration::from_secs(delay as u64))
                     .unwrap_or(COOLDOWN);
-                while let Some(url) = rx.recv().await {
+                while let Some((url, depth)) = rx.recv().await {
                     if let Some(robot) = &robots_txt {
                         if !robot.allowed(url.as_str()) {
+                            if let Some(frontier) = &crawler.frontier {
+                                frontier.visited(&url);
+                            }
+                            crawler.active.remove();
                             continue;
                         }
                     }
                     let mode = crawler.mode;
                     let wait_until = Instant::now() + cooldown;
-                    let page = Page::new_wait_until(url, mode, wait_until).unwrap();
+                    let page = Page::new_wait_until(url.clone(), mode, wait_until).unwrap();
 
                     let visit = crawler.visit.visit(page.clone());
 
@@ -330,6 +377,8 @@ impl<T: CrawlingCallback> DomainQueue<T> {
                         CrawlFeedback::Continue(mut filter) => match page.links().await {
                             Ok(mut new_urls) => {
                                 new_urls.retain(|url| filter.follow_link(url));
+                                let new_urls =
+                                    new_urls.into_iter().map(|url| (url, depth + 1)).collect();
                                 if let Err(err) = crawler.add_urls(new_urls).await {
                                     tracing::error!("Error adding urls: {}", err);
                                 }
@@ -341,6 +390,10 @@ impl<T: CrawlingCallback> DomainQueue<T> {
                             return;
                         }
                     }
+                    // The page is only marked as visited after the links are queued so an interrupted crawl visits the page again
+                    if let Some(frontier) = &crawler.frontier {
+                        frontier.visited(&url);
+                    }
                     crawler.active.remove();
                 }
             })
@@ -349,7 +402,7 @@ impl<T: CrawlingCallback> DomainQueue<T> {
         Ok(Self {
             task,
             queue,
-            visited: HashSet::new(),
+            seen: HashSet::new(),
             crawler,
         })
     }
@@ -358,16 +411,32 @@ impl<T: CrawlingCallback> DomainQueue<T> {
         self.task.abort();
     }
 
-    fn push(&mut self, url: Url) {
-        if self.visited.contains(&url) {
+    fn mark_visited(&mut self, url: &Url) {
+        self.seen.insert(self.crawler.config.canonicalize(url));
+    }
+
+    fn push(&mut self, url: Url, depth: usize) {
+        let canonical = self.crawler.config.canonicalize(&url);
+        if self.seen.contains(&canonical) {
+            return;
+        }
+        if self
+            .crawler
+            .config
+            .max_pages_per_domain()
+            .is_some_and(|max| self.seen.len() >= max)
+        {
             return;
         }
 
         self.crawler.active.add();
 
-        self.visited.insert(url.clone());
+        self.seen.insert(canonical);
+        if let Some(frontier) = &self.crawler.frontier {
+            frontier.queued(&url, depth);
+        }
 
-        let _ = self.queue.send(url);
+        let _ = self.queue.send((url, depth));
     }
 }
 
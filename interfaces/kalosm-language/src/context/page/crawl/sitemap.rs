@@ -0,0 +1,142 @@
+use crate::context::HttpClient;
+use url::{Origin, Url};
+
+/// The maximum number of sitemap files to read from a sitemap index.
+const MAX_SITEMAPS: usize = 100;
+
+/// Find the URLs of the sitemaps of an origin from robots.txt, falling back to `/sitemap.xml`.
+async fn sitemap_roots(origin: &Origin) -> anyhow::Result<Vec<Url>> {
+    let base = Url::parse(&origin.ascii_serialization())?;
+    let mut roots = Vec::new();
+    if let Ok(response) = HttpClient::global().get(&base.join("/robots.txt")?).await {
+        roots.extend(robots_txt_sitemaps(response.text()));
+    }
+    if roots.is_empty() {
+        roots.push(base.join("/sitemap.xml")?);
+    }
+    Ok(roots)
+}
+
+/// Read the `Sitemap:` lines from a robots.txt file.
+fn robots_txt_sitemaps(robots_txt: &str) -> Vec<Url> {
+    robots_txt
+        .lines()
+        .filter_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            if !key.trim().eq_ignore_ascii_case("sitemap") {
+                return None;
+            }
+            Url::parse(value.trim()).ok()
+        })
+        .collect()
+}
+
+/// Check if a sitemap should be read. Sitemaps on other origins are skipped so they can't seed the crawl with other hosts. Gzipped sitemaps are skipped because they are not decompressed.
+fn is_readable_sitemap(origin: &Origin, sitemap: &Url) -> bool {
+    if sitemap.origin() != *origin {
+        tracing::debug!("Skipping sitemap {} from another origin", sitemap);
+        return false;
+    }
+    if sitemap.path().ends_with(".gz") {
+        tracing::warn!("Skipping gzipped sitemap {}", sitemap);
+        return false;
+    }
+    true
+}
+
+/// Get all page URLs in the sitemaps of an origin. Sitemap indexes are followed up to a limit of 100 sitemaps. Sitemaps that fail to load are skipped.
+///
+/// Only sitemaps and pages on the same origin are used.
+pub(crate) async fn sitemap_urls(origin: &Origin) -> anyhow::Result<Vec<Url>> {
+    let mut pending = sitemap_roots(origin).await?;
+    let mut read = 0;
+    let mut pages = Vec::new();
+    while let Some(sitemap) = pending.pop() {
+        if !is_readable_sitemap(origin, &sitemap) {
+            continue;
+        }
+        if read >= MAX_SITEMAPS {
+            break;
+        }
+        read += 1;
+        let xml = match HttpClient::global().get_text(&sitemap).await {
+            Ok(xml) => xml,
+            Err(_) => continue,
+        };
+        let (is_index, locations) = parse_sitemap(&xml);
+        let locations = locations
+            .into_iter()
+            .filter_map(|location| sitemap.join(&location).ok());
+        if is_index {
+            pending.extend(locations);
+        } else {
+            pages.extend(locations.filter(|page| page.origin() == *origin));
+        }
+    }
+    Ok(pages)
+}
+
+/// Parse a sitemap into the locations it lists. Returns true if the sitemap is an index of other sitemaps.
+fn parse_sitemap(xml: &str) -> (bool, Vec<String>) {
+    let is_index = xml.contains("<sitemapindex");
+    let mut locations = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find("<loc>") {
+        rest = &rest[start + "<loc>".len()..];
+        let Some(end) = rest.find("</loc>") else {
+            break;
+        };
+        let location = rest[..end].trim();
+        let location = location
+            .strip_prefix("<![CDATA[")
+            .and_then(|location| location.strip_suffix("]]>"))
+            .unwrap_or(location);
+        locations.push(unescape_xml(location.trim()));
+        rest = &rest[end..];
+    }
+    (is_index, locations)
+}
+
+fn unescape_xml(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[test]
+fn parses_sitemaps() {
+    let sitemap = r#"<?xml version="1.0" encoding="UTF-8"?>
+<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+  <url><loc>https://example.com/docs?a=1&amp;b=2</loc></url>
+  <url><loc> <![CDATA[https://example.com/guide]]> </loc><lastmod>2024-01-01</lastmod></url>
+</urlset>"#;
+    assert_eq!(
+        parse_sitemap(sitemap),
+        (
+            false,
+            vec![
+                "https://example.com/docs?a=1&b=2".to_string(),
+                "https://example.com/guide".to_string()
+            ]
+        )
+    );
+
+    let index = "<sitemapindex><sitemap><loc>https://example.com/sitemap-1.xml</loc></sitemap></sitemapindex>";
+    assert!(parse_sitemap(index).0);
+
+    let robots_txt =
+        "User-agent: *\nDisallow: /private\nSitemap: https://example.com/sitemap_index.xml\n";
+    assert_eq!(
+        robots_txt_sitemaps(robots_txt),
+        vec![Url::parse("https://example.com/sitemap_index.xml").unwrap()]
+    );
+
+    let origin = Url::parse("https://example.com").unwrap().origin();
+    let readable = |sitemap: &str| is_readable_sitemap(&origin, &Url::parse(sitemap).unwrap());
+    assert!(readable("https://example.com/sitemap.xml"));
+    assert!(!readable("https://other.com/sitemap.xml"));
+    assert!(!readable("http://example.com/sitemap.xml"));
+    assert!(!readable("https://example.com/sitemap.xml.gz"));
+}
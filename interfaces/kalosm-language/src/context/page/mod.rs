@@ -1,4 +1,7 @@
-use super::document::Document;
+use super::document::{Document, DocumentSection, IntoDocument};
+use super::io::{html_document, HtmlMode};
+use super::HttpClient;
+use scraper::{Html, Selector};
 use url::Url;
 
 mod browse;
@@ -11,14 +14,117 @@ pub use node::*;
 mod page;
 pub use page::*;
 
-pub(crate) async fn get_article(url: Url) -> Result<Document, anyhow::Error> {
-    let html = reqwest::get(url.clone()).await?.text().await?;
-    extract_article(&html)
+/// A web page that is fetched and turned into a document with a chosen [`HtmlMode`].
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm_language::prelude::*;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let url = Url::parse("https://floneum.com").unwrap();
+///     let document = UrlDocument::new(url)
+///         .with_html_mode(HtmlMode::Markdown)
+///         .into_document()
+///         .await
+///         .unwrap();
+///     println!("{}", document.body());
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct UrlDocument {
+    url: Url,
+    html_mode: HtmlMode,
+}
+
+impl UrlDocument {
+    /// Create a new document for the page at the given URL.
+    pub fn new(url: Url) -> Self {
+        Self {
+            url,
+            html_mode: HtmlMode::default(),
+        }
+    }
+
+    /// Set how the text of the page is extracted. Defaults to [`HtmlMode::Article`].
+    pub fn with_html_mode(mut self, html_mode: HtmlMode) -> Self {
+        self.html_mode = html_mode;
+        self
+    }
+}
+
+impl From<Url> for UrlDocument {
+    fn from(url: Url) -> Self {
+        Self::new(url)
+    }
+}
+
+#[async_trait::async_trait]
+impl IntoDocument for UrlDocument {
+    async fn into_document(self) -> anyhow::Result<Document> {
+        get_article(self.url, self.html_mode).await
+    }
+}
+
+pub(crate) async fn get_article(url: Url, mode: HtmlMode) -> Result<Document, anyhow::Error> {
+    let html = HttpClient::global().get(&url).await?.into_text();
+    let mut document = html_document(&html, Some(&url), mode)?;
+    document.set_source(url);
+    Ok(document)
 }
 
 pub(crate) fn extract_article(html: &str) -> anyhow::Result<Document> {
     let cleaned =
         readability::extractor::extract(&mut html.as_bytes(), &Url::parse("https://example.com")?)
             .unwrap();
-    Ok(Document::from_parts(cleaned.title, cleaned.text))
+    let mut document = Document::from_parts(cleaned.title, cleaned.text);
+    let sections = DocumentSection::from_headings(document.body(), html_headings(html));
+    document.set_sections(sections);
+    Ok(document)
+}
+
+/// Find the level and text of every heading in an html document.
+fn html_headings(html: &str) -> Vec<(usize, String)> {
+    let selector = Selector::parse("h1, h2, h3, h4, h5, h6").unwrap();
+    Html::parse_document(html)
+        .select(&selector)
+        .filter_map(|heading| {
+            let level = heading.value().name()[1..].parse().ok()?;
+            let text = heading.text().collect::<String>();
+            Some((level, text.split_whitespace().collect::<Vec<_>>().join(" ")))
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn url_documents_can_be_read_as_markdown() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let page =
+        r#"<html><body><h1>Docs</h1><p>See <a href="/guide">the guide</a>.</p></body></html>"#;
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let mut request = [0; 1024];
+            let _ = stream.read(&mut request).await;
+            let response = format!(
+                "HTTP/1.1 200 OK\r\ncontent-type: text/html\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                page.len(),
+                page
+            );
+            let _ = stream.write_all(response.as_bytes()).await;
+        }
+    });
+
+    let url = Url::parse(&format!("http://{address}/docs")).unwrap();
+    let document = UrlDocument::new(url.clone())
+        .with_html_mode(HtmlMode::Markdown)
+        .into_document()
+        .await
+        .unwrap();
+    assert_eq!(
+        document.body(),
+        format!("# Docs\n\nSee [the guide](http://{address}/guide).")
+    );
 }
@@ -1,8 +1,10 @@
 use super::browse::Tab;
+use super::AnyNode;
 use super::{super::document::Document, NodeRef};
-use super::{extract_article, AnyNode};
-use crate::context::page::crawl::Crawler;
+use crate::context::io::{html_document, HtmlMode};
 pub use crate::context::page::crawl::CrawlingCallback;
+use crate::context::page::crawl::{CrawlConfig, Crawler};
+use crate::context::HttpClient;
 use image::DynamicImage;
 use once_cell::sync::OnceCell;
 use scraper::{Html, Selector};
@@ -115,9 +117,14 @@ impl Page {
 
     /// Extract the article from the page.
     pub async fn article(&self) -> anyhow::Result<Document> {
+        self.article_with_mode(HtmlMode::Article).await
+    }
+
+    /// Extract the text of the page with the given mode. [`HtmlMode::Markdown`] keeps the headings, lists, tables and code blocks of the page and resolves relative links against the URL of the page.
+    pub async fn article_with_mode(&self, mode: HtmlMode) -> anyhow::Result<Document> {
         match self {
-            Self::Static(page) => page.article().await,
-            Self::Dynamic(page) => page.article(),
+            Self::Static(page) => page.article_with_mode(mode).await,
+            Self::Dynamic(page) => page.article_with_mode(mode),
         }
     }
 
@@ -162,7 +169,17 @@ impl Page {
         mode: BrowserMode,
         visit: impl CrawlingCallback,
     ) -> anyhow::Result<()> {
-        Crawler::new(mode, visit).crawl(start).await
+        Self::crawl_with_config(start, mode, CrawlConfig::default(), visit).await
+    }
+
+    /// Start crawling from this page with limits on the depth and number of pages. If the config has a frontier file from an interrupted crawl, the crawl resumes where it stopped.
+    pub async fn crawl_with_config(
+        start: Url,
+        mode: BrowserMode,
+        config: CrawlConfig,
+        visit: impl CrawlingCallback,
+    ) -> anyhow::Result<()> {
+        Crawler::new(mode, config, visit)?.crawl(start).await
     }
 }
 
@@ -214,7 +231,7 @@ impl StaticPage {
             Some(html) => Ok(html),
             None => {
                 tokio::time::sleep_until(self.wait_until).await;
-                let html = reqwest::get(self.url.clone()).await?.text().await?;
+                let html = HttpClient::global().get(&self.url).await?.into_text();
                 let html = Html::parse_document(&html);
                 self.html.set(html).unwrap();
                 Ok(self.html.get().unwrap())
@@ -229,7 +246,15 @@ impl StaticPage {
 
     /// Extract the article from the page.
     pub async fn article(&self) -> anyhow::Result<Document> {
-        extract_article(&self.html_ref().await?.html())
+        self.article_with_mode(HtmlMode::Article).await
+    }
+
+    /// Extract the text of the page with the given mode.
+    pub async fn article_with_mode(&self, mode: HtmlMode) -> anyhow::Result<Document> {
+        let html = self.html_ref().await?.html();
+        let mut document = html_document(&html, Some(&self.url), mode)?;
+        document.set_source(self.url());
+        Ok(document)
     }
 
     /// Get the title of the page.
@@ -0,0 +1,343 @@
+use chrono::{DateTime, Utc};
+use url::Url;
+
+/// An item in an RSS, Atom or JSON feed.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct FeedItem {
+    /// The GUID or id of the item.
+    pub(crate) id: Option<String>,
+    pub(crate) title: Option<String>,
+    pub(crate) link: Option<Url>,
+    /// The full content of the item as HTML.
+    pub(crate) html: Option<String>,
+    /// The full content of the item as plain text.
+    pub(crate) text: Option<String>,
+    /// A short summary of the item as HTML.
+    pub(crate) summary: Option<String>,
+    pub(crate) author: Option<String>,
+    pub(crate) published: Option<DateTime<Utc>>,
+    pub(crate) categories: Vec<String>,
+}
+
+impl FeedItem {
+    /// Get the key used to deduplicate the item: the GUID if the feed has one, otherwise the link or title.
+    pub(crate) fn key(&self) -> Option<String> {
+        self.id
+            .clone()
+            .or_else(|| self.link.as_ref().map(|link| link.to_string()))
+            .or_else(|| self.title.clone())
+    }
+}
+
+/// Parse an RSS, Atom or JSON feed. Relative links are resolved against the URL of the feed.
+pub(crate) fn parse_feed(text: &str, feed_url: &Url) -> anyhow::Result<Vec<FeedItem>> {
+    let trimmed = text.trim_start();
+    if trimmed.starts_with('{') {
+        return parse_json_feed(trimmed, feed_url);
+    }
+    let atom = trimmed.find("<feed");
+    let rss = trimmed
+        .find("<rss")
+        .into_iter()
+        .chain(trimmed.find("<rdf:RDF"))
+        .min();
+    match (atom, rss) {
+        (Some(atom), Some(rss)) if atom < rss => parse_atom(trimmed, feed_url),
+        (Some(_), None) => parse_atom(trimmed, feed_url),
+        _ => parse_rss(trimmed, feed_url),
+    }
+}
+
+fn non_empty(text: impl Into<String>) -> Option<String> {
+    let text = text.into();
+    (!text.trim().is_empty()).then_some(text)
+}
+
+fn parse_rss(xml: &str, feed_url: &Url) -> anyhow::Result<Vec<FeedItem>> {
+    let channel = ::rss::Channel::read_from(xml.as_bytes())?;
+    Ok(channel
+        .items()
+        .iter()
+        .map(|item| FeedItem {
+            id: item.guid().map(|guid| guid.value().to_string()),
+            title: item.title().and_then(non_empty),
+            link: item.link().and_then(|link| feed_url.join(link).ok()),
+            html: item.content().and_then(non_empty),
+            text: None,
+            summary: item.description().and_then(non_empty),
+            author: item
+                .author()
+                .or_else(|| {
+                    let creators = item.dublin_core_ext()?.creators();
+                    creators.first().map(String::as_str)
+                })
+                .map(str::to_string),
+            published: item
+                .pub_date()
+                .and_then(|date| DateTime::parse_from_rfc2822(date).ok())
+                .map(|date| date.with_timezone(&Utc)),
+            categories: item
+                .categories()
+                .iter()
+                .map(|category| category.name().to_string())
+                .collect(),
+        })
+        .collect())
+}
+
+fn parse_atom(xml: &str, feed_url: &Url) -> anyhow::Result<Vec<FeedItem>> {
+    let feed = atom_syndication::Feed::read_from(xml.as_bytes())?;
+    Ok(feed
+        .entries()
+        .iter()
+        .map(|entry| {
+            // Prefer the alternate link, which points to the HTML version of the entry
+            let link = entry
+                .links()
+                .iter()
+                .find(|link| link.rel() == "alternate")
+                .or_else(|| entry.links().first())
+                .and_then(|link| feed_url.join(link.href()).ok());
+            let content = entry.content().and_then(|content| {
+                let value = non_empty(content.value()?)?;
+                Some((content.content_type().unwrap_or("text"), value))
+            });
+            let (html, text) = match content {
+                Some(("text", text)) => (None, Some(text)),
+                Some((_, html)) => (Some(html), None),
+                None => (None, None),
+            };
+            FeedItem {
+                id: non_empty(entry.id()),
+                title: non_empty(entry.title().value.as_str()),
+                link,
+                html,
+                text,
+                summary: entry
+                    .summary()
+                    .and_then(|summary| non_empty(summary.value.as_str())),
+                author: entry
+                    .authors()
+                    .first()
+                    .map(|author| author.name().to_string()),
+                published: Some(
+                    entry
+                        .published()
+                        .unwrap_or(entry.updated())
+                        .with_timezone(&Utc),
+                ),
+                categories: entry
+                    .categories()
+                    .iter()
+                    .map(|category| {
+                        category
+                            .label()
+                            .unwrap_or_else(|| category.term())
+                            .to_string()
+                    })
+                    .collect(),
+            }
+        })
+        .collect())
+}
+
+#[derive(serde::Deserialize)]
+struct JsonFeed {
+    #[serde(default)]
+    items: Vec<JsonFeedItem>,
+}
+
+#[derive(serde::Deserialize)]
+struct JsonFeedItem {
+    id: Option<serde_json::Value>,
+    url: Option<String>,
+    external_url: Option<String>,
+    title: Option<String>,
+    content_html: Option<String>,
+    content_text: Option<String>,
+    summary: Option<String>,
+    date_published: Option<String>,
+    date_modified: Option<String>,
+    author: Option<JsonFeedAuthor>,
+    #[serde(default)]
+    authors: Vec<JsonFeedAuthor>,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct JsonFeedAuthor {
+    name: Option<String>,
+}
+
+fn parse_json_feed(json: &str, feed_url: &Url) -> anyhow::Result<Vec<FeedItem>> {
+    let feed: JsonFeed = serde_json::from_str(json)?;
+    Ok(feed
+        .items
+        .into_iter()
+        .map(|item| FeedItem {
+            // Ids are strings in the spec, but some feeds use numbers
+            id: item.id.and_then(|id| match id {
+                serde_json::Value::String(id) => non_empty(id),
+                serde_json::Value::Null => None,
+                id => Some(id.to_string()),
+            }),
+            title: item.title.and_then(non_empty),
+            link: item
+                .url
+                .or(item.external_url)
+                .and_then(|link| feed_url.join(&link).ok()),
+            html: item.content_html.and_then(non_empty),
+            text: item.content_text.and_then(non_empty),
+            summary: item.summary.and_then(non_empty),
+            author: item
+                .authors
+                .into_iter()
+                .chain(item.author)
+                .find_map(|author| author.name),
+            published: item
+                .date_published
+                .or(item.date_modified)
+                .and_then(|date| DateTime::parse_from_rfc3339(&date).ok())
+                .map(|date| date.with_timezone(&Utc)),
+            categories: item.tags,
+        })
+        .collect())
+}
+
+#[test]
+fn parses_rss_feeds() {
+    let feed_url = Url::parse("https://example.com/feed").unwrap();
+
+    let rss = r#"<?xml version="1.0"?>
+<rss version="2.0"><channel><title>Example</title><link>https://example.com</link><description>News</description>
+<item><title>First</title><link>/posts/1</link><guid>post-1</guid><description>Summary</description>
+<pubDate>Tue, 10 Oct 2023 12:00:00 GMT</pubDate><category>rust</category></item>
+</channel></rss>"#;
+    let items = parse_feed(rss, &feed_url).unwrap();
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].key().as_deref(), Some("post-1"));
+    assert_eq!(
+        items[0].link.as_ref().map(Url::as_str),
+        Some("https://example.com/posts/1")
+    );
+    assert_eq!(items[0].summary.as_deref(), Some("Summary"));
+    assert_eq!(items[0].categories, vec!["rust".to_string()]);
+    assert_eq!(
+        items[0].published.unwrap().to_rfc3339(),
+        "2023-10-10T12:00:00+00:00"
+    );
+}
+
+#[test]
+fn parses_atom_feeds() {
+    let feed_url = Url::parse("https://example.com/feed").unwrap();
+
+    let atom = r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom"><title>Example</title><id>urn:example</id><updated>2023-10-11T08:00:00Z</updated>
+<entry><title>Second</title><id>urn:example:2</id>
+<link rel="related" href="https://other.example.com/2"/><link rel="alternate" href="https://example.com/posts/2"/>
+<updated>2023-10-11T08:00:00Z</updated><author><name>Ada</name></author><author><name>Grace</name></author>
+<category term="rust" label="Rust"/><category term="news"/>
+<summary>Short</summary><content type="html">&lt;p&gt;Hello&lt;/p&gt;</content></entry>
+<entry><title>Third</title><id>urn:example:3</id><link href="/posts/3"/>
+<published>2023-10-01T10:00:00+02:00</published><updated>2023-10-12T08:00:00Z</updated>
+<content type="text">Plain text</content></entry>
+<entry><title>Fourth</title><id> </id><link href="/posts/4"/><updated>2023-10-13T08:00:00Z</updated></entry>
+</feed>"#;
+    let items = parse_feed(atom, &feed_url).unwrap();
+    assert_eq!(items.len(), 3);
+
+    assert_eq!(items[0].key().as_deref(), Some("urn:example:2"));
+    assert_eq!(items[0].title.as_deref(), Some("Second"));
+    // The alternate link is preferred over the first link
+    assert_eq!(
+        items[0].link.as_ref().map(Url::as_str),
+        Some("https://example.com/posts/2")
+    );
+    assert_eq!(items[0].html.as_deref(), Some("<p>Hello</p>"));
+    assert_eq!(items[0].text, None);
+    assert_eq!(items[0].summary.as_deref(), Some("Short"));
+    assert_eq!(items[0].author.as_deref(), Some("Ada"));
+    assert_eq!(
+        items[0].categories,
+        vec!["Rust".to_string(), "news".to_string()]
+    );
+    // Entries without a published date use the updated date
+    assert_eq!(
+        items[0].published.unwrap().to_rfc3339(),
+        "2023-10-11T08:00:00+00:00"
+    );
+
+    assert_eq!(
+        items[1].link.as_ref().map(Url::as_str),
+        Some("https://example.com/posts/3")
+    );
+    assert_eq!(items[1].html, None);
+    assert_eq!(items[1].text.as_deref(), Some("Plain text"));
+    assert_eq!(items[1].author, None);
+    assert_eq!(
+        items[1].published.unwrap().to_rfc3339(),
+        "2023-10-01T08:00:00+00:00"
+    );
+
+    // Entries with a blank id are deduplicated by their link
+    assert_eq!(
+        items[2].key().as_deref(),
+        Some("https://example.com/posts/4")
+    );
+}
+
+#[test]
+fn parses_json_feeds() {
+    let feed_url = Url::parse("https://example.com/feed.json").unwrap();
+
+    let json = r#"
+    {
+        "version": "https://jsonfeed.org/version/1.1",
+        "title": "Example",
+        "items": [
+            { "id": 3, "url": "https://example.com/posts/3", "title": "Third", "content_text": "Plain text", "date_published": "2023-10-12T09:30:00Z", "tags": ["news"] },
+            { "id": "", "url": "/posts/4", "content_html": "<p>Fourth</p>", "summary": "Short", "date_modified": "2023-10-13T09:30:00+01:00", "author": { "name": "Ada" } },
+            { "id": null, "external_url": "https://other.example.com/5", "title": "Fifth", "authors": [{ "name": "Grace" }], "author": { "name": "Ada" } },
+            { "title": "Sixth", "date_published": "not a date" }
+        ]
+    }"#;
+    let items = parse_feed(json, &feed_url).unwrap();
+    assert_eq!(items.len(), 4);
+
+    // Numeric ids are used as strings
+    assert_eq!(items[0].key().as_deref(), Some("3"));
+    assert_eq!(items[0].title.as_deref(), Some("Third"));
+    assert_eq!(items[0].text.as_deref(), Some("Plain text"));
+    assert_eq!(items[0].html, None);
+    assert_eq!(items[0].categories, vec!["news".to_string()]);
+    assert_eq!(
+        items[0].published.unwrap().to_rfc3339(),
+        "2023-10-12T09:30:00+00:00"
+    );
+
+    // Empty ids fall back to the link, which is resolved against the feed
+    assert_eq!(
+        items[1].key().as_deref(),
+        Some("https://example.com/posts/4")
+    );
+    assert_eq!(items[1].html.as_deref(), Some("<p>Fourth</p>"));
+    assert_eq!(items[1].summary.as_deref(), Some("Short"));
+    assert_eq!(items[1].author.as_deref(), Some("Ada"));
+    assert_eq!(
+        items[1].published.unwrap().to_rfc3339(),
+        "2023-10-13T08:30:00+00:00"
+    );
+
+    // The external url is used when there is no url, and authors are preferred over the deprecated author
+    assert_eq!(
+        items[2].key().as_deref(),
+        Some("https://other.example.com/5")
+    );
+    assert_eq!(items[2].author.as_deref(), Some("Grace"));
+
+    // Items without an id or link are deduplicated by their title
+    assert_eq!(items[3].key().as_deref(), Some("Sixth"));
+    assert_eq!(items[3].published, None);
+}
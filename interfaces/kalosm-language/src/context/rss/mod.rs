@@ -0,0 +1,174 @@
+use std::path::{Path, PathBuf};
+
+use futures_util::StreamExt;
+use url::Url;
+
+use super::document::{Document, IntoDocuments};
+use super::io::{html_document, HtmlMode};
+use super::HttpClient;
+
+mod feed;
+pub(crate) use feed::*;
+mod subscribe;
+
+/// A RSS, Atom or JSON feed that can be used to add documents to a search index.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm_language::prelude::*;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let feed = RssFeed::new(
+///        url::Url::parse("https://www.nytimes.com/services/xml/rss/nyt/HomePage.xml").unwrap(),
+///     );
+///     let documents = feed.read_top_n(5).await.unwrap();
+///     println!("Documents: {:?}", documents);
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct RssFeed {
+    url: Url,
+    concurrency: usize,
+    state_file: Option<PathBuf>,
+    html_mode: HtmlMode,
+}
+
+impl From<Url> for RssFeed {
+    fn from(url: Url) -> Self {
+        Self::new(url)
+    }
+}
+
+#[async_trait::async_trait]
+impl IntoDocuments for RssFeed {
+    async fn into_documents(self) -> anyhow::Result<Vec<Document>> {
+        self.read_top_n(usize::MAX).await
+    }
+}
+
+impl RssFeed {
+    /// Create a new feed from the given URL.
+    pub fn new(url: Url) -> Self {
+        Self {
+            url,
+            concurrency: 4,
+            state_file: None,
+            html_mode: HtmlMode::default(),
+        }
+    }
+
+    /// Set the maximum number of item links that are fetched at the same time. Defaults to 4.
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Set the file that [`RssFeed::subscribe`] uses to remember which items were already read across restarts.
+    pub fn with_state_file(mut self, path: impl AsRef<Path>) -> Self {
+        self.state_file = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Set how the text of HTML items and linked pages is extracted. Defaults to [`HtmlMode::Article`].
+    pub fn with_html_mode(mut self, html_mode: HtmlMode) -> Self {
+        self.html_mode = html_mode;
+        self
+    }
+
+    /// Get the URL of the feed.
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    /// Read the top N documents from the feed.
+    pub async fn read_top_n(&self, top_n: usize) -> anyhow::Result<Vec<Document>> {
+        let text = HttpClient::global()
+            .get(&self.url)
+            .await?
+            .error_for_status()?
+            .into_text();
+        let items = parse_feed(&text, &self.url)?
+            .into_iter()
+            .take(top_n)
+            .collect();
+        item_documents(self, items).await.into_iter().collect()
+    }
+}
+
+/// Turn feed items into documents in order, fetching the links of items without content at most `feed.concurrency` at a time.
+pub(crate) async fn item_documents(
+    feed: &RssFeed,
+    items: Vec<FeedItem>,
+) -> Vec<anyhow::Result<Document>> {
+    futures_util::stream::iter(items)
+        .map(|item| item_document(item, feed))
+        .buffered(feed.concurrency)
+        .collect()
+        .await
+}
+
+async fn item_document(item: FeedItem, feed: &RssFeed) -> anyhow::Result<Document> {
+    let url = item.link.clone().unwrap_or_else(|| feed.url.clone());
+    let mode = feed.html_mode;
+    let (title, body) = match (&item.html, &item.text, &item.link) {
+        (Some(html), _, _) => extract_item(html, &url, mode)?,
+        (None, Some(text), _) => (String::new(), text.clone()),
+        (None, None, Some(link)) => {
+            let html = HttpClient::global()
+                .get(link)
+                .await?
+                .error_for_status()?
+                .into_text();
+            extract_item(&html, &url, mode)?
+        }
+        (None, None, None) => {
+            extract_item(item.summary.as_deref().unwrap_or_default(), &url, mode)?
+        }
+    };
+    let mut document = Document::from_parts(item.title.unwrap_or(title), body);
+    document.set_source(url);
+    if let Some(author) = item.author {
+        document.insert_metadata("author", author);
+    }
+    if let Some(published) = item.published {
+        document.insert_metadata("published", published.to_rfc3339());
+        document.set_created_at(published);
+    }
+    if !item.categories.is_empty() {
+        document.insert_metadata("categories", item.categories);
+    }
+    Ok(document)
+}
+
+/// Extract the title and text of an HTML page or snippet.
+fn extract_item(html: &str, url: &Url, mode: HtmlMode) -> anyhow::Result<(String, String)> {
+    match mode {
+        HtmlMode::Article => {
+            let article = readability::extractor::extract(&mut std::io::Cursor::new(html), url)?;
+            Ok((article.title, article.text))
+        }
+        HtmlMode::Markdown => {
+            let document = html_document(html, Some(url), mode)?;
+            Ok((document.title().to_string(), document.body().to_string()))
+        }
+    }
+}
+
+#[tokio::test]
+async fn feed_items_can_be_read_as_markdown() {
+    let feed = RssFeed::new(Url::parse("https://example.com/feed.xml").unwrap())
+        .with_html_mode(HtmlMode::Markdown);
+    let item = FeedItem {
+        title: Some("Release".to_string()),
+        link: Some(Url::parse("https://example.com/posts/release").unwrap()),
+        html: Some(r#"<h2>Changes</h2><ul><li><a href="notes">Notes</a></li></ul>"#.to_string()),
+        ..Default::default()
+    };
+    let document = item_document(item, &feed).await.unwrap();
+    assert_eq!(document.title(), "Release");
+    assert_eq!(
+        document.body(),
+        "## Changes\n\n- [Notes](https://example.com/posts/notes)"
+    );
+}
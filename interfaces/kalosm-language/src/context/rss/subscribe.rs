@@ -0,0 +1,263 @@
+use std::collections::{HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use futures_util::Stream;
+
+use super::{item_documents, parse_feed, RssFeed};
+use crate::context::document::Document;
+use crate::context::http::write_atomically;
+use crate::context::HttpClient;
+
+/// The maximum number of item keys remembered in the state file. Feeds only list their most recent items, so older keys are never seen again.
+const MAX_SEEN_ITEMS: usize = 10_000;
+
+/// The keys of the feed items that were already read, optionally persisted to a state file.
+#[derive(Debug, Default)]
+pub(crate) struct SeenItems {
+    path: Option<PathBuf>,
+    keys: HashSet<String>,
+    order: VecDeque<String>,
+}
+
+impl SeenItems {
+    /// Load the seen items from a state file. A missing file starts with no seen items. A file that can't be parsed is replaced the next time the items are saved, so every item in the feed is read again.
+    pub(crate) async fn load(path: Option<&Path>) -> anyhow::Result<Self> {
+        let mut seen = Self {
+            path: path.map(Path::to_path_buf),
+            ..Default::default()
+        };
+        if let Some(path) = path {
+            match tokio::fs::read_to_string(path).await {
+                Ok(json) => match serde_json::from_str::<Vec<String>>(&json) {
+                    Ok(keys) => {
+                        for key in keys {
+                            seen.insert(key);
+                        }
+                    }
+                    Err(err) => tracing::warn!(
+                        "Ignoring the unreadable feed state file {}: {}",
+                        path.display(),
+                        err
+                    ),
+                },
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Ok(seen)
+    }
+
+    pub(crate) fn contains(&self, key: &str) -> bool {
+        self.keys.contains(key)
+    }
+
+    pub(crate) fn insert(&mut self, key: String) {
+        if self.keys.insert(key.clone()) {
+            self.order.push_back(key);
+            while self.order.len() > MAX_SEEN_ITEMS {
+                if let Some(oldest) = self.order.pop_front() {
+                    self.keys.remove(&oldest);
+                }
+            }
+        }
+    }
+
+    /// Write the seen items to the state file, if there is one.
+    pub(crate) async fn save(&self) -> anyhow::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        write_atomically(path, &serde_json::to_string(&self.order)?).await
+    }
+}
+
+struct Subscription {
+    feed: RssFeed,
+    interval: Duration,
+    seen: Option<SeenItems>,
+    /// The documents that were read but not yielded yet with the keys of their items.
+    pending: VecDeque<(String, Document)>,
+    /// The key of the last yielded item, which is marked as seen when the next item is requested.
+    yielded: Option<String>,
+    polled: bool,
+}
+
+impl Subscription {
+    /// Read the items of the feed that were not seen before, oldest first.
+    async fn poll(&mut self) -> anyhow::Result<()> {
+        let seen = match &mut self.seen {
+            Some(seen) => seen,
+            None => self
+                .seen
+                .insert(SeenItems::load(self.feed.state_file.as_deref()).await?),
+        };
+        let text = HttpClient::global()
+            .get(&self.feed.url)
+            .await?
+            .error_for_status()?
+            .into_text();
+        let mut items = parse_feed(&text, &self.feed.url)?;
+        // Feeds list the newest items first
+        items.reverse();
+        let new_items = items
+            .into_iter()
+            .filter_map(|item| {
+                let key = item.key()?;
+                (!seen.contains(&key)).then_some((key, item))
+            })
+            .collect::<Vec<_>>();
+        if new_items.is_empty() {
+            return Ok(());
+        }
+
+        let (keys, items): (Vec<_>, Vec<_>) = new_items.into_iter().unzip();
+        let documents = item_documents(&self.feed, items).await;
+        for (key, document) in keys.into_iter().zip(documents) {
+            match document {
+                Ok(document) => self.pending.push_back((key, document)),
+                // The item is not marked as seen so it is retried on the next poll
+                Err(err) => tracing::warn!("Failed to read feed item {}: {}", key, err),
+            }
+        }
+        Ok(())
+    }
+
+    /// Mark the last yielded item as seen and persist it.
+    async fn mark_yielded(&mut self) -> anyhow::Result<()> {
+        if let (Some(key), Some(seen)) = (self.yielded.take(), &mut self.seen) {
+            seen.insert(key);
+            seen.save().await?;
+        }
+        Ok(())
+    }
+}
+
+impl RssFeed {
+    /// Subscribe to the feed, polling it at the given interval. The stream yields each new item once, oldest first.
+    ///
+    /// Items are deduplicated by their GUID, or by their link if the feed has no GUIDs. Set a [state file](RssFeed::with_state_file) to remember the items that were already read across restarts. An item is only marked as read when the stream is polled for the next item, so an item that was yielded right before the stream was dropped is yielded again after a restart instead of being lost.
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// use kalosm_language::prelude::*;
+    /// use std::time::Duration;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let feed = RssFeed::new(Url::parse("https://blog.rust-lang.org/feed.xml").unwrap())
+    ///         .with_state_file("./rust-blog.json");
+    ///     let mut documents = std::pin::pin!(feed.subscribe(Duration::from_secs(60 * 60)));
+    ///     while let Some(document) = documents.next().await {
+    ///         println!("New post: {}", document.title());
+    ///     }
+    /// }
+    /// ```
+    pub fn subscribe(&self, interval: Duration) -> impl Stream<Item = Document> + Send + 'static {
+        let subscription = Subscription {
+            feed: self.clone(),
+            interval,
+            seen: None,
+            pending: VecDeque::new(),
+            yielded: None,
+            polled: false,
+        };
+        futures_util::stream::unfold(subscription, |mut subscription| async move {
+            loop {
+                if let Err(err) = subscription.mark_yielded().await {
+                    tracing::warn!(
+                        "Failed to save the read items of feed {}: {}",
+                        subscription.feed.url,
+                        err
+                    );
+                }
+                if let Some((key, document)) = subscription.pending.pop_front() {
+                    subscription.yielded = Some(key);
+                    return Some((document, subscription));
+                }
+                if subscription.polled {
+                    tokio::time::sleep(subscription.interval).await;
+                }
+                subscription.polled = true;
+                if let Err(err) = subscription.poll().await {
+                    tracing::warn!("Failed to poll feed {}: {}", subscription.feed.url, err);
+                }
+            }
+        })
+    }
+}
+
+#[tokio::test]
+async fn seen_items_persist_across_restarts() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("state").join("feed.json");
+
+    let mut seen = SeenItems::load(Some(&path)).await.unwrap();
+    assert!(!seen.contains("post-1"));
+    seen.insert("post-1".to_string());
+    seen.insert("post-2".to_string());
+    seen.insert("post-1".to_string());
+    seen.save().await.unwrap();
+
+    let seen = SeenItems::load(Some(&path)).await.unwrap();
+    assert!(seen.contains("post-1"));
+    assert!(seen.contains("post-2"));
+    assert!(!seen.contains("post-3"));
+    assert_eq!(seen.order.len(), 2);
+
+    // A corrupted state file is treated as empty so the subscription doesn't stall
+    std::fs::write(&path, "[\"post-1\", ").unwrap();
+    let mut seen = SeenItems::load(Some(&path)).await.unwrap();
+    assert!(!seen.contains("post-1"));
+    seen.insert("post-3".to_string());
+    seen.save().await.unwrap();
+    assert!(SeenItems::load(Some(&path))
+        .await
+        .unwrap()
+        .contains("post-3"));
+}
+
+#[tokio::test]
+async fn subscription_marks_items_read_after_they_are_yielded() {
+    use futures_util::StreamExt;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    // Serve a feed with the newest item first from a local server
+    let feed = r#"{"items": [{"id": "2", "content_text": "Second"}, {"id": "1", "content_text": "First"}]}"#;
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let mut request = [0; 1024];
+            let _ = stream.read(&mut request).await;
+            let response = format!(
+                "HTTP/1.1 200 OK\r\ncontent-type: application/feed+json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                feed.len(),
+                feed
+            );
+            let _ = stream.write_all(response.as_bytes()).await;
+        }
+    });
+
+    let dir = tempfile::tempdir().unwrap();
+    let state = dir.path().join("feed.json");
+    let feed = RssFeed::new(url::Url::parse(&format!("http://{address}/feed.json")).unwrap())
+        .with_state_file(&state);
+    {
+        let mut documents = std::pin::pin!(feed.subscribe(Duration::from_secs(60)));
+        assert_eq!(documents.next().await.unwrap().body(), "First");
+        // The item is only marked as read once the next item is requested
+        assert!(!SeenItems::load(Some(&state)).await.unwrap().contains("1"));
+        assert_eq!(documents.next().await.unwrap().body(), "Second");
+        let seen = SeenItems::load(Some(&state)).await.unwrap();
+        assert!(seen.contains("1"));
+        assert!(!seen.contains("2"));
+    }
+
+    // The last item was never marked as read, so it is yielded again after a restart
+    let mut documents = std::pin::pin!(feed.subscribe(Duration::from_secs(60)));
+    assert_eq!(documents.next().await.unwrap().body(), "Second");
+}
@@ -0,0 +1,104 @@
+use url::Url;
+
+use super::{SearchProvider, WebSearchResult};
+use crate::context::HttpClient;
+
+/// A [`SearchProvider`] that searches with the [Brave Search](https://brave.com/search/api) API.
+#[derive(Debug, Clone)]
+pub struct BraveSearch {
+    api_key: String,
+}
+
+impl BraveSearch {
+    /// Create a new Brave search provider with an API key.
+    pub fn new(api_key: impl Into<String>) -> Self {
+        Self {
+            api_key: api_key.into(),
+        }
+    }
+
+    /// Create a new Brave search provider with the API key from the `BRAVE_API_KEY` environment variable.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let api_key = std::env::var("BRAVE_API_KEY")
+            .map_err(|_| anyhow::anyhow!("BRAVE_API_KEY environment variable not set"))?;
+        Ok(Self::new(api_key))
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct BraveResponse {
+    web: Option<BraveWebResults>,
+}
+
+#[derive(serde::Deserialize)]
+struct BraveWebResults {
+    #[serde(default)]
+    results: Vec<BraveResult>,
+}
+
+#[derive(serde::Deserialize)]
+struct BraveResult {
+    url: String,
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    description: String,
+}
+
+/// Brave highlights the query in descriptions with html tags. Remove them to get plain text.
+fn strip_tags(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut in_tag = false;
+    for c in text.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            _ if !in_tag => plain.push(c),
+            _ => {}
+        }
+    }
+    plain
+}
+
+#[async_trait::async_trait]
+impl SearchProvider for BraveSearch {
+    async fn search(&self, query: &str, top_n: usize) -> anyhow::Result<Vec<WebSearchResult>> {
+        let mut url = Url::parse("https://api.search.brave.com/res/v1/web/search")?;
+        url.query_pairs_mut()
+            .append_pair("q", query)
+            .append_pair("count", &top_n.min(20).to_string());
+        let response: BraveResponse = HttpClient::global()
+            .get_with_headers(
+                &url,
+                &[
+                    ("Accept", "application/json"),
+                    ("X-Subscription-Token", &self.api_key),
+                ],
+            )
+            .await?
+            .error_for_status()?
+            .json()?;
+        Ok(response
+            .web
+            .map(|web| web.results)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|result| {
+                Some(WebSearchResult {
+                    title: strip_tags(&result.title),
+                    url: Url::parse(&result.url).ok()?,
+                    snippet: strip_tags(&result.description),
+                })
+            })
+            .take(top_n)
+            .collect())
+    }
+}
+
+#[test]
+fn strips_highlight_tags() {
+    assert_eq!(
+        strip_tags("Learn <strong>Rust</strong> in a <em>week</em>"),
+        "Learn Rust in a week"
+    );
+}
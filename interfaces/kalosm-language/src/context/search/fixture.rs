@@ -0,0 +1,53 @@
+use std::collections::HashMap;
+
+use url::Url;
+
+use super::{SearchProvider, WebSearchResult};
+use crate::context::document::Document;
+
+/// A [`SearchProvider`] that returns fixed results without accessing the network. This is useful for tests and offline examples.
+///
+/// Results whose URL has no document set with [`FixtureSearch::with_document`] are loaded as a document with the title and snippet of the result.
+#[derive(Debug, Clone, Default)]
+pub struct FixtureSearch {
+    results: HashMap<String, Vec<WebSearchResult>>,
+    documents: HashMap<Url, Document>,
+}
+
+impl FixtureSearch {
+    /// Create a new fixture provider with no results.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the ranked results for a query.
+    pub fn with_results(mut self, query: impl Into<String>, results: Vec<WebSearchResult>) -> Self {
+        self.results.insert(query.into(), results);
+        self
+    }
+
+    /// Set the document that is loaded for a URL.
+    pub fn with_document(mut self, url: Url, document: Document) -> Self {
+        self.documents.insert(url, document);
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl SearchProvider for FixtureSearch {
+    async fn search(&self, query: &str, top_n: usize) -> anyhow::Result<Vec<WebSearchResult>> {
+        Ok(self
+            .results
+            .get(query)
+            .map(|results| results.iter().take(top_n).cloned().collect())
+            .unwrap_or_default())
+    }
+
+    async fn fetch(&self, result: &WebSearchResult) -> anyhow::Result<Document> {
+        Ok(self
+            .documents
+            .get(&result.url)
+            .cloned()
+            .unwrap_or_else(|| Document::from_parts(&result.title, &result.snippet)))
+    }
+}
@@ -1,13 +1,69 @@
 #![allow(missing_docs)]
 
-use rand::seq::SliceRandom;
+use std::sync::Arc;
+
 use url::Url;
 
 use super::{
     document::{Document, IntoDocuments},
+    io::HtmlMode,
     page::get_article,
 };
 
+mod brave;
+pub use brave::*;
+mod fixture;
+pub use fixture::*;
+mod searxng;
+pub use searxng::*;
+mod serper;
+pub use serper::*;
+
+/// A result from a web search.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WebSearchResult {
+    /// The title of the page.
+    pub title: String,
+    /// The URL of the page.
+    pub url: Url,
+    /// A short snippet of the page that matches the query.
+    pub snippet: String,
+}
+
+/// A web search engine that can be used with [`SearchQuery`] and [`crate::tool::WebSearchTool`].
+#[async_trait::async_trait]
+pub trait SearchProvider: Send + Sync {
+    /// Search for a query and return up to `top_n` results, with the most relevant result first.
+    async fn search(&self, query: &str, top_n: usize) -> anyhow::Result<Vec<WebSearchResult>>;
+
+    /// Load the document for a search result. By default this downloads the page and extracts the article.
+    async fn fetch(&self, result: &WebSearchResult) -> anyhow::Result<Document> {
+        get_article(result.url.clone(), HtmlMode::Article).await
+    }
+}
+
+#[async_trait::async_trait]
+impl<P: SearchProvider + ?Sized> SearchProvider for Arc<P> {
+    async fn search(&self, query: &str, top_n: usize) -> anyhow::Result<Vec<WebSearchResult>> {
+        (**self).search(query, top_n).await
+    }
+
+    async fn fetch(&self, result: &WebSearchResult) -> anyhow::Result<Document> {
+        (**self).fetch(result).await
+    }
+}
+
+#[async_trait::async_trait]
+impl<P: SearchProvider + ?Sized> SearchProvider for Box<P> {
+    async fn search(&self, query: &str, top_n: usize) -> anyhow::Result<Vec<WebSearchResult>> {
+        (**self).search(query, top_n).await
+    }
+
+    async fn fetch(&self, result: &WebSearchResult) -> anyhow::Result<Document> {
+        (**self).fetch(result).await
+    }
+}
+
 /// A search query that can be used to search for documents on the web.
 ///
 /// # Example
@@ -32,116 +88,77 @@ use super::{
 ///     println!("{}", text);
 /// }
 /// ```
-pub struct SearchQuery<'a> {
+///
+/// Other search engines can be used with [`SearchQuery::with_provider`]:
+/// ```rust, no_run
+/// use kalosm_language::prelude::*;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let provider = SearxngSearch::new(Url::parse("http://localhost:8080").unwrap());
+///     let results = SearchQuery::with_provider("rust async", provider, 5)
+///         .results()
+///         .await
+///         .unwrap();
+///     for result in results {
+///         println!("{}: {}", result.title, result.snippet);
+///     }
+/// }
+/// ```
+pub struct SearchQuery<'a, P = SerperSearch> {
     query: &'a str,
-    api_key: &'a str,
+    provider: P,
     top: usize,
 }
 
 impl<'a> SearchQuery<'a> {
-    /// Create a new search query.
+    /// Create a new search query that searches with [`SerperSearch`].
     pub fn new(query: &'a str, api_key: &'a str, top_n: usize) -> Self {
+        Self::with_provider(query, SerperSearch::new(api_key), top_n)
+    }
+}
+
+impl<'a, P: SearchProvider> SearchQuery<'a, P> {
+    /// Create a new search query that searches with the given provider.
+    pub fn with_provider(query: &'a str, provider: P, top_n: usize) -> Self {
         Self {
             query,
-            api_key,
+            provider,
             top: top_n,
         }
     }
+
+    /// Get the ranked search results without loading the pages.
+    pub async fn results(&self) -> anyhow::Result<Vec<WebSearchResult>> {
+        self.provider.search(self.query, self.top).await
+    }
 }
 
 #[async_trait::async_trait]
-impl IntoDocuments for SearchQuery<'_> {
+impl<P: SearchProvider> IntoDocuments for SearchQuery<'_, P> {
+    /// Load the page for each search result in ranked order. Each document has the `rank` (starting at 1) and `snippet` of the result in its metadata. Pages that fail to load are skipped.
     async fn into_documents(self) -> anyhow::Result<Vec<Document>> {
-        let mut search_results = search(self.api_key, self.query).await?;
+        let results = self.results().await?;
 
         let mut documents = vec![];
-        search_results.organic.shuffle(&mut rand::thread_rng());
-
-        for result in search_results.organic.into_iter().take(self.top) {
-            if let Some(link) = &result.link {
-                documents.push(get_article(Url::parse(link)?).await?);
-            }
+        for (index, result) in results.iter().enumerate() {
+            let mut document = match self.provider.fetch(result).await {
+                Ok(document) => document,
+                Err(err) => {
+                    tracing::warn!("Failed to load search result {}: {}", result.url, err);
+                    continue;
+                }
+            };
+            document.set_source(result.url.clone());
+            document.insert_metadata("rank", (index + 1) as f64);
+            document.insert_metadata("snippet", result.snippet.clone());
+            documents.push(document);
         }
 
         Ok(documents)
     }
 }
 
-#[derive(serde::Serialize, serde::Deserialize, Debug)]
-pub struct SearchResult {
-    pub knowledge_graph: Option<KnowledgeGraph>,
-    #[serde(default)]
-    pub organic: Vec<Organic>,
-    #[serde(default)]
-    pub people_also_ask: Vec<PeopleAlsoAsk>,
-    #[serde(default)]
-    pub related_searches: Vec<RelatedSearches>,
-}
-
-#[derive(serde::Serialize, serde::Deserialize, Debug)]
-pub struct KnowledgeGraph {
-    pub title: String,
-    pub type_: String,
-    pub website: String,
-    pub image_url: String,
-    pub description: String,
-    pub description_source: String,
-    pub description_link: String,
-    #[serde(default)]
-    pub attributes: Vec<Attributes>,
-}
-
-#[derive(serde::Serialize, serde::Deserialize, Debug)]
-pub struct Attributes {
-    pub key: String,
-    pub value: String,
-}
-
-#[derive(serde::Serialize, serde::Deserialize, Debug)]
-pub struct Organic {
-    pub title: Option<String>,
-    pub link: Option<String>,
-    #[serde(default)]
-    pub snippet: String,
-    #[serde(default)]
-    pub sitelinks: Vec<Sitelinks>,
-    pub position: u32,
-}
-
-#[derive(serde::Serialize, serde::Deserialize, Debug)]
-pub struct Sitelinks {
-    pub title: String,
-    pub link: String,
-}
-
-#[derive(serde::Serialize, serde::Deserialize, Debug)]
-pub struct PeopleAlsoAsk {
-    pub question: String,
-    pub snippet: String,
-    pub title: String,
-    pub link: String,
-}
-
-#[derive(serde::Serialize, serde::Deserialize, Debug)]
-pub struct RelatedSearches {
-    pub query: String,
-}
-
-pub async fn search(api_key: &str, query: &str) -> Result<SearchResult, reqwest::Error> {
-    let url = Url::parse("https://google.serper.dev/search").unwrap();
-    let client = reqwest::Client::new();
-    let res = client
-        .post(url)
-        .header("X-API-KEY", api_key)
-        .json(&serde_json::json!({
-            "q": query
-        }))
-        .send()
-        .await
-        .unwrap();
-    res.json().await
-}
-
 pub fn prompt_search_query(question: &str) -> String {
     let date = chrono::Local::now().format("%Y-%m-%d");
 
@@ -149,9 +166,36 @@ pub fn prompt_search_query(question: &str) -> String {
 }
 
 #[tokio::test]
-async fn search_result() {
-    if let Some(key) = option_env!("SERPER_API_KEY") {
-        let result = search(key, "apple inc").await.unwrap();
-        println!("{:#?}", result);
-    }
+async fn fixture_search_keeps_ranking() {
+    let first = Url::parse("https://example.com/first").unwrap();
+    let second = Url::parse("https://example.com/second").unwrap();
+    let provider = FixtureSearch::new()
+        .with_results(
+            "kalosm",
+            vec![
+                WebSearchResult {
+                    title: "First".to_string(),
+                    url: first.clone(),
+                    snippet: "The best match".to_string(),
+                },
+                WebSearchResult {
+                    title: "Second".to_string(),
+                    url: second,
+                    snippet: "Another match".to_string(),
+                },
+            ],
+        )
+        .with_document(first, Document::from_parts("First", "Full page text"));
+
+    let documents = SearchQuery::with_provider("kalosm", provider, 5)
+        .into_documents()
+        .await
+        .unwrap();
+    assert_eq!(documents.len(), 2);
+    assert_eq!(documents[0].body(), "Full page text");
+    assert_eq!(documents[1].body(), "Another match");
+    assert_eq!(
+        documents[1].metadata().get("rank"),
+        Some(&crate::vector_db::MetadataValue::Number(2.))
+    );
 }
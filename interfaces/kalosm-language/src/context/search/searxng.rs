@@ -0,0 +1,92 @@
+use url::Url;
+
+use super::{SearchProvider, WebSearchResult};
+use crate::context::HttpClient;
+
+/// A [`SearchProvider`] that searches with a [SearxNG](https://docs.searxng.org) instance. The instance must have the json output format enabled.
+#[derive(Debug, Clone)]
+pub struct SearxngSearch {
+    base_url: Url,
+}
+
+impl SearxngSearch {
+    /// Create a new SearxNG search provider for the instance at the given URL. The instance can be hosted under a path, like `http://localhost/searx`.
+    pub fn new(mut base_url: Url) -> Self {
+        // Without a trailing slash, joining a path would replace the last segment of the base URL
+        if !base_url.path().ends_with('/') {
+            let path = format!("{}/", base_url.path());
+            base_url.set_path(&path);
+        }
+        Self { base_url }
+    }
+
+    /// Get the URL of the JSON search results for a query.
+    fn search_url(&self, query: &str) -> anyhow::Result<Url> {
+        let mut url = self.base_url.join("search")?;
+        url.query_pairs_mut()
+            .append_pair("q", query)
+            .append_pair("format", "json");
+        Ok(url)
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct SearxngResponse {
+    #[serde(default)]
+    results: Vec<SearxngResult>,
+}
+
+#[derive(serde::Deserialize)]
+struct SearxngResult {
+    url: String,
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    content: String,
+}
+
+#[async_trait::async_trait]
+impl SearchProvider for SearxngSearch {
+    async fn search(&self, query: &str, top_n: usize) -> anyhow::Result<Vec<WebSearchResult>> {
+        let url = self.search_url(query)?;
+        let response: SearxngResponse = HttpClient::global()
+            .get(&url)
+            .await?
+            .error_for_status()?
+            .json()?;
+        Ok(response
+            .results
+            .into_iter()
+            .filter_map(|result| {
+                Some(WebSearchResult {
+                    title: result.title,
+                    url: Url::parse(&result.url).ok()?,
+                    snippet: result.content,
+                })
+            })
+            .take(top_n)
+            .collect())
+    }
+}
+
+#[test]
+fn search_url_keeps_the_path_of_the_instance() {
+    let search_url = |base_url: &str| {
+        SearxngSearch::new(Url::parse(base_url).unwrap())
+            .search_url("rust")
+            .unwrap()
+            .to_string()
+    };
+    assert_eq!(
+        search_url("http://localhost:8080"),
+        "http://localhost:8080/search?q=rust&format=json"
+    );
+    assert_eq!(
+        search_url("http://host/searx"),
+        "http://host/searx/search?q=rust&format=json"
+    );
+    assert_eq!(
+        search_url("http://host/searx/"),
+        "http://host/searx/search?q=rust&format=json"
+    );
+}
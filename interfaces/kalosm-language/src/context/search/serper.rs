@@ -0,0 +1,136 @@
+#![allow(missing_docs)]
+
+use url::Url;
+
+use super::{SearchProvider, WebSearchResult};
+use crate::context::HttpClient;
+
+/// A [`SearchProvider`] that searches Google with the [Serper](https://serper.dev) API.
+#[derive(Debug, Clone, Default)]
+pub struct SerperSearch {
+    api_key: Option<String>,
+}
+
+impl SerperSearch {
+    /// Create a new Serper search provider with an API key.
+    pub fn new(api_key: impl Into<String>) -> Self {
+        Self {
+            api_key: Some(api_key.into()),
+        }
+    }
+
+    /// Create a new Serper search provider that reads the API key from the `SERPER_API_KEY` environment variable when searching.
+    pub fn from_env() -> Self {
+        Self { api_key: None }
+    }
+
+    fn api_key(&self) -> anyhow::Result<String> {
+        match &self.api_key {
+            Some(api_key) => Ok(api_key.clone()),
+            None => std::env::var("SERPER_API_KEY")
+                .map_err(|_| anyhow::anyhow!("SERPER_API_KEY environment variable not set")),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl SearchProvider for SerperSearch {
+    async fn search(&self, query: &str, top_n: usize) -> anyhow::Result<Vec<WebSearchResult>> {
+        let mut organic = search(&self.api_key()?, query).await?.organic;
+        organic.sort_by_key(|result| result.position);
+        Ok(organic
+            .into_iter()
+            .filter_map(|result| {
+                Some(WebSearchResult {
+                    title: result.title.unwrap_or_default(),
+                    url: Url::parse(result.link.as_ref()?).ok()?,
+                    snippet: result.snippet,
+                })
+            })
+            .take(top_n)
+            .collect())
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct SearchResult {
+    pub knowledge_graph: Option<KnowledgeGraph>,
+    #[serde(default)]
+    pub organic: Vec<Organic>,
+    #[serde(default)]
+    pub people_also_ask: Vec<PeopleAlsoAsk>,
+    #[serde(default)]
+    pub related_searches: Vec<RelatedSearches>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct KnowledgeGraph {
+    pub title: String,
+    pub type_: String,
+    pub website: String,
+    pub image_url: String,
+    pub description: String,
+    pub description_source: String,
+    pub description_link: String,
+    #[serde(default)]
+    pub attributes: Vec<Attributes>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct Attributes {
+    pub key: String,
+    pub value: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct Organic {
+    pub title: Option<String>,
+    pub link: Option<String>,
+    #[serde(default)]
+    pub snippet: String,
+    #[serde(default)]
+    pub sitelinks: Vec<Sitelinks>,
+    pub position: u32,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct Sitelinks {
+    pub title: String,
+    pub link: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct PeopleAlsoAsk {
+    pub question: String,
+    pub snippet: String,
+    pub title: String,
+    pub link: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct RelatedSearches {
+    pub query: String,
+}
+
+pub async fn search(api_key: &str, query: &str) -> anyhow::Result<SearchResult> {
+    let url = Url::parse("https://google.serper.dev/search")?;
+    HttpClient::global()
+        .post_json(
+            &url,
+            &[("X-API-KEY", api_key)],
+            &serde_json::json!({
+                "q": query
+            }),
+        )
+        .await?
+        .error_for_status()?
+        .json()
+}
+
+#[tokio::test]
+async fn search_result() {
+    if let Some(key) = option_env!("SERPER_API_KEY") {
+        let result = search(key, "apple inc").await.unwrap();
+        println!("{:#?}", result);
+    }
+}
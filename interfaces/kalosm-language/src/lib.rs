@@ -18,6 +18,7 @@ pub mod chat;
 pub mod context;
 pub mod search;
 pub mod task;
+pub mod template;
 pub mod tool;
 pub mod vector_db;
 
@@ -33,6 +34,7 @@ pub mod prelude {
     pub use crate::context::*;
     pub use crate::search::*;
     pub use crate::task::*;
+    pub use crate::template::*;
     pub use crate::tool::*;
     pub use crate::vector_db::*;
     pub use futures_util::StreamExt as _;
@@ -40,6 +42,8 @@ pub mod prelude {
     pub use kalosm_llama::{Llama, LlamaBuilder, LlamaSession, LlamaSource};
     pub use kalosm_sample::*;
     pub use kalosm_streams::text_stream::*;
-    pub use rbert::{Bert, BertBuilder, BertSource, BertSpace};
+    pub use rbert::{
+        Bert, BertBuilder, BertCrossEncoder, BertCrossEncoderBuilder, BertSource, BertSpace,
+    };
     pub use rphi::{Phi, PhiBuilder, PhiSource};
 }
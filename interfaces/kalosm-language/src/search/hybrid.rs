@@ -0,0 +1,483 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use kalosm_language_model::Embedder;
+use slab::Slab;
+
+use super::{Bm25Index, ChunkStrategy, Chunker};
+use crate::context::Document;
+use crate::vector_db::{EmbeddingId, VectorDB};
+
+/// How the rankings from the keyword index and the vector database are combined in a [`HybridIndex`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fusion {
+    /// [Reciprocal rank fusion](https://plg.uwaterloo.ca/~gvcormac/cormacksigir09-rrf.pdf). Each chunk is scored with the sum of `1 / (k + rank)` over both rankings. This only uses the ranks, so it doesn't depend on the scale of the scores.
+    ReciprocalRank {
+        /// The constant added to each rank. Larger values make lower ranked results matter more. Defaults to 60.
+        k: f32,
+    },
+    /// A weighted sum of the scores from each ranking after normalizing them to the range 0 to 1.
+    Weighted {
+        /// The weight of the BM25 score.
+        keyword_weight: f32,
+        /// The weight of the vector similarity.
+        vector_weight: f32,
+    },
+}
+
+impl Default for Fusion {
+    fn default() -> Self {
+        Self::ReciprocalRank { k: 60. }
+    }
+}
+
+/// A document in a [`HybridIndex`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct HybridDocumentId(pub usize);
+
+struct IndexedDocument {
+    document: Document,
+    chunks: Vec<usize>,
+}
+
+struct IndexedChunk {
+    document: HybridDocumentId,
+    byte_range: Range<usize>,
+    embedding_ids: Vec<EmbeddingId>,
+}
+
+/// A search index that combines keyword search with [BM25](Bm25Index) and vector search with a [`VectorDB`].
+///
+/// Embeddings capture the meaning of text, but they handle exact identifiers like part numbers poorly. Keyword search handles exact identifiers well, but misses paraphrases. The hybrid index searches both and fuses the rankings with a [`Fusion`] strategy.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm_language::prelude::*;
+///
+/// #[tokio::main]
+/// async fn main() -> anyhow::Result<()> {
+///     let mut index = HybridIndex::builder(Bert::builder().build()?).build()?;
+///     index
+///         .add_document(Document::from_parts(
+///             "Pumps",
+///             "The XJ-4500 pump is rated for 40 psi.",
+///         ))
+///         .await?;
+///     for result in index.search("What pressure can the XJ-4500 handle?", 5).await? {
+///         println!("{}: {}", result.score, result.text());
+///     }
+///     Ok(())
+/// }
+/// ```
+pub struct HybridIndex<E: Embedder, K: Chunker = ChunkStrategy> {
+    embedder: E,
+    chunker: K,
+    fusion: Fusion,
+    candidate_count: usize,
+    vector_db: VectorDB<E::VectorSpace>,
+    keyword_index: Bm25Index,
+    documents: Slab<IndexedDocument>,
+    chunks: Slab<IndexedChunk>,
+    embedding_chunks: HashMap<EmbeddingId, usize>,
+}
+
+impl<E: Embedder> HybridIndex<E> {
+    /// Create a builder for a hybrid index with the given embedding model.
+    pub fn builder(embedder: E) -> HybridIndexBuilder<E> {
+        HybridIndexBuilder {
+            embedder,
+            chunker: ChunkStrategy::default(),
+            fusion: Fusion::default(),
+            candidate_count: 50,
+            vector_db: None,
+        }
+    }
+}
+
+impl<E: Embedder, K: Chunker> HybridIndex<E, K> {
+    /// Get the embedding model.
+    pub fn embedder(&self) -> &E {
+        &self.embedder
+    }
+
+    /// Get the vector database.
+    pub fn vector_db(&self) -> &VectorDB<E::VectorSpace> {
+        &self.vector_db
+    }
+
+    /// Get the keyword index.
+    pub fn keyword_index(&self) -> &Bm25Index {
+        &self.keyword_index
+    }
+
+    /// Get a document in the index.
+    pub fn document(&self, id: HybridDocumentId) -> Option<&Document> {
+        self.documents.get(id.0).map(|document| &document.document)
+    }
+
+    /// Chunk, embed and index a document.
+    pub async fn add_document(&mut self, document: Document) -> anyhow::Result<HybridDocumentId> {
+        let chunks = self.chunker.chunk(&document, &self.embedder).await?;
+        let document_id = HybridDocumentId(self.documents.vacant_key());
+
+        let mut transaction = self.vector_db.transaction()?;
+        let chunks = chunks
+            .into_iter()
+            .map(|chunk| {
+                Ok((
+                    chunk.byte_range,
+                    transaction.add_embeddings(chunk.embeddings)?,
+                ))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        transaction.commit()?;
+
+        let mut chunk_ids = Vec::with_capacity(chunks.len());
+        for (byte_range, embedding_ids) in chunks {
+            let chunk_id = self.chunks.vacant_key();
+            for id in &embedding_ids {
+                self.embedding_chunks.insert(*id, chunk_id);
+            }
+            self.keyword_index
+                .insert(chunk_id, &document.body()[byte_range.clone()]);
+            self.chunks.insert(IndexedChunk {
+                document: document_id,
+                byte_range,
+                embedding_ids,
+            });
+            chunk_ids.push(chunk_id);
+        }
+
+        self.documents.insert(IndexedDocument {
+            document,
+            chunks: chunk_ids,
+        });
+
+        Ok(document_id)
+    }
+
+    /// Chunk, embed and index a batch of documents.
+    pub async fn add_documents(
+        &mut self,
+        documents: impl IntoIterator<Item = Document>,
+    ) -> anyhow::Result<Vec<HybridDocumentId>> {
+        let mut ids = Vec::new();
+        for document in documents {
+            ids.push(self.add_document(document).await?);
+        }
+        Ok(ids)
+    }
+
+    /// Remove a document from the index.
+    pub fn remove_document(&mut self, id: HybridDocumentId) -> anyhow::Result<Option<Document>> {
+        let Some(document) = self.documents.get(id.0) else {
+            return Ok(None);
+        };
+
+        let mut transaction = self.vector_db.transaction()?;
+        for chunk_id in &document.chunks {
+            for embedding_id in &self.chunks[*chunk_id].embedding_ids {
+                transaction.remove_embedding(*embedding_id)?;
+            }
+        }
+        transaction.commit()?;
+
+        let document = self.documents.remove(id.0);
+        for chunk_id in &document.chunks {
+            let chunk = self.chunks.remove(*chunk_id);
+            self.keyword_index.remove(*chunk_id);
+            for embedding_id in chunk.embedding_ids {
+                self.embedding_chunks.remove(&embedding_id);
+            }
+        }
+
+        Ok(Some(document.document))
+    }
+
+    /// Search for the k chunks that best match the query with both keyword and vector search.
+    pub async fn search(
+        &self,
+        query: &str,
+        k: usize,
+    ) -> anyhow::Result<Vec<HybridSearchResult<'_>>> {
+        if self.chunks.is_empty() {
+            return Ok(Vec::new());
+        }
+        let candidate_count = self.candidate_count.max(k);
+
+        let keyword_ranking = self.keyword_index.search(query, candidate_count);
+
+        let embedding = self.embedder.embed(query).await?;
+        let mut vector_ranking: Vec<(usize, f32)> = Vec::new();
+        for result in self.vector_db.get_closest(embedding, candidate_count)? {
+            let Some(&chunk_id) = self.embedding_chunks.get(&result.value) else {
+                continue;
+            };
+            // Chunks with multiple embeddings are ranked by their best embedding
+            if !vector_ranking.iter().any(|(id, _)| *id == chunk_id) {
+                vector_ranking.push((chunk_id, result.similarity));
+            }
+        }
+
+        let mut results: HashMap<usize, HybridSearchResult> = HashMap::new();
+        let keyword_scores = self.fusion.scores(&keyword_ranking, FusionSource::Keyword);
+        let vector_scores = self.fusion.scores(&vector_ranking, FusionSource::Vector);
+        for (source, ranking, scores) in [
+            (FusionSource::Keyword, &keyword_ranking, keyword_scores),
+            (FusionSource::Vector, &vector_ranking, vector_scores),
+        ] {
+            for (rank, ((chunk_id, _), score)) in ranking.iter().zip(scores).enumerate() {
+                let chunk = &self.chunks[*chunk_id];
+                let result = results
+                    .entry(*chunk_id)
+                    .or_insert_with(|| HybridSearchResult {
+                        document_id: chunk.document,
+                        document: &self.documents[chunk.document.0].document,
+                        byte_range: chunk.byte_range.clone(),
+                        score: 0.,
+                        keyword_rank: None,
+                        vector_rank: None,
+                    });
+                result.score += score;
+                match source {
+                    FusionSource::Keyword => result.keyword_rank = Some(rank),
+                    FusionSource::Vector => result.vector_rank = Some(rank),
+                }
+            }
+        }
+
+        let mut results = results.into_values().collect::<Vec<_>>();
+        results.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then(a.document_id.cmp(&b.document_id))
+                .then(a.byte_range.start.cmp(&b.byte_range.start))
+        });
+        results.truncate(k);
+        Ok(results)
+    }
+}
+
+#[derive(Clone, Copy)]
+enum FusionSource {
+    Keyword,
+    Vector,
+}
+
+impl Fusion {
+    /// Score each entry in a ranking that is sorted from best to worst.
+    fn scores(&self, ranking: &[(usize, f32)], source: FusionSource) -> Vec<f32> {
+        match *self {
+            Fusion::ReciprocalRank { k } => (0..ranking.len())
+                .map(|rank| 1. / (k + rank as f32 + 1.))
+                .collect(),
+            Fusion::Weighted {
+                keyword_weight,
+                vector_weight,
+            } => {
+                let weight = match source {
+                    FusionSource::Keyword => keyword_weight,
+                    FusionSource::Vector => vector_weight,
+                };
+                let max = ranking
+                    .iter()
+                    .map(|(_, score)| *score)
+                    .fold(f32::NEG_INFINITY, f32::max);
+                let min = ranking
+                    .iter()
+                    .map(|(_, score)| *score)
+                    .fold(f32::INFINITY, f32::min);
+                ranking
+                    .iter()
+                    .map(|(_, score)| {
+                        let normalized = if max > min {
+                            (score - min) / (max - min)
+                        } else {
+                            1.
+                        };
+                        weight * normalized
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+/// A chunk of a document found by a [`HybridIndex`].
+#[derive(Debug, Clone)]
+pub struct HybridSearchResult<'a> {
+    /// The id of the document the chunk is from.
+    pub document_id: HybridDocumentId,
+    /// The document the chunk is from.
+    pub document: &'a Document,
+    /// The byte range of the chunk in the body of the document.
+    pub byte_range: Range<usize>,
+    /// The fused score of the chunk. Higher scores are better matches.
+    pub score: f32,
+    /// The rank of the chunk in the keyword search results, if it was found by keyword search.
+    pub keyword_rank: Option<usize>,
+    /// The rank of the chunk in the vector search results, if it was found by vector search.
+    pub vector_rank: Option<usize>,
+}
+
+impl<'a> HybridSearchResult<'a> {
+    /// The text of the chunk.
+    pub fn text(&self) -> &'a str {
+        &self.document.body()[self.byte_range.clone()]
+    }
+}
+
+/// A builder for a [`HybridIndex`].
+pub struct HybridIndexBuilder<E: Embedder, K: Chunker = ChunkStrategy> {
+    embedder: E,
+    chunker: K,
+    fusion: Fusion,
+    candidate_count: usize,
+    vector_db: Option<VectorDB<E::VectorSpace>>,
+}
+
+impl<E: Embedder, K: Chunker> HybridIndexBuilder<E, K> {
+    /// Set the chunking strategy for documents.
+    pub fn with_chunker<K2: Chunker>(self, chunker: K2) -> HybridIndexBuilder<E, K2> {
+        HybridIndexBuilder {
+            embedder: self.embedder,
+            chunker,
+            fusion: self.fusion,
+            candidate_count: self.candidate_count,
+            vector_db: self.vector_db,
+        }
+    }
+
+    /// Set how the keyword and vector rankings are combined. Defaults to [`Fusion::ReciprocalRank`].
+    pub fn with_fusion(mut self, fusion: Fusion) -> Self {
+        self.fusion = fusion;
+        self
+    }
+
+    /// Set the number of results to take from each ranking before fusing them. Defaults to 50.
+    pub fn with_candidate_count(mut self, candidate_count: usize) -> Self {
+        self.candidate_count = candidate_count;
+        self
+    }
+
+    /// Set the vector database to store embeddings in. The vector database should be empty. Defaults to a temporary vector database.
+    pub fn with_vector_db(mut self, vector_db: VectorDB<E::VectorSpace>) -> Self {
+        self.vector_db = Some(vector_db);
+        self
+    }
+
+    /// Build the hybrid index.
+    pub fn build(self) -> anyhow::Result<HybridIndex<E, K>> {
+        let vector_db = match self.vector_db {
+            Some(vector_db) => vector_db,
+            None => VectorDB::new()?,
+        };
+        Ok(HybridIndex {
+            embedder: self.embedder,
+            chunker: self.chunker,
+            fusion: self.fusion,
+            candidate_count: self.candidate_count,
+            vector_db,
+            keyword_index: Bm25Index::new(),
+            documents: Slab::new(),
+            chunks: Slab::new(),
+            embedding_chunks: HashMap::new(),
+        })
+    }
+}
+
+#[test]
+fn fusion_scores_rankings() {
+    let ranking = [(0, 4.), (1, 2.), (2, 0.)];
+    assert_eq!(
+        Fusion::default().scores(&ranking, FusionSource::Keyword),
+        vec![1. / 61., 1. / 62., 1. / 63.]
+    );
+
+    let weighted = Fusion::Weighted {
+        keyword_weight: 0.3,
+        vector_weight: 0.7,
+    };
+    let scores = weighted.scores(&ranking, FusionSource::Keyword);
+    assert!((scores[0] - 0.3).abs() < 1e-6);
+    assert!((scores[1] - 0.15).abs() < 1e-6);
+    assert_eq!(scores[2], 0.);
+    // A ranking with one score can't be normalized, so it gets the full weight
+    assert_eq!(
+        weighted.scores(&[(3, 0.2)], FusionSource::Vector),
+        vec![0.7]
+    );
+}
+
+#[tokio::test]
+async fn hybrid_index_finds_and_removes_chunks() {
+    use kalosm_language_model::{Embedding, UnknownVectorSpace};
+
+    /// Embeds text as the number of times it mentions water and pumps.
+    struct Mentions;
+
+    #[async_trait::async_trait]
+    impl Embedder for Mentions {
+        type VectorSpace = UnknownVectorSpace;
+
+        async fn embed(&self, input: &str) -> anyhow::Result<Embedding<UnknownVectorSpace>> {
+            let input = input.to_lowercase();
+            Ok(vec![
+                input.matches("water").count() as f32,
+                input.matches("pump").count() as f32,
+            ]
+            .into())
+        }
+    }
+
+    let mut index = HybridIndex::builder(Mentions)
+        .with_chunker(ChunkStrategy::Paragraph {
+            paragraph_count: 1,
+            overlap: 0,
+        })
+        .build()
+        .unwrap();
+    let pumps = index
+        .add_document(Document::from_parts(
+            "Pumps",
+            "The XJ-4500 pump is rated for 40 psi.\nPumps move water uphill.",
+        ))
+        .await
+        .unwrap();
+    let rivers = index
+        .add_document(Document::from_parts(
+            "Rivers",
+            "Rivers carry water to the sea.",
+        ))
+        .await
+        .unwrap();
+    assert_eq!(index.keyword_index().len(), 3);
+
+    // Found by both keyword and vector search
+    let results = index.search("XJ-4500", 3).await.unwrap();
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0].document_id, pumps);
+    assert_eq!(results[0].byte_range, 0..37);
+    assert_eq!(results[0].text(), "The XJ-4500 pump is rated for 40 psi.");
+    assert_eq!(results[0].keyword_rank, Some(0));
+    assert!(results[0].vector_rank.is_some());
+    assert!(results[1..]
+        .iter()
+        .all(|result| result.keyword_rank.is_none()));
+
+    let results = index.search("Pumps move water uphill", 1).await.unwrap();
+    assert_eq!(results[0].byte_range, 38..62);
+    assert_eq!(results[0].text(), "Pumps move water uphill.");
+
+    let removed = index.remove_document(pumps).unwrap().unwrap();
+    assert_eq!(removed.title(), "Pumps");
+    assert!(index.document(pumps).is_none());
+    assert!(index.remove_document(pumps).unwrap().is_none());
+    assert_eq!(index.keyword_index().len(), 1);
+    assert_eq!(index.vector_db().embedding_ids().len(), 1);
+
+    let results = index.search("XJ-4500 pump water", 3).await.unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].document_id, rivers);
+    assert_eq!(results[0].keyword_rank, Some(0));
+}
@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+/// Split text into lowercase search terms.
+///
+/// Words are split on whitespace and trimmed of surrounding punctuation. Words with punctuation inside of them (like part numbers such as `XJ-4500`) are kept whole and also split into their alphanumeric parts, so both exact identifiers and their parts can be matched.
+pub fn keyword_terms(text: &str) -> Vec<String> {
+    let mut terms = Vec::new();
+    for word in text.split_whitespace() {
+        let word = word.trim_matches(|c: char| !c.is_alphanumeric());
+        if word.is_empty() {
+            continue;
+        }
+        let word = word.to_lowercase();
+        if word.contains(|c: char| !c.is_alphanumeric()) {
+            terms.extend(
+                word.split(|c: char| !c.is_alphanumeric())
+                    .filter(|part| !part.is_empty())
+                    .map(str::to_string),
+            );
+        }
+        terms.push(word);
+    }
+    terms
+}
+
+/// An in memory inverted index that ranks entries with [BM25](https://en.wikipedia.org/wiki/Okapi_BM25).
+///
+/// Keyword search is useful for exact identifiers like part numbers or names that embeddings handle poorly.
+///
+/// # Example
+/// ```rust
+/// use kalosm_language::prelude::*;
+///
+/// let mut index = Bm25Index::new();
+/// index.insert(0, "The XJ-4500 pump is rated for 40 psi");
+/// index.insert(1, "Pumps move water uphill");
+/// let results = index.search("xj-4500", 10);
+/// assert_eq!(results[0].0, 0);
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bm25Index {
+    k1: f32,
+    b: f32,
+    postings: HashMap<String, HashMap<usize, u32>>,
+    lengths: HashMap<usize, usize>,
+    /// The distinct terms of each entry, so removing an entry only touches the postings of its terms.
+    #[serde(default)]
+    terms: HashMap<usize, Vec<String>>,
+    total_length: usize,
+}
+
+impl Default for Bm25Index {
+    fn default() -> Self {
+        Self {
+            k1: 1.2,
+            b: 0.75,
+            postings: HashMap::new(),
+            lengths: HashMap::new(),
+            terms: HashMap::new(),
+            total_length: 0,
+        }
+    }
+}
+
+impl Bm25Index {
+    /// Create a new empty index with the default parameters (`k1 = 1.2`, `b = 0.75`).
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the term frequency saturation parameter. Defaults to 1.2.
+    pub fn with_k1(mut self, k1: f32) -> Self {
+        self.k1 = k1;
+        self
+    }
+
+    /// Set how much the length of an entry normalizes its score, from 0 (no normalization) to 1 (full normalization). Defaults to 0.75.
+    pub fn with_b(mut self, b: f32) -> Self {
+        self.b = b;
+        self
+    }
+
+    /// The number of entries in the index.
+    pub fn len(&self) -> usize {
+        self.lengths.len()
+    }
+
+    /// Returns true if the index has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.lengths.is_empty()
+    }
+
+    /// Insert an entry into the index. If an entry with the same id exists, it is replaced.
+    pub fn insert(&mut self, id: usize, text: &str) {
+        self.remove(id);
+        let terms = keyword_terms(text);
+        self.lengths.insert(id, terms.len());
+        self.total_length += terms.len();
+        for term in &terms {
+            *self
+                .postings
+                .entry(term.clone())
+                .or_default()
+                .entry(id)
+                .or_default() += 1;
+        }
+        let mut distinct = terms;
+        distinct.sort();
+        distinct.dedup();
+        self.terms.insert(id, distinct);
+    }
+
+    /// Remove an entry from the index.
+    pub fn remove(&mut self, id: usize) {
+        let Some(length) = self.lengths.remove(&id) else {
+            return;
+        };
+        self.total_length -= length;
+        match self.terms.remove(&id) {
+            Some(terms) => {
+                for term in terms {
+                    if let Some(entries) = self.postings.get_mut(&term) {
+                        entries.remove(&id);
+                        if entries.is_empty() {
+                            self.postings.remove(&term);
+                        }
+                    }
+                }
+            }
+            // Indexes serialized before the terms of each entry were stored
+            None => self.postings.retain(|_, entries| {
+                entries.remove(&id);
+                !entries.is_empty()
+            }),
+        }
+    }
+
+    /// Find the top n entries for the query. Returns the id and the BM25 score of each entry, sorted from the highest score to the lowest.
+    pub fn search(&self, query: &str, n: usize) -> Vec<(usize, f32)> {
+        if self.is_empty() {
+            return Vec::new();
+        }
+        let entry_count = self.len() as f32;
+        let average_length = self.total_length as f32 / entry_count;
+
+        let mut query_terms = keyword_terms(query);
+        query_terms.sort();
+        query_terms.dedup();
+
+        let mut scores: HashMap<usize, f32> = HashMap::new();
+        for term in query_terms {
+            let Some(entries) = self.postings.get(&term) else {
+                continue;
+            };
+            let matching = entries.len() as f32;
+            let idf = ((entry_count - matching + 0.5) / (matching + 0.5) + 1.).ln();
+            for (id, frequency) in entries {
+                let frequency = *frequency as f32;
+                let length = self.lengths[id] as f32;
+                let normalization = 1. - self.b + self.b * length / average_length.max(1.);
+                *scores.entry(*id).or_default() +=
+                    idf * frequency * (self.k1 + 1.) / (frequency + self.k1 * normalization);
+            }
+        }
+
+        let mut scores = scores.into_iter().collect::<Vec<_>>();
+        scores.sort_by(|(id_a, a), (id_b, b)| b.total_cmp(a).then(id_a.cmp(id_b)));
+        scores.truncate(n);
+        scores
+    }
+}
+
+#[test]
+fn bm25_prefers_exact_identifiers() {
+    assert_eq!(
+        keyword_terms("Order XJ-4500, today!"),
+        vec!["order", "xj", "4500", "xj-4500", "today"]
+    );
+
+    let mut index = Bm25Index::new();
+    index.insert(0, "The XJ-4500 pump is rated for 40 psi.");
+    index.insert(1, "The XJ-4800 pump is rated for 60 psi.");
+    index.insert(2, "Pumps move water uphill.");
+    let results = index.search("XJ-4500 pump", 3);
+    assert_eq!(results[0].0, 0);
+    assert_eq!(results.len(), 2);
+
+    index.remove(0);
+    assert_eq!(index.search("XJ-4500", 3)[0].0, 1);
+    assert!(!index.postings.contains_key("xj-4500"));
+    assert_eq!(index.postings["pump"].len(), 1);
+    assert!(index.search("missing", 3).is_empty());
+}
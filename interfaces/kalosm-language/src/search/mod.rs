@@ -1,8 +1,15 @@
 //! The index module contains different types of search indexes that can be used to search for [`crate::context::Document`]s created from [`crate::context::IntoDocument`] or [`crate::context::IntoDocuments`]
 
+mod hybrid;
+pub use hybrid::*;
+mod keyword;
+pub use keyword::*;
 mod postprocessing;
+pub use postprocessing::*;
 mod preprocessing;
 pub use preprocessing::*;
+mod query;
+pub use query::*;
 
 use kalosm_language_model::*;
 use std::{fmt::Debug, ops::Range};
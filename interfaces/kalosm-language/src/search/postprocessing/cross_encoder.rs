@@ -0,0 +1,10 @@
+use rbert::BertCrossEncoder;
+
+use super::{sort_by_score, RerankResult, Reranker};
+
+#[async_trait::async_trait]
+impl Reranker for BertCrossEncoder {
+    async fn rerank(&self, query: &str, documents: &[&str]) -> anyhow::Result<Vec<RerankResult>> {
+        Ok(sort_by_score(self.score(query, documents)?))
+    }
+}
@@ -0,0 +1,290 @@
+use kalosm_language_model::{Model, SyncModel};
+use kalosm_sample::{IntegerParser, LiteralParser, ParserExt, SequenceParser};
+
+use super::{sort_by_score, RerankResult, Reranker};
+use crate::task::{StructuredRunner, Task, TaskResponse};
+
+const RERANK_DESCRIPTION: &str = "You judge search results. You are given a search query and a document. Rate how relevant the document is to the query from 0 (unrelated) to 10 (directly answers the query).";
+
+type RelevanceConstraints = SequenceParser<LiteralParser<&'static str>, IntegerParser>;
+
+fn relevance_task(description: String) -> Task<StructuredRunner<RelevanceConstraints>> {
+    Task::builder(description)
+        .with_constraints(LiteralParser::new("Relevance: ").then(IntegerParser::new(0..=10)))
+        .build()
+}
+
+/// A pointwise [`Reranker`] that asks a local model to rate the relevance of each document to the query from 0 to 10.
+///
+/// Each document is rated on its own with one run of the model, so reranking n documents takes n runs and the model never compares documents against each other. The scale only has 11 steps, so documents that are about equally relevant often tie; ties keep the order they were passed in. Use a [`rbert::BertCrossEncoder`] for faster, finer grained scores.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm_language::prelude::*;
+///
+/// #[tokio::main]
+/// async fn main() -> anyhow::Result<()> {
+///     let reranker = LlmReranker::new(Llama::new_chat());
+///     let documents = ["Berlin has 3.5 million inhabitants.", "Berlin has many museums."];
+///     let results = reranker
+///         .rerank("How many people live in Berlin?", &documents)
+///         .await?;
+///     println!("{}", documents[results[0].index]);
+///     Ok(())
+/// }
+/// ```
+pub struct LlmReranker<M: Model> {
+    model: M,
+    task: Task<StructuredRunner<RelevanceConstraints>>,
+    max_document_length: usize,
+}
+
+impl<M: Model> LlmReranker<M>
+where
+    <M::SyncModel as SyncModel>::Session: Send + Sync,
+{
+    /// Create a new reranker that uses the given model.
+    pub fn new(model: M) -> Self {
+        Self {
+            model,
+            task: relevance_task(RERANK_DESCRIPTION.to_string()),
+            max_document_length: 2000,
+        }
+    }
+
+    /// Set the instructions the model uses to rate documents. The model will always respond with a relevance from 0 to 10.
+    pub fn with_criteria(mut self, criteria: impl Into<String>) -> Self {
+        self.task = relevance_task(criteria.into());
+        self
+    }
+
+    /// Set the maximum number of characters of each document shown to the model. Defaults to 2000.
+    pub fn with_max_document_length(mut self, max_document_length: usize) -> Self {
+        self.max_document_length = max_document_length;
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl<M: Model> Reranker for LlmReranker<M>
+where
+    <M::SyncModel as SyncModel>::Session: Send + Sync,
+{
+    async fn rerank(&self, query: &str, documents: &[&str]) -> anyhow::Result<Vec<RerankResult>> {
+        let mut scores = Vec::with_capacity(documents.len());
+        for document in documents {
+            let document = match document.char_indices().nth(self.max_document_length) {
+                Some((end, _)) => &document[..end],
+                None => document,
+            };
+            let prompt = format!("Query: {}\nDocument: {}", query.trim(), document.trim());
+            let output = self.task.run(prompt, &self.model);
+            let (_, relevance) =
+                <StructuredRunner<RelevanceConstraints> as TaskResponse>::response(output).await?;
+            scores.push(relevance as f32 / 10.);
+        }
+        Ok(sort_by_score(scores))
+    }
+}
+
+#[tokio::test]
+async fn llm_reranker_sorts_by_relevance() {
+    use std::borrow::Cow;
+    use std::sync::Arc;
+
+    use kalosm_language_model::{GenerationParameters, Session};
+    use kalosm_streams::text_stream::ChannelTextStream;
+    use llm_samplers::types::Logits;
+
+    const STOP_TOKEN: u32 = 256;
+
+    /// A tokenizer with one token per byte and a stop token.
+    struct Bytes;
+
+    impl kalosm_sample::Tokenizer for Bytes {
+        fn encode(&self, text: &str, _: bool) -> anyhow::Result<Vec<u32>> {
+            Ok(text.bytes().map(u32::from).collect())
+        }
+
+        fn decode(&self, ids: &[u32]) -> anyhow::Result<Cow<'_, str>> {
+            let mut text = String::new();
+            for id in ids {
+                match *id {
+                    STOP_TOKEN => text += "<eos>",
+                    id => text += &String::from_utf8_lossy(&[id as u8]),
+                }
+            }
+            Ok(Cow::Owned(text))
+        }
+
+        fn get_all_tokens(&self) -> anyhow::Result<Cow<'_, [u32]>> {
+            Ok(Cow::Owned((0..=STOP_TOKEN).collect()))
+        }
+    }
+
+    #[derive(Clone)]
+    struct Text(String);
+
+    impl Session for Text {
+        fn try_clone(&self) -> anyhow::Result<Self> {
+            Ok(self.clone())
+        }
+    }
+
+    /// Rates a document with the number of words from the query it contains, then stops.
+    struct Scripted;
+
+    impl Scripted {
+        fn logits(session: &Text) -> anyhow::Result<Logits> {
+            let line = |prefix: &str| {
+                let start = session.0.rfind(prefix).map_or(0, |i| i + prefix.len());
+                let line = session.0[start..].lines().next().unwrap_or_default();
+                line.split_whitespace()
+                    .map(|word| {
+                        word.trim_matches(|c: char| !c.is_alphanumeric())
+                            .to_lowercase()
+                    })
+                    .collect::<Vec<_>>()
+            };
+            let next = if session.0.ends_with(|c: char| c.is_ascii_digit()) {
+                STOP_TOKEN
+            } else {
+                let document = line("Document: ");
+                let relevance = line("Query: ")
+                    .iter()
+                    .filter(|word| document.contains(word))
+                    .count()
+                    .min(9);
+                u32::from(b'0') + relevance as u32
+            };
+            Ok(Logits::try_from_iter((0..=STOP_TOKEN).map(|id| {
+                if id == next {
+                    100.
+                } else {
+                    -100.
+                }
+            }))?)
+        }
+    }
+
+    impl SyncModel for Scripted {
+        type Session = Text;
+
+        fn new_session(&self) -> anyhow::Result<Text> {
+            Ok(Text(String::new()))
+        }
+
+        fn feed_text(
+            &self,
+            session: &mut Text,
+            prompt: &str,
+            _: Option<usize>,
+        ) -> anyhow::Result<Logits> {
+            session.0 += prompt;
+            Self::logits(session)
+        }
+
+        fn feed_tokens(
+            &self,
+            session: &mut Text,
+            tokens: &[u32],
+            _: Option<usize>,
+        ) -> anyhow::Result<Logits> {
+            session.0 += &kalosm_sample::Tokenizer::decode(&Bytes, tokens)?;
+            Self::logits(session)
+        }
+
+        fn stop_token(&self) -> anyhow::Result<u32> {
+            Ok(STOP_TOKEN)
+        }
+
+        fn tokenizer(&self) -> Arc<dyn kalosm_sample::Tokenizer + Send + Sync> {
+            Arc::new(Bytes)
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl Model for Scripted {
+        type TextStream = ChannelTextStream<String>;
+        type SyncModel = Scripted;
+
+        fn tokenizer(&self) -> Arc<dyn kalosm_sample::Tokenizer + Send + Sync> {
+            Arc::new(Bytes)
+        }
+
+        fn run_sync_raw(
+            &self,
+            f: Box<
+                dyn for<'a> FnOnce(
+                        &'a mut Scripted,
+                    )
+                        -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + 'a>>
+                    + Send,
+            >,
+        ) -> anyhow::Result<()> {
+            std::thread::spawn(move || {
+                let runtime = tokio::runtime::Builder::new_current_thread()
+                    .build()
+                    .unwrap();
+                runtime.block_on(f(&mut Scripted));
+            });
+            Ok(())
+        }
+
+        async fn stream_text_inner(
+            &self,
+            _: &str,
+            _: GenerationParameters,
+        ) -> anyhow::Result<Self::TextStream> {
+            anyhow::bail!("Not implemented")
+        }
+    }
+
+    let query = "How many people live in Berlin?";
+    let documents = [
+        "Berlin has many museums.",
+        "About 3.5 million people live in Berlin.",
+        "Paris is in France.",
+    ];
+    let results = LlmReranker::new(Scripted)
+        .rerank(query, &documents)
+        .await
+        .unwrap();
+    assert_eq!(
+        results,
+        vec![
+            RerankResult {
+                index: 1,
+                score: 0.4
+            },
+            RerankResult {
+                index: 0,
+                score: 0.2
+            },
+            RerankResult {
+                index: 2,
+                score: 0.1
+            },
+        ]
+    );
+
+    // Only the start of each document is shown to the model
+    let results = LlmReranker::new(Scripted)
+        .with_max_document_length(6)
+        .rerank(
+            query,
+            &[
+                "Berlin has many museums.",
+                "Paris has people who live in Berlin.",
+            ],
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        results
+            .iter()
+            .map(|result| result.index)
+            .collect::<Vec<_>>(),
+        vec![0, 1]
+    );
+}
@@ -0,0 +1,120 @@
+use kalosm_language_model::{Embedder, Embedding, VectorSpace};
+
+use super::{RerankResult, Reranker};
+
+/// Select up to k embeddings with [maximal marginal relevance](https://www.cs.cmu.edu/~jgc/publication/The_Use_MMR_Diversity_Based_LTMIR_1998.pdf).
+///
+/// Each step picks the candidate that maximizes `lambda * similarity(query, candidate) - (1 - lambda) * max(similarity(candidate, selected))`. A lambda of 1 ranks purely by relevance; lower values prefer candidates that are different from the ones already selected.
+pub fn maximal_marginal_relevance<S: VectorSpace>(
+    query: &Embedding<S>,
+    candidates: &[Embedding<S>],
+    lambda: f32,
+    k: usize,
+) -> Vec<RerankResult> {
+    let relevance = candidates
+        .iter()
+        .map(|candidate| query.cosine_similarity(candidate))
+        .collect::<Vec<_>>();
+    // The highest similarity of each candidate to any selected candidate
+    let mut redundancy = vec![f32::NEG_INFINITY; candidates.len()];
+    let mut remaining = (0..candidates.len()).collect::<Vec<_>>();
+    let mut selected = Vec::new();
+
+    while selected.len() < k && !remaining.is_empty() {
+        let score = |index: usize| {
+            let redundancy = if redundancy[index] == f32::NEG_INFINITY {
+                0.
+            } else {
+                redundancy[index]
+            };
+            lambda * relevance[index] - (1. - lambda) * redundancy
+        };
+        let (position, best) = remaining
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| score(**a).total_cmp(&score(**b)).then(b.cmp(a)))
+            .map(|(position, index)| (position, *index))
+            .unwrap();
+        selected.push(RerankResult {
+            index: best,
+            score: score(best),
+        });
+        remaining.remove(position);
+
+        for &index in &remaining {
+            let similarity = candidates[index].cosine_similarity(&candidates[best]);
+            redundancy[index] = redundancy[index].max(similarity);
+        }
+    }
+
+    selected
+}
+
+/// A [`Reranker`] that diversifies results with [`maximal_marginal_relevance`] so the top results don't all say the same thing.
+pub struct MaximalMarginalRelevance<E: Embedder> {
+    embedder: E,
+    lambda: f32,
+    k: Option<usize>,
+}
+
+impl<E: Embedder> MaximalMarginalRelevance<E> {
+    /// Create a new diversifier that embeds the query and documents with the given embedding model.
+    pub fn new(embedder: E) -> Self {
+        Self {
+            embedder,
+            lambda: 0.5,
+            k: None,
+        }
+    }
+
+    /// Set the trade off between relevance (1) and diversity (0). Defaults to 0.5.
+    pub fn with_lambda(mut self, lambda: f32) -> Self {
+        self.lambda = lambda;
+        self
+    }
+
+    /// Only return the top k documents. Defaults to returning every document.
+    pub fn with_k(mut self, k: usize) -> Self {
+        self.k = Some(k);
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl<E: Embedder + Send + Sync> Reranker for MaximalMarginalRelevance<E> {
+    async fn rerank(&self, query: &str, documents: &[&str]) -> anyhow::Result<Vec<RerankResult>> {
+        let query = self.embedder.embed(query).await?;
+        let documents = self.embedder.embed_batch(documents).await?;
+        let k = self.k.unwrap_or(documents.len());
+        Ok(maximal_marginal_relevance(
+            &query,
+            &documents,
+            self.lambda,
+            k,
+        ))
+    }
+}
+
+#[test]
+fn mmr_prefers_diverse_results() {
+    use kalosm_language_model::UnknownVectorSpace;
+
+    let query: Embedding<UnknownVectorSpace> = vec![1.0, 0.0].into();
+    let candidates: Vec<Embedding<UnknownVectorSpace>> = vec![
+        vec![1.0, 0.1].into(),
+        vec![1.0, 0.11].into(),
+        vec![0.6, -0.8].into(),
+    ];
+
+    let relevant = maximal_marginal_relevance(&query, &candidates, 1.0, 3);
+    assert_eq!(
+        relevant.iter().map(|r| r.index).collect::<Vec<_>>(),
+        vec![0, 1, 2]
+    );
+
+    let diverse = maximal_marginal_relevance(&query, &candidates, 0.5, 2);
+    assert_eq!(
+        diverse.iter().map(|r| r.index).collect::<Vec<_>>(),
+        vec![0, 2]
+    );
+}
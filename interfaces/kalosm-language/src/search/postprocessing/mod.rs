@@ -1,4 +1,47 @@
-// Context extraction strategies:
-// 1. Dump all sentences
-// 2. Dump all sentences that mention an entity
-// 3. Extract relevant sentences with an llm
+mod cross_encoder;
+pub use cross_encoder::*;
+mod llm;
+pub use llm::*;
+mod mmr;
+pub use mmr::*;
+
+/// The new position and score of a document after reranking.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RerankResult {
+    /// The index of the document in the list that was reranked.
+    pub index: usize,
+    /// The score the reranker gave the document. Higher scores are better. The scale depends on the reranker.
+    pub score: f32,
+}
+
+/// A second stage of search that reorders the results of a fast first stage search (like a [`crate::vector_db::VectorDB`]) with a slower, more accurate model.
+#[async_trait::async_trait]
+pub trait Reranker {
+    /// Rerank documents for a query. Returns the documents from the best match to the worst match. Rerankers may drop documents from the results.
+    async fn rerank(&self, query: &str, documents: &[&str]) -> anyhow::Result<Vec<RerankResult>>;
+}
+
+#[async_trait::async_trait]
+impl<R: Reranker + Send + Sync + ?Sized> Reranker for std::sync::Arc<R> {
+    async fn rerank(&self, query: &str, documents: &[&str]) -> anyhow::Result<Vec<RerankResult>> {
+        (**self).rerank(query, documents).await
+    }
+}
+
+#[async_trait::async_trait]
+impl<R: Reranker + Send + Sync + ?Sized> Reranker for Box<R> {
+    async fn rerank(&self, query: &str, documents: &[&str]) -> anyhow::Result<Vec<RerankResult>> {
+        (**self).rerank(query, documents).await
+    }
+}
+
+/// Sort scored documents from the highest score to the lowest score.
+fn sort_by_score(scores: impl IntoIterator<Item = f32>) -> Vec<RerankResult> {
+    let mut results = scores
+        .into_iter()
+        .enumerate()
+        .map(|(index, score)| RerankResult { index, score })
+        .collect::<Vec<_>>();
+    results.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.index.cmp(&b.index)));
+    results
+}
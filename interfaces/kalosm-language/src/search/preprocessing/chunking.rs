@@ -1,13 +1,15 @@
+use kalosm_language_model::kalosm_sample::Tokenizer;
 use kalosm_language_model::{Embedder, VectorSpace};
+use serde::{Deserialize, Serialize};
 use std::ops::Range;
 
-use super::Chunker;
+use super::{versioned_config, Chunker};
 use crate::{prelude::Document, search::Chunk};
 
 /// A strategy for chunking a document into smaller pieces.
 ///
 /// This is used to split a document into smaller pieces to generate embeddings for each piece.
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum ChunkStrategy {
     /// Split the document into paragraphs.
     Paragraph {
@@ -30,12 +32,39 @@ pub enum ChunkStrategy {
         /// The number of words to overlap between chunks.
         overlap: usize,
     },
+    /// Split the document into chunks of whole words with at most `max_tokens` tokens.
+    ///
+    /// Tokens are counted with the tokenizer of the embedding model (see [`Embedder::tokenizer`]), including the special tokens (like `[CLS]` and `[SEP]`) the model adds to every input, so chunks are not truncated by the embedding model. If the embedding model doesn't expose a tokenizer, tokens are estimated as one token per four bytes.
+    Tokens {
+        /// The maximum number of tokens in each chunk.
+        max_tokens: usize,
+        /// The number of tokens to overlap between chunks.
+        overlap: usize,
+    },
+    /// Recursively split the document on paragraphs, then lines, then sentences, then words until each piece is at most `max_length` bytes long, then merge neighboring pieces back together up to `max_length` bytes.
+    Recursive {
+        /// The maximum length of each chunk in bytes.
+        max_length: usize,
+        /// The number of bytes to overlap between chunks.
+        overlap: usize,
+    },
 }
 
 impl ChunkStrategy {
     /// Chunk a string into smaller ranges.
+    ///
+    /// [`ChunkStrategy::Tokens`] estimates the number of tokens without a tokenizer. Use [`ChunkStrategy::chunk_str_with_tokenizer`] to count tokens exactly.
     pub fn chunk_str(&self, string: &str) -> Vec<Range<usize>> {
         match self {
+            Self::Tokens {
+                max_tokens,
+                overlap,
+            } => chunk_tokens(string, *max_tokens, *overlap, None)
+                .expect("estimating tokens cannot fail"),
+            Self::Recursive {
+                max_length,
+                overlap,
+            } => chunk_recursive(string, *max_length, *overlap),
             Self::Paragraph {
                 paragraph_count,
                 overlap,
@@ -121,6 +150,214 @@ impl ChunkStrategy {
     }
 }
 
+impl ChunkStrategy {
+    /// Chunk a string into smaller ranges, counting tokens with the tokenizer for [`ChunkStrategy::Tokens`].
+    pub fn chunk_str_with_tokenizer(
+        &self,
+        string: &str,
+        tokenizer: Option<&(dyn Tokenizer + Send + Sync)>,
+    ) -> anyhow::Result<Vec<Range<usize>>> {
+        match self {
+            Self::Tokens {
+                max_tokens,
+                overlap,
+            } => chunk_tokens(string, *max_tokens, *overlap, tokenizer),
+            _ => Ok(self.chunk_str(string)),
+        }
+    }
+}
+
+/// Find the byte ranges of every whitespace separated word in the string.
+fn word_ranges(string: &str) -> Vec<Range<usize>> {
+    let mut words = Vec::new();
+    let mut start = None;
+    for (i, c) in string.char_indices() {
+        match (c.is_whitespace(), start) {
+            (true, Some(word_start)) => {
+                words.push(word_start..i);
+                start = None;
+            }
+            (false, None) => start = Some(i),
+            _ => {}
+        }
+    }
+    if let Some(word_start) = start {
+        words.push(word_start..string.len());
+    }
+    words
+}
+
+fn chunk_tokens(
+    string: &str,
+    max_tokens: usize,
+    overlap: usize,
+    tokenizer: Option<&(dyn Tokenizer + Send + Sync)>,
+) -> anyhow::Result<Vec<Range<usize>>> {
+    let words = word_ranges(string);
+    let (token_counts, budget) = match tokenizer {
+        Some(tokenizer) => {
+            let words = words
+                .iter()
+                .map(|range| &string[range.clone()])
+                .collect::<Vec<_>>();
+            let token_counts = tokenizer
+                .encode_batch(&words, false)?
+                .into_iter()
+                .map(|tokens| tokens.len())
+                .collect::<Vec<_>>();
+            // Leave room for the special tokens the embedding model adds to every input
+            let special_tokens = tokenizer.encode("", true)?.len();
+            (token_counts, max_tokens.saturating_sub(special_tokens))
+        }
+        None => (
+            words
+                .iter()
+                .map(|range| ((range.len() + 3) / 4).max(1))
+                .collect(),
+            max_tokens,
+        ),
+    };
+
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while start < words.len() {
+        let mut end = start;
+        let mut tokens = 0;
+        // Always include at least one word so a single long word can't stall the chunker
+        while end < words.len() && (end == start || tokens + token_counts[end] <= budget) {
+            tokens += token_counts[end];
+            end += 1;
+        }
+        // Words can be tokenized differently next to each other, so check the whole chunk
+        if let Some(tokenizer) = tokenizer {
+            while end > start + 1
+                && tokenizer
+                    .encode(&string[words[start].start..words[end - 1].end], true)?
+                    .len()
+                    > max_tokens
+            {
+                end -= 1;
+            }
+        }
+        chunks.push(words[start].start..words[end - 1].end);
+        if end == words.len() {
+            break;
+        }
+
+        let mut next_start = end;
+        let mut overlap_tokens = 0;
+        while next_start > start + 1 && overlap_tokens + token_counts[next_start - 1] <= overlap {
+            overlap_tokens += token_counts[next_start - 1];
+            next_start -= 1;
+        }
+        start = next_start;
+    }
+
+    Ok(chunks)
+}
+
+/// The separators the recursive splitter tries in order.
+const RECURSIVE_SEPARATORS: &[&str] = &["\n\n", "\n", ". ", " "];
+
+/// Split the range into pieces that are at most `max_length` bytes long, preferring the earliest separators.
+fn split_recursive(
+    string: &str,
+    range: Range<usize>,
+    separators: &[&str],
+    max_length: usize,
+    pieces: &mut Vec<Range<usize>>,
+) {
+    if range.len() <= max_length {
+        pieces.push(range);
+        return;
+    }
+    let Some((separator, remaining_separators)) = separators.split_first() else {
+        // No separators are left, so split on character boundaries
+        let mut start = range.start;
+        for (i, _) in string[range.clone()].char_indices() {
+            let i = range.start + i;
+            if i - start >= max_length && i > start {
+                pieces.push(start..i);
+                start = i;
+            }
+        }
+        pieces.push(start..range.end);
+        return;
+    };
+
+    let text = &string[range.clone()];
+    let mut start = 0;
+    for (i, _) in text.match_indices(separator) {
+        let end = i + separator.len();
+        split_recursive(
+            string,
+            range.start + start..range.start + end,
+            remaining_separators,
+            max_length,
+            pieces,
+        );
+        start = end;
+    }
+    if start < text.len() {
+        split_recursive(
+            string,
+            range.start + start..range.end,
+            remaining_separators,
+            max_length,
+            pieces,
+        );
+    }
+}
+
+/// Shrink the range so it doesn't start or end with whitespace.
+pub(crate) fn trim_range(string: &str, range: Range<usize>) -> Range<usize> {
+    let text = &string[range.clone()];
+    let start = range.start + (text.len() - text.trim_start().len());
+    let end = range.end - (text.len() - text.trim_end().len());
+    start..end.max(start)
+}
+
+pub(crate) fn chunk_recursive(
+    string: &str,
+    max_length: usize,
+    overlap: usize,
+) -> Vec<Range<usize>> {
+    let mut pieces = Vec::new();
+    split_recursive(
+        string,
+        0..string.len(),
+        RECURSIVE_SEPARATORS,
+        max_length.max(1),
+        &mut pieces,
+    );
+
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while start < pieces.len() {
+        let mut end = start + 1;
+        while end < pieces.len() && pieces[end].end - pieces[start].start <= max_length {
+            end += 1;
+        }
+        let chunk = trim_range(string, pieces[start].start..pieces[end - 1].end);
+        if !chunk.is_empty() {
+            chunks.push(chunk);
+        }
+        if end == pieces.len() {
+            break;
+        }
+
+        let mut next_start = end;
+        while next_start > start + 1
+            && pieces[end - 1].end - pieces[next_start - 1].start <= overlap
+        {
+            next_start -= 1;
+        }
+        start = next_start;
+    }
+
+    chunks
+}
+
 impl Default for ChunkStrategy {
     fn default() -> Self {
         Self::Paragraph {
@@ -204,6 +441,79 @@ fn test_chunking() {
     );
 }
 
+#[test]
+fn test_token_and_recursive_chunking() {
+    // Without a tokenizer, every word of four bytes or less is estimated as one token
+    let string = "one two six ten red big map";
+    let chunks = ChunkStrategy::Tokens {
+        max_tokens: 3,
+        overlap: 1,
+    }
+    .chunk_str(string);
+    let chunks = chunks
+        .into_iter()
+        .map(|range| &string[range])
+        .collect::<Vec<_>>();
+    assert_eq!(chunks, vec!["one two six", "six ten red", "red big map"]);
+
+    /// One token per word, with a start and end token around every input.
+    struct Wrapped;
+
+    impl Tokenizer for Wrapped {
+        fn encode(&self, text: &str, add_special_tokens: bool) -> anyhow::Result<Vec<u32>> {
+            let mut tokens = text.split_whitespace().map(|_| 1).collect::<Vec<_>>();
+            if add_special_tokens {
+                tokens.insert(0, 0);
+                tokens.push(2);
+            }
+            Ok(tokens)
+        }
+
+        fn decode(&self, _: &[u32]) -> anyhow::Result<std::borrow::Cow<'_, str>> {
+            anyhow::bail!("Chunking only encodes text")
+        }
+
+        fn get_all_tokens(&self) -> anyhow::Result<std::borrow::Cow<'_, [u32]>> {
+            Ok((0..3).collect::<Vec<_>>().into())
+        }
+    }
+
+    let chunks = ChunkStrategy::Tokens {
+        max_tokens: 5,
+        overlap: 0,
+    }
+    .chunk_str_with_tokenizer(string, Some(&Wrapped))
+    .unwrap();
+    let chunks = chunks
+        .into_iter()
+        .map(|range| &string[range])
+        .collect::<Vec<_>>();
+    assert_eq!(chunks, vec!["one two six", "ten red big", "map"]);
+    assert!(chunks
+        .iter()
+        .all(|chunk| Wrapped.encode(chunk, true).unwrap().len() <= 5));
+
+    let string = "First paragraph is here.\n\nSecond paragraph. It has two sentences.\n\nThird.";
+    let chunks = ChunkStrategy::Recursive {
+        max_length: 30,
+        overlap: 0,
+    }
+    .chunk_str(string);
+    let chunks = chunks
+        .into_iter()
+        .map(|range| &string[range])
+        .collect::<Vec<_>>();
+    assert_eq!(
+        chunks,
+        vec![
+            "First paragraph is here.",
+            "Second paragraph.",
+            "It has two sentences.\n\nThird."
+        ]
+    );
+    assert!(chunks.iter().all(|chunk| chunk.len() <= 30));
+}
+
 /// A document that has been split into smaller chunks and embedded.
 pub struct EmbeddedDocument<S: VectorSpace> {
     raw: Document,
@@ -229,7 +539,8 @@ impl Chunker for ChunkStrategy {
         let mut chunks = Vec::new();
         let body = document.body();
         let mut documents = Vec::new();
-        let chunk_ranges = self.chunk_str(body);
+        let tokenizer = embedder.tokenizer();
+        let chunk_ranges = self.chunk_str_with_tokenizer(body, tokenizer.as_deref())?;
         for byte_range in &chunk_ranges {
             documents.push(&document.body()[byte_range.clone()]);
         }
@@ -254,9 +565,10 @@ impl Chunker for ChunkStrategy {
     {
         let mut chunks = Vec::new();
         let mut chunk_strings = Vec::new();
+        let tokenizer = embedder.tokenizer();
         for document in documents {
             let body = document.body();
-            let chunk = self.chunk_str(body);
+            let chunk = self.chunk_str_with_tokenizer(body, tokenizer.as_deref())?;
             for byte_range in &chunk {
                 chunk_strings.push(&body[byte_range.clone()]);
             }
@@ -281,4 +593,27 @@ impl Chunker for ChunkStrategy {
 
         Ok(embedded_chunks)
     }
+
+    fn config(&self) -> String {
+        versioned_config("chunk-strategy", self)
+    }
+}
+
+#[test]
+fn chunk_strategy_config_is_versioned_json() {
+    let strategy = ChunkStrategy::Sentence {
+        sentence_count: 3,
+        overlap: 1,
+    };
+    let config: serde_json::Value = serde_json::from_str(&strategy.config()).unwrap();
+    assert_eq!(config["version"], super::CHUNKER_CONFIG_VERSION);
+    assert_eq!(config["chunker"], "chunk-strategy");
+    let stored: ChunkStrategy = serde_json::from_value(config["config"].clone()).unwrap();
+    assert_eq!(stored, strategy);
+
+    let other = ChunkStrategy::Sentence {
+        sentence_count: 3,
+        overlap: 0,
+    };
+    assert_ne!(strategy.config(), other.config());
 }
@@ -2,11 +2,11 @@ use kalosm_language_model::{Embedder, Model, SyncModel};
 use kalosm_sample::{LiteralParser, ParserExt, StopOn};
 
 use crate::{
-    prelude::{Document, IndexParser, StructuredRunner, Task},
+    prelude::{ChatTemplate, Document, IndexParser, StructuredRunner, Task},
     search::Chunk,
 };
 
-use super::{ChunkStrategy, Chunker};
+use super::{versioned_config, ChunkStrategy, Chunker};
 
 const TASK_DESCRIPTION: &str =
     "You generate hypothetical questions that may be answered by the given text. The questions restate any information necessary to understand the question";
@@ -55,6 +55,7 @@ pub struct HypotheticalBuilder {
     task_description: Option<String>,
     examples: Option<Vec<(String, String)>>,
     chunking: Option<ChunkStrategy>,
+    chat_template: Option<ChatTemplate>,
 }
 
 impl HypotheticalBuilder {
@@ -84,6 +85,12 @@ impl HypotheticalBuilder {
         self
     }
 
+    /// Set the [`ChatTemplate`] used to format the prompt for the task that generates questions.
+    pub fn with_chat_template(mut self, chat_template: ChatTemplate) -> Self {
+        self.chat_template = Some(chat_template);
+        self
+    }
+
     /// Build the hypothetical chunker.
     pub fn build(self) -> anyhow::Result<Hypothetical> {
         let task_description = self
@@ -97,10 +104,13 @@ impl HypotheticalBuilder {
         });
         let chunking = self.chunking;
 
-        let task = Task::builder(task_description)
+        let mut task = Task::builder(task_description)
             .with_constraints(create_constraints())
-            .with_examples(examples)
-            .build();
+            .with_examples(examples);
+        if let Some(chat_template) = self.chat_template {
+            task = task.with_chat_template(chat_template);
+        }
+        let task = task.build();
 
         Ok(Hypothetical { chunking, task })
     }
@@ -118,6 +128,7 @@ impl Hypothetical {
         HypotheticalBuilder {
             task_description: None,
             examples: None,
+            chat_template: None,
             chunking: None,
         }
     }
@@ -222,4 +233,8 @@ where
 
         Ok(chunks)
     }
+
+    fn config(&self) -> String {
+        versioned_config("hypothetical", &self.hypothetical.chunking)
+    }
 }
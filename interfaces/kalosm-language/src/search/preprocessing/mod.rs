@@ -22,6 +22,10 @@ mod chunking;
 pub use chunking::*;
 mod hypothetical;
 pub use hypothetical::*;
+mod sections;
+pub use sections::*;
+mod semantic;
+pub use semantic::*;
 mod summary;
 pub use summary::*;
 
@@ -51,4 +55,24 @@ pub trait Chunker {
         }
         Ok(chunks)
     }
+
+    /// A description of the configuration of the chunker. Embeddings are only comparable if they were created from chunks with the same configuration, so this is stored with the embeddings to detect when a table is opened with a different chunker.
+    ///
+    /// Chunkers should override this with [`versioned_config`]. The default only includes the name of the type, which is not stable across compiler versions.
+    fn config(&self) -> String {
+        versioned_config(std::any::type_name::<Self>(), &())
+    }
+}
+
+/// The version of the format of [`Chunker::config`]. This is bumped when a built in chunker changes how it splits documents, so tables chunked with the old behavior are detected when they are opened.
+pub const CHUNKER_CONFIG_VERSION: u32 = 1;
+
+/// Serialize the configuration of a chunker as versioned JSON for [`Chunker::config`].
+pub fn versioned_config(chunker: &str, config: &impl serde::Serialize) -> String {
+    serde_json::json!({
+        "version": CHUNKER_CONFIG_VERSION,
+        "chunker": chunker,
+        "config": config,
+    })
+    .to_string()
 }
@@ -0,0 +1,356 @@
+use std::ops::Range;
+
+use kalosm_language_model::Embedder;
+use serde::{Deserialize, Serialize};
+
+use super::{chunk_recursive, trim_range, versioned_config, Chunker};
+use crate::context::Document;
+use crate::search::Chunk;
+
+/// A piece of a document with the title of the section it is in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TitledRange {
+    /// The title of the section the range is in. Nested titles are joined with ` > `.
+    pub title: String,
+    /// The byte range in the document.
+    pub byte_range: Range<usize>,
+}
+
+/// Iterate over the lines in a string with the byte range of each line, including the newline.
+fn line_ranges(string: &str) -> impl Iterator<Item = Range<usize>> + '_ {
+    let mut start = 0;
+    string.split_inclusive('\n').map(move |line| {
+        let range = start..start + line.len();
+        start = range.end;
+        range
+    })
+}
+
+/// Split sections that are longer than the maximum length and attach the section title to each piece.
+fn split_sections(
+    string: &str,
+    sections: Vec<(String, Range<usize>)>,
+    max_length: usize,
+) -> Vec<TitledRange> {
+    let mut ranges = Vec::new();
+    for (title, section) in sections {
+        for piece in chunk_recursive(&string[section.clone()], max_length, 0) {
+            ranges.push(TitledRange {
+                title: title.clone(),
+                byte_range: section.start + piece.start..section.start + piece.end,
+            });
+        }
+    }
+    ranges
+}
+
+async fn embed_titled_ranges<E: Embedder + Send>(
+    document: &Document,
+    ranges: Vec<TitledRange>,
+    embedder: &E,
+) -> anyhow::Result<Vec<Chunk<E::VectorSpace>>> {
+    let body = document.body();
+    let texts = ranges
+        .iter()
+        .map(|range| {
+            let text = &body[range.byte_range.clone()];
+            if range.title.is_empty() {
+                text.to_string()
+            } else {
+                format!("{}\n\n{}", range.title, text)
+            }
+        })
+        .collect::<Vec<_>>();
+    let texts = texts.iter().map(String::as_str).collect::<Vec<_>>();
+    let embeddings = embedder.embed_batch(&texts).await?;
+    Ok(ranges
+        .into_iter()
+        .zip(embeddings)
+        .map(|(range, embedding)| Chunk {
+            byte_range: range.byte_range,
+            embeddings: vec![embedding],
+        })
+        .collect())
+}
+
+/// A chunker that splits markdown documents on headings. Every chunk is embedded with the titles of the headings it is under, so chunks from deep in a section keep the context of the section.
+///
+/// Sections longer than the maximum length are split further with [`super::ChunkStrategy::Recursive`]. Headings inside of fenced code blocks are ignored.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MarkdownChunker {
+    max_length: usize,
+}
+
+impl Default for MarkdownChunker {
+    fn default() -> Self {
+        Self { max_length: 1000 }
+    }
+}
+
+impl MarkdownChunker {
+    /// Create a new markdown chunker with a maximum chunk length of 1000 bytes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the maximum length of each chunk in bytes.
+    pub fn with_max_length(mut self, max_length: usize) -> Self {
+        self.max_length = max_length;
+        self
+    }
+
+    /// Split a markdown string into ranges with the titles of the headings they are under.
+    pub fn chunk_str(&self, string: &str) -> Vec<TitledRange> {
+        let mut sections = Vec::new();
+        let mut headings: Vec<(usize, String)> = Vec::new();
+        let mut section_start = 0;
+        let mut in_code_block = false;
+
+        for line in line_ranges(string) {
+            let text = string[line.clone()].trim();
+            if text.starts_with("```") || text.starts_with("~~~") {
+                in_code_block = !in_code_block;
+                continue;
+            }
+            if in_code_block {
+                continue;
+            }
+            let level = text.chars().take_while(|c| *c == '#').count();
+            let is_heading =
+                (1..=6).contains(&level) && text[level..].starts_with(|c: char| c.is_whitespace());
+            if !is_heading {
+                continue;
+            }
+
+            if line.start > section_start {
+                sections.push((title_path(&headings), section_start..line.start));
+            }
+            while headings.last().is_some_and(|(last, _)| *last >= level) {
+                headings.pop();
+            }
+            headings.push((
+                level,
+                text[level..]
+                    .trim()
+                    .trim_end_matches('#')
+                    .trim()
+                    .to_string(),
+            ));
+            section_start = line.start;
+        }
+        if section_start < string.len() {
+            sections.push((title_path(&headings), section_start..string.len()));
+        }
+
+        let sections = sections
+            .into_iter()
+            .map(|(title, range)| (title, trim_range(string, range)))
+            .filter(|(_, range)| !range.is_empty())
+            .collect();
+        split_sections(string, sections, self.max_length)
+    }
+}
+
+fn title_path(headings: &[(usize, String)]) -> String {
+    headings
+        .iter()
+        .map(|(_, title)| title.as_str())
+        .collect::<Vec<_>>()
+        .join(" > ")
+}
+
+#[async_trait::async_trait]
+impl Chunker for MarkdownChunker {
+    async fn chunk<E: Embedder + Send>(
+        &self,
+        document: &Document,
+        embedder: &E,
+    ) -> anyhow::Result<Vec<Chunk<E::VectorSpace>>> {
+        let ranges = self.chunk_str(document.body());
+        embed_titled_ranges(document, ranges, embedder).await
+    }
+
+    fn config(&self) -> String {
+        versioned_config("markdown", self)
+    }
+}
+
+/// Modifiers that can come before a definition keyword.
+const DEFINITION_MODIFIERS: &[&str] = &[
+    "pub(crate) ",
+    "pub(super) ",
+    "pub ",
+    "export ",
+    "default ",
+    "public ",
+    "private ",
+    "protected ",
+    "static ",
+    "async ",
+    "unsafe ",
+    "const ",
+    "extern ",
+];
+
+/// Keywords that start a top level definition in common languages.
+const DEFINITION_KEYWORDS: &[&str] = &[
+    "fn ",
+    "struct ",
+    "enum ",
+    "trait ",
+    "impl ",
+    "impl<",
+    "mod ",
+    "def ",
+    "class ",
+    "function ",
+    "func ",
+    "interface ",
+    "type ",
+];
+
+fn is_definition(line: &str) -> bool {
+    if line.starts_with(char::is_whitespace) {
+        return false;
+    }
+    let mut line = line;
+    while let Some(rest) = DEFINITION_MODIFIERS
+        .iter()
+        .find_map(|modifier| line.strip_prefix(modifier))
+    {
+        line = rest;
+    }
+    DEFINITION_KEYWORDS
+        .iter()
+        .any(|keyword| line.starts_with(keyword))
+}
+
+fn is_comment_or_attribute(line: &str) -> bool {
+    let line = line.trim_start();
+    ["//", "#", "@", "/*", "*"]
+        .iter()
+        .any(|prefix| line.starts_with(prefix))
+}
+
+/// A chunker that splits source code on top level definitions like functions, classes and impl blocks. Every chunk is embedded with the signature of the definition it is in.
+///
+/// Comments, attributes and decorators directly above a definition are kept with the definition. Definitions longer than the maximum length are split further with [`super::ChunkStrategy::Recursive`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CodeChunker {
+    max_length: usize,
+}
+
+impl Default for CodeChunker {
+    fn default() -> Self {
+        Self { max_length: 1500 }
+    }
+}
+
+impl CodeChunker {
+    /// Create a new code chunker with a maximum chunk length of 1500 bytes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the maximum length of each chunk in bytes.
+    pub fn with_max_length(mut self, max_length: usize) -> Self {
+        self.max_length = max_length;
+        self
+    }
+
+    /// Split source code into ranges with the signature of the definition they are in.
+    pub fn chunk_str(&self, string: &str) -> Vec<TitledRange> {
+        let lines = line_ranges(string).collect::<Vec<_>>();
+        let mut sections = Vec::new();
+        let mut section_start = 0;
+        let mut title = String::new();
+
+        for (index, line) in lines.iter().enumerate() {
+            let text = string[line.clone()].trim_end();
+            if !is_definition(text) {
+                continue;
+            }
+            // Keep comments and attributes directly above the definition with the definition
+            let mut start = index;
+            while start > 0 && is_comment_or_attribute(&string[lines[start - 1].clone()]) {
+                start -= 1;
+            }
+            let start = lines[start].start.max(section_start);
+            if start > section_start {
+                sections.push((title, section_start..start));
+            }
+            title = text
+                .trim_end_matches('{')
+                .trim_end_matches(':')
+                .trim()
+                .to_string();
+            section_start = start;
+        }
+        if section_start < string.len() {
+            sections.push((title, section_start..string.len()));
+        }
+
+        let sections = sections
+            .into_iter()
+            .map(|(title, range)| (title, trim_range(string, range)))
+            .filter(|(_, range)| !range.is_empty())
+            .collect();
+        split_sections(string, sections, self.max_length)
+    }
+}
+
+#[async_trait::async_trait]
+impl Chunker for CodeChunker {
+    async fn chunk<E: Embedder + Send>(
+        &self,
+        document: &Document,
+        embedder: &E,
+    ) -> anyhow::Result<Vec<Chunk<E::VectorSpace>>> {
+        let ranges = self.chunk_str(document.body());
+        embed_titled_ranges(document, ranges, embedder).await
+    }
+
+    fn config(&self) -> String {
+        versioned_config("code", self)
+    }
+}
+
+#[test]
+fn test_section_chunking() {
+    let markdown = "Intro text.\n\n# Guide\n\nSome text.\n\n## Install\n\n```sh\n# not a heading\ncargo add kalosm\n```\n\n# Other\n\nMore text.";
+    let chunks = MarkdownChunker::new().chunk_str(markdown);
+    let chunks = chunks
+        .iter()
+        .map(|range| (range.title.as_str(), &markdown[range.byte_range.clone()]))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        chunks,
+        vec![
+            ("", "Intro text."),
+            ("Guide", "# Guide\n\nSome text."),
+            (
+                "Guide > Install",
+                "## Install\n\n```sh\n# not a heading\ncargo add kalosm\n```"
+            ),
+            ("Other", "# Other\n\nMore text."),
+        ]
+    );
+
+    let code = "use std::fs;\n\n/// Add two numbers\npub fn add(a: i32, b: i32) -> i32 {\n    a + b\n}\n\nstruct Point {\n    x: f32,\n}\n";
+    let chunks = CodeChunker::new().chunk_str(code);
+    let chunks = chunks
+        .iter()
+        .map(|range| (range.title.as_str(), &code[range.byte_range.clone()]))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        chunks,
+        vec![
+            ("", "use std::fs;"),
+            (
+                "pub fn add(a: i32, b: i32) -> i32",
+                "/// Add two numbers\npub fn add(a: i32, b: i32) -> i32 {\n    a + b\n}"
+            ),
+            ("struct Point", "struct Point {\n    x: f32,\n}"),
+        ]
+    );
+}
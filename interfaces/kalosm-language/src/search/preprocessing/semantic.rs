@@ -0,0 +1,192 @@
+use std::ops::Range;
+
+use kalosm_language_model::Embedder;
+use serde::{Deserialize, Serialize};
+
+use super::{trim_range, versioned_config, Chunker};
+use crate::context::Document;
+use crate::search::Chunk;
+
+/// Split a string into the byte ranges of each sentence. Sentences end with `.`, `!` or `?` followed by whitespace, or at a blank line.
+pub fn split_sentences(string: &str) -> Vec<Range<usize>> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut chars = string.char_indices().peekable();
+    while let Some((index, c)) = chars.next() {
+        let end = match (c, chars.peek()) {
+            ('.' | '!' | '?', Some((_, next))) if next.is_whitespace() => index + c.len_utf8(),
+            ('\n', Some((_, '\n'))) => index,
+            _ => continue,
+        };
+        let sentence = trim_range(string, start..end);
+        if !sentence.is_empty() {
+            sentences.push(sentence);
+        }
+        start = end;
+    }
+    let sentence = trim_range(string, start..string.len());
+    if !sentence.is_empty() {
+        sentences.push(sentence);
+    }
+    sentences
+}
+
+/// Find the indexes of the distances that are above the given percentile of all distances.
+fn breakpoints(distances: &[f32], percentile: f32) -> Vec<usize> {
+    if distances.is_empty() {
+        return Vec::new();
+    }
+    let mut sorted = distances.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let index = ((sorted.len() - 1) as f32 * percentile.clamp(0., 1.)).round() as usize;
+    let cutoff = sorted[index];
+    distances
+        .iter()
+        .enumerate()
+        .filter(|(_, distance)| **distance > cutoff)
+        .map(|(index, _)| index)
+        .collect()
+}
+
+/// A chunker that splits a document where the topic changes.
+///
+/// Each sentence in the document is embedded, and the document is split between sentences when the cosine distance between the neighboring sentences is above the breakpoint percentile of all distances in the document. Chunks are also split when they would grow past the maximum length.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SemanticChunker {
+    breakpoint_percentile: f32,
+    max_length: usize,
+}
+
+impl Default for SemanticChunker {
+    fn default() -> Self {
+        Self {
+            breakpoint_percentile: 0.9,
+            max_length: 1000,
+        }
+    }
+}
+
+impl SemanticChunker {
+    /// Create a new semantic chunker that splits at the 90th percentile of sentence distances with a maximum chunk length of 1000 bytes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the percentile (between 0 and 1) of sentence distances that starts a new chunk. Lower values create more, smaller chunks.
+    pub fn with_breakpoint_percentile(mut self, breakpoint_percentile: f32) -> Self {
+        self.breakpoint_percentile = breakpoint_percentile;
+        self
+    }
+
+    /// Set the maximum length of each chunk in bytes.
+    pub fn with_max_length(mut self, max_length: usize) -> Self {
+        self.max_length = max_length;
+        self
+    }
+
+    /// Group sentences into chunks given the distance between each pair of neighboring sentences.
+    fn group_sentences(&self, sentences: &[Range<usize>], distances: &[f32]) -> Vec<Range<usize>> {
+        let breakpoints = breakpoints(distances, self.breakpoint_percentile);
+        let mut chunks = Vec::new();
+        let mut current: Option<Range<usize>> = None;
+        for (index, sentence) in sentences.iter().enumerate() {
+            let split_here = index > 0 && breakpoints.contains(&(index - 1));
+            current = match current {
+                Some(chunk) if !split_here && sentence.end - chunk.start <= self.max_length => {
+                    Some(chunk.start..sentence.end)
+                }
+                Some(chunk) => {
+                    chunks.push(chunk);
+                    Some(sentence.clone())
+                }
+                None => Some(sentence.clone()),
+            };
+        }
+        chunks.extend(current);
+        chunks
+    }
+}
+
+#[async_trait::async_trait]
+impl Chunker for SemanticChunker {
+    async fn chunk<E: Embedder + Send>(
+        &self,
+        document: &Document,
+        embedder: &E,
+    ) -> anyhow::Result<Vec<Chunk<E::VectorSpace>>> {
+        let body = document.body();
+        let sentences = split_sentences(body);
+        if sentences.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let sentence_text = sentences
+            .iter()
+            .map(|range| &body[range.clone()])
+            .collect::<Vec<_>>();
+        let sentence_embeddings = embedder.embed_batch(&sentence_text).await?;
+        let distances = sentence_embeddings
+            .windows(2)
+            .map(|pair| 1. - pair[0].cosine_similarity(&pair[1]))
+            .collect::<Vec<_>>();
+
+        let ranges = self.group_sentences(&sentences, &distances);
+        let chunk_text = ranges
+            .iter()
+            .map(|range| &body[range.clone()])
+            .collect::<Vec<_>>();
+        let embeddings = embedder.embed_batch(&chunk_text).await?;
+
+        Ok(ranges
+            .into_iter()
+            .zip(embeddings)
+            .map(|(byte_range, embedding)| Chunk {
+                byte_range,
+                embeddings: vec![embedding],
+            })
+            .collect())
+    }
+
+    fn config(&self) -> String {
+        versioned_config("semantic", self)
+    }
+}
+
+#[test]
+fn test_semantic_grouping() {
+    let text = "Cats purr. Cats nap!\n\nRust compiles? Rust is fast. Done";
+    let sentences = split_sentences(text);
+    assert_eq!(
+        sentences
+            .iter()
+            .map(|range| &text[range.clone()])
+            .collect::<Vec<_>>(),
+        vec![
+            "Cats purr.",
+            "Cats nap!",
+            "Rust compiles?",
+            "Rust is fast.",
+            "Done"
+        ]
+    );
+
+    let chunker = SemanticChunker::new().with_breakpoint_percentile(0.4);
+    let chunks = chunker.group_sentences(&sentences, &[0.1, 0.8, 0.2, 0.9]);
+    assert_eq!(
+        chunks
+            .iter()
+            .map(|range| &text[range.clone()])
+            .collect::<Vec<_>>(),
+        vec![
+            "Cats purr. Cats nap!",
+            "Rust compiles? Rust is fast.",
+            "Done"
+        ]
+    );
+
+    let chunker = SemanticChunker::new()
+        .with_breakpoint_percentile(1.)
+        .with_max_length(20);
+    let chunks = chunker.group_sentences(&sentences, &[0.1, 0.8, 0.2, 0.9]);
+    assert_eq!(chunks.len(), 3);
+}
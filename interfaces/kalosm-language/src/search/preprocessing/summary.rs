@@ -6,7 +6,7 @@ use crate::{
     search::Chunk,
 };
 
-use super::{ChunkStrategy, Chunker};
+use super::{versioned_config, ChunkStrategy, Chunker};
 
 const TASK_DESCRIPTION: &str = "You generate summaries of the given text.";
 
@@ -110,4 +110,8 @@ where
         }
         Ok(chunks)
     }
+
+    fn config(&self) -> String {
+        versioned_config("summary", &self.summary.chunking)
+    }
 }
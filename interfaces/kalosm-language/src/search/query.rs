@@ -0,0 +1,436 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use kalosm_language_model::{Model, SyncModel};
+use kalosm_sample::{ParserExt, RepeatParser, StopOn};
+
+use crate::chat::{ChatHistoryItem, MessageType};
+use crate::task::{StructuredRunner, Task};
+
+const HYDE_DESCRIPTION: &str = "You write a short passage that answers the question the user asks, as if it were taken from a document about the topic. Write the passage even if you are not sure about the facts.";
+
+const MULTI_QUERY_DESCRIPTION: &str = "You rewrite search queries. Given a query, write different versions of the query that use other words to search for the same information. Write one query per line.";
+
+const CONDENSE_DESCRIPTION: &str = "You rewrite the last question in a conversation into a standalone search query that can be understood without the conversation. Restate any names or topics from the conversation that the question refers to. Only write the query.";
+
+type QueryConstraints = RepeatParser<StopOn<&'static str>>;
+
+fn line() -> StopOn<&'static str> {
+    StopOn::new("\n").filter_characters(|c| c == '\n' || !c.is_control())
+}
+
+/// A single paragraph of text.
+fn paragraph() -> StopOn<&'static str> {
+    StopOn::new("\n\n").filter_characters(|c| c == '\n' || !c.is_control())
+}
+
+fn hyde_task(instructions: impl Into<String>) -> Task<StructuredRunner<StopOn<&'static str>>> {
+    Task::builder(instructions)
+        .with_constraints(paragraph())
+        .build()
+}
+
+/// A transformation of a search query that runs before the search. A transformation turns the query into one or more queries. Search for each query and combine the results with [`fuse_rankings`], or use [`crate::vector_db::VectorDB::get_closest_to_any`].
+///
+/// Transformations can be chained with [`QueryTransform::then`].
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm_language::prelude::*;
+///
+/// #[tokio::main]
+/// async fn main() -> anyhow::Result<()> {
+///     let bert = Bert::builder().build()?;
+///     let db = VectorDB::new()?;
+///     let transform = MultiQuery::new(Llama::new_chat()).then(Hyde::new(Llama::new_chat()));
+///
+///     let queries = transform.transform("How do I reset my router?").await?;
+///     let queries = queries.iter().map(|query| query.as_str()).collect::<Vec<_>>();
+///     let embeddings = bert.embed_batch(&queries).await?;
+///     let results = db.get_closest_to_any(embeddings, 5)?;
+///     println!("{:?}", results);
+///     Ok(())
+/// }
+/// ```
+#[async_trait::async_trait]
+pub trait QueryTransform {
+    /// Transform a query into the queries to search for.
+    async fn transform(&self, query: &str) -> anyhow::Result<Vec<String>>;
+
+    /// Run another transformation on every query this transformation returns.
+    fn then<T: QueryTransform>(self, next: T) -> Then<Self, T>
+    where
+        Self: Sized,
+    {
+        Then { first: self, next }
+    }
+}
+
+/// Two query transformations that run one after the other. Created with [`QueryTransform::then`].
+pub struct Then<A, B> {
+    first: A,
+    next: B,
+}
+
+#[async_trait::async_trait]
+impl<A: QueryTransform + Send + Sync, B: QueryTransform + Send + Sync> QueryTransform
+    for Then<A, B>
+{
+    async fn transform(&self, query: &str) -> anyhow::Result<Vec<String>> {
+        let mut queries = Vec::new();
+        for query in self.first.transform(query).await? {
+            for query in self.next.transform(&query).await? {
+                if !queries.contains(&query) {
+                    queries.push(query);
+                }
+            }
+        }
+        Ok(queries)
+    }
+}
+
+/// [Hypothetical document embeddings](https://arxiv.org/abs/2212.10496) (HyDE). The model writes a passage that answers the query, and the passage is searched for instead of the query.
+///
+/// Questions are often phrased very differently from the documents that answer them. A made up answer is phrased like the documents, so its embedding is closer to them even if the facts in it are wrong.
+///
+/// The passage is a single paragraph. If the model writes an empty passage, the original query is searched for instead.
+pub struct Hyde<M: Model> {
+    model: M,
+    task: Task<StructuredRunner<StopOn<&'static str>>>,
+    keep_query: bool,
+}
+
+impl<M: Model> Hyde<M>
+where
+    <M::SyncModel as SyncModel>::Session: Send + Sync,
+{
+    /// Create a new HyDE transformation that uses the given model.
+    pub fn new(model: M) -> Self {
+        Self {
+            model,
+            task: hyde_task(HYDE_DESCRIPTION),
+            keep_query: false,
+        }
+    }
+
+    /// Set the instructions the model uses to write the hypothetical passage.
+    pub fn with_instructions(mut self, instructions: impl Into<String>) -> Self {
+        self.task = hyde_task(instructions);
+        self
+    }
+
+    /// Search for the original query as well as the hypothetical passage. Defaults to false.
+    pub fn with_query(mut self, keep_query: bool) -> Self {
+        self.keep_query = keep_query;
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl<M: Model> QueryTransform for Hyde<M>
+where
+    <M::SyncModel as SyncModel>::Session: Send + Sync,
+{
+    async fn transform(&self, query: &str) -> anyhow::Result<Vec<String>> {
+        let passage = self.task.run(query, &self.model).result().await?;
+        Ok(hyde_queries(query, &passage, self.keep_query))
+    }
+}
+
+/// The queries to search for with a hypothetical passage. The original query is searched for instead if the passage is empty.
+fn hyde_queries(query: &str, passage: &str, keep_query: bool) -> Vec<String> {
+    let query = query.trim();
+    let passage = passage.trim();
+    let mut queries = Vec::new();
+    if keep_query || passage.is_empty() {
+        queries.push(query.to_string());
+    }
+    if !passage.is_empty() && passage != query {
+        queries.push(passage.to_string());
+    }
+    queries
+}
+
+/// Multi-query expansion. The model rewrites the query in several different ways, and every version is searched for. This finds documents that only match some of the ways the query could be phrased.
+pub struct MultiQuery<M: Model> {
+    model: M,
+    description: String,
+    query_count: usize,
+    task: Task<StructuredRunner<QueryConstraints>>,
+}
+
+fn multi_query_task(
+    description: String,
+    query_count: usize,
+) -> Task<StructuredRunner<QueryConstraints>> {
+    Task::builder(description)
+        .with_constraints(line().repeat(1..=query_count))
+        .build()
+}
+
+impl<M: Model> MultiQuery<M>
+where
+    <M::SyncModel as SyncModel>::Session: Send + Sync,
+{
+    /// Create a new multi-query expansion that uses the given model.
+    pub fn new(model: M) -> Self {
+        Self {
+            model,
+            description: MULTI_QUERY_DESCRIPTION.to_string(),
+            query_count: 3,
+            task: multi_query_task(MULTI_QUERY_DESCRIPTION.to_string(), 3),
+        }
+    }
+
+    /// Set the maximum number of rewritten queries. The original query is always searched for as well. Defaults to 3.
+    pub fn with_query_count(mut self, query_count: usize) -> Self {
+        self.query_count = query_count.max(1);
+        self.task = multi_query_task(self.description.clone(), self.query_count);
+        self
+    }
+
+    /// Set the instructions the model uses to rewrite the query. The model always writes one query per line.
+    pub fn with_instructions(mut self, instructions: impl Into<String>) -> Self {
+        self.description = instructions.into();
+        self.task = multi_query_task(self.description.clone(), self.query_count);
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl<M: Model> QueryTransform for MultiQuery<M>
+where
+    <M::SyncModel as SyncModel>::Session: Send + Sync,
+{
+    async fn transform(&self, query: &str) -> anyhow::Result<Vec<String>> {
+        let rewritten = self.task.run(query, &self.model).result().await?;
+        Ok(with_rewritten_queries(query, rewritten))
+    }
+}
+
+/// Combine the original query with the rewritten queries. The original query always comes first, and empty or repeated rewrites are skipped.
+fn with_rewritten_queries(query: &str, rewritten: impl IntoIterator<Item = String>) -> Vec<String> {
+    let mut queries = vec![query.trim().to_string()];
+    for rewritten in rewritten {
+        let rewritten = rewritten.trim();
+        if !rewritten.is_empty() && !queries.iter().any(|query| query == rewritten) {
+            queries.push(rewritten.to_string());
+        }
+    }
+    queries
+}
+
+/// Conversational query condensation. Follow up questions in a chat like "How old is he?" can't be searched for without the conversation. The model rewrites the question into a standalone query with the names and topics from the [chat history](crate::chat::Chat::history).
+pub struct QueryCondenser<M: Model> {
+    model: M,
+    task: Task<StructuredRunner<StopOn<&'static str>>>,
+    history: Vec<ChatHistoryItem>,
+    max_history: usize,
+}
+
+impl<M: Model> QueryCondenser<M>
+where
+    <M::SyncModel as SyncModel>::Session: Send + Sync,
+{
+    /// Create a new query condenser that uses the given model.
+    pub fn new(model: M) -> Self {
+        Self {
+            model,
+            task: Task::builder(CONDENSE_DESCRIPTION)
+                .with_constraints(line())
+                .build(),
+            history: Vec::new(),
+            max_history: 6,
+        }
+    }
+
+    /// Set the chat history the question is asked in.
+    pub fn with_history(mut self, history: Vec<ChatHistoryItem>) -> Self {
+        self.history = history;
+        self
+    }
+
+    /// Replace the chat history the question is asked in.
+    pub fn set_history(&mut self, history: Vec<ChatHistoryItem>) {
+        self.history = history;
+    }
+
+    /// Set the maximum number of recent messages shown to the model. Defaults to 6.
+    pub fn with_max_history(mut self, max_history: usize) -> Self {
+        self.max_history = max_history;
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl<M: Model> QueryTransform for QueryCondenser<M>
+where
+    <M::SyncModel as SyncModel>::Session: Send + Sync,
+{
+    async fn transform(&self, query: &str) -> anyhow::Result<Vec<String>> {
+        let Some(conversation) = format_history(&self.history, self.max_history) else {
+            return Ok(vec![query.trim().to_string()]);
+        };
+        let prompt = format!("{conversation}\nQuestion: {}", query.trim());
+        let condensed = self.task.run(prompt, &self.model).result().await?;
+        let condensed = condensed.trim();
+        if condensed.is_empty() {
+            Ok(vec![query.trim().to_string()])
+        } else {
+            Ok(vec![condensed.to_string()])
+        }
+    }
+}
+
+/// Format the most recent messages of a chat, or return None if there are no messages besides the system prompt.
+fn format_history(history: &[ChatHistoryItem], max_history: usize) -> Option<String> {
+    let messages = history
+        .iter()
+        .filter(|item| item.ty() != MessageType::SystemPrompt)
+        .collect::<Vec<_>>();
+    if messages.is_empty() || max_history == 0 {
+        return None;
+    }
+    let mut conversation = String::new();
+    for item in &messages[messages.len().saturating_sub(max_history)..] {
+        let speaker = match item.ty() {
+            MessageType::UserMessage => "User",
+            _ => "Assistant",
+        };
+        conversation += &format!("{}: {}\n", speaker, item.contents().trim());
+    }
+    Some(conversation)
+}
+
+/// Combine rankings from several searches with [reciprocal rank fusion](https://plg.uwaterloo.ca/~gvcormac/cormacksigir09-rrf.pdf). Each item is scored with the sum of `1 / (k + rank)` over every ranking it appears in. Returns the items from the best score to the worst score.
+///
+/// Items that appear in several rankings are ranked higher than items that only one search found, which makes the results of multiple queries more reliable than the results of any one query. `k` is usually 60.
+pub fn fuse_rankings<T, K: Eq + Hash>(
+    rankings: impl IntoIterator<Item = impl IntoIterator<Item = T>>,
+    key: impl Fn(&T) -> K,
+    k: f32,
+) -> Vec<(T, f32)> {
+    let mut fused: Vec<(T, f32)> = Vec::new();
+    let mut positions: HashMap<K, usize> = HashMap::new();
+    for ranking in rankings {
+        for (rank, item) in ranking.into_iter().enumerate() {
+            let score = 1. / (k + rank as f32 + 1.);
+            match positions.get(&key(&item)) {
+                Some(&position) => fused[position].1 += score,
+                None => {
+                    positions.insert(key(&item), fused.len());
+                    fused.push((item, score));
+                }
+            }
+        }
+    }
+    // The sort is stable, so ties keep the order they were first found in
+    fused.sort_by(|a, b| b.1.total_cmp(&a.1));
+    fused
+}
+
+#[test]
+fn fused_rankings_prefer_items_found_by_every_query() {
+    let fused = fuse_rankings(
+        [vec!["a", "b", "c"], vec!["c", "d"], vec!["e", "c"]],
+        |item| *item,
+        60.,
+    );
+    let order = fused.iter().map(|(item, _)| *item).collect::<Vec<_>>();
+    assert_eq!(order, vec!["c", "a", "e", "b", "d"]);
+    assert!((fused[0].1 - (1. / 63. + 1. / 61. + 1. / 62.)).abs() < 1e-6);
+}
+
+#[test]
+fn format_history_keeps_the_most_recent_messages() {
+    let history = vec![
+        ChatHistoryItem::new(MessageType::SystemPrompt, "Be helpful"),
+        ChatHistoryItem::new(MessageType::UserMessage, "Who wrote Dune?"),
+        ChatHistoryItem::new(MessageType::ModelAnswer, "Frank Herbert."),
+    ];
+    assert_eq!(
+        format_history(&history, 1).as_deref(),
+        Some("Assistant: Frank Herbert.\n")
+    );
+    assert_eq!(format_history(&history[..1], 6), None);
+}
+
+#[test]
+fn hyde_falls_back_to_the_query_without_a_passage() {
+    let passage = "Routers are reset by holding the reset button for ten seconds.\n\n";
+    assert_eq!(
+        hyde_queries("How do I reset my router?", passage, false),
+        vec!["Routers are reset by holding the reset button for ten seconds."]
+    );
+    assert_eq!(
+        hyde_queries("How do I reset my router?", passage, true),
+        vec![
+            "How do I reset my router?",
+            "Routers are reset by holding the reset button for ten seconds.",
+        ]
+    );
+    assert_eq!(
+        hyde_queries(" How do I reset my router?\n", " \n", false),
+        vec!["How do I reset my router?"]
+    );
+    assert_eq!(
+        hyde_queries("How do I reset my router?", "", true),
+        vec!["How do I reset my router?"]
+    );
+}
+
+#[test]
+fn multi_query_keeps_the_original_query() {
+    let rewritten = [
+        " router reset steps ",
+        "",
+        "How do I reset my router?",
+        "router reset steps",
+        "factory reset a router",
+    ]
+    .map(String::from);
+    assert_eq!(
+        with_rewritten_queries(" How do I reset my router?\n", rewritten),
+        vec![
+            "How do I reset my router?",
+            "router reset steps",
+            "factory reset a router",
+        ]
+    );
+    assert_eq!(with_rewritten_queries("router", Vec::new()), vec!["router"]);
+}
+
+#[tokio::test]
+async fn then_runs_the_next_transform_on_every_query_without_duplicates() {
+    /// Returns the query and a lowercase copy of it.
+    struct Lowercase;
+
+    #[async_trait::async_trait]
+    impl QueryTransform for Lowercase {
+        async fn transform(&self, query: &str) -> anyhow::Result<Vec<String>> {
+            Ok(vec![query.to_string(), query.to_lowercase()])
+        }
+    }
+
+    /// Removes the question mark from the query.
+    struct Statement;
+
+    #[async_trait::async_trait]
+    impl QueryTransform for Statement {
+        async fn transform(&self, query: &str) -> anyhow::Result<Vec<String>> {
+            Ok(vec![query.trim_end_matches('?').to_string()])
+        }
+    }
+
+    let queries = Lowercase.then(Statement).transform("Reset?").await.unwrap();
+    assert_eq!(queries, vec!["Reset", "reset"]);
+
+    // Queries the next transform returns for different inputs are only searched once
+    let queries = Statement
+        .then(Lowercase)
+        .then(Lowercase)
+        .transform("Reset?")
+        .await
+        .unwrap();
+    assert_eq!(queries, vec!["Reset", "reset"]);
+}
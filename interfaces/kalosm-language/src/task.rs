@@ -1,7 +1,9 @@
 //! A task interface that builds on top of [`kalosm_language_model::Model`]
 
+use crate::template::{ChatFormat, ChatTemplate};
 use anyhow::Result;
 use futures_util::Stream;
+use futures_util::StreamExt;
 use kalosm_language_model::ChatMarkers;
 use kalosm_language_model::Session;
 use kalosm_language_model::StructureParserResult;
@@ -10,72 +12,37 @@ use kalosm_sample::{CreateParserState, Parser};
 use kalosm_streams::text_stream::ChannelTextStream;
 use llm_samplers::types::Sampler;
 use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
 use std::any::Any;
 use std::any::TypeId;
+use std::collections::hash_map::Entry;
+use std::future::Future;
+use std::ops::RangeInclusive;
+use std::pin::Pin;
 use std::sync::Arc;
 use std::sync::RwLock;
 use tokio::sync::{mpsc::unbounded_channel, oneshot};
 
 struct TaskSessionEntry<S> {
     cached_prompt: String,
-    after_input: String,
+    format: ChatFormat,
+    stop_on: String,
     session: Option<S>,
 }
 
 impl<S: Session> TaskSessionEntry<S> {
-    pub(crate) fn new(
-        markers: Option<ChatMarkers>,
-        system_prompt: String,
-        examples: &[TaskExample],
-    ) -> Self {
-        let (cached_prompt, after_input) = match markers {
-            Some(markers) => {
-                let mut cached_prompt = markers.system_prompt_marker.to_string() + &system_prompt;
-                cached_prompt += markers.end_system_prompt_marker;
-
-                for example in examples {
-                    cached_prompt += markers.user_marker;
-                    cached_prompt += &example.input;
-                    cached_prompt += markers.end_user_marker;
-                    cached_prompt += markers.assistant_marker;
-                    cached_prompt += &example.output;
-                    cached_prompt += markers.end_assistant_marker;
-                }
-
-                cached_prompt += markers.user_marker;
-                (
-                    cached_prompt,
-                    markers.end_user_marker.to_string() + markers.assistant_marker,
-                )
-            }
-            None => {
-                let mut cached_prompt = "# Instruction\n".to_string();
-                cached_prompt += &system_prompt;
-                if !system_prompt.ends_with('\n') {
-                    cached_prompt += "\n";
-                }
-
-                for example in examples {
-                    cached_prompt += "# Input\n";
-                    cached_prompt += &example.input;
-                    if !example.input.ends_with('\n') {
-                        cached_prompt += "\n";
-                    }
-                    cached_prompt += "# Output\n";
-                    cached_prompt += &example.output;
-                    if !example.output.ends_with('\n') {
-                        cached_prompt += "\n";
-                    }
-                }
-
-                cached_prompt += "# Input\n";
-                (cached_prompt, "# Output\n".to_string())
-            }
-        };
+    pub(crate) fn new(format: &ChatFormat, system_prompt: &str, examples: &[TaskExample]) -> Self {
+        let mut cached_prompt = format.system(system_prompt);
+        for example in examples {
+            cached_prompt += &format.user(&example.input);
+            cached_prompt += &format.assistant(&example.output);
+        }
+        cached_prompt += format.user_prefix();
 
         Self {
             cached_prompt,
-            after_input,
+            format: format.clone(),
+            stop_on: format.stop_on().to_string(),
             session: None,
         }
     }
@@ -111,7 +78,13 @@ impl<S: Session> TaskSessionEntry<S> {
     ) -> Result<S> {
         let mut session = self.create_session(model)?;
 
-        let prompt = message.to_string() + &self.after_input;
+        // The cached prompt already ends with the text before the user message
+        let user = self.format.user(message);
+        let prompt = user
+            .strip_prefix(self.format.user_prefix())
+            .unwrap_or(&user)
+            .to_string()
+            + self.format.assistant_prefix();
 
         // Feed the message to the model.
         model.feed_text(&mut session, &prompt, Some(0))?;
@@ -125,18 +98,51 @@ struct TaskSessions {
     sessions: RwLock<FxHashMap<TypeId, Box<dyn Any + Send + Sync>>>,
     system_prompt: String,
     examples: Vec<TaskExample>,
+    chat_template: ChatTemplate,
 }
 
 impl TaskSessions {
     #[allow(clippy::too_many_arguments)]
     /// Creates a new [`TaskSessions`].
-    pub(crate) fn new(system_prompt: String, examples: Vec<TaskExample>) -> Self {
+    pub(crate) fn new(
+        system_prompt: String,
+        examples: Vec<TaskExample>,
+        chat_template: ChatTemplate,
+    ) -> Self {
         Self {
             sessions: RwLock::new(FxHashMap::default()),
             system_prompt,
             examples,
+            chat_template,
         }
     }
+
+    /// Get the cached session entry for a model, rendering the chat template for the model if this is the first time the task is run with it.
+    fn entry<'a, M: Model>(
+        &self,
+        sessions: &'a mut FxHashMap<TypeId, Box<dyn Any + Send + Sync>>,
+        chat_markers: Option<&ChatMarkers>,
+    ) -> Result<&'a mut TaskSessionEntry<<M::SyncModel as SyncModel>::Session>>
+    where
+        <<M as kalosm_language_model::Model>::SyncModel as kalosm_language_model::SyncModel>::Session: Send + Sync,
+    {
+        let entry = match sessions.entry(TypeId::of::<M>()) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let format = self.chat_template.render(chat_markers)?;
+                entry.insert(Box::new(
+                    TaskSessionEntry::<<M::SyncModel as SyncModel>::Session>::new(
+                        &format,
+                        &self.system_prompt,
+                        &self.examples,
+                    ),
+                ))
+            }
+        };
+        entry
+            .downcast_mut()
+            .ok_or_else(|| anyhow::anyhow!("Cached task session has the wrong type"))
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -145,6 +151,44 @@ struct TaskExample {
     output: String,
 }
 
+/// The prompt of a [`TaskBuilder`]: the description of the task and the examples it is shown. This can be saved to disk and applied to a builder later with [`TaskBuilder::with_config`], for example to reuse the result of a prompt optimizer.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaskConfig {
+    /// The description of the task that is used as the system prompt.
+    pub description: String,
+    /// The examples of the task as (input, output) pairs.
+    pub examples: Vec<(String, String)>,
+}
+
+impl TaskConfig {
+    /// Create a new config with a description and no examples.
+    pub fn new(description: impl Into<String>) -> Self {
+        Self {
+            description: description.into(),
+            examples: Vec::new(),
+        }
+    }
+
+    /// Add an example to the config.
+    pub fn with_example(mut self, input: impl Into<String>, output: impl Into<String>) -> Self {
+        self.examples.push((input.into(), output.into()));
+        self
+    }
+
+    /// Save the config to a JSON file.
+    pub fn save(&self, path: impl AsRef<std::path::Path>) -> Result<()> {
+        let file = std::fs::File::create(path)?;
+        serde_json::to_writer_pretty(std::io::BufWriter::new(file), self)?;
+        Ok(())
+    }
+
+    /// Load a config from a JSON file created with [`TaskConfig::save`].
+    pub fn load(path: impl AsRef<std::path::Path>) -> Result<Self> {
+        let file = std::fs::File::open(path)?;
+        Ok(serde_json::from_reader(std::io::BufReader::new(file))?)
+    }
+}
+
 /// A marker for no parser.
 #[derive(Debug, Clone)]
 pub struct NoParser;
@@ -156,6 +200,8 @@ pub struct TaskBuilder<P = NoParser> {
     sampler: Arc<std::sync::Mutex<dyn Sampler + Send + Sync>>,
     constraints: P,
     examples: Vec<TaskExample>,
+    chat_template: ChatTemplate,
+    self_consistency: Option<SelfConsistency>,
 }
 
 impl TaskBuilder {
@@ -167,6 +213,8 @@ impl TaskBuilder {
             )),
             constraints: NoParser,
             examples: Vec::new(),
+            chat_template: ChatTemplate::default(),
+            self_consistency: None,
         }
     }
 }
@@ -188,6 +236,8 @@ impl<P: TaskBuilderReturn + Send + Sync + 'static> TaskBuilder<P> {
             system_prompt: self.system_prompt,
             sampler: self.sampler,
             examples: self.examples,
+            chat_template: self.chat_template,
+            self_consistency: self.self_consistency,
         }
     }
 
@@ -212,6 +262,35 @@ impl<P: TaskBuilderReturn + Send + Sync + 'static> TaskBuilder<P> {
         self
     }
 
+    /// Set the [`ChatTemplate`] used to format the system prompt, examples and input of the task. By default, the task uses the model's chat markers if it has them, and a markdown instruction format otherwise.
+    pub fn with_chat_template(mut self, chat_template: ChatTemplate) -> Self {
+        self.chat_template = chat_template;
+        self
+    }
+
+    /// Get the description and examples of the task.
+    pub fn config(&self) -> TaskConfig {
+        TaskConfig {
+            description: self.system_prompt.clone(),
+            examples: self
+                .examples
+                .iter()
+                .map(|example| (example.input.clone(), example.output.clone()))
+                .collect(),
+        }
+    }
+
+    /// Replace the description and examples of the task with the ones in the config.
+    pub fn with_config(mut self, config: TaskConfig) -> Self {
+        self.system_prompt = config.description;
+        self.examples = config
+            .examples
+            .into_iter()
+            .map(|(input, output)| TaskExample { input, output })
+            .collect();
+        self
+    }
+
     /// Build a [`Task`] from a [`TaskBuilder`].
     pub fn build(self) -> Task<<P as TaskBuilderReturn>::Output> {
         let inner = <P as TaskBuilderReturn>::build(self);
@@ -219,6 +298,154 @@ impl<P: TaskBuilderReturn + Send + Sync + 'static> TaskBuilder<P> {
     }
 }
 
+impl<P> TaskBuilder<P>
+where
+    P: Parser + CreateParserState + Sync + Send + 'static,
+{
+    /// Generate multiple constrained responses for every input and pick the answer most samples agree on.
+    ///
+    /// Each sample is generated with a different temperature (see [`TaskBuilder::with_self_consistency_temperatures`]). The stream returned by [`Task::run`] will only contain the text of the chosen answer. Use [`Task::run_self_consistent`] to get every sample and the agreement score.
+    ///
+    /// > **Note**: The samples use the default [`GenerationParameters`] sampler with the temperature changed instead of the sampler set with [`TaskBuilder::with_sampler`]. With a single sample, self-consistency is disabled and the sampler set with [`TaskBuilder::with_sampler`] is used.
+    pub fn with_self_consistency(mut self, samples: usize) -> Self {
+        let temperatures = self
+            .self_consistency
+            .map(|s| s.temperatures)
+            .unwrap_or(SelfConsistency::DEFAULT_TEMPERATURES);
+        self.self_consistency = Some(SelfConsistency {
+            samples: samples.max(1),
+            temperatures,
+        });
+        self
+    }
+
+    /// Set the range of temperatures the samples are spread over when self-consistency is enabled. (default: 0.3..=1.0)
+    ///
+    /// This does not enable self-consistency. Use [`TaskBuilder::with_self_consistency`] to set the number of samples.
+    pub fn with_self_consistency_temperatures(mut self, temperatures: RangeInclusive<f32>) -> Self {
+        let temperatures = (*temperatures.start(), *temperatures.end());
+        self.self_consistency = Some(match self.self_consistency {
+            Some(self_consistency) => SelfConsistency {
+                temperatures,
+                ..self_consistency
+            },
+            // A single sample keeps self-consistency disabled
+            None => SelfConsistency {
+                samples: 1,
+                temperatures,
+            },
+        });
+        self
+    }
+}
+
+/// The settings for generating multiple samples for each input of a task.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct SelfConsistency {
+    samples: usize,
+    temperatures: (f32, f32),
+}
+
+impl SelfConsistency {
+    const DEFAULT_TEMPERATURES: (f32, f32) = (0.3, 1.0);
+
+    /// The temperature to use for each sample, spread evenly over the temperature range.
+    fn temperatures(&self) -> impl Iterator<Item = f32> {
+        let (start, end) = self.temperatures;
+        let steps = self.samples.saturating_sub(1).max(1) as f32;
+        (0..self.samples).map(move |i| start + (end - start) * i as f32 / steps)
+    }
+}
+
+/// A single response generated while running a task with self-consistency.
+#[derive(Debug, Clone)]
+pub struct TaskSample<O> {
+    /// The raw text the model generated.
+    pub text: String,
+    /// The parsed output of the constraints.
+    pub output: O,
+    /// The temperature used to generate the sample.
+    pub temperature: f32,
+}
+
+/// The result of running a task with self-consistency. Created with [`Task::run_self_consistent`].
+#[derive(Debug, Clone)]
+pub struct SelfConsistentResult<O> {
+    samples: Vec<TaskSample<O>>,
+    chosen: usize,
+    agreement: f64,
+}
+
+impl<O> SelfConsistentResult<O> {
+    /// Choose the answer that the most samples generated. Samples are compared by their generated text with surrounding whitespace removed.
+    ///
+    /// # Panics
+    /// Panics if there are no samples.
+    pub fn majority(samples: Vec<TaskSample<O>>) -> Self {
+        assert!(!samples.is_empty(), "Cannot vote without any samples");
+        let mut best = (0, 0);
+        for (i, sample) in samples.iter().enumerate() {
+            let text = sample.text.trim();
+            let votes = samples.iter().filter(|s| s.text.trim() == text).count();
+            if votes > best.1 {
+                best = (i, votes);
+            }
+        }
+        let agreement = best.1 as f64 / samples.len() as f64;
+        Self {
+            samples,
+            chosen: best.0,
+            agreement,
+        }
+    }
+
+    /// Choose a different sample as the answer with the given agreement score.
+    ///
+    /// # Panics
+    /// Panics if the index is out of bounds.
+    pub fn choose(mut self, index: usize, agreement: f64) -> Self {
+        assert!(index < self.samples.len(), "Sample index out of bounds");
+        self.chosen = index;
+        self.agreement = agreement;
+        self
+    }
+
+    /// Get all of the samples that were generated.
+    pub fn samples(&self) -> &[TaskSample<O>] {
+        &self.samples
+    }
+
+    /// Get the index of the chosen sample.
+    pub fn chosen_index(&self) -> usize {
+        self.chosen
+    }
+
+    /// Get the chosen sample.
+    pub fn chosen(&self) -> &TaskSample<O> {
+        &self.samples[self.chosen]
+    }
+
+    /// Get the text of the chosen answer.
+    pub fn text(&self) -> &str {
+        &self.chosen().text
+    }
+
+    /// Get the parsed output of the chosen answer.
+    pub fn output(&self) -> &O {
+        &self.chosen().output
+    }
+
+    /// Take the parsed output of the chosen answer.
+    pub fn into_output(mut self) -> O {
+        self.samples.swap_remove(self.chosen).output
+    }
+
+    /// Get the agreement score between 0 and 1. For a majority vote, this is the fraction of samples that generated the chosen answer.
+    pub fn agreement(&self) -> f64 {
+        self.agreement
+    }
+}
+
 /// A trait for returning the output of a [`TaskBuilder`].
 pub trait TaskBuilderReturn
 where
@@ -239,10 +466,11 @@ impl TaskBuilderReturn for NoParser {
             system_prompt,
             sampler,
             examples,
+            chat_template,
             ..
         } = task_builder;
 
-        let sessions = TaskSessions::new(system_prompt.clone(), examples.clone());
+        let sessions = TaskSessions::new(system_prompt.clone(), examples.clone(), chat_template);
         UnstructuredRunner {
             sessions: Arc::new(sessions),
             sampler,
@@ -262,35 +490,22 @@ impl TaskRunner for UnstructuredRunner {
     fn run<M: Model>(&self, input: String, model: & M) -> Self::Output  where <<M as kalosm_language_model::Model>::SyncModel as kalosm_language_model::SyncModel>::Session: Send + Sync{
         let chat_markers = model.chat_markers();
 
-        let stop_on = chat_markers
-            .as_ref()
-            .map(|m| m.end_assistant_marker.to_string())
-            .unwrap_or_else(|| "# Input".to_string());
-
         let (tx, rx) = unbounded_channel();
 
         let sampler = self.sampler.clone();
-        let stop_on = stop_on.clone();
         let sessions = self.sessions.clone();
 
         model.run_sync(move |model| {
             Box::pin(async move {
                 let mut sessions_write = sessions.sessions.write().unwrap();
-                let session_entry: &mut TaskSessionEntry<<M::SyncModel as SyncModel>::Session> = {
-                    sessions_write
-                        .entry(TypeId::of::<M>())
-                        .or_insert_with(|| {
-                            Box::new(
-                                TaskSessionEntry::<<M::SyncModel as SyncModel>::Session>::new(
-                                    chat_markers.clone(),
-                                    sessions.system_prompt.clone(),
-                                    &sessions.examples,
-                                ),
-                            )
-                        })
-                        .downcast_mut()
-                        .unwrap()
+                let session_entry = match sessions.entry::<M>(&mut sessions_write, chat_markers.as_ref()) {
+                    Ok(session_entry) => session_entry,
+                    Err(err) => {
+                        tracing::error!("Failed to render the task prompt: {}", err);
+                        return;
+                    }
                 };
+                let stop_on = session_entry.stop_on.clone();
                 let mut session = match session_entry.start_session(&input, model) {
                     Ok(session) => session,
                     Err(err) => {
@@ -319,6 +534,17 @@ impl TaskRunner for UnstructuredRunner {
     }
 }
 
+impl TaskResponse for UnstructuredRunner {
+    type Response = String;
+
+    fn response(output: Self::Output) -> Pin<Box<dyn Future<Output = Result<Self::Response>> + Send>> {
+        Box::pin(async move {
+            use kalosm_streams::text_stream::TextStream;
+            Ok(output.all_text().await)
+        })
+    }
+}
+
 impl<P: Parser + CreateParserState + Sync + Send + 'static> TaskBuilderReturn for P
 where
     <P as Parser>::Output: Clone + Send + 'static,
@@ -332,6 +558,8 @@ where
             sampler,
             constraints,
             examples,
+            chat_template,
+            self_consistency,
         } = task_builder;
 
         let arc_parser = Arc::new(constraints);
@@ -348,12 +576,13 @@ where
             }
         }
 
-        let sessions = TaskSessions::new(system_prompt, examples);
+        let sessions = TaskSessions::new(system_prompt, examples, chat_template);
 
         StructuredRunner {
             sessions: Arc::new(sessions),
             sampler,
             parser: arc_parser,
+            self_consistency,
         }
     }
 }
@@ -363,6 +592,114 @@ pub struct StructuredRunner<P> {
     sessions: Arc<TaskSessions>,
     sampler: Arc<std::sync::Mutex<dyn Sampler + Send + Sync>>,
     parser: Arc<P>,
+    self_consistency: Option<SelfConsistency>,
+}
+
+impl<P> StructuredRunner<P>
+where
+    P: Parser + CreateParserState + Sync + Send + 'static,
+    <P as Parser>::Output: Clone + Send + 'static,
+    <P as Parser>::PartialState: Sync + Send,
+{
+    /// The self-consistency settings if more than one sample is generated for each input.
+    fn enabled_self_consistency(&self) -> Option<SelfConsistency> {
+        self.self_consistency.filter(|s| s.samples > 1)
+    }
+
+    /// Generate one sample for each temperature in the self-consistency settings and pass them to the callback. If the model cannot run the samples, the callback is called with the error.
+    fn run_samples<M: Model>(
+        &self,
+        input: String,
+        model: &M,
+        self_consistency: SelfConsistency,
+        on_samples: impl FnOnce(Result<Vec<TaskSample<P::Output>>>) + Send + 'static,
+    ) where
+        <<M as kalosm_language_model::Model>::SyncModel as kalosm_language_model::SyncModel>::Session: Send + Sync,
+    {
+        let arc_parser = self.parser.clone();
+        let sessions = self.sessions.clone();
+        let chat_markers = model.chat_markers();
+        // The callback is shared with the error path in case the model never runs the closure
+        let on_samples = Arc::new(std::sync::Mutex::new(Some(on_samples)));
+        let on_run_error = on_samples.clone();
+
+        let result = model.run_sync(move |model| {
+            Box::pin(async move {
+                let Some(on_samples) = on_samples.lock().unwrap().take() else {
+                    return;
+                };
+                let mut sessions_write = sessions.sessions.write().unwrap();
+                let session_entry = match sessions.entry::<M>(&mut sessions_write, chat_markers.as_ref()) {
+                    Ok(session_entry) => session_entry,
+                    Err(err) => {
+                        tracing::error!("Failed to render the task prompt: {}", err);
+                        on_samples(Err(err));
+                        return;
+                    }
+                };
+                let span = tracing::span!(tracing::Level::TRACE, "Task self-consistency session");
+                let _span = span.enter();
+
+                let mut samples = Vec::with_capacity(self_consistency.samples);
+                let mut last_error = None;
+                for temperature in self_consistency.temperatures() {
+                    let mut session = match session_entry.start_session(&input, model) {
+                        Ok(session) => session,
+                        Err(err) => {
+                            tracing::error!("Failed to start session: {}", err);
+                            last_error = Some(err);
+                            continue;
+                        }
+                    };
+
+                    let sampler = Arc::new(std::sync::Mutex::new(
+                        GenerationParameters::default()
+                            .with_temperature(temperature)
+                            .sampler(),
+                    ));
+                    let state = arc_parser.create_parser_state();
+                    let mut text = String::new();
+                    let on_token = |tok: String| {
+                        tracing::trace!("Task generated token: {}", tok);
+                        text += &tok;
+                        Ok(())
+                    };
+                    match model.generate_structured(
+                        &mut session,
+                        &input,
+                        arc_parser.clone(),
+                        state,
+                        sampler,
+                        on_token,
+                    ) {
+                        Ok(output) => samples.push(TaskSample {
+                            text,
+                            output,
+                            temperature,
+                        }),
+                        Err(err) => {
+                            tracing::warn!("Failed to generate sample at temperature {}: {}", temperature, err);
+                            last_error = Some(err);
+                        }
+                    }
+                }
+
+                if samples.is_empty() {
+                    on_samples(Err(last_error
+                        .unwrap_or_else(|| anyhow::anyhow!("No samples were generated"))));
+                } else {
+                    on_samples(Ok(samples));
+                }
+            })
+        });
+
+        if let Err(err) = result {
+            tracing::error!("Failed to run the task samples: {}", err);
+            if let Some(on_samples) = on_run_error.lock().unwrap().take() {
+                on_samples(Err(err));
+            }
+        }
+    }
 }
 
 impl<P> TaskRunner for StructuredRunner<P>
@@ -376,6 +713,22 @@ where
     fn run<M: Model>(&self, input: String, model: & M) -> Self::Output where <<M as kalosm_language_model::Model>::SyncModel as kalosm_language_model::SyncModel>::Session: Send + Sync{
         let (tx, rx) = unbounded_channel();
         let (parsed_tx, parsed_rx) = oneshot::channel();
+
+        if let Some(self_consistency) = self.enabled_self_consistency() {
+            self.run_samples(input, model, self_consistency, move |samples| {
+                let result = samples.map(|samples| {
+                    let result = SelfConsistentResult::majority(samples);
+                    let _ = tx.send(result.text().to_string());
+                    result.into_output()
+                });
+                if parsed_tx.send(result).is_err() {
+                    tracing::error!("Failed to send parsed result");
+                }
+            });
+
+            return StructureParserResult::new(rx.into(), parsed_rx);
+        }
+
         let arc_parser = self.parser.clone();
         let sampler = self.sampler.clone();
         let sessions = self.sessions.clone();
@@ -384,20 +737,13 @@ where
         model.run_sync(move |model| {
             Box::pin(async move {
                 let mut sessions_write = sessions.sessions.write().unwrap();
-                let session_entry: &mut TaskSessionEntry<<M::SyncModel as SyncModel>::Session> = {
-                    sessions_write
-                        .entry(TypeId::of::<M>())
-                        .or_insert_with(|| {
-                            Box::new(
-                                TaskSessionEntry::<<M::SyncModel as SyncModel>::Session>::new(
-                                    chat_markers,
-                                    sessions.system_prompt.clone(),
-                                    &sessions.examples,
-                                ),
-                            )
-                        })
-                        .downcast_mut()
-                        .unwrap()
+                let session_entry = match sessions.entry::<M>(&mut sessions_write, chat_markers.as_ref()) {
+                    Ok(session_entry) => session_entry,
+                    Err(err) => {
+                        tracing::error!("Failed to render the task prompt: {}", err);
+                        let _ = parsed_tx.send(Err(err));
+                        return;
+                    }
                 };
                 let span = tracing::span!(tracing::Level::TRACE, "Task session");
                 let _span = span.enter();
@@ -434,6 +780,24 @@ where
     }
 }
 
+impl<P> TaskResponse for StructuredRunner<P>
+where
+    P: Parser + CreateParserState + Sync + Send + 'static,
+    <P as Parser>::Output: Clone + Send + 'static,
+    <P as Parser>::PartialState: Sync + Send,
+{
+    type Response = P::Output;
+
+    fn response(output: Self::Output) -> Pin<Box<dyn Future<Output = Result<Self::Response>> + Send>> {
+        let (_, result) = output.split();
+        Box::pin(async move {
+            result
+                .await
+                .map_err(|_| anyhow::anyhow!("Task stopped before producing a result"))?
+        })
+    }
+}
+
 // This is essentially a manual implementation of a closure so you can name the type
 /// Something that can run a task.
 pub trait TaskRunner {
@@ -444,6 +808,15 @@ pub trait TaskRunner {
     fn run<M: Model>(&self, input: String, model: & M) -> Self::Output where <<M as kalosm_language_model::Model>::SyncModel as kalosm_language_model::SyncModel>::Session: Send + Sync;
 }
 
+/// A [`TaskRunner`] with a final response that can be awaited once the output stream has finished. This is required to run a task over a batch of inputs with [`Task::run_batch`].
+pub trait TaskResponse: TaskRunner {
+    /// The final response of the task once the output stream has finished.
+    type Response: Send + 'static;
+
+    /// Wait for the output of [`TaskRunner::run`] to finish and return the final response. For structured tasks, this returns an error if the response could not be parsed.
+    fn response(output: Self::Output) -> Pin<Box<dyn Future<Output = Result<Self::Response>> + Send>>;
+}
+
 /// A task session lets you efficiently run a task with a model. The task session will reuse the model's cache to avoid re-feeding the task prompt repeatedly.
 ///
 /// # Example
@@ -490,6 +863,62 @@ impl Task {
     }
 }
 
+impl<P> Task<StructuredRunner<P>>
+where
+    P: Parser + CreateParserState + Sync + Send + 'static,
+    <P as Parser>::Output: Clone + Send + 'static,
+    <P as Parser>::PartialState: Sync + Send,
+{
+    /// Run the task with a message and return every sample along with the majority answer and agreement score.
+    ///
+    /// The number of samples is set with [`TaskBuilder::with_self_consistency`]. If self-consistency was not enabled, a single sample is generated.
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// use kalosm_language::prelude::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let llm = Llama::new_chat();
+    ///     let task = Task::builder("You answer arithmetic questions with a single number.")
+    ///         .with_constraints(LiteralParser::new("Answer: ").then(IntegerParser::new(0..=1000)))
+    ///         .with_self_consistency(5)
+    ///         .build();
+    ///
+    ///     let result = task.run_self_consistent("What is 12 * 12?", &llm).await.unwrap();
+    ///     println!("{} (agreement: {:.2})", result.text(), result.agreement());
+    /// }
+    /// ```
+    pub async fn run_self_consistent<M>(
+        &self,
+        message: impl Into<String>,
+        model: &M,
+    ) -> Result<SelfConsistentResult<P::Output>>
+    where
+        M: Model,
+        <<M as kalosm_language_model::Model>::SyncModel as kalosm_language_model::SyncModel>::Session: Send + Sync
+    {
+        let message = message.into();
+        let message = message.trim().to_string();
+        let self_consistency = self.runner.self_consistency.unwrap_or(SelfConsistency {
+            samples: 1,
+            temperatures: SelfConsistency::DEFAULT_TEMPERATURES,
+        });
+        let (tx, rx) = oneshot::channel();
+        self.runner
+            .run_samples(message, model, self_consistency, move |samples| {
+                if tx.send(samples).is_err() {
+                    tracing::error!("Failed to send self-consistency samples");
+                }
+            });
+        let samples = rx
+            .await
+            .map_err(|_| anyhow::anyhow!("Task stopped before producing a result"))??;
+
+        Ok(SelfConsistentResult::majority(samples))
+    }
+}
+
 impl<R: TaskRunner> Task<R> {
     /// Run the task with a message.
     pub fn run<M>(&self, message: impl Into<String>, model: & M) -> R::Output
@@ -501,4 +930,334 @@ impl<R: TaskRunner> Task<R> {
         let message = message.trim().to_string();
         self.runner.run(message, model)
     }
+
+    /// Run the task with a batch of messages. This returns a [`TaskBatch`] stream that yields the index of each input along with the response of the task for that input as soon as it finishes.
+    ///
+    /// All inputs share the task's cached prompt, so if the model supports cloning sessions the system prompt and examples are only fed to the model once.
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// use kalosm_language::prelude::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let llm = Llama::new_chat();
+    ///     let task = Task::new("You classify the sentiment of reviews as positive or negative.");
+    ///
+    ///     let reviews = ["I loved it!", "It broke after a day.", "Would buy again."];
+    ///     let mut batch = task
+    ///         .run_batch(reviews, &llm)
+    ///         .with_concurrency_limit(2)
+    ///         .on_progress(|progress| println!("{}/{}", progress.completed, progress.total));
+    ///
+    ///     while let Some((index, response)) = batch.next().await {
+    ///         println!("{}: {:?}", reviews[index], response);
+    ///     }
+    /// }
+    /// ```
+    pub fn run_batch<'a, M>(
+        &'a self,
+        messages: impl IntoIterator<Item = impl Into<String>>,
+        model: &'a M,
+    ) -> TaskBatch<'a, R, M>
+    where
+        R: TaskResponse + Sync,
+        M: Model,
+        <<M as kalosm_language_model::Model>::SyncModel as kalosm_language_model::SyncModel>::Session: Send + Sync
+    {
+        TaskBatch::new(self, messages.into_iter().map(Into::into).collect(), model)
+    }
+}
+
+/// The progress of a [`TaskBatch`]. This is passed to the callback set with [`TaskBatch::on_progress`] every time an input finishes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchProgress {
+    /// The number of inputs that have finished, successfully or not.
+    pub completed: usize,
+    /// The number of inputs that failed after all retries.
+    pub failed: usize,
+    /// The total number of inputs in the batch.
+    pub total: usize,
+}
+
+type BatchStream<'a, T> = Pin<Box<dyn Stream<Item = (usize, Result<T>)> + Send + 'a>>;
+
+/// A stream of responses from running a [`Task`] over a batch of inputs. Created with [`Task::run_batch`].
+///
+/// The stream yields `(index, response)` pairs in the order the inputs finish, where `index` is the position of the input in the original batch.
+pub struct TaskBatch<'a, R: TaskResponse, M: Model> {
+    task: &'a Task<R>,
+    model: &'a M,
+    inputs: Vec<String>,
+    concurrency_limit: usize,
+    retries: usize,
+    on_progress: Option<Box<dyn FnMut(BatchProgress) + Send + 'a>>,
+    stream: Option<BatchStream<'a, R::Response>>,
+}
+
+impl<'a, R, M> TaskBatch<'a, R, M>
+where
+    R: TaskResponse + Sync,
+    M: Model,
+    <<M as kalosm_language_model::Model>::SyncModel as kalosm_language_model::SyncModel>::Session: Send + Sync
+{
+    fn new(task: &'a Task<R>, inputs: Vec<String>, model: &'a M) -> Self {
+        Self {
+            task,
+            model,
+            inputs,
+            concurrency_limit: 4,
+            retries: 0,
+            on_progress: None,
+            stream: None,
+        }
+    }
+
+    /// Set the maximum number of inputs that can be queued on the model at the same time. (default: 4)
+    pub fn with_concurrency_limit(mut self, concurrency_limit: usize) -> Self {
+        self.concurrency_limit = concurrency_limit.max(1);
+        self
+    }
+
+    /// Set the number of times an input will be retried if the task fails, for example if a structured response could not be parsed. (default: 0)
+    pub fn with_retries(mut self, retries: usize) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Set a callback that is called with the progress of the batch every time an input finishes.
+    pub fn on_progress(mut self, on_progress: impl FnMut(BatchProgress) + Send + 'a) -> Self {
+        self.on_progress = Some(Box::new(on_progress));
+        self
+    }
+
+    fn start(&mut self) -> BatchStream<'a, R::Response> {
+        let task = self.task;
+        let model = self.model;
+        let retries = self.retries;
+        let inputs = std::mem::take(&mut self.inputs);
+        let mut on_progress = self.on_progress.take();
+        let mut progress = BatchProgress {
+            completed: 0,
+            failed: 0,
+            total: inputs.len(),
+        };
+
+        let stream = futures_util::stream::iter(inputs.into_iter().enumerate())
+            .map(move |(index, input)| async move {
+                let mut attempt = 0;
+                loop {
+                    let output = task.run(input.clone(), model);
+                    match R::response(output).await {
+                        Ok(response) => break (index, Ok(response)),
+                        Err(err) if attempt < retries => {
+                            attempt += 1;
+                            tracing::warn!(
+                                "Task failed on input {} (attempt {}): {}",
+                                index,
+                                attempt,
+                                err
+                            );
+                        }
+                        Err(err) => break (index, Err(err)),
+                    }
+                }
+            })
+            .buffer_unordered(self.concurrency_limit)
+            .map(move |(index, response)| {
+                progress.completed += 1;
+                if response.is_err() {
+                    progress.failed += 1;
+                }
+                if let Some(on_progress) = &mut on_progress {
+                    on_progress(progress);
+                }
+                (index, response)
+            });
+
+        Box::pin(stream)
+    }
+}
+
+impl<'a, R, M> Stream for TaskBatch<'a, R, M>
+where
+    R: TaskResponse + Sync,
+    M: Model,
+    <<M as kalosm_language_model::Model>::SyncModel as kalosm_language_model::SyncModel>::Session: Send + Sync
+{
+    type Item = (usize, Result<R::Response>);
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.stream.is_none() {
+            this.stream = Some(this.start());
+        }
+        this.stream.as_mut().unwrap().poll_next_unpin(cx)
+    }
+}
+
+#[test]
+fn test_self_consistency_majority() {
+    let samples = ["4", " 5", "5 ", "6"]
+        .iter()
+        .enumerate()
+        .map(|(i, text)| TaskSample {
+            text: text.to_string(),
+            output: i,
+            temperature: 0.5,
+        })
+        .collect();
+    let result = SelfConsistentResult::majority(samples);
+    assert_eq!(result.chosen_index(), 1);
+    assert_eq!(*result.output(), 1);
+    assert_eq!(result.agreement(), 0.5);
+
+    let temperatures = SelfConsistency {
+        samples: 3,
+        temperatures: (0.0, 1.0),
+    }
+    .temperatures()
+    .collect::<Vec<_>>();
+    assert_eq!(temperatures, vec![0.0, 0.5, 1.0]);
+}
+
+#[test]
+fn self_consistency_temperatures_do_not_enable_self_consistency() {
+    let task = Task::builder("Answer with a number")
+        .with_constraints(kalosm_sample::LiteralParser::new("4"))
+        .with_self_consistency_temperatures(0.1..=0.5)
+        .build();
+    assert_eq!(task.runner.enabled_self_consistency(), None);
+
+    let task = Task::builder("Answer with a number")
+        .with_constraints(kalosm_sample::LiteralParser::new("4"))
+        .with_self_consistency_temperatures(0.1..=0.5)
+        .with_self_consistency(3)
+        .build();
+    assert_eq!(
+        task.runner.enabled_self_consistency(),
+        Some(SelfConsistency {
+            samples: 3,
+            temperatures: (0.1, 0.5),
+        })
+    );
+}
+
+#[tokio::test]
+async fn batch_returns_every_input_with_its_index() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    static FLAKY_ATTEMPTS: AtomicUsize = AtomicUsize::new(0);
+
+    struct Echo;
+
+    impl TaskRunner for Echo {
+        type Output = futures_util::stream::Iter<std::vec::IntoIter<String>>;
+
+        fn run<M: Model>(&self, input: String, _: &M) -> Self::Output
+        where
+            <<M as Model>::SyncModel as SyncModel>::Session: Send + Sync,
+        {
+            futures_util::stream::iter(vec![input])
+        }
+    }
+
+    impl TaskResponse for Echo {
+        type Response = String;
+
+        fn response(
+            output: Self::Output,
+        ) -> Pin<Box<dyn Future<Output = Result<Self::Response>> + Send>> {
+            Box::pin(async move {
+                let text = output.collect::<String>().await;
+                match text.as_str() {
+                    "fail" => Err(anyhow::anyhow!("failed")),
+                    "flaky" if FLAKY_ATTEMPTS.fetch_add(1, Ordering::SeqCst) == 0 => {
+                        Err(anyhow::anyhow!("failed once"))
+                    }
+                    _ => Ok(text.to_uppercase()),
+                }
+            })
+        }
+    }
+
+    /// A tokenizer with one token per byte.
+    struct Bytes;
+
+    impl kalosm_sample::Tokenizer for Bytes {
+        fn encode(&self, text: &str, _: bool) -> Result<Vec<u32>> {
+            Ok(text.bytes().map(u32::from).collect())
+        }
+
+        fn decode(&self, ids: &[u32]) -> Result<std::borrow::Cow<'_, str>> {
+            let bytes = ids.iter().map(|id| *id as u8).collect::<Vec<_>>();
+            Ok(String::from_utf8_lossy(&bytes).into_owned().into())
+        }
+
+        fn get_all_tokens(&self) -> Result<std::borrow::Cow<'_, [u32]>> {
+            Ok((0..256).collect::<Vec<_>>().into())
+        }
+    }
+
+    struct NoModel;
+
+    #[async_trait::async_trait]
+    impl Model for NoModel {
+        type TextStream = ChannelTextStream<String>;
+        type SyncModel = kalosm_language_model::SyncModelNotSupported;
+
+        fn tokenizer(&self) -> Arc<dyn kalosm_sample::Tokenizer + Send + Sync> {
+            Arc::new(Bytes)
+        }
+
+        async fn stream_text_inner(
+            &self,
+            _: &str,
+            _: GenerationParameters,
+        ) -> Result<Self::TextStream> {
+            anyhow::bail!("Not implemented")
+        }
+    }
+
+    let task = Task { runner: Echo };
+    let progress = Arc::new(Mutex::new(Vec::new()));
+    let batch = task
+        .run_batch(["a", "fail", "flaky", "b"], &NoModel)
+        .with_concurrency_limit(2)
+        .with_retries(1)
+        .on_progress({
+            let progress = progress.clone();
+            move |update| progress.lock().unwrap().push(update)
+        });
+    let mut results = batch
+        .map(|(index, response)| (index, response.ok()))
+        .collect::<Vec<_>>()
+        .await;
+    results.sort_by_key(|(index, _)| *index);
+
+    assert_eq!(
+        results,
+        vec![
+            (0, Some("A".to_string())),
+            (1, None),
+            (2, Some("FLAKY".to_string())),
+            (3, Some("B".to_string())),
+        ]
+    );
+    // The flaky input succeeds when it is retried
+    assert_eq!(FLAKY_ATTEMPTS.load(Ordering::SeqCst), 2);
+    let progress = progress.lock().unwrap();
+    assert_eq!(progress.len(), 4);
+    assert_eq!(
+        progress.last(),
+        Some(&BatchProgress {
+            completed: 4,
+            failed: 1,
+            total: 4,
+        })
+    );
 }
@@ -0,0 +1,910 @@
+//! Prompt templates that control how system prompts, user messages and assistant messages are formatted for a model.
+//!
+//! Templates use a small subset of the jinja syntax:
+//! - `{{ name }}` inserts a variable. Fields of a map can be accessed with `{{ example.input }}`. Filters can be applied with `{{ name | trim }}` (`trim`, `trim_start`, `trim_end`, `upper`, `lower` and `ensure_newline`, which adds a newline if the text does not end with one, are supported).
+//! - `{% if name %}...{% else %}...{% endif %}` renders a section if a variable is set and not empty. Conditions can be negated with `{% if not name %}`.
+//! - `{% for item in list %}...{% endfor %}` renders a section for every item in a list. Inside the loop, `loop.index`, `loop.index0`, `loop.first` and `loop.last` are available.
+//! - Adding a `-` to the inside of a tag (`{{-`, `-}}`, `{%-` or `-%}`) removes the whitespace before or after the tag.
+//!
+//! # Example
+//! ```rust
+//! use kalosm_language::prelude::*;
+//!
+//! let template = Template::new(
+//!     "{% for example in examples %}Q: {{ example.question }}\nA: {{ example.answer }}\n{% endfor %}Q: {{ question | trim }}\nA:",
+//! )
+//! .unwrap();
+//!
+//! let examples = vec![TemplateValue::from_iter([
+//!     ("question", "What is 2 + 2?"),
+//!     ("answer", "4"),
+//! ])];
+//! let prompt = template
+//!     .render(
+//!         &TemplateVariables::new()
+//!             .with("examples", examples)
+//!             .with("question", " What is 3 + 3? "),
+//!     )
+//!     .unwrap();
+//! assert_eq!(prompt, "Q: What is 2 + 2?\nA: 4\nQ: What is 3 + 3?\nA:");
+//! ```
+
+use anyhow::{anyhow, bail, Result};
+use kalosm_language_model::ChatMarkers;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// A value that can be inserted into a [`Template`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum TemplateValue {
+    /// A boolean.
+    Bool(bool),
+    /// A number.
+    Number(f64),
+    /// A string.
+    String(String),
+    /// A list of values that can be looped over.
+    List(Vec<TemplateValue>),
+    /// A map of named values that can be accessed with `.`.
+    Map(BTreeMap<String, TemplateValue>),
+}
+
+impl TemplateValue {
+    fn type_name(&self) -> &'static str {
+        match self {
+            TemplateValue::Bool(_) => "boolean",
+            TemplateValue::Number(_) => "number",
+            TemplateValue::String(_) => "string",
+            TemplateValue::List(_) => "list",
+            TemplateValue::Map(_) => "map",
+        }
+    }
+
+    /// Check if the value should be treated as true in an `if` block. Empty strings, lists and maps, `false` and `0` are false.
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            TemplateValue::Bool(value) => *value,
+            TemplateValue::Number(value) => *value != 0.0,
+            TemplateValue::String(value) => !value.is_empty(),
+            TemplateValue::List(value) => !value.is_empty(),
+            TemplateValue::Map(value) => !value.is_empty(),
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<&TemplateValue> {
+        match self {
+            TemplateValue::Map(map) => map.get(key),
+            _ => None,
+        }
+    }
+
+    fn as_text(&self) -> Option<String> {
+        match self {
+            TemplateValue::Bool(value) => Some(value.to_string()),
+            TemplateValue::Number(value) => {
+                if value.fract() == 0.0 && value.abs() < 1e15 {
+                    Some((*value as i64).to_string())
+                } else {
+                    Some(value.to_string())
+                }
+            }
+            TemplateValue::String(value) => Some(value.clone()),
+            TemplateValue::List(_) | TemplateValue::Map(_) => None,
+        }
+    }
+}
+
+impl From<bool> for TemplateValue {
+    fn from(value: bool) -> Self {
+        TemplateValue::Bool(value)
+    }
+}
+
+impl From<&str> for TemplateValue {
+    fn from(value: &str) -> Self {
+        TemplateValue::String(value.to_string())
+    }
+}
+
+impl From<String> for TemplateValue {
+    fn from(value: String) -> Self {
+        TemplateValue::String(value)
+    }
+}
+
+impl From<&String> for TemplateValue {
+    fn from(value: &String) -> Self {
+        TemplateValue::String(value.clone())
+    }
+}
+
+macro_rules! impl_from_number {
+    ($($ty:ty),*) => {
+        $(
+            impl From<$ty> for TemplateValue {
+                fn from(value: $ty) -> Self {
+                    TemplateValue::Number(value as f64)
+                }
+            }
+        )*
+    };
+}
+
+impl_from_number!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize, f32, f64);
+
+impl<T: Into<TemplateValue>> From<Vec<T>> for TemplateValue {
+    fn from(value: Vec<T>) -> Self {
+        TemplateValue::List(value.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<K: Into<String>, V: Into<TemplateValue>> From<BTreeMap<K, V>> for TemplateValue {
+    fn from(value: BTreeMap<K, V>) -> Self {
+        value.into_iter().collect()
+    }
+}
+
+impl<K: Into<String>, V: Into<TemplateValue>> FromIterator<(K, V)> for TemplateValue {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        TemplateValue::Map(
+            iter.into_iter()
+                .map(|(key, value)| (key.into(), value.into()))
+                .collect(),
+        )
+    }
+}
+
+/// The variables a [`Template`] is rendered with.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct TemplateVariables {
+    variables: BTreeMap<String, TemplateValue>,
+}
+
+impl TemplateVariables {
+    /// Create an empty set of variables.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set a variable.
+    pub fn with(mut self, name: impl Into<String>, value: impl Into<TemplateValue>) -> Self {
+        self.insert(name, value);
+        self
+    }
+
+    /// Set a variable.
+    pub fn insert(&mut self, name: impl Into<String>, value: impl Into<TemplateValue>) {
+        self.variables.insert(name.into(), value.into());
+    }
+
+    /// Get a variable.
+    pub fn get(&self, name: &str) -> Option<&TemplateValue> {
+        self.variables.get(name)
+    }
+
+    /// Add all of the variables from another set of variables, replacing any variables with the same name.
+    pub fn extend(&mut self, other: TemplateVariables) {
+        self.variables.extend(other.variables);
+    }
+}
+
+/// A parsed prompt template. See the [module level documentation](self) for the syntax.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    source: String,
+    nodes: Vec<Node>,
+}
+
+impl Template {
+    /// Parse a template.
+    pub fn new(source: impl Into<String>) -> Result<Self> {
+        let source = source.into();
+        let mut tokens = tokenize(&source)?.into_iter();
+        let (nodes, end) = parse_nodes(&mut tokens, &[])?;
+        debug_assert!(end.is_none());
+        Ok(Self { source, nodes })
+    }
+
+    /// Read and parse a template from a file.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)
+            .map_err(|err| anyhow!("Failed to read template {}: {}", path.display(), err))?;
+        Self::new(source)
+    }
+
+    /// Get the source the template was parsed from.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Render the template with the given variables.
+    ///
+    /// Inserting a variable that is not set, a list or a map is an error. Variables that are not set are treated as false in `if` blocks.
+    pub fn render(&self, variables: &TemplateVariables) -> Result<String> {
+        let mut output = String::new();
+        let mut scope = Scope {
+            variables,
+            locals: Vec::new(),
+        };
+        render_nodes(&self.nodes, &mut scope, &mut output)?;
+        Ok(output)
+    }
+}
+
+impl std::str::FromStr for Template {
+    type Err = anyhow::Error;
+
+    fn from_str(source: &str) -> Result<Self> {
+        Self::new(source)
+    }
+}
+
+impl Serialize for Template {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.source)
+    }
+}
+
+impl<'de> Deserialize<'de> for Template {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let source = String::deserialize(deserializer)?;
+        Template::new(source).map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Text(String),
+    Expression(String),
+    Tag(String),
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut rest = source;
+    let mut trim_next = false;
+
+    loop {
+        let start = [rest.find("{{"), rest.find("{%")]
+            .into_iter()
+            .flatten()
+            .min();
+        let Some(start) = start else {
+            push_text(&mut tokens, rest, trim_next, false);
+            break;
+        };
+
+        let is_expression = rest[start..].starts_with("{{");
+        let close = if is_expression { "}}" } else { "%}" };
+        let after_open = &rest[start + 2..];
+        let end = after_open
+            .find(close)
+            .ok_or_else(|| anyhow!("Unclosed `{}` in template", &rest[start..start + 2]))?;
+
+        let mut inner = &after_open[..end];
+        let trim_before = inner.starts_with('-');
+        if trim_before {
+            inner = &inner[1..];
+        }
+        let trim_after = inner.ends_with('-');
+        if trim_after {
+            inner = &inner[..inner.len() - 1];
+        }
+
+        push_text(&mut tokens, &rest[..start], trim_next, trim_before);
+        let inner = inner.trim().to_string();
+        tokens.push(if is_expression {
+            Token::Expression(inner)
+        } else {
+            Token::Tag(inner)
+        });
+
+        trim_next = trim_after;
+        rest = &after_open[end + 2..];
+    }
+
+    Ok(tokens)
+}
+
+fn push_text(tokens: &mut Vec<Token>, mut text: &str, trim_start: bool, trim_end: bool) {
+    if trim_start {
+        text = text.trim_start();
+    }
+    if trim_end {
+        text = text.trim_end();
+    }
+    if !text.is_empty() {
+        tokens.push(Token::Text(text.to_string()));
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Text(String),
+    Expression {
+        path: Vec<String>,
+        filters: Vec<Filter>,
+    },
+    If {
+        negated: bool,
+        path: Vec<String>,
+        then: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+    For {
+        variable: String,
+        path: Vec<String>,
+        body: Vec<Node>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Filter {
+    Trim,
+    TrimStart,
+    TrimEnd,
+    Upper,
+    Lower,
+    EnsureNewline,
+}
+
+impl Filter {
+    fn parse(name: &str) -> Result<Self> {
+        match name {
+            "trim" => Ok(Filter::Trim),
+            "trim_start" => Ok(Filter::TrimStart),
+            "trim_end" => Ok(Filter::TrimEnd),
+            "upper" => Ok(Filter::Upper),
+            "lower" => Ok(Filter::Lower),
+            "ensure_newline" => Ok(Filter::EnsureNewline),
+            _ => bail!("Unknown template filter `{}`", name),
+        }
+    }
+
+    fn apply(&self, text: String) -> String {
+        match self {
+            Filter::Trim => text.trim().to_string(),
+            Filter::TrimStart => text.trim_start().to_string(),
+            Filter::TrimEnd => text.trim_end().to_string(),
+            Filter::Upper => text.to_uppercase(),
+            Filter::Lower => text.to_lowercase(),
+            Filter::EnsureNewline if text.ends_with('\n') => text,
+            Filter::EnsureNewline => text + "\n",
+        }
+    }
+}
+
+fn parse_path(path: &str) -> Result<Vec<String>> {
+    let path = path.trim();
+    let segments: Vec<String> = path.split('.').map(str::to_string).collect();
+    let valid = segments.iter().all(|segment| {
+        !segment.is_empty() && segment.chars().all(|c| c.is_alphanumeric() || c == '_')
+    });
+    if !valid {
+        bail!("Invalid variable name `{}` in template", path);
+    }
+    Ok(segments)
+}
+
+fn parse_nodes(
+    tokens: &mut std::vec::IntoIter<Token>,
+    end_tags: &[&str],
+) -> Result<(Vec<Node>, Option<String>)> {
+    let mut nodes = Vec::new();
+
+    while let Some(token) = tokens.next() {
+        match token {
+            Token::Text(text) => nodes.push(Node::Text(text)),
+            Token::Expression(expression) => {
+                let mut parts = expression.split('|');
+                let path = parse_path(parts.next().unwrap_or_default())?;
+                let filters = parts
+                    .map(|filter| Filter::parse(filter.trim()))
+                    .collect::<Result<_>>()?;
+                nodes.push(Node::Expression { path, filters });
+            }
+            Token::Tag(tag) => {
+                let words: Vec<&str> = tag.split_whitespace().collect();
+                let keyword = words.first().copied().unwrap_or_default();
+                if end_tags.contains(&keyword) {
+                    if words.len() > 1 {
+                        bail!("Unexpected arguments in `{{% {} %}}`", tag);
+                    }
+                    return Ok((nodes, Some(keyword.to_string())));
+                }
+                match (keyword, &words[1..]) {
+                    ("if", ["not", path]) | ("if", [path]) => {
+                        let negated = words.len() == 3;
+                        let path = parse_path(path)?;
+                        let (then, end) = parse_nodes(tokens, &["else", "endif"])?;
+                        let otherwise = match end.as_deref() {
+                            Some("else") => {
+                                let (otherwise, end) = parse_nodes(tokens, &["endif"])?;
+                                if end.is_none() {
+                                    bail!("Expected `{{% endif %}}` after `{{% {} %}}`", tag);
+                                }
+                                otherwise
+                            }
+                            Some(_) => Vec::new(),
+                            None => bail!("Expected `{{% endif %}}` after `{{% {} %}}`", tag),
+                        };
+                        nodes.push(Node::If {
+                            negated,
+                            path,
+                            then,
+                            otherwise,
+                        });
+                    }
+                    ("for", [variable, "in", path]) => {
+                        let variable = parse_path(variable)?;
+                        if variable.len() != 1 {
+                            bail!("Invalid loop variable in `{{% {} %}}`", tag);
+                        }
+                        let path = parse_path(path)?;
+                        let (body, end) = parse_nodes(tokens, &["endfor"])?;
+                        if end.is_none() {
+                            bail!("Expected `{{% endfor %}}` after `{{% {} %}}`", tag);
+                        }
+                        nodes.push(Node::For {
+                            variable: variable.into_iter().next().unwrap(),
+                            path,
+                            body,
+                        });
+                    }
+                    ("if", _) => bail!("Expected `{{% if name %}}` but found `{{% {} %}}`", tag),
+                    ("for", _) => bail!(
+                        "Expected `{{% for item in list %}}` but found `{{% {} %}}`",
+                        tag
+                    ),
+                    _ => bail!("Unexpected `{{% {} %}}` in template", tag),
+                }
+            }
+        }
+    }
+
+    Ok((nodes, None))
+}
+
+struct Scope<'a> {
+    variables: &'a TemplateVariables,
+    locals: Vec<(String, TemplateValue)>,
+}
+
+impl Scope<'_> {
+    fn lookup(&self, path: &[String]) -> Option<&TemplateValue> {
+        let (first, rest) = path.split_first()?;
+        let mut value = self
+            .locals
+            .iter()
+            .rev()
+            .find(|(name, _)| name == first)
+            .map(|(_, value)| value)
+            .or_else(|| self.variables.get(first))?;
+        for key in rest {
+            value = value.get(key)?;
+        }
+        Some(value)
+    }
+}
+
+fn render_nodes(nodes: &[Node], scope: &mut Scope, output: &mut String) -> Result<()> {
+    for node in nodes {
+        match node {
+            Node::Text(text) => output.push_str(text),
+            Node::Expression { path, filters } => {
+                let value = scope.lookup(path).ok_or_else(|| {
+                    anyhow!("Undefined variable `{}` in template", path.join("."))
+                })?;
+                let mut text = value.as_text().ok_or_else(|| {
+                    anyhow!(
+                        "Variable `{}` is a {} and cannot be inserted into the template",
+                        path.join("."),
+                        value.type_name()
+                    )
+                })?;
+                for filter in filters {
+                    text = filter.apply(text);
+                }
+                output.push_str(&text);
+            }
+            Node::If {
+                negated,
+                path,
+                then,
+                otherwise,
+            } => {
+                let truthy = scope
+                    .lookup(path)
+                    .map(TemplateValue::is_truthy)
+                    .unwrap_or(false);
+                if truthy != *negated {
+                    render_nodes(then, scope, output)?;
+                } else {
+                    render_nodes(otherwise, scope, output)?;
+                }
+            }
+            Node::For {
+                variable,
+                path,
+                body,
+            } => {
+                let items = match scope.lookup(path) {
+                    Some(TemplateValue::List(items)) => items.clone(),
+                    Some(value) => bail!(
+                        "Variable `{}` is a {} and cannot be looped over",
+                        path.join("."),
+                        value.type_name()
+                    ),
+                    None => bail!("Undefined variable `{}` in template", path.join(".")),
+                };
+                let len = items.len();
+                for (index, item) in items.into_iter().enumerate() {
+                    let loop_info = TemplateValue::from_iter([
+                        ("index", TemplateValue::from(index + 1)),
+                        ("index0", TemplateValue::from(index)),
+                        ("first", TemplateValue::from(index == 0)),
+                        ("last", TemplateValue::from(index + 1 == len)),
+                    ]);
+                    scope.locals.push((variable.clone(), item));
+                    scope.locals.push(("loop".to_string(), loop_info));
+                    let result = render_nodes(body, scope, output);
+                    scope.locals.truncate(scope.locals.len() - 2);
+                    result?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// A placeholder that is inserted in place of the content of a message so the rendered turn can be split into the text before and after the content. Messages are rendered with their real content, so filters applied to the content are only skipped for the prefix and suffix.
+const CONTENT_PLACEHOLDER: &str = "\u{0}";
+
+const DEFAULT_SYSTEM_TEMPLATE: &str = "{% if has_chat_markers %}{{ system_prompt_marker }}{{ content }}{{ end_system_prompt_marker }}{% else %}# Instruction\n{{ content | ensure_newline }}{% endif %}";
+const DEFAULT_USER_TEMPLATE: &str = "{% if has_chat_markers %}{{ user_marker }}{{ content }}{{ end_user_marker }}{% else %}# Input\n{{ content | ensure_newline }}{% endif %}";
+const DEFAULT_ASSISTANT_TEMPLATE: &str = "{% if has_chat_markers %}{{ assistant_marker }}{{ content }}{{ end_assistant_marker }}{% else %}# Output\n{{ content | ensure_newline }}{% endif %}";
+const DEFAULT_STOP_ON_TEMPLATE: &str =
+    "{% if has_chat_markers %}{{ end_assistant_marker }}{% else %}# Input{% endif %}";
+
+/// Templates for each turn of a conversation. This controls how [`crate::task::Task`] and [`crate::chat::Chat`] format the prompt they feed to the model.
+///
+/// Each template must insert `{{ content }}` exactly once. The templates are rendered with these variables:
+/// - `content`: The text of the message
+/// - `system_prompt_marker`, `end_system_prompt_marker`, `user_marker`, `end_user_marker`, `assistant_marker` and `end_assistant_marker`: The [`ChatMarkers`] of the model, or empty strings if the model does not have any
+/// - `has_chat_markers`: If the model has [`ChatMarkers`]
+/// - Any variables set with [`ChatTemplate::with_variable`]
+///
+/// The default template uses the model's chat markers if it has them, and a markdown instruction format otherwise.
+///
+/// # Example
+/// ```rust
+/// use kalosm_language::prelude::*;
+///
+/// let template = ChatTemplate::new(
+///     "<|system|>\n{{ content }}</s>\n",
+///     "<|user|>\n{{ content }}</s>\n",
+///     "<|assistant|>\n{{ content }}</s>\n",
+/// )
+/// .unwrap();
+/// let format = template.render(None).unwrap();
+/// assert_eq!(format.user("Hello"), "<|user|>\nHello</s>\n");
+/// assert_eq!(format.stop_on(), "</s>");
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatTemplate {
+    system: Template,
+    user: Template,
+    assistant: Template,
+    #[serde(default)]
+    stop_on: Option<Template>,
+    #[serde(default)]
+    variables: TemplateVariables,
+}
+
+impl Default for ChatTemplate {
+    fn default() -> Self {
+        Self {
+            system: Template::new(DEFAULT_SYSTEM_TEMPLATE).unwrap(),
+            user: Template::new(DEFAULT_USER_TEMPLATE).unwrap(),
+            assistant: Template::new(DEFAULT_ASSISTANT_TEMPLATE).unwrap(),
+            stop_on: Some(Template::new(DEFAULT_STOP_ON_TEMPLATE).unwrap()),
+            variables: TemplateVariables::new(),
+        }
+    }
+}
+
+impl ChatTemplate {
+    /// Create a chat template from the templates for the system prompt, user messages and assistant messages.
+    pub fn new(
+        system: impl Into<String>,
+        user: impl Into<String>,
+        assistant: impl Into<String>,
+    ) -> Result<Self> {
+        let myself = Self {
+            system: Template::new(system)?,
+            user: Template::new(user)?,
+            assistant: Template::new(assistant)?,
+            stop_on: None,
+            variables: TemplateVariables::new(),
+        };
+        myself.validate()?;
+        Ok(myself)
+    }
+
+    /// Load a chat template from a JSON file with `system`, `user` and `assistant` templates, and optionally `stop_on` and `variables`.
+    ///
+    /// ```json
+    /// {
+    ///     "system": "<|im_start|>system\n{{ content }}<|im_end|>\n",
+    ///     "user": "<|im_start|>user\n{{ content }}<|im_end|>\n",
+    ///     "assistant": "<|im_start|>assistant\n{{ content }}<|im_end|>\n",
+    ///     "stop_on": "<|im_end|>"
+    /// }
+    /// ```
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = std::fs::File::open(path)
+            .map_err(|err| anyhow!("Failed to open chat template {}: {}", path.display(), err))?;
+        let myself: Self = serde_json::from_reader(std::io::BufReader::new(file))?;
+        myself.validate()?;
+        Ok(myself)
+    }
+
+    /// Save the chat template to a JSON file that can be loaded with [`ChatTemplate::from_file`].
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let file = std::fs::File::create(path)?;
+        serde_json::to_writer_pretty(std::io::BufWriter::new(file), self)?;
+        Ok(())
+    }
+
+    /// Set the template for the text that ends the assistant's response. By default, this is the text after the content of the assistant template, or the text before the content of the user template if the assistant template ends with whitespace.
+    pub fn with_stop_on(mut self, stop_on: impl Into<String>) -> Result<Self> {
+        self.stop_on = Some(Template::new(stop_on)?);
+        self.validate()?;
+        Ok(self)
+    }
+
+    /// Set an extra variable that every template can use.
+    pub fn with_variable(
+        mut self,
+        name: impl Into<String>,
+        value: impl Into<TemplateValue>,
+    ) -> Self {
+        self.variables.insert(name, value);
+        self
+    }
+
+    fn validate(&self) -> Result<()> {
+        self.render(None)?;
+        self.render(Some(&ChatMarkers::default()))?;
+        Ok(())
+    }
+
+    /// Render the template for a model with the given chat markers.
+    pub fn render(&self, markers: Option<&ChatMarkers>) -> Result<ChatFormat> {
+        let default_markers = ChatMarkers::default();
+        let marker_values = markers.unwrap_or(&default_markers);
+        let mut variables = TemplateVariables::new()
+            .with("has_chat_markers", markers.is_some())
+            .with("system_prompt_marker", marker_values.system_prompt_marker)
+            .with(
+                "end_system_prompt_marker",
+                marker_values.end_system_prompt_marker,
+            )
+            .with("user_marker", marker_values.user_marker)
+            .with("end_user_marker", marker_values.end_user_marker)
+            .with("assistant_marker", marker_values.assistant_marker)
+            .with("end_assistant_marker", marker_values.end_assistant_marker);
+        variables.extend(self.variables.clone());
+
+        let system = Turn::render(&self.system, &variables, "system")?;
+        let user = Turn::render(&self.user, &variables, "user")?;
+        let assistant = Turn::render(&self.assistant, &variables, "assistant")?;
+
+        let stop_on = match &self.stop_on {
+            Some(stop_on) => stop_on.render(&variables)?,
+            None => match assistant.suffix.trim() {
+                "" => user.prefix.trim().to_string(),
+                suffix => suffix.to_string(),
+            },
+        };
+
+        Ok(ChatFormat {
+            system,
+            user,
+            assistant,
+            stop_on,
+        })
+    }
+}
+
+/// A template for one kind of message, rendered for a specific model.
+#[derive(Debug, Clone, PartialEq)]
+struct Turn {
+    template: Template,
+    variables: TemplateVariables,
+    prefix: String,
+    suffix: String,
+}
+
+impl Turn {
+    fn render(template: &Template, variables: &TemplateVariables, name: &str) -> Result<Self> {
+        let rendered = template.render(&variables.clone().with("content", CONTENT_PLACEHOLDER))?;
+        let mut parts = rendered.split(CONTENT_PLACEHOLDER);
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(prefix), Some(suffix), None) => Ok(Self {
+                template: template.clone(),
+                variables: variables.clone(),
+                prefix: prefix.to_string(),
+                suffix: suffix.to_string(),
+            }),
+            _ => bail!(
+                "The {} template must insert `{{{{ content }}}}` exactly once",
+                name
+            ),
+        }
+    }
+
+    fn format(&self, content: &str) -> String {
+        let variables = self.variables.clone().with("content", content);
+        match self.template.render(&variables) {
+            Ok(rendered) => rendered,
+            Err(err) => {
+                // Rendering only fails for variables that were already checked when the turn was created
+                tracing::error!("Failed to render message: {}", err);
+                format!("{}{}{}", self.prefix, content, self.suffix)
+            }
+        }
+    }
+}
+
+/// A [`ChatTemplate`] rendered for a specific model. This formats each kind of message, and contains the text that is inserted before and after the content of each kind of message.
+///
+/// The prefixes and suffixes are rendered without the content of a message, so filters applied to `{{ content }}` only change the formatted messages.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChatFormat {
+    system: Turn,
+    user: Turn,
+    assistant: Turn,
+    stop_on: String,
+}
+
+impl ChatFormat {
+    /// Format a system prompt.
+    pub fn system(&self, content: &str) -> String {
+        self.system.format(content)
+    }
+
+    /// Format a user message.
+    pub fn user(&self, content: &str) -> String {
+        self.user.format(content)
+    }
+
+    /// Format an assistant message.
+    pub fn assistant(&self, content: &str) -> String {
+        self.assistant.format(content)
+    }
+
+    /// The text before a system prompt.
+    pub fn system_prefix(&self) -> &str {
+        &self.system.prefix
+    }
+
+    /// The text after a system prompt.
+    pub fn system_suffix(&self) -> &str {
+        &self.system.suffix
+    }
+
+    /// The text before a user message.
+    pub fn user_prefix(&self) -> &str {
+        &self.user.prefix
+    }
+
+    /// The text after a user message.
+    pub fn user_suffix(&self) -> &str {
+        &self.user.suffix
+    }
+
+    /// The text before an assistant message. This is fed to the model before it starts generating a response.
+    pub fn assistant_prefix(&self) -> &str {
+        &self.assistant.prefix
+    }
+
+    /// The text after an assistant message.
+    pub fn assistant_suffix(&self) -> &str {
+        &self.assistant.suffix
+    }
+
+    /// The text that ends the assistant's response. Generation stops when the model generates this text.
+    pub fn stop_on(&self) -> &str {
+        &self.stop_on
+    }
+}
+
+#[test]
+fn test_template_rendering() {
+    let template = Template::new(
+        "{% for item in items -%}\n{{ loop.index }}. {{ item.name | upper }}{% if not loop.last %}, {% endif %}\n{%- endfor %}{% if missing %}!{% else %}.{% endif %}",
+    )
+    .unwrap();
+    let items = vec![
+        TemplateValue::from_iter([("name", "a")]),
+        TemplateValue::from_iter([("name", "b")]),
+    ];
+    let rendered = template
+        .render(&TemplateVariables::new().with("items", items))
+        .unwrap();
+    assert_eq!(rendered, "1. A, 2. B.");
+
+    assert!(Template::new("{{ name").is_err());
+    assert!(Template::new("{% if name %}").is_err());
+    assert!(Template::new("{% endfor %}").is_err());
+    assert!(Template::new("{{ name | unknown }}").is_err());
+    assert!(Template::new("{{ name }}")
+        .unwrap()
+        .render(&TemplateVariables::new())
+        .is_err());
+    assert!(Template::new("{{ name }}")
+        .unwrap()
+        .render(&TemplateVariables::new().with("name", vec![1, 2]))
+        .is_err());
+}
+
+#[test]
+fn test_chat_template() {
+    let format = ChatTemplate::default().render(None).unwrap();
+    assert_eq!(format.system("Be nice"), "# Instruction\nBe nice\n");
+    assert_eq!(format.system("Be nice\n"), "# Instruction\nBe nice\n");
+    assert_eq!(format.user("Hi  \n\n"), "# Input\nHi  \n\n");
+    assert_eq!(format.user_prefix(), "# Input\n");
+    assert_eq!(format.assistant_prefix(), "# Output\n");
+    assert_eq!(format.stop_on(), "# Input");
+
+    let markers = ChatMarkers {
+        system_prompt_marker: "<|im_start|>system\n",
+        end_system_prompt_marker: "<|im_end|>",
+        user_marker: "<|im_start|>user\n",
+        end_user_marker: "<|im_end|>",
+        assistant_marker: "<|im_start|>assistant\n",
+        end_assistant_marker: "<|im_end|>",
+    };
+    let format = ChatTemplate::default().render(Some(&markers)).unwrap();
+    assert_eq!(format.user("Hi"), "<|im_start|>user\nHi<|im_end|>");
+    assert_eq!(format.stop_on(), "<|im_end|>");
+
+    assert!(ChatTemplate::new("{{ content }}", "no content", "{{ content }}").is_err());
+    let format = ChatTemplate::new(
+        "{{ content }}\n\n",
+        "{{ name }}: {{ content }}\n",
+        "Bot: {{ content }}\n",
+    );
+    assert!(format.is_err());
+    let format = ChatTemplate::new(
+        "{{ content }}\n\n",
+        "{% if name %}{{ name }}{% else %}User{% endif %}: {{ content }}\n",
+        "Bot: {{ content }}\n",
+    )
+    .unwrap()
+    .with_variable("name", "Alice")
+    .render(None)
+    .unwrap();
+    assert_eq!(format.user("Hi"), "Alice: Hi\n");
+    assert_eq!(format.stop_on(), "Alice:");
+
+    let format = ChatTemplate::new(
+        "{{ content | upper }}\n",
+        "User: {{ content | trim }}\n",
+        "Bot: {{ content }}\n",
+    )
+    .unwrap()
+    .render(None)
+    .unwrap();
+    assert_eq!(format.system("Be nice"), "BE NICE\n");
+    assert_eq!(format.user("  Hi \n"), "User: Hi\n");
+    assert_eq!(format.user_prefix(), "User: ");
+}
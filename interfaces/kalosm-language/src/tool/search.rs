@@ -1,23 +1,30 @@
-use crate::context::IntoDocuments;
-use crate::context::SearchQuery;
+use crate::context::{SearchProvider, SerperSearch};
 use crate::tool::Tool;
 
 use super::OneLine;
 
-/// A tool that can search the web
-pub struct WebSearchTool {
+/// A tool that can search the web. Searches with [`SerperSearch`] by default, and any other [`SearchProvider`] with [`WebSearchTool::with_provider`].
+pub struct WebSearchTool<P = SerperSearch> {
     top_n: usize,
+    provider: P,
 }
 
 impl WebSearchTool {
-    /// Create a new web search tool
+    /// Create a new web search tool that searches with [`SerperSearch`] using the `SERPER_API_KEY` environment variable
     pub fn new(top_n: usize) -> Self {
-        Self { top_n }
+        Self::with_provider(SerperSearch::from_env(), top_n)
+    }
+}
+
+impl<P: SearchProvider> WebSearchTool<P> {
+    /// Create a new web search tool that searches with the given provider
+    pub fn with_provider(provider: P, top_n: usize) -> Self {
+        Self { top_n, provider }
     }
 }
 
 #[async_trait::async_trait]
-impl Tool for WebSearchTool {
+impl<P: SearchProvider> Tool for WebSearchTool<P> {
     type Constraint = OneLine;
 
     fn constraints(&self) -> Self::Constraint {
@@ -37,18 +44,48 @@ impl Tool for WebSearchTool {
     }
 
     async fn run(&mut self, query: String) -> String {
-        let api_key =
-            std::env::var("SERPER_API_KEY").expect("SERPER_API_KEY environment variable not set");
-        let search_query = SearchQuery::new(&query, &api_key, self.top_n);
-        let documents = search_query.into_documents().await.unwrap();
+        let results = match self.provider.search(&query, self.top_n).await {
+            Ok(results) => results,
+            Err(err) => return format!("Failed to search the web: {}", err),
+        };
+        if results.is_empty() {
+            return "No results found.".to_string();
+        }
         let mut text = String::new();
-        for document in documents {
-            for word in document.body().split(' ').take(300) {
-                text.push_str(word);
-                text.push(' ');
+        for (index, result) in results.iter().enumerate() {
+            text += &format!("[{}] {} ({})\n", index + 1, result.title, result.url);
+            if !result.snippet.is_empty() {
+                text += &result.snippet;
+                text.push('\n');
+            }
+            // Fall back to just the snippet if the page can't be loaded
+            if let Ok(document) = self.provider.fetch(result).await {
+                for word in document.body().split(' ').take(300) {
+                    text.push_str(word);
+                    text.push(' ');
+                }
+                text.push('\n');
             }
-            text.push('\n');
         }
         text
     }
 }
+
+#[tokio::test]
+async fn web_search_tool_lists_ranked_results() {
+    use crate::context::{FixtureSearch, WebSearchResult};
+
+    let provider = FixtureSearch::new().with_results(
+        "floneum",
+        vec![WebSearchResult {
+            title: "Floneum".to_string(),
+            url: url::Url::parse("https://floneum.com").unwrap(),
+            snippet: "A visual editor for AI workflows".to_string(),
+        }],
+    );
+    let mut tool = WebSearchTool::with_provider(provider, 3);
+    let output = tool.run("floneum".to_string()).await;
+    assert!(output
+        .starts_with("[1] Floneum (https://floneum.com/)\nA visual editor for AI workflows\n"));
+    assert_eq!(tool.run("unknown".to_string()).await, "No results found.");
+}
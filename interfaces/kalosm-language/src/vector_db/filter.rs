@@ -0,0 +1,302 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+/// A value in the [`EmbeddingMetadata`] of an embedding.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum MetadataValue {
+    /// A boolean value.
+    Bool(bool),
+    /// A number.
+    Number(f64),
+    /// A string. Dates should be stored as RFC 3339 strings so they can be compared with [`Filter::lt`] and [`Filter::gt`].
+    String(String),
+    /// A list of values, like a list of tags.
+    List(Vec<MetadataValue>),
+}
+
+impl MetadataValue {
+    fn compare(&self, other: &MetadataValue) -> Option<Ordering> {
+        match (self, other) {
+            (MetadataValue::Bool(a), MetadataValue::Bool(b)) => Some(a.cmp(b)),
+            (MetadataValue::Number(a), MetadataValue::Number(b)) => a.partial_cmp(b),
+            (MetadataValue::String(a), MetadataValue::String(b)) => Some(a.cmp(b)),
+            _ => None,
+        }
+    }
+
+    /// Check if any of the values (or the value itself if it isn't a list) satisfy the predicate.
+    fn any(&self, mut predicate: impl FnMut(&MetadataValue) -> bool) -> bool {
+        match self {
+            MetadataValue::List(values) => values.iter().any(predicate),
+            value => predicate(value),
+        }
+    }
+}
+
+impl From<bool> for MetadataValue {
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
+    }
+}
+
+impl From<f64> for MetadataValue {
+    fn from(value: f64) -> Self {
+        Self::Number(value)
+    }
+}
+
+impl From<i64> for MetadataValue {
+    fn from(value: i64) -> Self {
+        Self::Number(value as f64)
+    }
+}
+
+impl From<i32> for MetadataValue {
+    fn from(value: i32) -> Self {
+        Self::Number(value as f64)
+    }
+}
+
+impl From<String> for MetadataValue {
+    fn from(value: String) -> Self {
+        Self::String(value)
+    }
+}
+
+impl From<&str> for MetadataValue {
+    fn from(value: &str) -> Self {
+        Self::String(value.to_string())
+    }
+}
+
+impl<T: Into<MetadataValue>> From<Vec<T>> for MetadataValue {
+    fn from(value: Vec<T>) -> Self {
+        Self::List(value.into_iter().map(Into::into).collect())
+    }
+}
+
+/// Key/value metadata attached to an embedding in a [`crate::vector_db::VectorDB`]. The metadata can be used to filter search results with a [`Filter`].
+///
+/// # Example
+/// ```rust
+/// use kalosm_language::prelude::*;
+///
+/// let metadata = EmbeddingMetadata::new()
+///     .with("tenant", "acme")
+///     .with("tags", vec!["billing", "faq"]);
+/// assert!(Filter::eq("tenant", "acme").matches(&metadata));
+/// assert!(Filter::eq("tags", "faq").matches(&metadata));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct EmbeddingMetadata {
+    values: BTreeMap<String, MetadataValue>,
+}
+
+impl EmbeddingMetadata {
+    /// Create empty metadata.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a value to the metadata.
+    pub fn with(mut self, key: impl ToString, value: impl Into<MetadataValue>) -> Self {
+        self.insert(key, value);
+        self
+    }
+
+    /// Insert a value into the metadata, replacing any existing value for the key.
+    pub fn insert(&mut self, key: impl ToString, value: impl Into<MetadataValue>) {
+        self.values.insert(key.to_string(), value.into());
+    }
+
+    /// Get the value for a key.
+    pub fn get(&self, key: &str) -> Option<&MetadataValue> {
+        self.values.get(key)
+    }
+
+    /// Check if the metadata is empty.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Iterate over the keys and values in the metadata.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &MetadataValue)> {
+        self.values.iter().map(|(key, value)| (key.as_str(), value))
+    }
+}
+
+impl<K: ToString, V: Into<MetadataValue>> FromIterator<(K, V)> for EmbeddingMetadata {
+    fn from_iter<T: IntoIterator<Item = (K, V)>>(iter: T) -> Self {
+        let mut metadata = Self::new();
+        for (key, value) in iter {
+            metadata.insert(key, value);
+        }
+        metadata
+    }
+}
+
+/// A filter over the [`EmbeddingMetadata`] of embeddings.
+///
+/// Comparisons against a list value match if any item in the list matches. Comparisons between values of different types never match.
+///
+/// # Example
+/// ```rust
+/// use kalosm_language::prelude::*;
+///
+/// let filter = Filter::eq("tenant", "acme")
+///     .and(Filter::gte("published", "2024-01-01"))
+///     .and(Filter::eq("draft", true).not());
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Filter {
+    /// The value for the key is equal to the value.
+    Eq(String, MetadataValue),
+    /// The value for the key is less than the value.
+    Lt(String, MetadataValue),
+    /// The value for the key is less than or equal to the value.
+    Lte(String, MetadataValue),
+    /// The value for the key is greater than the value.
+    Gt(String, MetadataValue),
+    /// The value for the key is greater than or equal to the value.
+    Gte(String, MetadataValue),
+    /// The value for the key is equal to any of the values.
+    AnyOf(String, Vec<MetadataValue>),
+    /// The metadata contains the key.
+    Exists(String),
+    /// All of the filters match.
+    And(Vec<Filter>),
+    /// Any of the filters match.
+    Or(Vec<Filter>),
+    /// The filter does not match.
+    Not(Box<Filter>),
+}
+
+impl Filter {
+    /// Match metadata where the value for the key is equal to the value.
+    pub fn eq(key: impl ToString, value: impl Into<MetadataValue>) -> Self {
+        Self::Eq(key.to_string(), value.into())
+    }
+
+    /// Match metadata where the value for the key is not equal to the value (or the key is missing).
+    pub fn ne(key: impl ToString, value: impl Into<MetadataValue>) -> Self {
+        Self::eq(key, value).not()
+    }
+
+    /// Match metadata where the value for the key is less than the value.
+    pub fn lt(key: impl ToString, value: impl Into<MetadataValue>) -> Self {
+        Self::Lt(key.to_string(), value.into())
+    }
+
+    /// Match metadata where the value for the key is less than or equal to the value.
+    pub fn lte(key: impl ToString, value: impl Into<MetadataValue>) -> Self {
+        Self::Lte(key.to_string(), value.into())
+    }
+
+    /// Match metadata where the value for the key is greater than the value.
+    pub fn gt(key: impl ToString, value: impl Into<MetadataValue>) -> Self {
+        Self::Gt(key.to_string(), value.into())
+    }
+
+    /// Match metadata where the value for the key is greater than or equal to the value.
+    pub fn gte(key: impl ToString, value: impl Into<MetadataValue>) -> Self {
+        Self::Gte(key.to_string(), value.into())
+    }
+
+    /// Match metadata where the value for the key is equal to any of the values.
+    pub fn any_of<V: Into<MetadataValue>>(
+        key: impl ToString,
+        values: impl IntoIterator<Item = V>,
+    ) -> Self {
+        Self::AnyOf(
+            key.to_string(),
+            values.into_iter().map(Into::into).collect(),
+        )
+    }
+
+    /// Match metadata that contains the key.
+    pub fn exists(key: impl ToString) -> Self {
+        Self::Exists(key.to_string())
+    }
+
+    /// Match metadata that matches both this filter and the other filter.
+    pub fn and(self, other: Filter) -> Self {
+        match self {
+            Self::And(mut filters) => {
+                filters.push(other);
+                Self::And(filters)
+            }
+            filter => Self::And(vec![filter, other]),
+        }
+    }
+
+    /// Match metadata that matches either this filter or the other filter.
+    pub fn or(self, other: Filter) -> Self {
+        match self {
+            Self::Or(mut filters) => {
+                filters.push(other);
+                Self::Or(filters)
+            }
+            filter => Self::Or(vec![filter, other]),
+        }
+    }
+
+    /// Match metadata that doesn't match this filter.
+    #[allow(clippy::should_implement_trait)]
+    pub fn not(self) -> Self {
+        match self {
+            Self::Not(filter) => *filter,
+            filter => Self::Not(Box::new(filter)),
+        }
+    }
+
+    /// Check if the metadata matches the filter.
+    pub fn matches(&self, metadata: &EmbeddingMetadata) -> bool {
+        let compare = |key: &str, expected: &MetadataValue, accept: fn(Ordering) -> bool| {
+            metadata
+                .get(key)
+                .is_some_and(|value| value.any(|value| value.compare(expected).is_some_and(accept)))
+        };
+        match self {
+            Self::Eq(key, expected) => compare(key, expected, Ordering::is_eq),
+            Self::Lt(key, expected) => compare(key, expected, Ordering::is_lt),
+            Self::Lte(key, expected) => compare(key, expected, Ordering::is_le),
+            Self::Gt(key, expected) => compare(key, expected, Ordering::is_gt),
+            Self::Gte(key, expected) => compare(key, expected, Ordering::is_ge),
+            Self::AnyOf(key, expected) => expected
+                .iter()
+                .any(|expected| compare(key, expected, Ordering::is_eq)),
+            Self::Exists(key) => metadata.get(key).is_some(),
+            Self::And(filters) => filters.iter().all(|filter| filter.matches(metadata)),
+            Self::Or(filters) => filters.iter().any(|filter| filter.matches(metadata)),
+            Self::Not(filter) => !filter.matches(metadata),
+        }
+    }
+}
+
+#[test]
+fn filter_matches_metadata() {
+    let metadata = EmbeddingMetadata::new()
+        .with("tenant", "acme")
+        .with("published", "2024-03-01")
+        .with("views", 10)
+        .with("tags", vec!["rust", "search"]);
+
+    assert!(Filter::eq("tenant", "acme").matches(&metadata));
+    assert!(!Filter::eq("tenant", "globex").matches(&metadata));
+    assert!(Filter::ne("tenant", "globex").matches(&metadata));
+    assert!(Filter::gte("published", "2024-01-01").matches(&metadata));
+    assert!(!Filter::lt("views", 5).matches(&metadata));
+    assert!(!Filter::eq("views", "10").matches(&metadata));
+    assert!(Filter::any_of("tags", ["python", "search"]).matches(&metadata));
+    assert!(Filter::exists("tags")
+        .and(Filter::eq("tenant", "acme"))
+        .matches(&metadata));
+    assert!(!Filter::eq("missing", true).matches(&metadata));
+    assert!(Filter::eq("missing", true)
+        .or(Filter::gt("views", 1))
+        .matches(&metadata));
+}
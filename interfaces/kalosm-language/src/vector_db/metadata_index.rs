@@ -0,0 +1,264 @@
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+
+use heed::byteorder::BigEndian;
+use heed::types::{SerdeJson, Str, U32};
+use heed::{BoxedError, BytesDecode, BytesEncode};
+use roaring::RoaringBitmap;
+
+use super::{EmbeddingMetadata, Filter, MetadataValue};
+
+/// The name of the table the inverted index of the [`EmbeddingMetadata`] is stored in.
+const METADATA_INDEX_DATABASE: &str = "kalosm-vector-db-metadata-index";
+/// The longest metadata key that can be stored.
+const MAX_KEY_LEN: usize = 256;
+/// The longest encoded value that is stored in the index. LMDB keys are at most 511 bytes, so longer values are checked against the metadata table instead.
+const MAX_VALUE_LEN: usize = 250;
+
+type MetadataDatabase = heed::Database<U32<BigEndian>, SerdeJson<EmbeddingMetadata>>;
+
+/// Stores a [`RoaringBitmap`] in a heed database.
+pub(super) struct RoaringBitmapCodec;
+
+impl<'a> BytesEncode<'a> for RoaringBitmapCodec {
+    type EItem = RoaringBitmap;
+
+    fn bytes_encode(item: &'a Self::EItem) -> Result<Cow<'a, [u8]>, BoxedError> {
+        let mut bytes = Vec::with_capacity(item.serialized_size());
+        item.serialize_into(&mut bytes)?;
+        Ok(Cow::Owned(bytes))
+    }
+}
+
+impl<'a> BytesDecode<'a> for RoaringBitmapCodec {
+    type DItem = RoaringBitmap;
+
+    fn bytes_decode(bytes: &'a [u8]) -> Result<Self::DItem, BoxedError> {
+        Ok(RoaringBitmap::deserialize_from(bytes)?)
+    }
+}
+
+/// The key of the ids that have a metadata key.
+fn exists_key(key: &str) -> String {
+    format!("{key}\0")
+}
+
+/// The key of the ids that have a value for a metadata key.
+fn value_key(key: &str, encoded_value: &str) -> String {
+    format!("{key}\0{encoded_value}")
+}
+
+/// Encode a value for an index key. `-0.0` is stored as `0.0` because filters treat them as equal.
+fn encode_value(value: &MetadataValue) -> anyhow::Result<String> {
+    match value {
+        MetadataValue::Number(number) if *number == 0.0 => {
+            Ok(serde_json::to_string(&MetadataValue::Number(0.0))?)
+        }
+        value => Ok(serde_json::to_string(value)?),
+    }
+}
+
+/// The key of the ids with a value for a metadata key that is too long to store in the index.
+fn overflow_key(key: &str) -> String {
+    format!("{key}\u{1}")
+}
+
+/// An inverted index from metadata keys and values to the ids of the embeddings with that metadata. Filters are evaluated by combining the id sets of the keys and values they mention instead of reading the metadata of every embedding.
+///
+/// Values in a list are indexed separately so filters match any item in the list like [`Filter::matches`].
+#[derive(Clone, Copy)]
+pub(super) struct MetadataIndex {
+    database: heed::Database<Str, RoaringBitmapCodec>,
+}
+
+impl MetadataIndex {
+    /// Open the index, or create it if it doesn't exist. Databases created before the index existed are indexed from the metadata table.
+    pub(super) fn create(
+        env: &heed::Env,
+        wtxn: &mut heed::RwTxn,
+        metadata_database: MetadataDatabase,
+    ) -> anyhow::Result<Self> {
+        let index = Self {
+            database: env.create_database(wtxn, Some(METADATA_INDEX_DATABASE))?,
+        };
+        if index.database.is_empty(wtxn)? && !metadata_database.is_empty(wtxn)? {
+            let existing = metadata_database
+                .iter(wtxn)?
+                .collect::<Result<Vec<_>, _>>()?;
+            for (id, metadata) in existing {
+                index.insert(wtxn, id, &Self::entries(&metadata)?)?;
+            }
+        }
+        Ok(index)
+    }
+
+    /// Get the index keys the metadata is stored under. This fails if a metadata key can't be stored.
+    pub(super) fn entries(metadata: &EmbeddingMetadata) -> anyhow::Result<Vec<String>> {
+        let mut entries = BTreeSet::new();
+        for (key, value) in metadata.iter() {
+            if key.len() > MAX_KEY_LEN || key.contains(['\0', '\u{1}']) {
+                anyhow::bail!(
+                    "Metadata keys must be at most {} bytes long and can't contain control characters, but found the key {:?}",
+                    MAX_KEY_LEN,
+                    key
+                );
+            }
+            entries.insert(exists_key(key));
+            let values = match value {
+                MetadataValue::List(values) => values.as_slice(),
+                value => std::slice::from_ref(value),
+            };
+            for value in values {
+                // Filters never match lists inside of lists
+                if let MetadataValue::List(_) = value {
+                    continue;
+                }
+                let encoded = encode_value(value)?;
+                if encoded.len() <= MAX_VALUE_LEN {
+                    entries.insert(value_key(key, &encoded));
+                } else {
+                    entries.insert(overflow_key(key));
+                }
+            }
+        }
+        Ok(entries.into_iter().collect())
+    }
+
+    /// Add an id to the index under the entries from [`MetadataIndex::entries`].
+    pub(super) fn insert(
+        &self,
+        wtxn: &mut heed::RwTxn,
+        id: u32,
+        entries: &[String],
+    ) -> anyhow::Result<()> {
+        for entry in entries {
+            let mut ids = self.database.get(wtxn, entry)?.unwrap_or_default();
+            ids.insert(id);
+            self.database.put(wtxn, entry, &ids)?;
+        }
+        Ok(())
+    }
+
+    /// Remove an id with the given metadata from the index.
+    pub(super) fn remove(
+        &self,
+        wtxn: &mut heed::RwTxn,
+        id: u32,
+        metadata: &EmbeddingMetadata,
+    ) -> anyhow::Result<()> {
+        for entry in Self::entries(metadata)? {
+            if let Some(mut ids) = self.database.get(wtxn, &entry)? {
+                ids.remove(id);
+                if ids.is_empty() {
+                    self.database.delete(wtxn, &entry)?;
+                } else {
+                    self.database.put(wtxn, &entry, &ids)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Find the ids in `item_ids` with metadata that matches the filter.
+    pub(super) fn filter(
+        &self,
+        rtxn: &heed::RoTxn,
+        metadata_database: MetadataDatabase,
+        item_ids: &RoaringBitmap,
+        filter: &Filter,
+    ) -> anyhow::Result<RoaringBitmap> {
+        let ids = match filter {
+            Filter::Eq(key, expected) => {
+                self.equal(rtxn, key, expected)?
+                    | self.overflow(rtxn, metadata_database, key, filter)?
+            }
+            Filter::AnyOf(key, expected) => {
+                let mut ids = self.overflow(rtxn, metadata_database, key, filter)?;
+                for expected in expected {
+                    ids |= self.equal(rtxn, key, expected)?;
+                }
+                ids
+            }
+            Filter::Lt(key, _) | Filter::Lte(key, _) | Filter::Gt(key, _) | Filter::Gte(key, _) => {
+                self.range(rtxn, key, filter)?
+                    | self.overflow(rtxn, metadata_database, key, filter)?
+            }
+            Filter::Exists(key) => self.ids(rtxn, &exists_key(key))?,
+            Filter::And(filters) => {
+                let mut ids = item_ids.clone();
+                for filter in filters {
+                    if ids.is_empty() {
+                        break;
+                    }
+                    let matching = self.filter(rtxn, metadata_database, &ids, filter)?;
+                    ids &= matching;
+                }
+                ids
+            }
+            Filter::Or(filters) => {
+                let mut ids = RoaringBitmap::new();
+                for filter in filters {
+                    ids |= self.filter(rtxn, metadata_database, item_ids, filter)?;
+                }
+                ids
+            }
+            Filter::Not(filter) => {
+                item_ids - self.filter(rtxn, metadata_database, item_ids, filter)?
+            }
+        };
+        Ok(ids & item_ids)
+    }
+
+    fn ids(&self, rtxn: &heed::RoTxn, entry: &str) -> anyhow::Result<RoaringBitmap> {
+        Ok(self.database.get(rtxn, entry)?.unwrap_or_default())
+    }
+
+    /// The ids with a value equal to the expected value.
+    fn equal(
+        &self,
+        rtxn: &heed::RoTxn,
+        key: &str,
+        expected: &MetadataValue,
+    ) -> anyhow::Result<RoaringBitmap> {
+        self.ids(rtxn, &value_key(key, &encode_value(expected)?))
+    }
+
+    /// The ids with any indexed value for the key that matches a comparison filter on that key.
+    fn range(
+        &self,
+        rtxn: &heed::RoTxn,
+        key: &str,
+        filter: &Filter,
+    ) -> anyhow::Result<RoaringBitmap> {
+        let prefix = exists_key(key);
+        let mut ids = RoaringBitmap::new();
+        for entry in self.database.prefix_iter(rtxn, &prefix)? {
+            let (entry, entry_ids) = entry?;
+            let Ok(value) = serde_json::from_str::<MetadataValue>(&entry[prefix.len()..]) else {
+                continue;
+            };
+            if filter.matches(&EmbeddingMetadata::new().with(key, value)) {
+                ids |= entry_ids;
+            }
+        }
+        Ok(ids)
+    }
+
+    /// The ids with a value for the key that was too long to index and that match the filter.
+    fn overflow(
+        &self,
+        rtxn: &heed::RoTxn,
+        metadata_database: MetadataDatabase,
+        key: &str,
+        filter: &Filter,
+    ) -> anyhow::Result<RoaringBitmap> {
+        let mut ids = RoaringBitmap::new();
+        for id in self.ids(rtxn, &overflow_key(key))? {
+            let metadata = metadata_database.get(rtxn, &id)?.unwrap_or_default();
+            if filter.matches(&metadata) {
+                ids.insert(id);
+            }
+        }
+        Ok(ids)
+    }
+}
@@ -0,0 +1,955 @@
+//! A vector database that can be used to store embeddings and search for similar embeddings.
+
+use std::fmt::Debug;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use arroy::distances::Euclidean;
+use arroy::{Database as ArroyDatabase, Distance, Reader, Writer};
+use heed::byteorder::BigEndian;
+use heed::types::{SerdeJson, Str, U32};
+use heed::EnvOpenOptions;
+use kalosm_language_model::*;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use roaring::RoaringBitmap;
+use serde::{Deserialize, Serialize};
+
+mod filter;
+pub use filter::*;
+mod metadata_index;
+use metadata_index::*;
+
+/// The metric used to compare embeddings in a [`VectorDB`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DistanceMetric {
+    /// The euclidean distance between embeddings. The similarity is `1 / (1 + distance)`.
+    #[default]
+    Euclidean,
+    /// The angle between embeddings. The similarity is the cosine similarity between the embeddings.
+    Cosine,
+    /// The dot product of the embeddings. The similarity is the dot product.
+    ///
+    /// This is only meaningful for embeddings that are trained for inner-product search.
+    DotProduct,
+}
+
+impl DistanceMetric {
+    /// Get the similarity between two vectors with this metric. Higher values are more similar.
+    pub fn similarity(&self, first: &[f32], second: &[f32]) -> f32 {
+        let dot = || first.iter().zip(second).map(|(a, b)| a * b).sum::<f32>();
+        match self {
+            DistanceMetric::Euclidean => {
+                let distance = first
+                    .iter()
+                    .zip(second)
+                    .map(|(a, b)| (a - b).powi(2))
+                    .sum::<f32>()
+                    .sqrt();
+                1. / (1. + distance)
+            }
+            DistanceMetric::Cosine => {
+                let norm = |vector: &[f32]| vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+                let norms = norm(first) * norm(second);
+                if norms == 0. {
+                    0.
+                } else {
+                    dot() / norms
+                }
+            }
+            DistanceMetric::DotProduct => dot(),
+        }
+    }
+}
+
+/// Evaluate the body with `$distance` set to the arroy distance type for the metric.
+macro_rules! with_distance {
+    ($metric:expr, $distance:ident => $body:expr) => {
+        match $metric {
+            DistanceMetric::Euclidean => {
+                type $distance = arroy::distances::Euclidean;
+                $body
+            }
+            DistanceMetric::Cosine => {
+                type $distance = arroy::distances::Angular;
+                $body
+            }
+            DistanceMetric::DotProduct => {
+                type $distance = arroy::distances::DotProduct;
+                $body
+            }
+        }
+    };
+}
+
+mod transaction;
+pub use transaction::*;
+
+/// A vector database that can be used to store embeddings and search for similar embeddings.
+///
+/// It uses an in memory database with fast lookups for nearest neighbors and points within a certain distance.
+///
+/// # Example
+///
+/// ```rust, no_run
+/// use kalosm_language::prelude::VectorDB;
+/// use kalosm_language_model::Embedder;
+/// use rbert::*;
+///
+/// #[tokio::main]
+/// async fn main() -> anyhow::Result<()> {
+///     let mut bert = Bert::builder().build()?;
+///     let sentences = vec![
+///         "Cats are cool",
+///         "The geopolitical situation is dire",
+///         "Pets are great",
+///         "Napoleon was a tyrant",
+///         "Napoleon was a great general",
+///     ];
+///     let embeddings = bert.embed_batch(&sentences).await?;
+///     println!("embeddings {:?}", embeddings);
+///
+///     // Create a vector database from the embeddings
+///     let mut db = VectorDB::new()?;
+///     println!("added {:?}", db.add_embeddings(embeddings)?);
+///     // Find the closest sentence to "Cats are good"
+///     let embedding = bert.embed("Cats are good").await?;
+///     let closest = db.get_closest(embedding, 1)?;
+///     println!("closest: {:?}", closest);
+///
+///     Ok(())
+/// }
+/// ```
+pub struct VectorDB<S = UnknownVectorSpace> {
+    database: ArroyDatabase<Euclidean>,
+    info_database: heed::Database<Str, SerdeJson<VectorDBInfo>>,
+    metadata_database: heed::Database<U32<BigEndian>, SerdeJson<EmbeddingMetadata>>,
+    metadata_index: MetadataIndex,
+    env: heed::Env,
+    info: Mutex<VectorDBInfo>,
+    write_lock: Mutex<()>,
+    n_trees: Option<usize>,
+    _phantom: std::marker::PhantomData<S>,
+}
+
+/// The name of the table the [`VectorDBInfo`] is stored in.
+const INFO_DATABASE: &str = "kalosm-vector-db-info";
+/// The key of the [`VectorDBInfo`] in the info table.
+const INFO_KEY: &str = "info";
+/// The name of the table the [`EmbeddingMetadata`] of each embedding is stored in.
+const METADATA_DATABASE: &str = "kalosm-vector-db-metadata";
+
+/// Information about the vector database that is persisted next to the embeddings so the database can be reopened.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+struct VectorDBInfo {
+    /// The next id that has never been used.
+    max_id: u32,
+    /// Ids that were removed and can be reused.
+    recycled_ids: Vec<u32>,
+    /// The number of dimensions of the embeddings in the database.
+    dimensions: Option<usize>,
+    /// The version of the schema of the data associated with the embeddings.
+    schema_version: Option<u32>,
+    /// The name of the vector space the embeddings are in.
+    vector_space: Option<String>,
+    /// A description of how the embedded data was split into chunks.
+    #[serde(default)]
+    chunker: Option<String>,
+    /// The metric used to compare embeddings.
+    #[serde(default)]
+    metric: DistanceMetric,
+    /// If embeddings were added or removed since the index was last built.
+    #[serde(default)]
+    needs_build: bool,
+}
+
+impl VectorDBInfo {
+    /// Read the id state of a database created before the info table existed.
+    fn from_existing(rtxn: &heed::RoTxn, database: ArroyDatabase<Euclidean>) -> Self {
+        let Ok(reader) = Reader::<Euclidean>::open(rtxn, 0, database) else {
+            return Self::default();
+        };
+        let item_ids = reader.item_ids();
+        let max_id = item_ids.max().map_or(0, |id| id + 1);
+        Self {
+            max_id,
+            recycled_ids: (0..max_id).filter(|id| !item_ids.contains(*id)).collect(),
+            dimensions: Some(reader.dimensions()),
+            ..Default::default()
+        }
+    }
+
+    fn take_id(&mut self) -> EmbeddingId {
+        self.recycled_ids.pop().map(EmbeddingId).unwrap_or_else(|| {
+            let id = EmbeddingId(self.max_id);
+            self.max_id += 1;
+            id
+        })
+    }
+
+    fn recycle_id(&mut self, id: EmbeddingId) {
+        self.recycled_ids.push(id.0);
+    }
+
+    fn check_dimensions(&mut self, dimensions: usize) -> anyhow::Result<()> {
+        if dimensions == 0 {
+            anyhow::bail!("Embeddings must have at least one dimension");
+        }
+        match self.dimensions {
+            Some(expected) if expected != dimensions => anyhow::bail!(
+                "Embedding has {} dimensions, but the vector database contains embeddings with {} dimensions",
+                dimensions,
+                expected
+            ),
+            _ => {
+                self.dimensions = Some(dimensions);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// A builder for a [`VectorDB`].
+#[derive(Debug, Clone)]
+pub struct VectorDBBuilder<S = UnknownVectorSpace> {
+    location: Option<PathBuf>,
+    dimensions: Option<usize>,
+    schema_version: Option<u32>,
+    vector_space: Option<String>,
+    chunker: Option<String>,
+    metric: Option<DistanceMetric>,
+    n_trees: Option<usize>,
+    _phantom: std::marker::PhantomData<S>,
+}
+
+impl<S> Default for VectorDBBuilder<S> {
+    fn default() -> Self {
+        Self {
+            location: None,
+            dimensions: None,
+            schema_version: None,
+            vector_space: None,
+            chunker: None,
+            metric: None,
+            n_trees: None,
+            _phantom: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<S: VectorSpace + Sync> VectorDBBuilder<S> {
+    /// Set the location of the vector database. If no location is set, the database will be created in a temporary directory.
+    pub fn at(mut self, location: impl AsRef<Path>) -> Self {
+        self.location = Some(location.as_ref().to_path_buf());
+        self
+    }
+
+    /// Set the number of dimensions of the embeddings. Opening an existing database with a different number of dimensions will fail.
+    pub fn with_dimensions(mut self, dimensions: usize) -> Self {
+        self.dimensions = Some(dimensions);
+        self
+    }
+
+    /// Set the version of the schema of the data associated with the embeddings. Opening an existing database with a different schema version will fail.
+    pub fn with_schema_version(mut self, schema_version: u32) -> Self {
+        self.schema_version = Some(schema_version);
+        self
+    }
+
+    /// Set the name of the vector space the embeddings are in (typically the name of the embedding model). Opening an existing database with a different vector space will fail.
+    pub fn with_vector_space(mut self, vector_space: impl ToString) -> Self {
+        self.vector_space = Some(vector_space.to_string());
+        self
+    }
+
+    /// Set a description of how the embedded data was split into chunks (typically the configuration of the [`crate::search::Chunker`]). Opening an existing database with a different chunker will fail.
+    pub fn with_chunker(mut self, chunker: impl ToString) -> Self {
+        self.chunker = Some(chunker.to_string());
+        self
+    }
+
+    /// Set the metric used to compare embeddings. Defaults to [`DistanceMetric::Euclidean`] for new databases.
+    ///
+    /// Opening an existing database that contains embeddings with a different metric will fail.
+    pub fn with_distance_metric(mut self, metric: DistanceMetric) -> Self {
+        self.metric = Some(metric);
+        self
+    }
+
+    /// Set the number of trees in the index. More trees give more accurate results, but take longer to build and use more space. Defaults to a number of trees based on the number of embeddings.
+    pub fn with_tree_count(mut self, n_trees: usize) -> Self {
+        self.n_trees = Some(n_trees);
+        self
+    }
+
+    /// Open the vector database, or create it if it doesn't exist.
+    pub fn build(self) -> anyhow::Result<VectorDB<S>> {
+        const TWENTY_HUNDRED_MIB: usize = 2 * 1024 * 1024 * 1024;
+
+        let temp_dir;
+        let path = match self.location {
+            Some(location) => location,
+            None => {
+                temp_dir = tempfile::tempdir()?;
+                temp_dir.path().to_path_buf()
+            }
+        };
+        std::fs::create_dir_all(&path)?;
+
+        let env = EnvOpenOptions::new()
+            .map_size(TWENTY_HUNDRED_MIB)
+            .max_dbs(3)
+            .open(path)?;
+
+        let mut wtxn = env.write_txn()?;
+        let database: ArroyDatabase<Euclidean> = env.create_database(&mut wtxn, None)?;
+        let info_database: heed::Database<Str, SerdeJson<VectorDBInfo>> =
+            env.create_database(&mut wtxn, Some(INFO_DATABASE))?;
+        let metadata_database = env.create_database(&mut wtxn, Some(METADATA_DATABASE))?;
+        let metadata_index = MetadataIndex::create(&env, &mut wtxn, metadata_database)?;
+
+        let mut info = match info_database.get(&wtxn, INFO_KEY)? {
+            Some(info) => info,
+            None => VectorDBInfo::from_existing(&wtxn, database),
+        };
+        if let Some(dimensions) = self.dimensions {
+            info.check_dimensions(dimensions)?;
+        }
+        if let Some(schema_version) = self.schema_version {
+            if let Some(existing) = info.schema_version {
+                if existing != schema_version {
+                    anyhow::bail!(
+                        "The vector database has schema version {}, but schema version {} was requested",
+                        existing,
+                        schema_version
+                    );
+                }
+            }
+            info.schema_version = Some(schema_version);
+        }
+        if let Some(vector_space) = self.vector_space {
+            if let Some(existing) = &info.vector_space {
+                if *existing != vector_space {
+                    anyhow::bail!(
+                        "The vector database contains embeddings from the vector space {:?}, but the vector space {:?} was requested",
+                        existing,
+                        vector_space
+                    );
+                }
+            }
+            info.vector_space = Some(vector_space);
+        }
+        if let Some(chunker) = self.chunker {
+            if let Some(existing) = &info.chunker {
+                if *existing != chunker {
+                    anyhow::bail!(
+                        "The vector database contains embeddings of chunks from the chunker {:?}, but the chunker {:?} was requested",
+                        existing,
+                        chunker
+                    );
+                }
+            }
+            info.chunker = Some(chunker);
+        }
+        if let Some(metric) = self.metric {
+            if info.dimensions.is_some() && info.metric != metric {
+                anyhow::bail!(
+                    "The vector database uses the {:?} distance metric, but the {:?} distance metric was requested",
+                    info.metric,
+                    metric
+                );
+            }
+            info.metric = metric;
+        }
+        info_database.put(&mut wtxn, INFO_KEY, &info)?;
+        wtxn.commit()?;
+
+        Ok(VectorDB {
+            database,
+            info_database,
+            metadata_database,
+            metadata_index,
+            env,
+            info: Mutex::new(info),
+            write_lock: Mutex::new(()),
+            n_trees: self.n_trees,
+            _phantom: std::marker::PhantomData,
+        })
+    }
+}
+
+impl<S: VectorSpace + Sync> Default for VectorDB<S> {
+    fn default() -> Self {
+        Self::new().unwrap()
+    }
+}
+
+impl<S: VectorSpace + Sync> VectorDB<S> {
+    /// Create a builder for a vector database.
+    pub fn builder() -> VectorDBBuilder<S> {
+        VectorDBBuilder::default()
+    }
+
+    /// Create a new temporary vector database.
+    #[tracing::instrument]
+    pub fn new() -> anyhow::Result<Self> {
+        Self::builder().build()
+    }
+
+    /// Open the vector database at the given path, or create it if it doesn't exist.
+    ///
+    /// The ids of the embeddings and the recycled ids are stored in the database, so reopening a database will not reuse ids of embeddings that are still in the database.
+    pub fn new_at(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        Self::builder().at(path).build()
+    }
+
+    /// Get the number of dimensions of the embeddings in the database if any embeddings have been added.
+    pub fn dimensions(&self) -> Option<usize> {
+        self.info.lock().unwrap().dimensions
+    }
+
+    /// Get the schema version the database was created with.
+    pub fn schema_version(&self) -> Option<u32> {
+        self.info.lock().unwrap().schema_version
+    }
+
+    /// Get the name of the vector space the database was created with.
+    pub fn vector_space(&self) -> Option<String> {
+        self.info.lock().unwrap().vector_space.clone()
+    }
+
+    /// Get the description of the chunker the database was created with.
+    pub fn chunker(&self) -> Option<String> {
+        self.info.lock().unwrap().chunker.clone()
+    }
+
+    /// Get the metric used to compare embeddings.
+    pub fn distance_metric(&self) -> DistanceMetric {
+        self.info.lock().unwrap().metric
+    }
+
+    /// Start a transaction that batches adds and removes and builds the index once when it is committed.
+    ///
+    /// Note: Every call to [`VectorDB::add_embedding`], [`VectorDB::add_embeddings`] or [`VectorDB::remove_embedding`] rebuilds the index. Use a transaction when adding or removing many embeddings.
+    pub fn transaction(&self) -> anyhow::Result<VectorDBTransaction<'_, S>> {
+        VectorDBTransaction::new(self)
+    }
+
+    /// Returns true if embeddings were added or removed without building the index.
+    pub fn needs_build(&self) -> bool {
+        self.info.lock().unwrap().needs_build
+    }
+
+    /// Build the index if any embeddings were added or removed without building it.
+    pub fn build(&self) -> anyhow::Result<()> {
+        if self.needs_build() {
+            self.transaction()?.commit()?;
+        }
+        Ok(())
+    }
+
+    /// Build the index on a background thread. Searches use the old index until the new index is committed.
+    pub fn build_in_background(self: &Arc<Self>) -> std::thread::JoinHandle<anyhow::Result<()>>
+    where
+        S: Send + 'static,
+    {
+        let db = self.clone();
+        std::thread::spawn(move || db.build())
+    }
+
+    fn build_index(&self, wtxn: &mut heed::RwTxn, info: &mut VectorDBInfo) -> anyhow::Result<()> {
+        if !info.needs_build {
+            return Ok(());
+        }
+        if let Some(dims) = info.dimensions {
+            with_distance!(info.metric, D => {
+                let writer = Writer::<D>::new(self.database::<D>(), 0, dims)?;
+                let mut rng = StdRng::from_entropy();
+                writer.build(wtxn, &mut rng, self.n_trees)?;
+            });
+        }
+        info.needs_build = false;
+        Ok(())
+    }
+
+    /// Get the underlying database.
+    ///
+    /// The database is typed with the euclidean distance. If the database uses a different [`DistanceMetric`], remap the data type to the matching arroy distance before using it.
+    pub fn raw(&self) -> (&ArroyDatabase<Euclidean>, &heed::Env) {
+        (&self.database, &self.env)
+    }
+
+    fn database<D: Distance>(&self) -> ArroyDatabase<D> {
+        self.database.remap_data_type()
+    }
+
+    fn search<D: Distance>(
+        &self,
+        metric: DistanceMetric,
+        vector: &[f32],
+        n: usize,
+        filter: Option<&Filter>,
+    ) -> anyhow::Result<Vec<VectorDBSearchResult>> {
+        let rtxn = self.env.read_txn()?;
+        let reader = Reader::<D>::open(&rtxn, 0, self.database::<D>())?;
+
+        let candidates = match filter {
+            Some(filter) => {
+                let candidates = self.filter_ids_in(&rtxn, reader.item_ids(), filter)?;
+                if candidates.is_empty() {
+                    return Ok(Vec::new());
+                }
+                Some(candidates)
+            }
+            None => None,
+        };
+        let arroy_results = reader.nns_by_vector(&rtxn, vector, n, None, candidates.as_ref())?;
+
+        arroy_results
+            .into_iter()
+            .map(|(id, distance)| {
+                let item = reader
+                    .item_vector(&rtxn, id)?
+                    .ok_or_else(|| anyhow::anyhow!("Embedding {} not found", id))?;
+                Ok(VectorDBSearchResult {
+                    distance,
+                    similarity: metric.similarity(vector, &item),
+                    value: EmbeddingId(id),
+                })
+            })
+            .collect()
+    }
+
+    /// Find the ids in `item_ids` with metadata that matches the filter.
+    fn filter_ids_in(
+        &self,
+        rtxn: &heed::RoTxn,
+        item_ids: &RoaringBitmap,
+        filter: &Filter,
+    ) -> anyhow::Result<RoaringBitmap> {
+        self.metadata_index
+            .filter(rtxn, self.metadata_database, item_ids, filter)
+    }
+
+    /// Get the ids of all embeddings with metadata that matches the filter.
+    pub fn filter_ids(&self, filter: &Filter) -> anyhow::Result<RoaringBitmap> {
+        let metric = self.distance_metric();
+        let rtxn = self.env.read_txn()?;
+        with_distance!(metric, D => {
+            let reader = Reader::<D>::open(&rtxn, 0, self.database::<D>())?;
+            self.filter_ids_in(&rtxn, reader.item_ids(), filter)
+        })
+    }
+
+    /// Get the ids of all embeddings in the database, including embeddings that were added without building the index.
+    pub fn embedding_ids(&self) -> RoaringBitmap {
+        let info = self.info.lock().unwrap();
+        let mut ids = RoaringBitmap::new();
+        ids.insert_range(0..info.max_id);
+        for id in &info.recycled_ids {
+            ids.remove(*id);
+        }
+        ids
+    }
+
+    /// Get the metadata of an embedding.
+    pub fn metadata(&self, embedding_id: EmbeddingId) -> anyhow::Result<EmbeddingMetadata> {
+        let rtxn = self.env.read_txn()?;
+        Ok(self
+            .metadata_database
+            .get(&rtxn, &embedding_id.0)?
+            .unwrap_or_default())
+    }
+
+    /// Remove an embedding from the vector database.
+    pub fn remove_embedding(&self, embedding_id: EmbeddingId) -> anyhow::Result<()> {
+        let mut transaction = self.transaction()?;
+        transaction.remove_embedding(embedding_id)?;
+        transaction.commit()
+    }
+
+    /// Add a new embedding to the vector database.
+    ///
+    /// Note: Adding embeddings in a batch with [`VectorDB::add_embeddings`] will be faster.
+    pub fn add_embedding(&self, embedding: Embedding<S>) -> anyhow::Result<EmbeddingId> {
+        self.add_embedding_with_metadata(embedding, EmbeddingMetadata::default())
+    }
+
+    /// Add a new embedding with metadata to the vector database. The metadata can be used to filter results with [`VectorDB::get_closest_with_filter`].
+    pub fn add_embedding_with_metadata(
+        &self,
+        embedding: Embedding<S>,
+        metadata: EmbeddingMetadata,
+    ) -> anyhow::Result<EmbeddingId> {
+        let mut ids = self.add_embeddings_with_metadata([(embedding, metadata)])?;
+        Ok(ids.remove(0))
+    }
+
+    /// Add a new batch of embeddings to the vector database.
+    pub fn add_embeddings(
+        &self,
+        embedding: impl IntoIterator<Item = Embedding<S>>,
+    ) -> anyhow::Result<Vec<EmbeddingId>> {
+        self.add_embeddings_with_metadata(
+            embedding
+                .into_iter()
+                .map(|embedding| (embedding, EmbeddingMetadata::default())),
+        )
+    }
+
+    /// Add a new batch of embeddings with metadata to the vector database.
+    pub fn add_embeddings_with_metadata(
+        &self,
+        embeddings: impl IntoIterator<Item = (Embedding<S>, EmbeddingMetadata)>,
+    ) -> anyhow::Result<Vec<EmbeddingId>> {
+        let mut transaction = self.transaction()?;
+        let ids = transaction.add_embeddings_with_metadata(embeddings)?;
+        transaction.commit()?;
+        Ok(ids)
+    }
+
+    /// Get the closest N embeddings to the given embedding.
+    pub fn get_closest(
+        &self,
+        embedding: Embedding<S>,
+        n: usize,
+    ) -> anyhow::Result<Vec<VectorDBSearchResult>> {
+        let vector = embedding.vector().to_vec1()?;
+        let metric = self.distance_metric();
+
+        with_distance!(metric, D => self.search::<D>(metric, &vector, n, None))
+    }
+
+    /// Get the closest N embeddings to any of the given embeddings. The results of each embedding are combined with reciprocal rank fusion (see [`crate::search::fuse_rankings`]), so embeddings that are close to several of the searched embeddings are ranked first. The similarity of each result is the best similarity to any of the searched embeddings.
+    ///
+    /// This is used to search for every query a [`crate::search::QueryTransform`] returns.
+    pub fn get_closest_to_any(
+        &self,
+        embeddings: impl IntoIterator<Item = Embedding<S>>,
+        n: usize,
+    ) -> anyhow::Result<Vec<VectorDBSearchResult>> {
+        let rankings = embeddings
+            .into_iter()
+            .map(|embedding| self.get_closest(embedding, n))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let mut best_similarity = std::collections::HashMap::new();
+        for result in rankings.iter().flatten() {
+            let best = best_similarity
+                .entry(result.value)
+                .or_insert_with(|| result.clone());
+            if result.similarity > best.similarity {
+                *best = result.clone();
+            }
+        }
+        Ok(
+            crate::search::fuse_rankings(rankings, |result| result.value, 60.)
+                .into_iter()
+                .take(n)
+                .map(|(result, _)| best_similarity[&result.value].clone())
+                .collect(),
+        )
+    }
+
+    /// Get the closest N embeddings to the given embedding with metadata that matches the filter.
+    ///
+    /// The filter is applied before the nearest neighbor search, so the results will only contain embeddings that match the filter.
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// use kalosm_language::prelude::*;
+    ///
+    /// # fn main() -> anyhow::Result<()> {
+    /// let db = VectorDB::<UnknownVectorSpace>::new()?;
+    /// db.add_embedding_with_metadata(
+    ///     vec![1.0, 0.0].into(),
+    ///     EmbeddingMetadata::new().with("tenant", "acme"),
+    /// )?;
+    /// db.add_embedding_with_metadata(
+    ///     vec![1.0, 0.1].into(),
+    ///     EmbeddingMetadata::new().with("tenant", "globex"),
+    /// )?;
+    /// let closest = db.get_closest_with_filter(vec![1.0, 0.0].into(), 10, &Filter::eq("tenant", "acme"))?;
+    /// assert_eq!(closest.len(), 1);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn get_closest_with_filter(
+        &self,
+        embedding: Embedding<S>,
+        n: usize,
+        filter: &Filter,
+    ) -> anyhow::Result<Vec<VectorDBSearchResult>> {
+        let vector = embedding.vector().to_vec1()?;
+        let metric = self.distance_metric();
+
+        with_distance!(metric, D => self.search::<D>(metric, &vector, n, Some(filter)))
+    }
+}
+
+/// A resulting point from a search.
+#[derive(Debug, Clone)]
+pub struct VectorDBSearchResult {
+    /// The distance from the searched point as reported by the index.
+    pub distance: f32,
+    /// The similarity to the searched point with the [`DistanceMetric`] of the database. Higher values are more similar.
+    pub similarity: f32,
+    /// The value of the point.
+    pub value: EmbeddingId,
+}
+
+/// A unique identifier for an embedding. If you delete an embedding, the id will be recycled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct EmbeddingId(pub u32);
+
+#[test]
+fn reopened_vector_db_keeps_ids() {
+    let dir = tempfile::tempdir().unwrap();
+
+    {
+        let db = VectorDB::<UnknownVectorSpace>::builder()
+            .at(dir.path())
+            .with_vector_space("test")
+            .with_chunker("sentences")
+            .build()
+            .unwrap();
+        let ids = db
+            .add_embeddings([vec![1.0, 0.0], vec![0.0, 1.0], vec![1.0, 1.0]].map(Embedding::from))
+            .unwrap();
+        db.remove_embedding(ids[1]).unwrap();
+    }
+
+    let db = VectorDB::<UnknownVectorSpace>::new_at(dir.path()).unwrap();
+    assert_eq!(db.dimensions(), Some(2));
+    assert_eq!(db.vector_space().as_deref(), Some("test"));
+    assert_eq!(db.chunker().as_deref(), Some("sentences"));
+    assert_eq!(
+        db.add_embedding(vec![2.0, 2.0].into()).unwrap(),
+        EmbeddingId(1)
+    );
+    assert_eq!(
+        db.add_embedding(vec![3.0, 3.0].into()).unwrap(),
+        EmbeddingId(3)
+    );
+    assert!(db.add_embedding(vec![1.0, 2.0, 3.0].into()).is_err());
+    drop(db);
+
+    assert!(VectorDB::<UnknownVectorSpace>::builder()
+        .at(dir.path())
+        .with_vector_space("other")
+        .build()
+        .is_err());
+    assert!(VectorDB::<UnknownVectorSpace>::builder()
+        .at(dir.path())
+        .with_chunker("paragraphs")
+        .build()
+        .is_err());
+}
+
+#[test]
+fn similarity_matches_metric() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = VectorDB::<UnknownVectorSpace>::builder()
+        .at(dir.path())
+        .with_distance_metric(DistanceMetric::Cosine)
+        .build()
+        .unwrap();
+    db.add_embeddings([vec![1.0, 0.0], vec![0.0, 2.0], vec![3.0, 3.0]].map(Embedding::from))
+        .unwrap();
+
+    let results = db.get_closest(vec![2.0, 0.0].into(), 3).unwrap();
+    assert_eq!(results[0].value, EmbeddingId(0));
+    assert!((results[0].similarity - 1.0).abs() < 1e-5);
+    assert!((results[1].similarity - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-5);
+    assert!(results[2].similarity.abs() < 1e-5);
+    drop(db);
+
+    assert!(VectorDB::<UnknownVectorSpace>::builder()
+        .at(dir.path())
+        .with_distance_metric(DistanceMetric::Euclidean)
+        .build()
+        .is_err());
+}
+
+#[test]
+fn filtered_search_only_returns_matching_embeddings() {
+    let db = VectorDB::<UnknownVectorSpace>::new().unwrap();
+    let ids = db
+        .add_embeddings_with_metadata([
+            (
+                vec![1.0, 0.0].into(),
+                EmbeddingMetadata::new().with("tenant", "acme"),
+            ),
+            (
+                vec![1.0, 0.1].into(),
+                EmbeddingMetadata::new().with("tenant", "globex"),
+            ),
+            (vec![1.0, 0.2].into(), EmbeddingMetadata::new()),
+        ])
+        .unwrap();
+
+    let results = db
+        .get_closest_with_filter(vec![1.0, 0.1].into(), 3, &Filter::eq("tenant", "acme"))
+        .unwrap();
+    assert_eq!(
+        results
+            .iter()
+            .map(|result| result.value)
+            .collect::<Vec<_>>(),
+        vec![ids[0]]
+    );
+
+    let results = db
+        .get_closest_with_filter(vec![1.0, 0.1].into(), 3, &Filter::ne("tenant", "globex"))
+        .unwrap();
+    assert_eq!(results.len(), 2);
+    assert!(results.iter().all(|result| result.value != ids[1]));
+
+    db.remove_embedding(ids[0]).unwrap();
+    assert!(db.metadata(ids[0]).unwrap().is_empty());
+    assert!(db
+        .filter_ids(&Filter::eq("tenant", "acme"))
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn indexed_equality_filters_match_negative_zero() {
+    let db = VectorDB::<UnknownVectorSpace>::new().unwrap();
+    let ids = db
+        .add_embeddings_with_metadata([
+            (
+                vec![1.0, 0.0].into(),
+                EmbeddingMetadata::new().with("offset", -0.0),
+            ),
+            (
+                vec![1.0, 0.1].into(),
+                EmbeddingMetadata::new().with("offset", 0.0),
+            ),
+        ])
+        .unwrap();
+
+    for filter in [Filter::eq("offset", 0.0), Filter::eq("offset", -0.0)] {
+        assert_eq!(
+            db.filter_ids(&filter).unwrap().iter().collect::<Vec<_>>(),
+            vec![ids[0].0, ids[1].0]
+        );
+    }
+}
+
+#[test]
+fn deferred_build_hides_embeddings_until_built() {
+    let db = Arc::new(
+        VectorDB::<UnknownVectorSpace>::builder()
+            .with_tree_count(2)
+            .build()
+            .unwrap(),
+    );
+    let mut transaction = db.transaction().unwrap();
+    let first = transaction.add_embedding(vec![1.0, 0.0].into()).unwrap();
+    transaction.commit().unwrap();
+    assert!(!db.needs_build());
+
+    let mut transaction = db.transaction().unwrap();
+    let second = transaction.add_embedding(vec![0.0, 1.0].into()).unwrap();
+    transaction.commit_without_build().unwrap();
+    assert!(db.needs_build());
+    let results = db.get_closest(vec![0.0, 1.0].into(), 2).unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].value, first);
+
+    db.build_in_background().join().unwrap().unwrap();
+    assert!(!db.needs_build());
+    let results = db.get_closest(vec![0.0, 1.0].into(), 2).unwrap();
+    assert_eq!(results[0].value, second);
+
+    let mut transaction = db.transaction().unwrap();
+    transaction.add_embedding(vec![1.0, 1.0].into()).unwrap();
+    drop(transaction);
+    assert_eq!(db.get_closest(vec![1.0, 1.0].into(), 3).unwrap().len(), 2);
+}
+
+#[test]
+fn closest_to_any_fuses_the_results_of_every_embedding() {
+    let db = VectorDB::<UnknownVectorSpace>::new().unwrap();
+    db.add_embeddings([vec![1.0, 0.0], vec![0.0, 1.0], vec![1.0, 1.0]].map(Embedding::from))
+        .unwrap();
+
+    let results = db
+        .get_closest_to_any([vec![1.0, 0.1].into(), vec![0.1, 1.0].into()], 2)
+        .unwrap();
+    assert_eq!(results.len(), 2);
+    // The embedding between both queries is the second result of both searches
+    assert_eq!(results[0].value, EmbeddingId(2));
+    assert_eq!(results[1].value, EmbeddingId(0));
+    let closest = db.get_closest(vec![1.0, 0.1].into(), 1).unwrap();
+    assert_eq!(results[1].similarity, closest[0].similarity);
+}
+
+#[test]
+fn removing_missing_embeddings_does_not_reuse_ids() {
+    let db = VectorDB::<UnknownVectorSpace>::new().unwrap();
+    let ids = db
+        .add_embeddings([vec![1.0, 0.0], vec![0.0, 1.0]].map(Embedding::from))
+        .unwrap();
+    db.remove_embedding(ids[0]).unwrap();
+    db.remove_embedding(ids[0]).unwrap();
+    db.remove_embedding(EmbeddingId(42)).unwrap();
+
+    let new_ids = db
+        .add_embeddings([vec![1.0, 1.0], vec![2.0, 2.0]].map(Embedding::from))
+        .unwrap();
+    assert_eq!(new_ids, vec![ids[0], EmbeddingId(2)]);
+    assert_eq!(db.embedding_ids().len(), 3);
+}
+
+#[test]
+fn metadata_index_matches_filters() {
+    let db = VectorDB::<UnknownVectorSpace>::new().unwrap();
+    let long = "x".repeat(300);
+    let ids = db
+        .add_embeddings_with_metadata([
+            (
+                vec![1.0, 0.0].into(),
+                EmbeddingMetadata::new()
+                    .with("views", 10)
+                    .with("tags", vec!["rust", "search"]),
+            ),
+            (
+                vec![0.0, 1.0].into(),
+                EmbeddingMetadata::new()
+                    .with("views", 3)
+                    .with("body", long.as_str()),
+            ),
+            (vec![1.0, 1.0].into(), EmbeddingMetadata::new()),
+        ])
+        .unwrap();
+    let filter_ids = |filter: Filter| db.filter_ids(&filter).unwrap().iter().collect::<Vec<_>>();
+
+    assert_eq!(filter_ids(Filter::gt("views", 5)), vec![ids[0].0]);
+    assert_eq!(
+        filter_ids(Filter::lte("views", 10)),
+        vec![ids[0].0, ids[1].0]
+    );
+    assert_eq!(filter_ids(Filter::eq("tags", "search")), vec![ids[0].0]);
+    assert_eq!(
+        filter_ids(Filter::eq("body", long.as_str())),
+        vec![ids[1].0]
+    );
+    assert_eq!(filter_ids(Filter::exists("body")), vec![ids[1].0]);
+    assert_eq!(
+        filter_ids(Filter::any_of("tags", ["python", "rust"]).or(Filter::lt("views", 5))),
+        vec![ids[0].0, ids[1].0]
+    );
+    assert_eq!(filter_ids(Filter::exists("views").not()), vec![ids[2].0]);
+    assert!(filter_ids(Filter::eq("views", 10).and(Filter::exists("body"))).is_empty());
+
+    db.remove_embedding(ids[0]).unwrap();
+    assert!(filter_ids(Filter::eq("tags", "rust")).is_empty());
+    assert_eq!(filter_ids(Filter::exists("views")), vec![ids[1].0]);
+    assert!(db
+        .add_embedding_with_metadata(
+            vec![2.0, 2.0].into(),
+            EmbeddingMetadata::new().with("bad\0key", true)
+        )
+        .is_err());
+}
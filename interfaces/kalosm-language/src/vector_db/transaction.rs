@@ -0,0 +1,153 @@
+use std::sync::MutexGuard;
+
+use arroy::Writer;
+use kalosm_language_model::*;
+
+use super::{
+    DistanceMetric, EmbeddingId, EmbeddingMetadata, MetadataIndex, VectorDB, VectorDBInfo, INFO_KEY,
+};
+
+/// A batch of changes to a [`VectorDB`]. Changes are written to disk as they are made, but they are only visible to readers after the transaction is committed. The index is built once when the transaction is committed instead of after every change.
+///
+/// Dropping the transaction without committing it discards all changes.
+///
+/// Only one transaction can be open at a time. Other writes to the database will wait until the transaction is committed or dropped.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm_language::prelude::*;
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let db = VectorDB::<UnknownVectorSpace>::new()?;
+/// let mut transaction = db.transaction()?;
+/// for i in 0..1000 {
+///     transaction.add_embedding(vec![i as f32, 1.0].into())?;
+/// }
+/// transaction.commit()?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct VectorDBTransaction<'a, S: VectorSpace + Sync = UnknownVectorSpace> {
+    db: &'a VectorDB<S>,
+    wtxn: heed::RwTxn<'a>,
+    info: VectorDBInfo,
+    _write_lock: MutexGuard<'a, ()>,
+}
+
+impl<'a, S: VectorSpace + Sync> VectorDBTransaction<'a, S> {
+    pub(super) fn new(db: &'a VectorDB<S>) -> anyhow::Result<Self> {
+        let write_lock = db.write_lock.lock().unwrap();
+        let wtxn = db.env.write_txn()?;
+        let info = match db.info_database.get(&wtxn, INFO_KEY)? {
+            Some(info) => info,
+            None => db.info.lock().unwrap().clone(),
+        };
+        Ok(Self {
+            db,
+            wtxn,
+            info,
+            _write_lock: write_lock,
+        })
+    }
+
+    /// Add a new embedding to the vector database.
+    pub fn add_embedding(&mut self, embedding: Embedding<S>) -> anyhow::Result<EmbeddingId> {
+        self.add_embedding_with_metadata(embedding, EmbeddingMetadata::default())
+    }
+
+    /// Add a new embedding with metadata to the vector database.
+    pub fn add_embedding_with_metadata(
+        &mut self,
+        embedding: Embedding<S>,
+        metadata: EmbeddingMetadata,
+    ) -> anyhow::Result<EmbeddingId> {
+        let embedding = embedding.vector().to_vec1()?;
+        self.info.check_dimensions(embedding.len())?;
+        let index_entries = MetadataIndex::entries(&metadata)?;
+        let id = self.info.take_id();
+
+        with_distance!(self.info.metric, D => {
+            let writer = Writer::<D>::new(self.db.database::<D>(), 0, embedding.len())?;
+            writer.add_item(&mut self.wtxn, id.0, &embedding)?;
+        });
+        if !metadata.is_empty() {
+            self.db
+                .metadata_database
+                .put(&mut self.wtxn, &id.0, &metadata)?;
+            self.db
+                .metadata_index
+                .insert(&mut self.wtxn, id.0, &index_entries)?;
+        }
+        self.info.needs_build = true;
+
+        Ok(id)
+    }
+
+    /// Add a new batch of embeddings to the vector database.
+    pub fn add_embeddings(
+        &mut self,
+        embeddings: impl IntoIterator<Item = Embedding<S>>,
+    ) -> anyhow::Result<Vec<EmbeddingId>> {
+        embeddings
+            .into_iter()
+            .map(|embedding| self.add_embedding(embedding))
+            .collect()
+    }
+
+    /// Add a new batch of embeddings with metadata to the vector database.
+    pub fn add_embeddings_with_metadata(
+        &mut self,
+        embeddings: impl IntoIterator<Item = (Embedding<S>, EmbeddingMetadata)>,
+    ) -> anyhow::Result<Vec<EmbeddingId>> {
+        embeddings
+            .into_iter()
+            .map(|(embedding, metadata)| self.add_embedding_with_metadata(embedding, metadata))
+            .collect()
+    }
+
+    /// Remove an embedding from the vector database. Removing an embedding that is not in the database does nothing.
+    pub fn remove_embedding(&mut self, embedding_id: EmbeddingId) -> anyhow::Result<()> {
+        let dims = self.info.dimensions.ok_or_else(|| {
+            anyhow::anyhow!("The vector database does not contain any embeddings")
+        })?;
+
+        let removed = with_distance!(self.info.metric, D => {
+            let writer = Writer::<D>::new(self.db.database::<D>(), 0, dims)?;
+            writer.del_item(&mut self.wtxn, embedding_id.0)?
+        });
+        // Only ids that were in use can be handed out again, otherwise the same id could be given to two embeddings
+        if !removed {
+            return Ok(());
+        }
+        if let Some(metadata) = self.db.metadata_database.get(&self.wtxn, &embedding_id.0)? {
+            self.db
+                .metadata_index
+                .remove(&mut self.wtxn, embedding_id.0, &metadata)?;
+            self.db
+                .metadata_database
+                .delete(&mut self.wtxn, &embedding_id.0)?;
+        }
+        self.info.recycle_id(embedding_id);
+        self.info.needs_build = true;
+
+        Ok(())
+    }
+
+    /// Build the index and commit the changes.
+    pub fn commit(mut self) -> anyhow::Result<()> {
+        self.db.build_index(&mut self.wtxn, &mut self.info)?;
+        self.commit_without_build()
+    }
+
+    /// Commit the changes without building the index. Embeddings added in the transaction will not be returned from searches until the index is built with [`VectorDB::build`] or [`VectorDB::build_in_background`].
+    ///
+    /// This is useful when ingesting many batches of embeddings: build the index once after the last batch instead of after every batch.
+    pub fn commit_without_build(mut self) -> anyhow::Result<()> {
+        self.db
+            .info_database
+            .put(&mut self.wtxn, INFO_KEY, &self.info)?;
+        self.wtxn.commit()?;
+        *self.db.info.lock().unwrap() = self.info;
+        Ok(())
+    }
+}
@@ -31,7 +31,7 @@ const EXAMPLES: &[(&str, &str)]= &[
 
 #[tokio::main]
 async fn main() {
-    let mut llm = Phi::v2();
+    let llm = Phi::v2();
     const PREFIX: &str = "Questions that are answered by the previous text: ";
     const QUESTION_STARTERS: [&str; 9] = [
         "Who", "What", "When", "Where", "Why", "How", "Which", "Whom", "Whose",
@@ -51,13 +51,20 @@ async fn main() {
     );
     let task = Task::builder("You generate hypothetical questions that may be answered by the given text. The questions restate any information necessary to understand the question")
         .with_constraints(constraints);
-    let mut annealing = kalosm::PromptAnnealer::builder(&mut llm, EXAMPLES, task)
-        .with_initial_temperature(0.6)
-        .with_initial_choice_range(1..4)
+    let mut optimizer = kalosm::PromptOptimizer::builder(&llm, EXAMPLES.iter().copied(), task)
+        .with_strategy(
+            kalosm::Annealing::default()
+                .with_initial_temperature(0.6)
+                .with_initial_choice_range(1..4),
+        )
+        .with_strategy(kalosm::InstructionRewrite::default())
+        .on_iteration(|step| println!("({}) score = {}", step.strategy, step.score))
         .build()
-        .await;
+        .await
+        .unwrap();
 
-    let result = annealing.run().await;
+    let result = optimizer.run().await.unwrap();
 
-    println!("Result: {:?}", result);
+    println!("Result: {:?}", result.best());
+    result.save_best("hypothetical-questions-task.json").unwrap();
 }
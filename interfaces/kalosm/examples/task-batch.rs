@@ -0,0 +1,39 @@
+use kalosm::language::*;
+
+#[tokio::main]
+async fn main() {
+    tracing_subscriber::fmt::init();
+
+    let llm = Llama::new_chat();
+
+    let constraints = LiteralParser::new("Sentiment: ")
+        .then(LiteralParser::new("positive").or(LiteralParser::new("negative")));
+
+    let task = Task::builder("You classify the sentiment of product reviews as positive or negative.")
+        .with_constraints(constraints)
+        .with_example("I love this blender, it crushes ice in seconds.", "Sentiment: positive")
+        .with_example("The handle snapped off the first time I used it.", "Sentiment: negative")
+        .build();
+
+    let reviews = [
+        "Great battery life and the screen is gorgeous.",
+        "Shipping took a month and the box was crushed.",
+        "Does exactly what it says on the tin.",
+        "Stopped charging after a week.",
+    ];
+
+    let mut batch = task
+        .run_batch(reviews, &llm)
+        .with_concurrency_limit(2)
+        .with_retries(1)
+        .on_progress(|progress| {
+            println!("finished {}/{}", progress.completed, progress.total);
+        });
+
+    while let Some((index, response)) = batch.next().await {
+        match response {
+            Ok((_, sentiment)) => println!("{} -> {:?}", reviews[index], sentiment),
+            Err(err) => println!("{} -> failed: {}", reviews[index], err),
+        }
+    }
+}
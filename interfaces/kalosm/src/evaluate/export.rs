@@ -0,0 +1,536 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Display;
+use std::path::Path;
+
+use comfy_table::{Cell, Color, Table};
+use serde::{Deserialize, Serialize};
+
+use super::EvaluationResult;
+
+/// A single scored test case in an [`EvaluationSummary`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScoredCase {
+    /// The position of the test case in the run. Summaries saved before the position was recorded don't have one.
+    #[serde(default)]
+    pub index: Option<usize>,
+    /// The expected output.
+    pub expected: String,
+    /// The actual output.
+    pub actual: String,
+    /// The score of the actual output.
+    pub score: f64,
+}
+
+/// A serializable snapshot of an [`EvaluationResult`]. Summaries can be saved as JSON or CSV, and a saved JSON summary can be loaded as a baseline to compare later runs against with [`EvaluationResult::diff`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EvaluationSummary {
+    /// The name of the test cases.
+    pub name: String,
+    /// The mean score.
+    pub mean: f64,
+    /// The median score.
+    pub median: f64,
+    /// The minimum score.
+    pub min: f64,
+    /// The maximum score.
+    pub max: f64,
+    /// The score at the 25th percentile.
+    pub percentile_25: f64,
+    /// The score at the 75th percentile.
+    pub percentile_75: f64,
+    /// Every test case with its score, in the order the cases were run.
+    pub cases: Vec<ScoredCase>,
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+impl EvaluationSummary {
+    /// Serialize the summary to JSON.
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Serialize the test cases to CSV with the columns `expected`, `actual` and `score`.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("expected,actual,score\n");
+        for case in &self.cases {
+            csv += &format!(
+                "{},{},{}\n",
+                csv_field(&case.expected),
+                csv_field(&case.actual),
+                case.score
+            );
+        }
+        csv
+    }
+
+    /// Save the summary as JSON.
+    pub fn save_json(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        std::fs::write(path, self.to_json()?)?;
+        Ok(())
+    }
+
+    /// Save the test cases as CSV.
+    pub fn save_csv(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        std::fs::write(path, self.to_csv())?;
+        Ok(())
+    }
+
+    /// Load a summary that was saved with [`EvaluationSummary::save_json`].
+    pub fn load_json(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let json = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    /// Compare this summary against a baseline summary.
+    ///
+    /// Test cases are matched by their position in the run if the expected output at that position is the same. Otherwise, for example in baselines saved without positions or after test cases were added, they are matched by their expected output in run order.
+    pub fn diff(&self, baseline: &EvaluationSummary) -> EvaluationDiff {
+        let mut pairs: Vec<Option<usize>> = vec![None; self.cases.len()];
+        let mut paired = vec![false; baseline.cases.len()];
+
+        let baseline_positions: HashMap<usize, usize> = baseline
+            .cases
+            .iter()
+            .enumerate()
+            .filter_map(|(position, case)| Some((case.index?, position)))
+            .collect();
+        for (case, pair) in self.cases.iter().zip(&mut pairs) {
+            let Some(&position) = case.index.and_then(|index| baseline_positions.get(&index))
+            else {
+                continue;
+            };
+            if !paired[position] && baseline.cases[position].expected == case.expected {
+                paired[position] = true;
+                *pair = Some(position);
+            }
+        }
+
+        let mut unpaired: HashMap<&str, VecDeque<usize>> = HashMap::new();
+        for (position, case) in baseline.cases.iter().enumerate() {
+            if !paired[position] {
+                unpaired
+                    .entry(case.expected.as_str())
+                    .or_default()
+                    .push_back(position);
+            }
+        }
+        for (case, pair) in self.cases.iter().zip(&mut pairs) {
+            if pair.is_none() {
+                *pair = unpaired
+                    .get_mut(case.expected.as_str())
+                    .and_then(|positions| positions.pop_front());
+                if let Some(position) = *pair {
+                    paired[position] = true;
+                }
+            }
+        }
+
+        let mut cases = Vec::new();
+        for (case, pair) in self.cases.iter().zip(pairs) {
+            let baseline_case = pair.map(|position| &baseline.cases[position]);
+            cases.push(CaseDiff {
+                expected: case.expected.clone(),
+                actual: Some(case.actual.clone()),
+                score: Some(case.score),
+                baseline_actual: baseline_case.map(|case| case.actual.clone()),
+                baseline_score: baseline_case.map(|case| case.score),
+            });
+        }
+        for (case, _) in baseline
+            .cases
+            .iter()
+            .zip(paired)
+            .filter(|(_, paired)| !paired)
+        {
+            cases.push(CaseDiff {
+                expected: case.expected.clone(),
+                actual: None,
+                score: None,
+                baseline_actual: Some(case.actual.clone()),
+                baseline_score: Some(case.score),
+            });
+        }
+
+        EvaluationDiff {
+            name: self.name.clone(),
+            mean: self.mean,
+            baseline_mean: baseline.mean,
+            median: self.median,
+            baseline_median: baseline.median,
+            cases,
+        }
+    }
+}
+
+impl<'a, I: Display> EvaluationResult<'a, I> {
+    /// Create a serializable summary of the result.
+    pub fn summary(&self) -> EvaluationSummary {
+        EvaluationSummary {
+            name: self.name.clone(),
+            mean: self.mean_score(),
+            median: self.median_score(),
+            min: self.min_score(),
+            max: self.max_score(),
+            percentile_25: self.quantile_score(0.25),
+            percentile_75: self.quantile_score(0.75),
+            cases: {
+                let mut tests = self.tests.iter().collect::<Vec<_>>();
+                tests.sort_by_key(|test| test.index);
+                tests
+                    .into_iter()
+                    .map(|test| ScoredCase {
+                        index: Some(test.index),
+                        expected: test.case.expected.to_string(),
+                        actual: test.case.actual.to_string(),
+                        score: test.score,
+                    })
+                    .collect()
+            },
+        }
+    }
+
+    /// Serialize the result to JSON.
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        self.summary().to_json()
+    }
+
+    /// Serialize the test cases to CSV with the columns `expected`, `actual` and `score`.
+    pub fn to_csv(&self) -> String {
+        self.summary().to_csv()
+    }
+
+    /// Save the result as JSON. The file can be loaded as a baseline with [`EvaluationSummary::load_json`].
+    pub fn save_json(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        self.summary().save_json(path)
+    }
+
+    /// Save the test cases as CSV.
+    pub fn save_csv(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        self.summary().save_csv(path)
+    }
+
+    /// Compare the result against a baseline run.
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// use kalosm::{EvaluationSummary, ExactMatch, TestCases};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut test_cases = TestCases::new();
+    ///     test_cases.push_case("4".to_string(), "4".to_string());
+    ///     let result = test_cases.evaluate(&mut ExactMatch::new()).await;
+    ///
+    ///     let baseline = EvaluationSummary::load_json("baseline.json").unwrap();
+    ///     let diff = result.diff(&baseline);
+    ///     println!("{}", diff);
+    ///     assert!(!diff.is_regression(0.05), "The mean score dropped");
+    /// }
+    /// ```
+    pub fn diff(&self, baseline: &EvaluationSummary) -> EvaluationDiff {
+        self.summary().diff(baseline)
+    }
+}
+
+/// The change in score of a single test case between a baseline run and the current run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaseDiff {
+    /// The expected output of the test case.
+    pub expected: String,
+    /// The actual output in the current run, if the case exists in the current run.
+    pub actual: Option<String>,
+    /// The score in the current run, if the case exists in the current run.
+    pub score: Option<f64>,
+    /// The actual output in the baseline run, if the case exists in the baseline run.
+    pub baseline_actual: Option<String>,
+    /// The score in the baseline run, if the case exists in the baseline run.
+    pub baseline_score: Option<f64>,
+}
+
+impl CaseDiff {
+    /// The change in score from the baseline run. Returns `None` if the case only exists in one of the runs.
+    pub fn change(&self) -> Option<f64> {
+        Some(self.score? - self.baseline_score?)
+    }
+}
+
+/// The difference between an evaluation and a baseline evaluation. Created with [`EvaluationResult::diff`] or [`EvaluationSummary::diff`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct EvaluationDiff {
+    name: String,
+    mean: f64,
+    baseline_mean: f64,
+    median: f64,
+    baseline_median: f64,
+    cases: Vec<CaseDiff>,
+}
+
+impl EvaluationDiff {
+    /// The change in the mean score from the baseline.
+    pub fn mean_change(&self) -> f64 {
+        self.mean - self.baseline_mean
+    }
+
+    /// The change in the median score from the baseline.
+    pub fn median_change(&self) -> f64 {
+        self.median - self.baseline_median
+    }
+
+    /// Every test case in either run.
+    pub fn cases(&self) -> &[CaseDiff] {
+        &self.cases
+    }
+
+    /// The test cases whose score dropped by more than the tolerance.
+    pub fn regressions(&self, tolerance: f64) -> impl Iterator<Item = &CaseDiff> {
+        self.cases
+            .iter()
+            .filter(move |case| case.change().is_some_and(|change| change < -tolerance))
+    }
+
+    /// The test cases whose score increased by more than the tolerance.
+    pub fn improvements(&self, tolerance: f64) -> impl Iterator<Item = &CaseDiff> {
+        self.cases
+            .iter()
+            .filter(move |case| case.change().is_some_and(|change| change > tolerance))
+    }
+
+    /// Returns true if the mean score dropped by more than the tolerance. This is useful for failing CI when a model or prompt change makes results worse.
+    pub fn is_regression(&self, tolerance: f64) -> bool {
+        self.mean_change() < -tolerance
+    }
+}
+
+fn change_cell(change: f64) -> Cell {
+    let cell = Cell::new(format!("{:+.2}", change));
+    if change < 0.0 {
+        cell.fg(Color::Red)
+    } else if change > 0.0 {
+        cell.fg(Color::Green)
+    } else {
+        cell
+    }
+}
+
+fn optional_score(score: Option<f64>) -> String {
+    score
+        .map(|score| format!("{:.2}", score))
+        .unwrap_or_else(|| "-".to_string())
+}
+
+impl Display for EvaluationDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}", self.name)?;
+
+        let mut statistics = Table::new();
+        statistics.set_header(vec!["Statistic", "Baseline", "Current", "Change"]);
+        statistics.add_row(vec![
+            Cell::new("Mean"),
+            Cell::new(format!("{:.2}", self.baseline_mean)),
+            Cell::new(format!("{:.2}", self.mean)),
+            change_cell(self.mean_change()),
+        ]);
+        statistics.add_row(vec![
+            Cell::new("Median"),
+            Cell::new(format!("{:.2}", self.baseline_median)),
+            Cell::new(format!("{:.2}", self.median)),
+            change_cell(self.median_change()),
+        ]);
+        writeln!(f, "{}", statistics)?;
+
+        let mut changed = self
+            .cases
+            .iter()
+            .filter(|case| case.change() != Some(0.0))
+            .collect::<Vec<_>>();
+        changed.sort_by(|a, b| {
+            a.change()
+                .unwrap_or(0.0)
+                .total_cmp(&b.change().unwrap_or(0.0))
+        });
+
+        if !changed.is_empty() {
+            let mut table = Table::new();
+            table.set_header(vec![
+                "Expected Output",
+                "Baseline Output",
+                "Actual Output",
+                "Baseline",
+                "Current",
+                "Change",
+            ]);
+            for case in changed {
+                table.add_row(vec![
+                    Cell::new(&case.expected),
+                    Cell::new(case.baseline_actual.as_deref().unwrap_or("-")),
+                    Cell::new(case.actual.as_deref().unwrap_or("-")),
+                    Cell::new(optional_score(case.baseline_score)),
+                    Cell::new(optional_score(case.score)),
+                    match case.change() {
+                        Some(change) => change_cell(change),
+                        None => Cell::new("-"),
+                    },
+                ]);
+            }
+            writeln!(f, "{}", table)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[test]
+fn summary_round_trips_through_json_and_escapes_csv() {
+    let summary = EvaluationSummary {
+        name: "Arithmetic".to_string(),
+        mean: 0.5,
+        median: 0.5,
+        min: 0.0,
+        max: 1.0,
+        percentile_25: 0.0,
+        percentile_75: 1.0,
+        cases: vec![
+            ScoredCase {
+                index: Some(0),
+                expected: "4".to_string(),
+                actual: "4".to_string(),
+                score: 1.0,
+            },
+            ScoredCase {
+                index: Some(1),
+                expected: "a, \"b\"".to_string(),
+                actual: "line\nbreak".to_string(),
+                score: 0.0,
+            },
+        ],
+    };
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("baseline.json");
+    summary.save_json(&path).unwrap();
+    assert_eq!(EvaluationSummary::load_json(&path).unwrap(), summary);
+
+    assert_eq!(
+        summary.to_csv(),
+        "expected,actual,score\n4,4,1\n\"a, \"\"b\"\"\",\"line\nbreak\",0\n"
+    );
+    let csv_path = dir.path().join("cases.csv");
+    summary.save_csv(&csv_path).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&csv_path).unwrap(),
+        summary.to_csv()
+    );
+}
+
+#[test]
+fn diff_matches_cases_by_expected_output() {
+    fn summary(mean: f64, cases: &[(&str, &str, f64)]) -> EvaluationSummary {
+        EvaluationSummary {
+            name: "Cases".to_string(),
+            mean,
+            median: mean,
+            min: 0.0,
+            max: 1.0,
+            percentile_25: 0.0,
+            percentile_75: 1.0,
+            cases: cases
+                .iter()
+                .map(|(expected, actual, score)| ScoredCase {
+                    index: None,
+                    expected: expected.to_string(),
+                    actual: actual.to_string(),
+                    score: *score,
+                })
+                .collect(),
+        }
+    }
+
+    let baseline = summary(
+        0.6,
+        &[
+            ("a", "a", 1.0),
+            ("b", "x", 0.2),
+            ("b", "b", 0.8),
+            ("removed", "removed", 1.0),
+        ],
+    );
+    let current = summary(
+        0.5,
+        &[
+            ("a", "z", 0.4),
+            ("b", "b", 0.9),
+            ("b", "b", 0.8),
+            ("added", "added", 1.0),
+        ],
+    );
+    let diff = current.diff(&baseline);
+
+    assert!((diff.mean_change() + 0.1).abs() < 1e-9);
+    assert!((diff.median_change() + 0.1).abs() < 1e-9);
+    assert!(diff.is_regression(0.05));
+    assert!(!diff.is_regression(0.2));
+
+    let cases = diff.cases();
+    assert_eq!(cases.len(), 5);
+    // Duplicate expected outputs are matched in order
+    assert_eq!(cases[1].baseline_actual.as_deref(), Some("x"));
+    assert_eq!(cases[2].baseline_actual.as_deref(), Some("b"));
+    assert_eq!(cases[3].expected, "added");
+    assert_eq!(cases[3].baseline_score, None);
+    assert_eq!(cases[3].change(), None);
+    assert_eq!(cases[4].expected, "removed");
+    assert_eq!(cases[4].actual, None);
+    assert_eq!(cases[4].baseline_score, Some(1.0));
+
+    let regressions = diff.regressions(0.1).collect::<Vec<_>>();
+    assert_eq!(regressions.len(), 1);
+    assert_eq!(regressions[0].expected, "a");
+    let improvements = diff.improvements(0.1).collect::<Vec<_>>();
+    assert_eq!(improvements.len(), 1);
+    assert_eq!(improvements[0].actual.as_deref(), Some("b"));
+    assert!(diff.to_string().contains("Median"));
+
+    let unchanged = current.diff(&current);
+    assert_eq!(unchanged.mean_change(), 0.0);
+    assert!(unchanged
+        .cases()
+        .iter()
+        .all(|case| case.change() == Some(0.0)));
+}
+
+#[test]
+fn diff_matches_repeated_cases_by_their_position_in_the_run() {
+    let case = |index, actual: &str, score| ScoredCase {
+        index: Some(index),
+        expected: "yes".to_string(),
+        actual: actual.to_string(),
+        score,
+    };
+    let summary = |cases| EvaluationSummary {
+        name: "Repeated".to_string(),
+        mean: 0.5,
+        median: 0.5,
+        min: 0.0,
+        max: 1.0,
+        percentile_25: 0.0,
+        percentile_75: 1.0,
+        cases,
+    };
+    // The second case regressed and the first improved, so the cases must not be paired by score
+    let baseline = summary(vec![case(1, "yes", 1.0), case(0, "no", 0.0)]);
+    let current = summary(vec![case(0, "yes", 1.0), case(1, "no", 0.0)]);
+    let diff = current.diff(&baseline);
+
+    let regressions = diff.regressions(0.5).collect::<Vec<_>>();
+    assert_eq!(regressions.len(), 1);
+    assert_eq!(regressions[0].actual.as_deref(), Some("no"));
+    assert_eq!(regressions[0].baseline_actual.as_deref(), Some("yes"));
+    assert_eq!(diff.improvements(0.5).count(), 1);
+}
@@ -0,0 +1,475 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use kalosm_language::kalosm_language_model::{Model, SyncModel};
+use kalosm_language::prelude::{
+    IntegerParser, LiteralParser, ParserExt, SequenceParser, StructuredRunner, Task, TaskResponse,
+};
+use serde_json::Value;
+
+use super::Metric;
+
+/// Split text into lowercase words, ignoring punctuation.
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect()
+}
+
+fn f1(overlap: usize, expected_len: usize, actual_len: usize) -> f64 {
+    if expected_len == 0 && actual_len == 0 {
+        return 1.0;
+    }
+    if overlap == 0 {
+        return 0.0;
+    }
+    let precision = overlap as f64 / actual_len as f64;
+    let recall = overlap as f64 / expected_len as f64;
+    2.0 * precision * recall / (precision + recall)
+}
+
+/// A metric that scores 1 if the two strings are the same and 0 otherwise. Surrounding whitespace is ignored.
+#[derive(Debug, Clone, Default)]
+pub struct ExactMatch {
+    case_insensitive: bool,
+}
+
+impl ExactMatch {
+    /// Create a new exact match metric.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ignore the case of the strings when comparing them.
+    pub fn case_insensitive(mut self) -> Self {
+        self.case_insensitive = true;
+        self
+    }
+}
+
+#[async_trait]
+impl<S: AsRef<str> + Send + Sync> Metric<S> for ExactMatch {
+    async fn distance(&mut self, first: &S, other: &S) -> f64 {
+        let first = first.as_ref().trim();
+        let other = other.as_ref().trim();
+        let matches = if self.case_insensitive {
+            first.to_lowercase() == other.to_lowercase()
+        } else {
+            first == other
+        };
+        if matches {
+            1.0
+        } else {
+            0.0
+        }
+    }
+}
+
+fn token_f1(expected: &str, actual: &str) -> f64 {
+    let expected = words(expected);
+    let actual = words(actual);
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for word in &expected {
+        *counts.entry(word.as_str()).or_default() += 1;
+    }
+    let mut overlap = 0;
+    for word in &actual {
+        if let Some(count) = counts.get_mut(word.as_str()) {
+            if *count > 0 {
+                *count -= 1;
+                overlap += 1;
+            }
+        }
+    }
+    f1(overlap, expected.len(), actual.len())
+}
+
+/// A metric that computes the F1 score of the words in the actual output compared to the words in the expected output. Words are compared without case or punctuation.
+#[derive(Debug, Clone, Default)]
+pub struct TokenF1;
+
+#[async_trait]
+impl<S: AsRef<str> + Send + Sync> Metric<S> for TokenF1 {
+    async fn distance(&mut self, first: &S, other: &S) -> f64 {
+        token_f1(first.as_ref(), other.as_ref())
+    }
+}
+
+fn ngram_counts(words: &[String], n: usize) -> HashMap<&[String], usize> {
+    let mut counts = HashMap::new();
+    for ngram in words.windows(n) {
+        *counts.entry(ngram).or_default() += 1;
+    }
+    counts
+}
+
+fn bleu(expected: &str, actual: &str, max_n: usize) -> f64 {
+    let expected = words(expected);
+    let actual = words(actual);
+    if actual.is_empty() || expected.is_empty() {
+        return if actual.is_empty() && expected.is_empty() {
+            1.0
+        } else {
+            0.0
+        };
+    }
+
+    let mut log_precision = 0.0;
+    for n in 1..=max_n {
+        let expected_counts = ngram_counts(&expected, n);
+        let actual_counts = ngram_counts(&actual, n);
+        let total: usize = actual_counts.values().sum();
+        let clipped: usize = actual_counts
+            .iter()
+            .map(|(ngram, count)| (*count).min(*expected_counts.get(ngram).unwrap_or(&0)))
+            .sum();
+        // Add one smoothing for the higher order n-grams so short outputs do not always score 0
+        let smoothing = if n == 1 { 0.0 } else { 1.0 };
+        let precision = (clipped as f64 + smoothing) / (total as f64 + smoothing);
+        if precision == 0.0 {
+            return 0.0;
+        }
+        log_precision += precision.ln();
+    }
+
+    let brevity_penalty = if actual.len() > expected.len() {
+        1.0
+    } else {
+        (1.0 - expected.len() as f64 / actual.len() as f64).exp()
+    };
+
+    brevity_penalty * (log_precision / max_n as f64).exp()
+}
+
+/// A metric that computes the smoothed sentence level BLEU score of the actual output with the expected output as the reference.
+#[derive(Debug, Clone)]
+pub struct Bleu {
+    max_n: usize,
+}
+
+impl Default for Bleu {
+    fn default() -> Self {
+        Self { max_n: 4 }
+    }
+}
+
+impl Bleu {
+    /// Create a new BLEU metric that uses n-grams up to length 4.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the length of the longest n-gram to compare. (default: 4)
+    pub fn with_max_n(mut self, max_n: usize) -> Self {
+        self.max_n = max_n.max(1);
+        self
+    }
+}
+
+#[async_trait]
+impl<S: AsRef<str> + Send + Sync> Metric<S> for Bleu {
+    async fn distance(&mut self, first: &S, other: &S) -> f64 {
+        bleu(first.as_ref(), other.as_ref(), self.max_n)
+    }
+}
+
+fn longest_common_subsequence(first: &[String], second: &[String]) -> usize {
+    let mut previous = vec![0; second.len() + 1];
+    let mut current = vec![0; second.len() + 1];
+    for first_word in first {
+        for (j, second_word) in second.iter().enumerate() {
+            current[j + 1] = if first_word == second_word {
+                previous[j] + 1
+            } else {
+                previous[j + 1].max(current[j])
+            };
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[second.len()]
+}
+
+fn rouge_l(expected: &str, actual: &str) -> f64 {
+    let expected = words(expected);
+    let actual = words(actual);
+    let lcs = longest_common_subsequence(&expected, &actual);
+    f1(lcs, expected.len(), actual.len())
+}
+
+/// A metric that computes the ROUGE-L F1 score, based on the longest common subsequence of words in the expected and actual output.
+#[derive(Debug, Clone, Default)]
+pub struct RougeL;
+
+#[async_trait]
+impl<S: AsRef<str> + Send + Sync> Metric<S> for RougeL {
+    async fn distance(&mut self, first: &S, other: &S) -> f64 {
+        rouge_l(first.as_ref(), other.as_ref())
+    }
+}
+
+fn json_leaves<'a>(
+    value: &'a Value,
+    path: Vec<String>,
+    leaves: &mut Vec<(Vec<String>, &'a Value)>,
+) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            for (key, value) in map {
+                let mut path = path.clone();
+                path.push(key.clone());
+                json_leaves(value, path, leaves);
+            }
+        }
+        Value::Array(items) if !items.is_empty() => {
+            for (index, value) in items.iter().enumerate() {
+                let mut path = path.clone();
+                path.push(index.to_string());
+                json_leaves(value, path, leaves);
+            }
+        }
+        _ => leaves.push((path, value)),
+    }
+}
+
+/// Split a field path like `author.name` or `tags[0]` into the object keys and array indexes it selects.
+fn parse_json_path(path: &str) -> Vec<String> {
+    let mut segments = Vec::new();
+    for segment in path.split('.').filter(|segment| !segment.is_empty()) {
+        let (key, indexes) = match segment.find('[') {
+            Some(start) => segment.split_at(start),
+            None => (segment, ""),
+        };
+        if !key.is_empty() {
+            segments.push(key.to_string());
+        }
+        for index in indexes.split('[').filter(|index| !index.is_empty()) {
+            segments.push(index.trim_end_matches(']').to_string());
+        }
+    }
+    segments
+}
+
+/// Look up a value by its path. Each segment of the path is a key in an object or an index in an array.
+fn json_lookup<'a>(value: &'a Value, path: &[String]) -> Option<&'a Value> {
+    let mut value = value;
+    for segment in path {
+        value = match value {
+            Value::Array(items) => items.get(segment.parse::<usize>().ok()?)?,
+            _ => value.get(segment)?,
+        };
+    }
+    Some(value)
+}
+
+fn json_values_match(first: &Value, second: &Value) -> bool {
+    match (first, second) {
+        (Value::Number(first), Value::Number(second)) => first.as_f64() == second.as_f64(),
+        (Value::String(first), Value::String(second)) => first.trim() == second.trim(),
+        _ => first == second,
+    }
+}
+
+fn json_field_accuracy(expected: &str, actual: &str, fields: Option<&[Vec<String>]>) -> f64 {
+    let expected: Value = match serde_json::from_str(expected) {
+        Ok(expected) => expected,
+        Err(err) => {
+            tracing::error!("Expected output is not valid JSON: {}", err);
+            return 0.0;
+        }
+    };
+    let Ok(actual) = serde_json::from_str::<Value>(actual) else {
+        return 0.0;
+    };
+
+    let expected_fields = match fields {
+        Some(fields) => fields
+            .iter()
+            .filter_map(|field| Some((field.clone(), json_lookup(&expected, field)?)))
+            .collect(),
+        None => {
+            let mut leaves = Vec::new();
+            json_leaves(&expected, Vec::new(), &mut leaves);
+            leaves
+        }
+    };
+    if expected_fields.is_empty() {
+        return if json_values_match(&expected, &actual) {
+            1.0
+        } else {
+            0.0
+        };
+    }
+
+    let matching = expected_fields
+        .iter()
+        .filter(|(path, expected)| {
+            json_lookup(&actual, path).is_some_and(|actual| json_values_match(expected, actual))
+        })
+        .count();
+    matching as f64 / expected_fields.len() as f64
+}
+
+/// A metric for structured outputs that parses the expected and actual output as JSON and scores the fraction of fields in the expected output that have the same value in the actual output.
+///
+/// Nested fields are compared individually. If the actual output is not valid JSON, the score is 0.
+#[derive(Debug, Clone, Default)]
+pub struct JsonFieldAccuracy {
+    fields: Option<Vec<Vec<String>>>,
+}
+
+impl JsonFieldAccuracy {
+    /// Create a new metric that compares every field in the expected output.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only compare the given fields. Nested fields can be selected with `.` and array items with `[index]`, for example `author.name` or `tags[0]`. Use [`JsonFieldAccuracy::with_field_path`] for keys that contain `.` or `[`.
+    pub fn with_fields(mut self, fields: impl IntoIterator<Item = impl AsRef<str>>) -> Self {
+        self.fields = Some(
+            fields
+                .into_iter()
+                .map(|field| parse_json_path(field.as_ref()))
+                .collect(),
+        );
+        self
+    }
+
+    /// Also compare the field at the given path. Each segment of the path is a key in an object or an index in an array, for example `&["author", "first.name"]` or `&["tags", "0"]`. Keys are matched exactly, so they can contain `.` or `[`.
+    pub fn with_field_path(mut self, path: &[&str]) -> Self {
+        self.fields
+            .get_or_insert_with(Vec::new)
+            .push(path.iter().map(|segment| segment.to_string()).collect());
+        self
+    }
+}
+
+#[async_trait]
+impl<S: AsRef<str> + Send + Sync> Metric<S> for JsonFieldAccuracy {
+    async fn distance(&mut self, first: &S, other: &S) -> f64 {
+        json_field_accuracy(first.as_ref(), other.as_ref(), self.fields.as_deref())
+    }
+}
+
+const JUDGE_DESCRIPTION: &str = "You grade answers. You are given the expected answer and an actual answer. Rate how well the actual answer matches the meaning of the expected answer from 1 (completely wrong) to 10 (equivalent).";
+
+type JudgeConstraints = SequenceParser<LiteralParser<&'static str>, IntegerParser>;
+
+fn judge_task(description: String) -> Task<StructuredRunner<JudgeConstraints>> {
+    Task::builder(description)
+        .with_constraints(LiteralParser::new("Score: ").then(IntegerParser::new(1..=10)))
+        .build()
+}
+
+/// A metric that asks a local model to grade the actual output against the expected output on a scale from 1 to 10. The score is normalized to a value between 0 and 1.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+/// use kalosm::{LlmJudge, TestCases};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let mut judge = LlmJudge::new(Llama::new_chat())
+///         .with_criteria("Rate how factually consistent the actual answer is with the expected answer from 1 to 10.");
+///     let mut test_cases = TestCases::new();
+///     test_cases.push_case("Paris".to_string(), "The capital of France is Paris.".to_string());
+///     println!("{}", test_cases.evaluate(&mut judge).await);
+/// }
+/// ```
+pub struct LlmJudge<M: Model> {
+    model: M,
+    task: Task<StructuredRunner<JudgeConstraints>>,
+}
+
+impl<M: Model> LlmJudge<M>
+where
+    <M::SyncModel as SyncModel>::Session: Send + Sync,
+{
+    /// Create a new judge that uses the given model.
+    pub fn new(model: M) -> Self {
+        Self {
+            model,
+            task: judge_task(JUDGE_DESCRIPTION.to_string()),
+        }
+    }
+
+    /// Set the instructions the model uses to grade the outputs. The model will always respond with a score from 1 to 10.
+    pub fn with_criteria(mut self, criteria: impl Into<String>) -> Self {
+        self.task = judge_task(criteria.into());
+        self
+    }
+}
+
+#[async_trait]
+impl<M: Model, S: AsRef<str> + Send + Sync> Metric<S> for LlmJudge<M>
+where
+    <M::SyncModel as SyncModel>::Session: Send + Sync,
+{
+    async fn distance(&mut self, first: &S, other: &S) -> f64 {
+        let prompt = format!(
+            "Expected answer: {}\nActual answer: {}",
+            first.as_ref().trim(),
+            other.as_ref().trim()
+        );
+        let output = self.task.run(prompt, &self.model);
+        match <StructuredRunner<JudgeConstraints> as TaskResponse>::response(output).await {
+            Ok((_, score)) => (score as f64 - 1.0) / 9.0,
+            Err(err) => {
+                tracing::error!("Failed to grade answer: {}", err);
+                0.0
+            }
+        }
+    }
+}
+
+#[test]
+fn test_text_metrics() {
+    assert_eq!(token_f1("The cat sat.", "the cat sat"), 1.0);
+    assert_eq!(token_f1("the cat", "a dog"), 0.0);
+    assert!((token_f1("the cat sat", "the cat") - 0.8).abs() < 1e-9);
+
+    assert!((bleu("the cat sat on the mat", "the cat sat on the mat", 4) - 1.0).abs() < 1e-9);
+    assert_eq!(bleu("the cat sat on the mat", "dogs bark", 4), 0.0);
+    let partial = bleu("the cat sat on the mat", "the cat sat on a mat", 4);
+    assert!(partial > 0.0 && partial < 1.0);
+
+    assert_eq!(rouge_l("a b c d", "a b c d"), 1.0);
+    assert!((rouge_l("a b c d", "a c d") - 2.0 * 0.75 / 1.75).abs() < 1e-9);
+
+    let expected = r#"{"name": "Ada", "age": 36, "tags": ["math", "code"]}"#;
+    assert_eq!(json_field_accuracy(expected, expected, None), 1.0);
+    assert_eq!(
+        json_field_accuracy(
+            expected,
+            r#"{"name": "Ada", "age": 36.0, "tags": ["math"]}"#,
+            None
+        ),
+        0.75
+    );
+    assert_eq!(json_field_accuracy(expected, "not json", None), 0.0);
+    assert_eq!(
+        json_field_accuracy(
+            expected,
+            r#"{"name": "Bob", "tags": ["math"]}"#,
+            Some(&[parse_json_path("tags[0]")])
+        ),
+        1.0
+    );
+
+    // Keys that contain dots are compared as a single key
+    let expected = r#"{"version.major": 1, "build": {"commit.id": "abc"}}"#;
+    assert_eq!(
+        json_field_accuracy(expected, r#"{"version.major": 1, "build": {}}"#, None),
+        0.5
+    );
+    let fields = JsonFieldAccuracy::new().with_field_path(&["build", "commit.id"]);
+    assert_eq!(
+        json_field_accuracy(
+            expected,
+            r#"{"build": {"commit.id": "abc"}}"#,
+            fields.fields.as_deref()
+        ),
+        1.0
+    );
+}
@@ -9,6 +9,12 @@ use std::ops::RangeInclusive;
 use async_trait::async_trait;
 use kalosm_language::prelude::Bert;
 use kalosm_language::prelude::Embedder;
+use kalosm_language::prelude::SelfConsistentResult;
+
+mod export;
+pub use export::*;
+mod metrics;
+pub use metrics::*;
 
 /// A metric is a way to compare two pieces of data. It is used to evaluate the performance of a model.
 #[async_trait]
@@ -50,6 +56,69 @@ impl<S: AsRef<str> + Send + Sync> Metric<S> for BertDistance {
     }
 }
 
+/// An extension trait for choosing the answer of a [`SelfConsistentResult`] with a [`Metric`] instead of an exact majority vote.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+/// use kalosm::{BertDistance, SelfConsistencyMetricExt};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let llm = Llama::new_chat();
+///     let task = Task::builder("You summarize the given text in one sentence.")
+///         .with_constraints(OneLine)
+///         .with_self_consistency(5)
+///         .build();
+///
+///     let result = task
+///         .run_self_consistent("The quick brown fox jumps over the lazy dog.", &llm)
+///         .await
+///         .unwrap()
+///         .choose_with_metric(&mut BertDistance::default())
+///         .await;
+///     println!("{} (agreement: {:.2})", result.text(), result.agreement());
+/// }
+/// ```
+#[async_trait]
+pub trait SelfConsistencyMetricExt: Sized {
+    /// Choose the sample that agrees the most with the other samples according to the metric. The agreement score is the mean metric score between the chosen sample and the other samples, normalized to a value between 0 and 1.
+    async fn choose_with_metric<M: Metric<String> + Send>(self, metric: &mut M) -> Self;
+}
+
+#[async_trait]
+impl<O: Send> SelfConsistencyMetricExt for SelfConsistentResult<O> {
+    async fn choose_with_metric<M: Metric<String> + Send>(self, metric: &mut M) -> Self {
+        let texts = self
+            .samples()
+            .iter()
+            .map(|sample| sample.text.clone())
+            .collect::<Vec<_>>();
+        if texts.len() < 2 {
+            return self;
+        }
+
+        let mut best = (0, f64::MIN);
+        for (i, first) in texts.iter().enumerate() {
+            let mut total = 0.0;
+            for (j, other) in texts.iter().enumerate() {
+                if i != j {
+                    total += metric.distance(first, other).await;
+                }
+            }
+            let mean = total / (texts.len() - 1) as f64;
+            if mean > best.1 {
+                best = (i, mean);
+            }
+        }
+
+        let min = M::RANGE.start();
+        let max = M::RANGE.end();
+        let agreement = ((best.1 - min) / (max - min)).clamp(0.0, 1.0);
+        self.choose(best.0, agreement)
+    }
+}
+
 /// A set of test cases to evaluate a model.
 pub struct TestCases<I> {
     name: String,
@@ -93,10 +162,11 @@ impl<I> TestCases<I> {
     /// Evaluate a model using this set of test cases.
     pub async fn evaluate<M: Metric<I>>(&mut self, metric: &mut M) -> EvaluationResult<'_, I> {
         let mut values = Vec::new();
-        for case in &self.tests {
+        for (index, case) in self.tests.iter().enumerate() {
             let TestCase { expected, actual } = case;
             let distance = metric.distance(expected, actual).await;
             values.push(TestCaseScored {
+                index,
                 case,
                 score: distance,
             });
@@ -359,6 +429,8 @@ struct TestCase<I> {
 
 #[derive(Clone)]
 struct TestCaseScored<'a, I> {
+    /// The position of the test case in the order the cases were added.
+    index: usize,
     case: &'a TestCase<I>,
     score: f64,
 }
@@ -13,10 +13,13 @@ pub mod language {
     pub use kalosm_language::kalosm_language_model::{Model as _, ModelExt as _, *};
     pub use kalosm_language::kalosm_llama::{Llama, LlamaBuilder, LlamaSession, LlamaSource};
     pub use kalosm_language::kalosm_sample::*;
-    pub use kalosm_language::rbert::{Bert, BertBuilder, BertSource, BertSpace};
+    pub use kalosm_language::rbert::{
+        Bert, BertBuilder, BertCrossEncoder, BertCrossEncoderBuilder, BertSource, BertSpace,
+    };
     pub use kalosm_language::rphi::{Phi, PhiBuilder, PhiSource};
     pub use kalosm_language::search::*;
     pub use kalosm_language::task::*;
+    pub use kalosm_language::template::*;
     pub use kalosm_language::tool::*;
     pub use kalosm_language::vector_db::*;
     pub use kalosm_streams::text_stream::*;
@@ -29,7 +32,10 @@ pub use kalosm_vision as vision;
 mod evaluate;
 pub use evaluate::*;
 
+mod prompt_optimizer;
+pub use prompt_optimizer::*;
 mod prompt_annealing;
+#[allow(deprecated)]
 pub use prompt_annealing::*;
 
 #[cfg(feature = "surrealdb")]
@@ -0,0 +1,975 @@
+use async_trait::async_trait;
+use kalosm_language::prelude::*;
+use kalosm_language::{
+    kalosm_language_model::{Embedder, Embedding, Model, SyncModel},
+    rbert::{Bert, BertSpace},
+    task::TaskBuilderReturn,
+};
+use rand::{random, seq::index::sample};
+
+use crate::{BertDistance, Metric, TestCases};
+
+/// An example from the train set of a [`PromptOptimizer`] along with the embedding of its input.
+#[derive(Debug, Clone)]
+pub struct TrainingExample {
+    input: String,
+    output: String,
+    embedding: Embedding<BertSpace>,
+}
+
+impl TrainingExample {
+    /// The input of the example.
+    pub fn input(&self) -> &str {
+        &self.input
+    }
+
+    /// The expected output of the example.
+    pub fn output(&self) -> &str {
+        &self.output
+    }
+
+    /// The embedding of the input of the example.
+    pub fn embedding(&self) -> &Embedding<BertSpace> {
+        &self.embedding
+    }
+}
+
+/// A single configuration evaluated by a [`PromptOptimizer`].
+#[derive(Debug, Clone)]
+pub struct OptimizationStep {
+    /// The number of configurations evaluated before this one.
+    pub iteration: usize,
+    /// The name of the strategy that proposed the configuration.
+    pub strategy: &'static str,
+    /// The score of the configuration. Higher is better.
+    pub score: f64,
+    /// The configuration that was evaluated.
+    pub config: TaskConfig,
+}
+
+/// The result of running a [`PromptOptimizer`].
+#[derive(Debug, Clone)]
+pub struct OptimizationResult {
+    history: Vec<OptimizationStep>,
+    best: usize,
+}
+
+impl OptimizationResult {
+    /// The configuration with the highest score.
+    pub fn best(&self) -> &OptimizationStep {
+        &self.history[self.best]
+    }
+
+    /// Every configuration that was evaluated, in the order they were evaluated.
+    pub fn history(&self) -> &[OptimizationStep] {
+        &self.history
+    }
+
+    /// Save the best configuration to a JSON file. The file can be loaded with [`TaskConfig::load`] and applied to a task with [`TaskBuilder::with_config`].
+    pub fn save_best(&self, path: impl AsRef<std::path::Path>) -> anyhow::Result<()> {
+        self.best().config.save(path)
+    }
+}
+
+/// The interface a [`OptimizationStrategy`] uses to score configurations and query the model that is being optimized.
+#[async_trait]
+pub trait PromptEvaluator: Send {
+    /// Score a configuration on the test set. Higher is better. Configurations that were already evaluated are not run again.
+    async fn evaluate(&mut self, config: &TaskConfig) -> anyhow::Result<f64>;
+
+    /// Run the model being optimized with a task description and input.
+    async fn generate(&mut self, description: &str, input: &str) -> anyhow::Result<String>;
+
+    /// The examples that strategies can choose from.
+    fn train_set(&self) -> &[TrainingExample];
+
+    /// Every configuration that has been evaluated so far.
+    fn history(&self) -> &[OptimizationStep];
+
+    /// The configuration with the highest score so far.
+    fn best(&self) -> &OptimizationStep;
+}
+
+/// A strategy for searching for a better prompt. Strategies propose configurations and score them with a [`PromptEvaluator`]. The best configuration is tracked by the evaluator.
+#[async_trait]
+pub trait OptimizationStrategy: Send {
+    /// The name of the strategy. This is recorded in each [`OptimizationStep`].
+    fn name(&self) -> &'static str;
+
+    /// Search for better configurations, starting from the best configuration of the evaluator.
+    async fn optimize(&mut self, evaluator: &mut dyn PromptEvaluator) -> anyhow::Result<()>;
+}
+
+fn choose_examples(
+    base: &TaskConfig,
+    train_set: &[TrainingExample],
+    chosen: &[usize],
+) -> TaskConfig {
+    TaskConfig {
+        description: base.description.clone(),
+        examples: chosen
+            .iter()
+            .map(|&index| {
+                let example = &train_set[index];
+                (example.input.clone(), example.output.clone())
+            })
+            .collect(),
+    }
+}
+
+fn random_subset(len: usize, amount: usize) -> Vec<usize> {
+    sample(&mut rand::thread_rng(), len, amount.min(len)).into_vec()
+}
+
+/// Chooses the combination and order of examples from the train set with simulated annealing.
+#[derive(Debug, Clone)]
+pub struct Annealing {
+    initial_temperature: f64,
+    decay_rate: f64,
+    cutoff_temperature: f64,
+    population: usize,
+    initial_choice_range: std::ops::Range<usize>,
+}
+
+impl Default for Annealing {
+    fn default() -> Self {
+        Self {
+            initial_temperature: 0.6,
+            decay_rate: 0.9,
+            cutoff_temperature: 0.10,
+            population: 10,
+            initial_choice_range: 1..3,
+        }
+    }
+}
+
+impl Annealing {
+    /// Set the initial temperature for the annealing process a higher temperature will allow for more exploration, but it will also take longer to converge to a solution.
+    pub fn with_initial_temperature(mut self, temperature: f64) -> Self {
+        self.initial_temperature = temperature;
+        self
+    }
+
+    /// Set the initial population size. A larger population will allow for more exploration, but it will also take longer to run.
+    pub fn with_initial_population(mut self, population: usize) -> Self {
+        self.population = population;
+        self
+    }
+
+    /// Set the initial range of examples to choose from.
+    pub fn with_initial_choice_range(mut self, range: std::ops::Range<usize>) -> Self {
+        self.initial_choice_range = range;
+        self
+    }
+
+    /// Set the decay rate for the temperature. A higher decay rate will cause the temperature to decrease faster which will allow for faster convergence, but it will also increase the risk of getting stuck in a local optimum.
+    pub fn with_decay_rate(mut self, rate: f64) -> Self {
+        self.decay_rate = rate;
+        self
+    }
+
+    /// Set the cutoff temperature. Once the temperature reaches this value, the annealing process will stop.
+    pub fn with_cutoff_temperature(mut self, temperature: f64) -> Self {
+        self.cutoff_temperature = temperature;
+        self
+    }
+
+    pub(crate) fn population(&self) -> usize {
+        self.population
+    }
+}
+
+struct AnnealingInstance {
+    chosen: Vec<usize>,
+    score: f64,
+}
+
+#[async_trait]
+impl OptimizationStrategy for Annealing {
+    fn name(&self) -> &'static str {
+        "annealing"
+    }
+
+    async fn optimize(&mut self, evaluator: &mut dyn PromptEvaluator) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            self.decay_rate > 0.0 && self.decay_rate < 1.0,
+            "The decay rate must be between 0 and 1"
+        );
+        let train_set = evaluator.train_set().to_vec();
+        anyhow::ensure!(!train_set.is_empty(), "Train set is empty");
+        anyhow::ensure!(
+            self.initial_choice_range.start < self.initial_choice_range.end
+                && self.initial_choice_range.end <= train_set.len(),
+            "Initial choice range may select more examples than the train set contains"
+        );
+        let base = evaluator.best().config.clone();
+
+        let mut population = Vec::with_capacity(self.population);
+        for _ in 0..self.population {
+            let amount = self.initial_choice_range.start
+                + random::<usize>() % self.initial_choice_range.len();
+            let chosen = random_subset(train_set.len(), amount);
+            let score = evaluator
+                .evaluate(&choose_examples(&base, &train_set, &chosen))
+                .await?;
+            population.push(AnnealingInstance { chosen, score });
+        }
+
+        let mut temperature = self.initial_temperature;
+        while temperature >= self.cutoff_temperature {
+            for instance in &mut population {
+                let unused: Vec<usize> = (0..train_set.len())
+                    .filter(|index| !instance.chosen.contains(index))
+                    .collect();
+                let action = if instance.chosen.is_empty() {
+                    2
+                } else if unused.is_empty() {
+                    random::<usize>() % 2
+                } else {
+                    random::<usize>() % 3
+                };
+
+                let mut mutated = instance.chosen.clone();
+                match action {
+                    // remove example
+                    0 => {
+                        mutated.remove(random::<usize>() % mutated.len());
+                    }
+                    // swap examples
+                    1 => {
+                        let index1 = random::<usize>() % mutated.len();
+                        let index2 = random::<usize>() % mutated.len();
+                        mutated.swap(index1, index2);
+                    }
+                    // add example
+                    _ => {
+                        mutated.push(unused[random::<usize>() % unused.len()]);
+                    }
+                }
+
+                let accept_regardless = random::<f64>() < temperature;
+                let score = evaluator
+                    .evaluate(&choose_examples(&base, &train_set, &mutated))
+                    .await?;
+                if accept_regardless || score > instance.score {
+                    instance.chosen = mutated;
+                    instance.score = score;
+                }
+            }
+
+            temperature *= self.decay_rate;
+            tracing::trace!("current temperature: {}", temperature);
+        }
+
+        Ok(())
+    }
+}
+
+/// Builds the list of examples one at a time, adding the example from the train set that improves the score the most until no example improves it.
+#[derive(Debug, Clone)]
+pub struct GreedyForwardSelection {
+    max_examples: usize,
+}
+
+impl Default for GreedyForwardSelection {
+    fn default() -> Self {
+        Self { max_examples: 4 }
+    }
+}
+
+impl GreedyForwardSelection {
+    /// Set the maximum number of examples to select. (default: 4)
+    pub fn with_max_examples(mut self, max_examples: usize) -> Self {
+        self.max_examples = max_examples;
+        self
+    }
+}
+
+#[async_trait]
+impl OptimizationStrategy for GreedyForwardSelection {
+    fn name(&self) -> &'static str {
+        "greedy forward selection"
+    }
+
+    async fn optimize(&mut self, evaluator: &mut dyn PromptEvaluator) -> anyhow::Result<()> {
+        let train_set = evaluator.train_set().to_vec();
+        let base = evaluator.best().config.clone();
+
+        let mut chosen = Vec::new();
+        let mut best_score = evaluator
+            .evaluate(&choose_examples(&base, &train_set, &chosen))
+            .await?;
+
+        while chosen.len() < self.max_examples.min(train_set.len()) {
+            let mut best_candidate = None;
+            for index in 0..train_set.len() {
+                if chosen.contains(&index) {
+                    continue;
+                }
+                let mut candidate = chosen.clone();
+                candidate.push(index);
+                let score = evaluator
+                    .evaluate(&choose_examples(&base, &train_set, &candidate))
+                    .await?;
+                if best_candidate.map_or(true, |(_, best)| score > best) {
+                    best_candidate = Some((index, score));
+                }
+            }
+
+            match best_candidate {
+                Some((index, score)) if score > best_score => {
+                    chosen.push(index);
+                    best_score = score;
+                }
+                _ => break,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+const REWRITE_DESCRIPTION: &str = "You improve the instructions given to a language model. You are shown instructions that were already tried along with their score, where a higher score is better. Respond with only a new instruction that is likely to score higher.";
+
+/// Asks the model to propose rewrites of the task description based on the descriptions that have been tried so far and their scores.
+#[derive(Debug, Clone)]
+pub struct InstructionRewrite {
+    iterations: usize,
+    candidates: usize,
+    shown_attempts: usize,
+}
+
+impl Default for InstructionRewrite {
+    fn default() -> Self {
+        Self {
+            iterations: 3,
+            candidates: 3,
+            shown_attempts: 5,
+        }
+    }
+}
+
+impl InstructionRewrite {
+    /// Set the number of rounds of rewrites. (default: 3)
+    pub fn with_iterations(mut self, iterations: usize) -> Self {
+        self.iterations = iterations;
+        self
+    }
+
+    /// Set the number of rewrites proposed in each round. (default: 3)
+    pub fn with_candidates(mut self, candidates: usize) -> Self {
+        self.candidates = candidates;
+        self
+    }
+
+    /// Set the number of the best previous descriptions shown to the model when it proposes a rewrite. (default: 5)
+    pub fn with_shown_attempts(mut self, shown_attempts: usize) -> Self {
+        self.shown_attempts = shown_attempts;
+        self
+    }
+
+    fn rewrite_prompt(&self, history: &[OptimizationStep]) -> String {
+        let mut attempts: Vec<&OptimizationStep> = Vec::new();
+        for step in history {
+            match attempts
+                .iter_mut()
+                .find(|attempt| attempt.config.description == step.config.description)
+            {
+                Some(attempt) if attempt.score < step.score => *attempt = step,
+                Some(_) => {}
+                None => attempts.push(step),
+            }
+        }
+        attempts.sort_by(|a, b| a.score.total_cmp(&b.score));
+        let skip = attempts.len().saturating_sub(self.shown_attempts);
+
+        let mut prompt = String::new();
+        for attempt in &attempts[skip..] {
+            prompt += &format!(
+                "Instruction: {}\nScore: {:.3}\n\n",
+                attempt.config.description.trim(),
+                attempt.score
+            );
+        }
+        prompt += "Write a new instruction that scores higher.";
+        prompt
+    }
+}
+
+#[async_trait]
+impl OptimizationStrategy for InstructionRewrite {
+    fn name(&self) -> &'static str {
+        "instruction rewrite"
+    }
+
+    async fn optimize(&mut self, evaluator: &mut dyn PromptEvaluator) -> anyhow::Result<()> {
+        for _ in 0..self.iterations {
+            let best = evaluator.best().config.clone();
+            let prompt = self.rewrite_prompt(evaluator.history());
+            for _ in 0..self.candidates {
+                let rewritten = evaluator.generate(REWRITE_DESCRIPTION, &prompt).await?;
+                let rewritten = rewritten.trim();
+                let rewritten = rewritten.strip_prefix("Instruction:").unwrap_or(rewritten);
+                if rewritten.trim().is_empty() {
+                    continue;
+                }
+                let config = TaskConfig {
+                    description: rewritten.trim().to_string(),
+                    examples: best.examples.clone(),
+                };
+                evaluator.evaluate(&config).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Chooses sets of examples that are spread out in embedding space. Each candidate set starts from a random example and repeatedly adds the example that is furthest from every example already chosen.
+#[derive(Debug, Clone)]
+pub struct EmbeddingDiversity {
+    examples: usize,
+    candidates: usize,
+}
+
+impl Default for EmbeddingDiversity {
+    fn default() -> Self {
+        Self {
+            examples: 3,
+            candidates: 5,
+        }
+    }
+}
+
+impl EmbeddingDiversity {
+    /// Set the number of examples in each candidate set. (default: 3)
+    pub fn with_examples(mut self, examples: usize) -> Self {
+        self.examples = examples;
+        self
+    }
+
+    /// Set the number of candidate sets to evaluate. (default: 5)
+    pub fn with_candidates(mut self, candidates: usize) -> Self {
+        self.candidates = candidates;
+        self
+    }
+}
+
+fn furthest_point_sample(train_set: &[TrainingExample], start: usize, amount: usize) -> Vec<usize> {
+    let mut chosen = vec![start];
+    while chosen.len() < amount.min(train_set.len()) {
+        let distance_to_chosen = |index: usize| {
+            chosen
+                .iter()
+                .map(|&other| {
+                    1.0 - train_set[index]
+                        .embedding
+                        .cosine_similarity(&train_set[other].embedding)
+                })
+                .fold(f32::INFINITY, f32::min)
+        };
+        let next = (0..train_set.len())
+            .filter(|index| !chosen.contains(index))
+            .max_by(|a, b| distance_to_chosen(*a).total_cmp(&distance_to_chosen(*b)));
+        match next {
+            Some(next) => chosen.push(next),
+            None => break,
+        }
+    }
+    chosen
+}
+
+#[async_trait]
+impl OptimizationStrategy for EmbeddingDiversity {
+    fn name(&self) -> &'static str {
+        "embedding diversity"
+    }
+
+    async fn optimize(&mut self, evaluator: &mut dyn PromptEvaluator) -> anyhow::Result<()> {
+        let train_set = evaluator.train_set().to_vec();
+        anyhow::ensure!(!train_set.is_empty(), "Train set is empty");
+        let base = evaluator.best().config.clone();
+
+        for _ in 0..self.candidates {
+            let start = random::<usize>() % train_set.len();
+            let chosen = furthest_point_sample(&train_set, start, self.examples);
+            evaluator
+                .evaluate(&choose_examples(&base, &train_set, &chosen))
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A builder for [`PromptOptimizer`].
+pub struct PromptOptimizerBuilder<'a, M: Model, P, Met: Metric<String> = BertDistance>
+where
+    <<M as Model>::SyncModel as SyncModel>::Session: Sync + Send,
+{
+    llm: &'a M,
+    task: TaskBuilder<P>,
+    metric: Met,
+    train: Vec<(String, String)>,
+    test: Vec<(String, String)>,
+    strategies: Vec<Box<dyn OptimizationStrategy>>,
+    token_penalty: f64,
+    embedder: Option<Bert>,
+    on_iteration: Option<Box<dyn FnMut(&OptimizationStep) + Send + 'a>>,
+}
+
+impl<'a, M: Model, P> PromptOptimizer<'a, M, P>
+where
+    <<M as Model>::SyncModel as SyncModel>::Session: Sync + Send,
+    P: Clone + TaskBuilderReturn + Send + Sync + 'static,
+{
+    /// Create a new builder for [`PromptOptimizer`] that optimizes a task with a set of (input, expected output) pairs.
+    pub fn builder(
+        model: &'a M,
+        train_set: impl IntoIterator<Item = (impl Into<String>, impl Into<String>)>,
+        task: TaskBuilder<P>,
+    ) -> PromptOptimizerBuilder<'a, M, P, BertDistance> {
+        PromptOptimizerBuilder {
+            llm: model,
+            task,
+            metric: BertDistance::default(),
+            train: train_set
+                .into_iter()
+                .map(|(input, output)| (input.into(), output.into()))
+                .collect(),
+            test: Vec::new(),
+            strategies: Vec::new(),
+            token_penalty: 0.0001,
+            embedder: None,
+            on_iteration: None,
+        }
+    }
+}
+
+impl<'a, M: Model, P, Met: Metric<String> + Send> PromptOptimizerBuilder<'a, M, P, Met>
+where
+    <<M as Model>::SyncModel as SyncModel>::Session: Sync + Send,
+    P: Clone + TaskBuilderReturn + Send + Sync + 'static,
+{
+    /// Set the test set to use for evaluation. If no test set is provided, a subset of the train set will be used.
+    pub fn with_test_set(
+        mut self,
+        test_set: impl IntoIterator<Item = (impl Into<String>, impl Into<String>)>,
+    ) -> Self {
+        self.test = test_set
+            .into_iter()
+            .map(|(input, output)| (input.into(), output.into()))
+            .collect();
+        self
+    }
+
+    /// Set the metric used to compare the output of the task with the expected output. (default: [`BertDistance`])
+    pub fn with_metric<Met2: Metric<String> + Send>(
+        self,
+        metric: Met2,
+    ) -> PromptOptimizerBuilder<'a, M, P, Met2> {
+        PromptOptimizerBuilder {
+            llm: self.llm,
+            task: self.task,
+            metric,
+            train: self.train,
+            test: self.test,
+            strategies: self.strategies,
+            token_penalty: self.token_penalty,
+            embedder: self.embedder,
+            on_iteration: self.on_iteration,
+        }
+    }
+
+    /// Add a strategy to the optimizer. Strategies run in the order they are added, and each strategy starts from the best configuration found so far. If no strategy is added, [`Annealing`] is used.
+    pub fn with_strategy(mut self, strategy: impl OptimizationStrategy + 'static) -> Self {
+        self.strategies.push(Box::new(strategy));
+        self
+    }
+
+    /// Set the penalty subtracted from the score for every token in the examples. This encourages shorter prompts. (default: 0.0001)
+    pub fn with_token_penalty(mut self, token_penalty: f64) -> Self {
+        self.token_penalty = token_penalty;
+        self
+    }
+
+    /// Set the model used to embed the train set.
+    pub fn with_embedder(mut self, embedder: Bert) -> Self {
+        self.embedder = Some(embedder);
+        self
+    }
+
+    /// Set a callback that is called with every configuration that is evaluated.
+    pub fn on_iteration(mut self, on_iteration: impl FnMut(&OptimizationStep) + Send + 'a) -> Self {
+        self.on_iteration = Some(Box::new(on_iteration));
+        self
+    }
+
+    /// Build the [`PromptOptimizer`].
+    pub async fn build(self) -> anyhow::Result<PromptOptimizer<'a, M, P, Met>> {
+        let (train_set, test_set) = if self.test.is_empty() {
+            tracing::warn!("No test set provided, using a subset of the train set for evaluation");
+
+            let mut train = self.train;
+            let split = (train.len() / 3).max(1);
+
+            anyhow::ensure!(
+                split < train.len(),
+                "Train set is too small to split into train and test sets. Provide more examples."
+            );
+
+            let train_set = train.split_off(split);
+            (train_set, train)
+        } else {
+            (self.train, self.test)
+        };
+
+        anyhow::ensure!(!train_set.is_empty(), "Train set is empty");
+        anyhow::ensure!(!test_set.is_empty(), "Test set is empty");
+
+        let bert = self.embedder.unwrap_or_default();
+
+        // Calculate embeddings for all examples
+        let inputs: Vec<&str> = train_set.iter().map(|(input, _)| input.as_str()).collect();
+        let embeddings = bert.embed_batch(&inputs).await?;
+        let train_set = train_set
+            .into_iter()
+            .zip(embeddings)
+            .map(|((input, output), embedding)| TrainingExample {
+                input,
+                output,
+                embedding,
+            })
+            .collect();
+
+        let mut strategies = self.strategies;
+        if strategies.is_empty() {
+            strategies.push(Box::<Annealing>::default());
+        }
+
+        Ok(PromptOptimizer {
+            evaluator: Evaluator {
+                llm: self.llm,
+                task: self.task,
+                metric: self.metric,
+                train: train_set,
+                test: test_set,
+                token_penalty: self.token_penalty,
+                strategy: "initial",
+                history: Vec::new(),
+                best: 0,
+                on_iteration: self.on_iteration,
+            },
+            strategies,
+        })
+    }
+}
+
+struct Evaluator<'a, M: Model, P, Met> {
+    llm: &'a M,
+    task: TaskBuilder<P>,
+    metric: Met,
+    train: Vec<TrainingExample>,
+    test: Vec<(String, String)>,
+    token_penalty: f64,
+    strategy: &'static str,
+    history: Vec<OptimizationStep>,
+    best: usize,
+    on_iteration: Option<Box<dyn FnMut(&OptimizationStep) + Send + 'a>>,
+}
+
+#[async_trait]
+impl<'a, M: Model, P, Met: Metric<String> + Send> PromptEvaluator for Evaluator<'a, M, P, Met>
+where
+    <<M as Model>::SyncModel as SyncModel>::Session: Sync + Send,
+    P: Clone + TaskBuilderReturn + Send + Sync + 'static,
+{
+    async fn evaluate(&mut self, config: &TaskConfig) -> anyhow::Result<f64> {
+        if let Some(step) = self.history.iter().find(|step| &step.config == config) {
+            return Ok(step.score);
+        }
+
+        let outputs = {
+            let task = self.task.clone().with_config(config.clone()).build();
+            self.test
+                .iter()
+                .map(|(input, _)| task.run(input.clone(), self.llm))
+                .collect::<Vec<_>>()
+        };
+
+        let mut test_cases = TestCases::new();
+        for ((_, expected), output) in self.test.iter().zip(outputs) {
+            test_cases.push_case(expected.clone(), output.all_text().await);
+        }
+        let evaluation = test_cases.evaluate(&mut self.metric).await.normalized();
+
+        let tokenizer = self.llm.tokenizer();
+        let example_tokens: usize = config
+            .examples
+            .iter()
+            .filter_map(|(input, output)| {
+                Some(
+                    tokenizer.encode(input, true).ok()?.len()
+                        + tokenizer.encode(output, true).ok()?.len(),
+                )
+            })
+            .sum();
+
+        let score = evaluation.mean_score() - example_tokens as f64 * self.token_penalty;
+        tracing::trace!("{}", evaluation);
+
+        let step = OptimizationStep {
+            iteration: self.history.len(),
+            strategy: self.strategy,
+            score,
+            config: config.clone(),
+        };
+        tracing::info!(
+            "({}) iteration {} scored {}",
+            step.strategy,
+            step.iteration,
+            step.score
+        );
+        if let Some(on_iteration) = &mut self.on_iteration {
+            on_iteration(&step);
+        }
+        if self
+            .history
+            .get(self.best)
+            .map_or(true, |best| score > best.score)
+        {
+            self.best = self.history.len();
+        }
+        self.history.push(step);
+
+        Ok(score)
+    }
+
+    async fn generate(&mut self, description: &str, input: &str) -> anyhow::Result<String> {
+        let output = Task::new(description).run(input, self.llm);
+        Ok(output.all_text().await)
+    }
+
+    fn train_set(&self) -> &[TrainingExample] {
+        &self.train
+    }
+
+    fn history(&self) -> &[OptimizationStep] {
+        &self.history
+    }
+
+    fn best(&self) -> &OptimizationStep {
+        &self.history[self.best]
+    }
+}
+
+/// A prompt optimizer searches for the description and examples that make a task perform best on a test set.
+///
+/// The optimizer runs a list of [`OptimizationStrategy`]s. The built in strategies are:
+/// - [`Annealing`]: Chooses the combination and order of examples with simulated annealing
+/// - [`GreedyForwardSelection`]: Adds the example that improves the score the most until no example helps
+/// - [`InstructionRewrite`]: Asks the model to rewrite the task description
+/// - [`EmbeddingDiversity`]: Chooses examples that are spread out in embedding space
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+/// use kalosm::*;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let llm = Phi::v2();
+///     let examples = [
+///         ("I love this blender.", "positive"),
+///         ("The handle snapped off.", "negative"),
+///         ("Works exactly as described.", "positive"),
+///         ("It stopped charging after a week.", "negative"),
+///     ];
+///     let task = Task::builder("Classify the sentiment of the review.");
+///     let mut optimizer = PromptOptimizer::builder(&llm, examples, task)
+///         .with_strategy(GreedyForwardSelection::default())
+///         .with_strategy(InstructionRewrite::default())
+///         .build()
+///         .await
+///         .unwrap();
+///
+///     let result = optimizer.run().await.unwrap();
+///     println!("best score: {}", result.best().score);
+///     result.save_best("sentiment-task.json").unwrap();
+/// }
+/// ```
+pub struct PromptOptimizer<'a, M: Model, P, Met: Metric<String> = BertDistance>
+where
+    <<M as Model>::SyncModel as SyncModel>::Session: Sync + Send,
+{
+    evaluator: Evaluator<'a, M, P, Met>,
+    strategies: Vec<Box<dyn OptimizationStrategy>>,
+}
+
+impl<'a, M: Model, P, Met> PromptOptimizer<'a, M, P, Met>
+where
+    <<M as Model>::SyncModel as SyncModel>::Session: Sync + Send,
+    P: Clone + TaskBuilderReturn + Send + Sync + 'static,
+    Met: Metric<String> + Send,
+{
+    /// Evaluate the initial configuration of the task and run every strategy.
+    pub async fn run(&mut self) -> anyhow::Result<OptimizationResult> {
+        if self.evaluator.history.is_empty() {
+            self.evaluator.strategy = "initial";
+            let initial = self.evaluator.task.config();
+            self.evaluator.evaluate(&initial).await?;
+        }
+
+        for strategy in &mut self.strategies {
+            self.evaluator.strategy = strategy.name();
+            strategy.optimize(&mut self.evaluator).await?;
+        }
+
+        Ok(OptimizationResult {
+            history: self.evaluator.history.clone(),
+            best: self.evaluator.best,
+        })
+    }
+}
+
+#[tokio::test]
+async fn strategies_search_the_train_set() {
+    struct StubEvaluator {
+        train: Vec<TrainingExample>,
+        history: Vec<OptimizationStep>,
+        best: usize,
+    }
+
+    #[async_trait]
+    impl PromptEvaluator for StubEvaluator {
+        // Examples with the output "good" improve the score and examples with the output "bad" hurt it
+        async fn evaluate(&mut self, config: &TaskConfig) -> anyhow::Result<f64> {
+            let examples: f64 = config
+                .examples
+                .iter()
+                .map(|(_, output)| if output == "good" { 0.9 } else { -1.1 })
+                .sum();
+            let description = if config.description.contains("carefully") {
+                0.5
+            } else {
+                0.0
+            };
+            let score = examples + description;
+            if self
+                .history
+                .get(self.best)
+                .map_or(true, |best| score > best.score)
+            {
+                self.best = self.history.len();
+            }
+            self.history.push(OptimizationStep {
+                iteration: self.history.len(),
+                strategy: "test",
+                score,
+                config: config.clone(),
+            });
+            Ok(score)
+        }
+
+        async fn generate(&mut self, _: &str, _: &str) -> anyhow::Result<String> {
+            Ok("Instruction: Answer carefully".to_string())
+        }
+
+        fn train_set(&self) -> &[TrainingExample] {
+            &self.train
+        }
+
+        fn history(&self) -> &[OptimizationStep] {
+            &self.history
+        }
+
+        fn best(&self) -> &OptimizationStep {
+            &self.history[self.best]
+        }
+    }
+
+    async fn evaluator() -> StubEvaluator {
+        let train = [
+            ("a", "good", vec![1.0, 0.0]),
+            ("b", "bad", vec![0.0, 1.0]),
+            ("c", "good", vec![0.9, 0.1]),
+            ("d", "bad", vec![-1.0, 0.0]),
+        ]
+        .into_iter()
+        .map(|(input, output, embedding)| TrainingExample {
+            input: input.to_string(),
+            output: output.to_string(),
+            embedding: embedding.into(),
+        })
+        .collect();
+        let mut evaluator = StubEvaluator {
+            train,
+            history: Vec::new(),
+            best: 0,
+        };
+        evaluator
+            .evaluate(&TaskConfig::new("Answer"))
+            .await
+            .unwrap();
+        evaluator
+    }
+
+    // Greedy selection adds examples until the score stops improving
+    let mut stub = evaluator().await;
+    GreedyForwardSelection::default()
+        .optimize(&mut stub)
+        .await
+        .unwrap();
+    let examples = vec![
+        ("a".to_string(), "good".to_string()),
+        ("c".to_string(), "good".to_string()),
+    ];
+    assert_eq!(stub.best().config.examples, examples);
+
+    // Instruction rewrites keep the best examples
+    InstructionRewrite::default()
+        .with_iterations(1)
+        .with_candidates(1)
+        .optimize(&mut stub)
+        .await
+        .unwrap();
+    assert_eq!(stub.best().config.description, "Answer carefully");
+    assert_eq!(stub.best().config.examples, examples);
+    assert_eq!(
+        InstructionRewrite::default()
+            .with_shown_attempts(1)
+            .rewrite_prompt(stub.history()),
+        "Instruction: Answer carefully\nScore: 2.300\n\nWrite a new instruction that scores higher."
+    );
+
+    // Annealing evaluates every member of the population at each temperature and never repeats an example
+    let mut stub = evaluator().await;
+    Annealing::default()
+        .with_initial_population(3)
+        .with_decay_rate(0.5)
+        .optimize(&mut stub)
+        .await
+        .unwrap();
+    // The temperature starts at 0.6 and is above the cutoff for 0.6, 0.3 and 0.15
+    assert_eq!(stub.history().len(), 1 + 3 + 3 * 3);
+    for step in stub.history() {
+        let mut inputs = step
+            .config
+            .examples
+            .iter()
+            .map(|(input, _)| input.as_str())
+            .collect::<Vec<_>>();
+        inputs.sort();
+        inputs.dedup();
+        assert_eq!(inputs.len(), step.config.examples.len());
+    }
+    assert!(Annealing::default()
+        .with_decay_rate(1.0)
+        .optimize(&mut stub)
+        .await
+        .is_err());
+
+    // Embedding diversity picks the examples furthest from the examples already chosen
+    assert_eq!(furthest_point_sample(stub.train_set(), 0, 2), vec![0, 3]);
+    assert_eq!(furthest_point_sample(stub.train_set(), 1, 3), vec![1, 3, 0]);
+}
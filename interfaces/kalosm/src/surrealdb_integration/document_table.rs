@@ -1,8 +1,12 @@
-use super::{EmbeddingIndexedTable, EmbeddingIndexedTableSearchResult};
+use super::reembed::{metadata_for_chunk, open_generation, ReembeddedRecord};
+use super::{EmbeddingIndexedTable, EmbeddingIndexedTableSearchResult, RepairReport};
 use kalosm_language::prelude::*;
 use serde::de::DeserializeOwned;
-use serde::Serialize;
-use surrealdb::sql::Id;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use surrealdb::sql::{Id, Thing};
 use surrealdb::Connection;
 use surrealdb::Surreal;
 
@@ -18,11 +22,57 @@ impl HasDocument for Document {
     }
 }
 
+/// The records that were created from a file in a watched folder.
+#[derive(Serialize, Deserialize)]
+struct SourceLink {
+    record_ids: Vec<Id>,
+    hash: String,
+    /// The length of the file. Links created before the length was recorded don't have one.
+    #[serde(default)]
+    len: Option<u64>,
+    /// When the file was last modified.
+    #[serde(default)]
+    modified: Option<SystemTime>,
+}
+
+impl SourceLink {
+    /// Get the state of the file the records were created from.
+    fn file(&self) -> Option<WatchedFile> {
+        Some(WatchedFile {
+            hash: u64::from_str_radix(&self.hash, 16).ok()?,
+            len: self.len.unwrap_or_default(),
+            modified: self.modified,
+        })
+    }
+}
+
+/// A record in the sources table.
+#[derive(Deserialize)]
+struct SourceRecord {
+    id: Thing,
+    #[serde(flatten)]
+    link: SourceLink,
+}
+
+/// Get the path a file is stored under in the sources table. The folder of the file is canonicalized, so relative and absolute paths to the same file have the same key, even after the file is removed.
+fn source_path(path: &Path) -> PathBuf {
+    let parent = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    match (parent.canonicalize(), path.file_name()) {
+        (Ok(parent), Some(file_name)) => parent.join(file_name),
+        _ => path.to_path_buf(),
+    }
+}
+
 /// A table in a surreal database that is indexed by embeddings from a vector database.
 pub struct DocumentTable<C: Connection, R, M: Embedder, K: Chunker> {
     embedding_model: M,
     chunker: K,
     table: EmbeddingIndexedTable<C, R, M::VectorSpace>,
+    reranker: Option<Arc<dyn Reranker + Send + Sync>>,
+    rerank_candidates: usize,
 }
 
 impl<C: Connection, R, M: Embedder, K: Chunker> DocumentTable<C, R, M, K> {
@@ -36,9 +86,23 @@ impl<C: Connection, R, M: Embedder, K: Chunker> DocumentTable<C, R, M, K> {
             embedding_model,
             table,
             chunker,
+            reranker: None,
+            rerank_candidates: 20,
         }
     }
 
+    /// Rerank the results of [`DocumentTable::select_nearest`] with a [`Reranker`]. The nearest candidates are selected from the vector database first, then the reranker picks the top results from the candidates.
+    pub fn with_reranker(mut self, reranker: impl Reranker + Send + Sync + 'static) -> Self {
+        self.reranker = Some(Arc::new(reranker));
+        self
+    }
+
+    /// Set the number of candidates selected from the vector database before reranking. If fewer candidates than the number of requested results are set, the number of requested results is used. Defaults to 20.
+    pub fn with_rerank_candidates(mut self, rerank_candidates: usize) -> Self {
+        self.rerank_candidates = rerank_candidates;
+        self
+    }
+
     /// Get the raw table.
     pub fn table(&self) -> &EmbeddingIndexedTable<C, R, M::VectorSpace> {
         &self.table
@@ -54,21 +118,39 @@ impl<C: Connection, R, M: Embedder, K: Chunker> DocumentTable<C, R, M, K> {
         &mut self.embedding_model
     }
 
-    /// Insert a new record into the table with the given embedding.
+    /// Insert a new record into the table. The document of the record is split into chunks with the chunker of the table, and the byte range of each chunk is stored with its embeddings.
     pub async fn insert(&self, value: R) -> anyhow::Result<Id>
     where
         R: HasDocument + Serialize + DeserializeOwned,
     {
-        let embeddings = self
+        self.insert_with_metadata(value, EmbeddingMetadata::default())
+            .await
+    }
+
+    /// Insert a new record into the table. Every chunk of the document is tagged with the metadata, which can be used to filter results with [`DocumentTable::select_nearest_with_filter`].
+    pub async fn insert_with_metadata(
+        &self,
+        value: R,
+        metadata: EmbeddingMetadata,
+    ) -> anyhow::Result<Id>
+    where
+        R: HasDocument + Serialize + DeserializeOwned,
+    {
+        let chunks = self
             .chunker
             .chunk(value.document(), &self.embedding_model)
             .await?;
         self.table
-            .insert(
-                embeddings
-                    .into_iter()
-                    .flat_map(|embedding| embedding.embeddings),
+            .insert_chunks_with_metadata(
+                chunks.into_iter().flat_map(|chunk| {
+                    let byte_range = chunk.byte_range;
+                    chunk
+                        .embeddings
+                        .into_iter()
+                        .map(move |embedding| (embedding, Some(byte_range.clone())))
+                }),
                 value,
+                metadata,
             )
             .await
     }
@@ -97,6 +179,224 @@ impl<C: Connection, R, M: Embedder, K: Chunker> DocumentTable<C, R, M, K> {
         self.table.delete(id).await
     }
 
+    /// Remove orphaned embeddings and dangling links left behind by an insert or delete that was interrupted. See [`EmbeddingIndexedTable::repair`].
+    pub async fn repair(&self) -> anyhow::Result<RepairReport> {
+        self.table.repair().await
+    }
+
+    /// Re-embed every record in the table with a new embedding model, for example to move a table to a better model without inserting the documents again.
+    ///
+    /// The documents are read from the table in pages, chunked with the chunker of the table and embedded into a new vector database next to the current one. The links to the new embeddings of each page are written to a new links table in one transaction. Searches keep using the old embeddings and links until every record is re-embedded. Then the new vector database replaces the old one, and the table switches to the new links with it. The metadata of each old embedding is copied to the new embeddings of the chunks that overlap it.
+    ///
+    /// The id and embedding dimensions of the new model are recorded in the vector database, so opening the table with the old model afterwards fails instead of returning meaningless results. If the process stops before every record is re-embedded, the table still uses the old model; re-embed it again. If it stops while the new vector database is moved into place, the move is finished the next time the table is opened.
+    pub async fn reembed<N: Embedder>(
+        self,
+        embedding_model: N,
+    ) -> anyhow::Result<DocumentTable<C, R, N, K>>
+    where
+        R: HasDocument + Serialize + DeserializeOwned,
+    {
+        const PAGE_SIZE: usize = 64;
+
+        let staged = self
+            .table
+            .stage_vector_db(vector_db_builder(&embedding_model, &self.chunker))
+            .await?;
+        let mut start = 0;
+        loop {
+            let page = self.table.select_page(start, PAGE_SIZE).await?;
+            if page.is_empty() {
+                break;
+            }
+            start += page.len();
+            let mut records = Vec::new();
+            for record in page {
+                let sources = self
+                    .table
+                    .embedding_sources(&record.object.embedding_ids)
+                    .await?;
+                let chunks = self
+                    .chunker
+                    .chunk(record.object.object.document(), &embedding_model)
+                    .await?;
+                let mut transaction = staged.vector_db.transaction()?;
+                let mut embeddings = Vec::new();
+                for chunk in chunks {
+                    for embedding in chunk.embeddings {
+                        let metadata =
+                            metadata_for_chunk(&sources, embeddings.len(), &chunk.byte_range);
+                        let id = transaction.add_embedding_with_metadata(embedding, metadata)?;
+                        embeddings.push((id, Some(chunk.byte_range.clone())));
+                    }
+                }
+                transaction.commit_without_build()?;
+                records.push(ReembeddedRecord {
+                    id: record.id.id,
+                    embeddings,
+                });
+            }
+            self.table.stage_links(&staged, records).await?;
+        }
+
+        let table = self.table.swap_vector_db(staged).await?;
+        Ok(DocumentTable {
+            embedding_model,
+            chunker: self.chunker,
+            table,
+            reranker: self.reranker,
+            rerank_candidates: self.rerank_candidates,
+        })
+    }
+
+    /// Get the name of the table that links each watched file to the records created from it.
+    fn table_sources(&self) -> String {
+        format!("{}-sources", self.table.table())
+    }
+
+    fn source_thing(&self, path: &Path) -> Thing {
+        Thing {
+            tb: self.table_sources(),
+            id: Id::String(path.to_string_lossy().to_string()),
+        }
+    }
+
+    /// Apply a change from a watched [`DocumentFolder`] to the table. Every document from a file is inserted with the document metadata and the path of the file in the `source` metadata field. When a file is modified, the records and embeddings for the new documents are inserted before the old ones are deleted, so a failed insert keeps the old records. When a file is removed, the records and embeddings from the file are deleted.
+    ///
+    /// Files with the same hash as the last time they were applied are skipped, so restarting a watch does not embed unchanged files again.
+    pub async fn apply_change(&self, change: DocumentChange) -> anyhow::Result<()>
+    where
+        R: From<Document> + HasDocument + Serialize + DeserializeOwned,
+    {
+        match change {
+            DocumentChange::Added {
+                path,
+                documents,
+                file,
+            }
+            | DocumentChange::Modified {
+                path,
+                documents,
+                file,
+            } => {
+                let path = source_path(&path);
+                let thing = self.source_thing(&path);
+                let hash = format!("{:x}", file.hash);
+                let existing = self
+                    .table
+                    .db()
+                    .select::<Option<SourceLink>>(thing.clone())
+                    .await?;
+                let mut link = SourceLink {
+                    record_ids: Vec::new(),
+                    hash,
+                    len: Some(file.len),
+                    modified: file.modified,
+                };
+                if let Some(existing) = &existing {
+                    if existing.hash == link.hash {
+                        if existing.len == link.len && existing.modified == link.modified {
+                            return Ok(());
+                        }
+                        // Only the modification time changed. Record it so the next watch doesn't hash the file again
+                        link.record_ids = existing.record_ids.clone();
+                        self.table
+                            .db()
+                            .update::<Option<SourceLink>>(thing)
+                            .content(link)
+                            .await?;
+                        return Ok(());
+                    }
+                }
+
+                for document in documents {
+                    let mut metadata = document.metadata().clone();
+                    metadata.insert("source", path.to_string_lossy().to_string());
+                    match self.insert_with_metadata(R::from(document), metadata).await {
+                        Ok(id) => link.record_ids.push(id),
+                        Err(err) => {
+                            self.delete_records(link.record_ids).await?;
+                            return Err(err);
+                        }
+                    }
+                }
+                let new_ids = link.record_ids.clone();
+                if let Err(err) = self
+                    .table
+                    .db()
+                    .update::<Option<SourceLink>>(thing)
+                    .content(link)
+                    .await
+                {
+                    self.delete_records(new_ids).await?;
+                    return Err(err.into());
+                }
+                if let Some(existing) = existing {
+                    self.delete_records(existing.record_ids).await?;
+                }
+            }
+            DocumentChange::Removed { path } => {
+                let link = self
+                    .table
+                    .db()
+                    .delete::<Option<SourceLink>>(self.source_thing(&source_path(&path)))
+                    .await?;
+                if let Some(link) = link {
+                    self.delete_records(link.record_ids).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn delete_records(&self, ids: Vec<Id>) -> anyhow::Result<()>
+    where
+        R: Serialize + DeserializeOwned,
+    {
+        for id in ids {
+            self.delete(id).await?;
+        }
+        Ok(())
+    }
+
+    /// Get the state of every file in a folder that records were created from with [`DocumentTable::apply_change`].
+    async fn sources_in(&self, folder: &Path) -> anyhow::Result<Vec<(PathBuf, WatchedFile)>> {
+        let folder = folder.canonicalize()?;
+        let records = self
+            .table
+            .db()
+            .select::<Vec<SourceRecord>>(self.table_sources())
+            .await?;
+        Ok(records
+            .into_iter()
+            .filter_map(|record| {
+                let Id::String(path) = record.id.id else {
+                    return None;
+                };
+                let path = PathBuf::from(path);
+                let file = record.link.file()?;
+                path.starts_with(&folder).then_some((path, file))
+            })
+            .collect())
+    }
+
+    /// Watch a folder and keep the table in sync with the documents in the folder with [`DocumentTable::apply_change`]. Files that fail to load are logged and skipped. This runs until an error occurs while updating the table.
+    ///
+    /// The watch resumes from the files in the table (see [`DocumentFolder::watch_from`]). Files that were removed while the folder was not synced are deleted from the table, and files with the same length and modification time are not read again.
+    pub async fn sync_folder(&self, folder: &DocumentFolder) -> anyhow::Result<()>
+    where
+        R: From<Document> + HasDocument + Serialize + DeserializeOwned,
+    {
+        let known = self.sources_in(folder.path()).await?;
+        let mut changes = std::pin::pin!(folder.watch_from(known, Duration::from_secs(2)));
+        while let Some(change) = changes.next().await {
+            match change {
+                Ok(change) => self.apply_change(change).await?,
+                Err(err) => tracing::warn!("Failed to load a watched document: {}", err),
+            }
+        }
+        Ok(())
+    }
+
     /// Select all records from the table.
     pub async fn select_all(&self) -> anyhow::Result<Vec<R>>
     where
@@ -105,20 +405,119 @@ impl<C: Connection, R, M: Embedder, K: Chunker> DocumentTable<C, R, M, K> {
         self.table.select_all().await
     }
 
-    /// Select the top k records nearest records to the given record.
+    /// Select the top k records nearest records to the given record. If a reranker is set with [`DocumentTable::with_reranker`], the results are reranked.
     pub async fn select_nearest(
         &self,
         record: impl IntoDocument,
         k: usize,
     ) -> anyhow::Result<Vec<EmbeddingIndexedTableSearchResult<R>>>
     where
-        R: DeserializeOwned,
+        R: HasDocument + DeserializeOwned,
+    {
+        let query = record.into_document().await?;
+        let embedding = self.embedding_model.embed(query.body()).await?;
+        let candidates = self
+            .table
+            .select_nearest(embedding, self.candidate_count(k))
+            .await?;
+        self.rerank(query.body(), candidates, k).await
+    }
+
+    /// Select the top k records nearest to the queries the transformation creates from the given record. The results of each query are combined with [`fuse_rankings`]. If a reranker is set with [`DocumentTable::with_reranker`], the results are reranked with the original query.
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// use kalosm::language::*;
+    /// use kalosm::*;
+    /// use surrealdb::{engine::local::RocksDb, Surreal};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let db = Surreal::new::<RocksDb>("./db/temp.db").await?;
+    ///     db.use_ns("test").use_db("test").await?;
+    ///     let table = db.document_table_builder("documents").build::<Document>()?;
+    ///
+    ///     let history = vec![
+    ///         ChatHistoryItem::new(MessageType::UserMessage, "Who wrote Dune?"),
+    ///         ChatHistoryItem::new(MessageType::ModelAnswer, "Frank Herbert."),
+    ///     ];
+    ///     let condenser = QueryCondenser::new(Llama::new_chat()).with_history(history);
+    ///     let results = table
+    ///         .select_nearest_with_transform("What else did he write?", &condenser, 5)
+    ///         .await?;
+    ///     for result in results {
+    ///         println!("{}", result.record.title());
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn select_nearest_with_transform(
+        &self,
+        record: impl IntoDocument,
+        transform: &impl QueryTransform,
+        k: usize,
+    ) -> anyhow::Result<Vec<EmbeddingIndexedTableSearchResult<R>>>
+    where
+        R: HasDocument + DeserializeOwned,
     {
-        let embedding = self
+        let query = record.into_document().await?;
+        let candidate_count = self.candidate_count(k);
+        let queries = transform.transform(query.body()).await?;
+        let embeddings = self
             .embedding_model
-            .embed(record.into_document().await?.body())
+            .embed_batch(&queries.iter().map(String::as_str).collect::<Vec<_>>())
             .await?;
-        self.select_nearest_embedding(embedding, k).await
+        let mut rankings = Vec::new();
+        for embedding in embeddings {
+            rankings.push(
+                self.table
+                    .select_nearest(embedding, candidate_count)
+                    .await?,
+            );
+        }
+        let candidates = fuse_rankings(rankings, |result| result.id, 60.)
+            .into_iter()
+            .take(candidate_count)
+            .map(|(result, _)| result)
+            .collect();
+        self.rerank(query.body(), candidates, k).await
+    }
+
+    fn candidate_count(&self, k: usize) -> usize {
+        match self.reranker {
+            Some(_) => self.rerank_candidates.max(k),
+            None => k,
+        }
+    }
+
+    async fn rerank(
+        &self,
+        query: &str,
+        candidates: Vec<EmbeddingIndexedTableSearchResult<R>>,
+        k: usize,
+    ) -> anyhow::Result<Vec<EmbeddingIndexedTableSearchResult<R>>>
+    where
+        R: HasDocument,
+    {
+        let Some(reranker) = &self.reranker else {
+            return Ok(candidates);
+        };
+        let documents = candidates
+            .iter()
+            .map(|candidate| candidate.record.document().body())
+            .collect::<Vec<_>>();
+        let ranking = reranker.rerank(query, &documents).await?;
+
+        let mut candidates = candidates.into_iter().map(Some).collect::<Vec<_>>();
+        Ok(ranking
+            .into_iter()
+            .filter_map(|result| {
+                let mut candidate = candidates.get_mut(result.index)?.take()?;
+                candidate.rerank_score = Some(result.score);
+                Some(candidate)
+            })
+            .take(k)
+            .collect())
     }
 
     /// Select the top k records nearest records to the given embedding.
@@ -132,6 +531,40 @@ impl<C: Connection, R, M: Embedder, K: Chunker> DocumentTable<C, R, M, K> {
     {
         self.table.select_nearest(embedding, k).await
     }
+
+    /// Select the top k records nearest records to the given record with metadata that matches the filter. If a reranker is set with [`DocumentTable::with_reranker`], the results are reranked.
+    pub async fn select_nearest_with_filter(
+        &self,
+        record: impl IntoDocument,
+        k: usize,
+        filter: &Filter,
+    ) -> anyhow::Result<Vec<EmbeddingIndexedTableSearchResult<R>>>
+    where
+        R: HasDocument + DeserializeOwned,
+    {
+        let query = record.into_document().await?;
+        let embedding = self.embedding_model.embed(query.body()).await?;
+        let candidates = self
+            .table
+            .select_nearest_with_filter(embedding, self.candidate_count(k), filter)
+            .await?;
+        self.rerank(query.body(), candidates, k).await
+    }
+}
+
+/// Create a builder for a vector database that records the embedding model and chunker of the embeddings, so opening the vector database with a different model or chunker fails instead of returning meaningless results.
+fn vector_db_builder<E: Embedder>(
+    embedding_model: &E,
+    chunker: &impl Chunker,
+) -> VectorDBBuilder<E::VectorSpace> {
+    let mut builder = VectorDB::builder().with_chunker(chunker.config());
+    if let Some(model_id) = embedding_model.model_id() {
+        builder = builder.with_vector_space(model_id);
+    }
+    if let Some(dimensions) = embedding_model.dimensions() {
+        builder = builder.with_dimensions(dimensions);
+    }
+    builder
 }
 
 /// A builder for creating a new document table.
@@ -141,6 +574,7 @@ pub struct DocumentTableBuilder<C: Connection, E: Embedder, K: Chunker> {
     embedding_model: E,
     chunker: K,
     location: Option<std::path::PathBuf>,
+    metric: Option<DistanceMetric>,
 }
 
 impl<C: Connection> DocumentTableBuilder<C, Bert, ChunkStrategy> {
@@ -150,6 +584,7 @@ impl<C: Connection> DocumentTableBuilder<C, Bert, ChunkStrategy> {
             table: table.to_string(),
             db,
             location: None,
+            metric: None,
             chunker: ChunkStrategy::Sentence {
                 sentence_count: 1,
                 overlap: 0,
@@ -166,6 +601,12 @@ impl<C: Connection, E: Embedder, K: Chunker> DocumentTableBuilder<C, E, K> {
         self
     }
 
+    /// Set the distance metric of the vector database.
+    pub fn with_distance_metric(mut self, metric: DistanceMetric) -> Self {
+        self.metric = Some(metric);
+        self
+    }
+
     /// Set the embedding model for the table.
     pub fn with_embedding_model(mut self, embedding_model: E) -> Self {
         self.embedding_model = embedding_model;
@@ -182,18 +623,273 @@ impl<C: Connection, E: Embedder, K: Chunker> DocumentTableBuilder<C, E, K> {
     pub fn build<R: Serialize + DeserializeOwned>(
         self,
     ) -> anyhow::Result<DocumentTable<C, R, E, K>> {
-        let vector_db = if let Some(location) = self.location {
-            VectorDB::new_at(location)?
-        } else {
-            VectorDB::new()?
-        };
+        let mut vector_db = vector_db_builder(&self.embedding_model, &self.chunker);
+        let generation = open_generation(self.location.as_deref())?;
+        if let Some(location) = &self.location {
+            vector_db = vector_db.at(location);
+        }
+        if let Some(metric) = self.metric {
+            vector_db = vector_db.with_distance_metric(metric);
+        }
+        let vector_db = vector_db.build()?;
         let table = EmbeddingIndexedTable {
             table: self.table.to_string(),
             db: self.db,
             vector_db,
+            location: self.location,
+            generation,
             phantom: std::marker::PhantomData,
         };
         let embedding_model = self.embedding_model;
         Ok(DocumentTable::new(embedding_model, table, self.chunker))
     }
 }
+
+#[tokio::test]
+async fn reembed_replaces_embeddings_with_a_new_model() {
+    use super::test_support::{memory_db, Length};
+    use super::VectorDbSurrealExt;
+    use kalosm_language::kalosm_language_model::VectorSpace;
+
+    struct LetterSpace;
+
+    impl VectorSpace for LetterSpace {}
+
+    /// Embeds text as the number of vowels and consonants.
+    struct Letters;
+
+    #[async_trait::async_trait]
+    impl Embedder for Letters {
+        type VectorSpace = LetterSpace;
+
+        async fn embed(&self, input: &str) -> anyhow::Result<Embedding<LetterSpace>> {
+            let vowels = input.chars().filter(|c| "aeiou".contains(*c)).count();
+            let consonants = input.chars().filter(char::is_ascii_alphabetic).count() - vowels;
+            Ok(vec![vowels as f32, consonants as f32].into())
+        }
+
+        fn model_id(&self) -> Option<String> {
+            Some("letters".to_string())
+        }
+
+        fn dimensions(&self) -> Option<usize> {
+            Some(2)
+        }
+    }
+
+    let dir = tempfile::tempdir().unwrap();
+    let location = dir.path().join("vectors");
+    let db = memory_db().await;
+    let chunker = ChunkStrategy::Paragraph {
+        paragraph_count: 1,
+        overlap: 0,
+    };
+    let table = DocumentTable::new(
+        Length,
+        db.vector_indexed_table_builder("documents")
+            .at(&location)
+            .build()
+            .unwrap(),
+        chunker,
+    );
+    let vowels = table
+        .insert_with_metadata(
+            Document::from_parts("Vowels", "aeiou\n\naaaa"),
+            EmbeddingMetadata::new().with("kind", "vowels"),
+        )
+        .await
+        .unwrap();
+    let consonants = table
+        .insert(Document::from_parts("Consonants", "bcdfg\n\nxyz"))
+        .await
+        .unwrap();
+
+    let table = table.reembed(Letters).await.unwrap();
+    let vector_db = table.table().vector_db();
+    assert_eq!(vector_db.dimensions(), Some(2));
+    assert_eq!(vector_db.vector_space().as_deref(), Some("letters"));
+    assert_eq!(vector_db.chunker(), Some(chunker.config()));
+    assert_eq!(vector_db.embedding_ids().len(), 4);
+    assert!(table.repair().await.unwrap().is_clean());
+
+    let results = table.select_nearest("aeiouu", 1).await.unwrap();
+    assert_eq!(results[0].record_id, vowels);
+    let byte_range = results[0].byte_range.clone().unwrap();
+    assert_eq!(results[0].record.body()[byte_range].trim(), "aeiou");
+    let results = table
+        .select_nearest_with_filter("aeiouu", 10, &Filter::eq("kind", "vowels"))
+        .await
+        .unwrap();
+    assert!(results.iter().all(|result| result.record_id == vowels));
+    let results = table.select_nearest("xyz", 1).await.unwrap();
+    assert_eq!(results[0].record_id, consonants);
+    // The links to the old embeddings are removed after the swap
+    let old_links: Vec<super::DocumentLink> = db.select("documents-links").await.unwrap();
+    assert!(old_links.is_empty());
+    drop(table);
+
+    // The table is opened with the links to the new embeddings
+    let table = DocumentTable::new(
+        Letters,
+        db.vector_indexed_table_builder("documents")
+            .at(&location)
+            .build()
+            .unwrap(),
+        chunker,
+    );
+    let results = table.select_nearest("aeiouu", 1).await.unwrap();
+    assert_eq!(results[0].record_id, vowels);
+    assert!(table.repair().await.unwrap().is_clean());
+    drop(table);
+
+    // The old embedding model no longer matches the table
+    assert!(vector_db_builder(&Length, &chunker)
+        .at(&location)
+        .build()
+        .is_err());
+    assert!(VectorDB::<LetterSpace>::builder()
+        .at(&location)
+        .with_dimensions(3)
+        .build()
+        .is_err());
+    assert!(!location.with_file_name("vectors.reembed").exists());
+    assert!(!location.with_file_name("vectors.swap").exists());
+}
+
+#[tokio::test]
+async fn apply_change_replaces_and_removes_records() {
+    use super::test_support::{memory_table, Length};
+
+    let folder = tempfile::tempdir().unwrap();
+    let path = folder.path().join("notes.txt");
+    let table = DocumentTable::new(
+        Length,
+        memory_table("documents").await,
+        ChunkStrategy::Paragraph {
+            paragraph_count: 1,
+            overlap: 0,
+        },
+    );
+    let file = |hash| WatchedFile {
+        hash,
+        len: 0,
+        modified: None,
+    };
+    let bodies = |records: Vec<Document>| {
+        let mut bodies = records
+            .iter()
+            .map(|record| record.body().to_string())
+            .collect::<Vec<_>>();
+        bodies.sort();
+        bodies
+    };
+
+    table
+        .apply_change(DocumentChange::Added {
+            path: path.clone(),
+            documents: vec![Document::from_parts("Notes", "first\n\nversion")],
+            file: file(1),
+        })
+        .await
+        .unwrap();
+    assert_eq!(
+        bodies(table.select_all().await.unwrap()),
+        ["first\n\nversion"]
+    );
+    assert_eq!(table.table().vector_db().embedding_ids().len(), 2);
+
+    // A different path to the same file replaces the records
+    table
+        .apply_change(DocumentChange::Modified {
+            path: folder.path().join(".").join("notes.txt"),
+            documents: vec![
+                Document::from_parts("Notes", "second"),
+                Document::from_parts("More notes", "third"),
+            ],
+            file: file(2),
+        })
+        .await
+        .unwrap();
+    assert_eq!(
+        bodies(table.select_all().await.unwrap()),
+        ["second", "third"]
+    );
+    assert_eq!(table.table().vector_db().embedding_ids().len(), 2);
+    let results = table
+        .select_nearest_with_filter(
+            "second",
+            10,
+            &Filter::eq("source", source_path(&path).to_string_lossy().to_string()),
+        )
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 2);
+
+    // Changes with the same hash are skipped
+    table
+        .apply_change(DocumentChange::Modified {
+            path: path.clone(),
+            documents: vec![Document::from_parts("Notes", "ignored")],
+            file: file(2),
+        })
+        .await
+        .unwrap();
+    assert_eq!(
+        bodies(table.select_all().await.unwrap()),
+        ["second", "third"]
+    );
+
+    table
+        .apply_change(DocumentChange::Removed { path })
+        .await
+        .unwrap();
+    assert!(table.select_all().await.unwrap().is_empty());
+    assert!(table.table().vector_db().embedding_ids().is_empty());
+    assert!(table.repair().await.unwrap().is_clean());
+}
+
+#[tokio::test]
+async fn sync_folder_removes_files_deleted_while_stopped() {
+    use super::test_support::{memory_table, Length};
+
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("kept.txt"), "kept").unwrap();
+    std::fs::write(dir.path().join("removed.txt"), "removed").unwrap();
+    let folder = DocumentFolder::try_from(dir.path().to_path_buf()).unwrap();
+    let table = DocumentTable::new(
+        Length,
+        memory_table("documents").await,
+        ChunkStrategy::Paragraph {
+            paragraph_count: 1,
+            overlap: 0,
+        },
+    );
+
+    /// The sync never finishes, so stop it once the first scan is applied.
+    async fn table_sync<C: Connection, M: Embedder, K: Chunker>(
+        table: &DocumentTable<C, Document, M, K>,
+        folder: &DocumentFolder,
+    ) {
+        let _ = tokio::time::timeout(Duration::from_secs(1), table.sync_folder(folder)).await;
+    }
+
+    table_sync(&table, &folder).await;
+    let records = table.select_all().await.unwrap();
+    assert_eq!(records.len(), 2);
+    let kept_id = table
+        .select_nearest("kept", 1)
+        .await
+        .unwrap()
+        .remove(0)
+        .record_id;
+
+    std::fs::remove_file(dir.path().join("removed.txt")).unwrap();
+    table_sync(&table, &folder).await;
+    let records = table.select_all().await.unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].body(), "kept");
+    // The unchanged file was not inserted again
+    let results = table.select_nearest("kept", 1).await.unwrap();
+    assert_eq!(results[0].record_id, kept_id);
+    assert!(table.repair().await.unwrap().is_clean());
+}
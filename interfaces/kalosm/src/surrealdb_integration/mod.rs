@@ -1,19 +1,35 @@
 use kalosm_language::kalosm_language_model::{UnknownVectorSpace, VectorSpace};
 use kalosm_language::prelude::*;
-use kalosm_language::vector_db::VectorDB;
+use kalosm_language::vector_db::{
+    DistanceMetric, EmbeddingMetadata, Filter, VectorDB, VectorDBSearchResult,
+};
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use std::ops::Range;
 use surrealdb::sql::{Id, Thing};
 use surrealdb::{Connection, Surreal};
 
+mod reembed;
+mod repair;
+pub use repair::*;
+#[cfg(test)]
+mod test_support;
+
 #[cfg(feature = "language")]
 mod document_table;
 #[cfg(feature = "language")]
 pub use document_table::*;
+#[cfg(feature = "language")]
+mod rag;
+#[cfg(feature = "language")]
+pub use rag::*;
 
 #[derive(Serialize, Deserialize)]
 struct DocumentLink {
     document_id: Id,
+    /// The byte range of the chunk of the document the embedding was created from.
+    #[serde(default)]
+    byte_range: Option<Range<usize>>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -21,6 +37,20 @@ struct ObjectWithEmbeddingIds<T> {
     #[serde(flatten)]
     object: T,
     embedding_ids: Vec<EmbeddingId>,
+    /// The embedding ids of the record in the vector database from a re-embedding. They replace `embedding_ids` once the vector database of the re-embedding is swapped in.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    reembedded: Option<reembed::ReembeddedIds>,
+}
+
+impl<T> ObjectWithEmbeddingIds<T> {
+    /// Split the record into the object and the ids of its embeddings in the vector database with the given links generation.
+    fn into_parts(self, generation: u64) -> (T, Vec<EmbeddingId>) {
+        let embedding_ids = match self.reembedded {
+            Some(reembedded) if reembedded.generation == generation => reembedded.embedding_ids,
+            _ => self.embedding_ids,
+        };
+        (self.object, embedding_ids)
+    }
 }
 
 /// A table in a surreal database with a primary key tied to an embedding in a vector database.
@@ -28,6 +58,9 @@ pub struct EmbeddingIndexedTable<C: Connection, R, S = UnknownVectorSpace> {
     table: String,
     db: Surreal<C>,
     vector_db: VectorDB<S>,
+    location: Option<std::path::PathBuf>,
+    /// The number of times the vector database was replaced by [`DocumentTable::reembed`]. Every generation of the vector database has its own links table.
+    generation: u64,
     phantom: std::marker::PhantomData<R>,
 }
 
@@ -39,7 +72,7 @@ impl<C: Connection, R, S: VectorSpace> EmbeddingIndexedTable<C, R, S> {
 
     /// Get the name of the table. The table has a id with the same number as an embedding id and the value of the id of the object in the table
     fn table_links(&self) -> String {
-        format!("{}-links", &self.table)
+        reembed::links_table(&self.table, self.generation)
     }
 
     /// Get the raw vector database.
@@ -58,20 +91,54 @@ impl<C: Connection, R, S: VectorSpace> EmbeddingIndexedTable<C, R, S> {
         embeddings: impl IntoIterator<Item = Embedding<S>>,
         value: R,
     ) -> anyhow::Result<Id>
+    where
+        R: Serialize + DeserializeOwned,
+    {
+        self.insert_with_metadata(embeddings, value, EmbeddingMetadata::default())
+            .await
+    }
+
+    /// Insert a new record into the table with the given embeddings. Every embedding is tagged with the metadata, which can be used to filter results with [`EmbeddingIndexedTable::select_nearest_with_filter`].
+    pub async fn insert_with_metadata(
+        &self,
+        embeddings: impl IntoIterator<Item = Embedding<S>>,
+        value: R,
+        metadata: EmbeddingMetadata,
+    ) -> anyhow::Result<Id>
+    where
+        R: Serialize + DeserializeOwned,
+    {
+        self.insert_chunks_with_metadata(
+            embeddings.into_iter().map(|embedding| (embedding, None)),
+            value,
+            metadata,
+        )
+        .await
+    }
+
+    /// Insert a new record with embeddings that are tagged with the byte range of the chunk they were created from.
+    pub(crate) async fn insert_chunks_with_metadata(
+        &self,
+        chunks: impl IntoIterator<Item = (Embedding<S>, Option<Range<usize>>)>,
+        value: R,
+        metadata: EmbeddingMetadata,
+    ) -> anyhow::Result<Id>
     where
         R: Serialize + DeserializeOwned,
     {
         let id = Id::uuid();
-        let embedding_ids = embeddings
-            .into_iter()
-            .map(|embedding| self.vector_db.add_embedding(embedding))
-            .collect::<anyhow::Result<Vec<_>>>()?;
+        let (embeddings, byte_ranges): (Vec<_>, Vec<_>) = chunks.into_iter().unzip();
+        let embedding_ids = self.vector_db.add_embeddings_with_metadata(
+            embeddings
+                .into_iter()
+                .map(|embedding| (embedding, metadata.clone())),
+        )?;
         let thing = Thing {
             tb: self.table.clone(),
             id: id.clone(),
         };
 
-        for embedding_id in &embedding_ids {
+        for (embedding_id, byte_range) in embedding_ids.iter().zip(byte_ranges) {
             let link = Thing {
                 tb: self.table_links(),
                 id: Id::Number(embedding_id.0 as i64),
@@ -80,6 +147,7 @@ impl<C: Connection, R, S: VectorSpace> EmbeddingIndexedTable<C, R, S> {
                 .create::<Option<DocumentLink>>(link)
                 .content(DocumentLink {
                     document_id: id.clone(),
+                    byte_range,
                 })
                 .await?;
         }
@@ -89,6 +157,7 @@ impl<C: Connection, R, S: VectorSpace> EmbeddingIndexedTable<C, R, S> {
             .content(ObjectWithEmbeddingIds {
                 object: value,
                 embedding_ids,
+                reembedded: None,
             })
             .await?;
 
@@ -141,10 +210,7 @@ impl<C: Connection, R, S: VectorSpace> EmbeddingIndexedTable<C, R, S> {
             .await?;
 
         if let Some(old) = old {
-            let ObjectWithEmbeddingIds {
-                object,
-                embedding_ids,
-            } = old;
+            let (object, embedding_ids) = old.into_parts(self.generation);
             // Then delete the links from the links table
             for id in embedding_ids {
                 let link = Thing {
@@ -181,6 +247,32 @@ impl<C: Connection, R, S: VectorSpace> EmbeddingIndexedTable<C, R, S> {
         R: DeserializeOwned,
     {
         let ids = self.vector_db.get_closest(embedding, k)?;
+        self.select_search_results(ids).await
+    }
+
+    /// Select the top k records nearest records to the given embedding with metadata that matches the filter.
+    pub async fn select_nearest_with_filter(
+        &self,
+        embedding: Embedding<S>,
+        k: usize,
+        filter: &Filter,
+    ) -> anyhow::Result<Vec<EmbeddingIndexedTableSearchResult<R>>>
+    where
+        R: DeserializeOwned,
+    {
+        let ids = self
+            .vector_db
+            .get_closest_with_filter(embedding, k, filter)?;
+        self.select_search_results(ids).await
+    }
+
+    async fn select_search_results(
+        &self,
+        ids: Vec<VectorDBSearchResult>,
+    ) -> anyhow::Result<Vec<EmbeddingIndexedTableSearchResult<R>>>
+    where
+        R: DeserializeOwned,
+    {
         let mut records = Vec::new();
         for id in ids {
             let main_table_id = self
@@ -194,8 +286,11 @@ impl<C: Connection, R, S: VectorSpace> EmbeddingIndexedTable<C, R, S> {
             let record = self.select(main_table_id.document_id.clone()).await?;
             records.push(EmbeddingIndexedTableSearchResult {
                 distance: id.distance,
+                similarity: id.similarity,
+                rerank_score: None,
                 id: id.value,
                 record_id: main_table_id.document_id,
+                byte_range: main_table_id.byte_range,
                 record,
             });
         }
@@ -208,10 +303,16 @@ impl<C: Connection, R, S: VectorSpace> EmbeddingIndexedTable<C, R, S> {
 pub struct EmbeddingIndexedTableSearchResult<R> {
     /// The distance from the searched point.
     pub distance: f32,
+    /// The similarity to the searched point with the distance metric of the vector database. Higher values are more similar.
+    pub similarity: f32,
+    /// The score from the reranker, if the results were reranked.
+    pub rerank_score: Option<f32>,
     /// The embedding id of the record.
     pub id: EmbeddingId,
     /// The record id.
     pub record_id: Id,
+    /// The byte range of the chunk of the record that matched, if the record was inserted from a [`DocumentTable`].
+    pub byte_range: Option<Range<usize>>,
     /// The record.
     pub record: R,
 }
@@ -221,6 +322,7 @@ pub struct EmbeddingIndexedTableBuilder<C: Connection> {
     table: String,
     db: Surreal<C>,
     location: Option<std::path::PathBuf>,
+    metric: Option<DistanceMetric>,
 }
 
 impl<C: Connection> EmbeddingIndexedTableBuilder<C> {
@@ -230,6 +332,7 @@ impl<C: Connection> EmbeddingIndexedTableBuilder<C> {
             table: table.to_string(),
             db,
             location: None,
+            metric: None,
         }
     }
 
@@ -239,19 +342,31 @@ impl<C: Connection> EmbeddingIndexedTableBuilder<C> {
         self
     }
 
+    /// Set the distance metric of the vector database.
+    pub fn with_distance_metric(mut self, metric: DistanceMetric) -> Self {
+        self.metric = Some(metric);
+        self
+    }
+
     /// Build the document table.
     pub fn build<S: VectorSpace, R: Serialize + DeserializeOwned>(
         self,
     ) -> anyhow::Result<EmbeddingIndexedTable<C, R, S>> {
-        let vector_db = if let Some(location) = self.location {
-            VectorDB::new_at(location)?
-        } else {
-            VectorDB::new()?
-        };
+        let mut vector_db = VectorDB::builder();
+        let generation = reembed::open_generation(self.location.as_deref())?;
+        if let Some(location) = &self.location {
+            vector_db = vector_db.at(location);
+        }
+        if let Some(metric) = self.metric {
+            vector_db = vector_db.with_distance_metric(metric);
+        }
+        let vector_db = vector_db.build()?;
         Ok(EmbeddingIndexedTable {
             table: self.table.to_string(),
             db: self.db,
             vector_db,
+            location: self.location,
+            generation,
             phantom: std::marker::PhantomData,
         })
     }
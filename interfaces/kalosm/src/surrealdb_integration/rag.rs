@@ -0,0 +1,452 @@
+use super::DocumentTable;
+use crate::TestCases;
+use futures_util::Stream;
+use kalosm_language::prelude::*;
+use std::ops::Range;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use surrealdb::sql::Id;
+use surrealdb::Connection;
+
+const DEFAULT_INSTRUCTIONS: &str = "You answer questions using only the numbered context passages the user gives you. Cite every passage you use with its marker, like [1]. If the passages do not contain the answer, say that you do not know.";
+
+/// A passage of a document that was retrieved as context for an answer.
+#[derive(Debug, Clone)]
+pub struct Citation {
+    /// The number of the marker for the passage, like `[1]`.
+    pub marker: usize,
+    /// The id of the record the passage is from.
+    pub record_id: Id,
+    /// The title of the document the passage is from.
+    pub title: String,
+    /// The byte range of the passage in the body of the document. This is the byte range of the [`Chunk`] that matched the question.
+    pub byte_range: Range<usize>,
+    /// Where the passage is in the original document.
+    pub location: DocumentLocation,
+    /// The text of the passage that was given to the model. This may be shorter than the passage if it did not fit in the context budget.
+    pub text: String,
+    /// The rerank score of the passage if the table has a reranker, otherwise the similarity to the question.
+    pub score: f32,
+}
+
+/// A streaming answer from a [`RagPipeline`] with the passages it was given as context.
+pub struct RagAnswer {
+    citations: Vec<Citation>,
+    stream: ChannelTextStream<String>,
+}
+
+impl RagAnswer {
+    /// Get the passages that were given to the model as context.
+    pub fn citations(&self) -> &[Citation] {
+        &self.citations
+    }
+
+    /// Get the passages that are cited with a marker in the answer text.
+    pub fn cited<'a>(&'a self, answer: &str) -> Vec<&'a Citation> {
+        let markers = citation_markers(answer);
+        self.citations
+            .iter()
+            .filter(|citation| markers.contains(&citation.marker))
+            .collect()
+    }
+
+    /// Wait for the whole answer.
+    pub async fn text(self) -> String {
+        self.stream.all_text().await
+    }
+}
+
+impl Stream for RagAnswer {
+    type Item = String;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.stream).poll_next(cx)
+    }
+}
+
+/// A builder for a [`RagPipeline`].
+pub struct RagPipelineBuilder<C: Connection, M: Model, E: Embedder, K: Chunker> {
+    table: DocumentTable<C, Document, E, K>,
+    model: M,
+    instructions: String,
+    top_k: usize,
+    context_tokens: usize,
+}
+
+impl<C: Connection, M: Model, E: Embedder, K: Chunker> RagPipelineBuilder<C, M, E, K> {
+    /// Set the number of chunks retrieved for each question. Defaults to 4.
+    pub fn with_top_k(mut self, top_k: usize) -> Self {
+        self.top_k = top_k;
+        self
+    }
+
+    /// Set the maximum number of tokens of retrieved passages in the prompt. Passages past the budget are dropped, and the last passage that fits is truncated. Defaults to 2048.
+    pub fn with_context_tokens(mut self, context_tokens: usize) -> Self {
+        self.context_tokens = context_tokens;
+        self
+    }
+
+    /// Set the system prompt that tells the model how to answer with the context.
+    pub fn with_instructions(mut self, instructions: impl Into<String>) -> Self {
+        self.instructions = instructions.into();
+        self
+    }
+
+    /// Build the pipeline.
+    pub fn build(self) -> RagPipeline<C, M, E, K> {
+        RagPipeline {
+            table: self.table,
+            model: self.model,
+            task: Task::new(self.instructions),
+            top_k: self.top_k,
+            context_tokens: self.context_tokens,
+        }
+    }
+}
+
+/// A retrieval augmented generation pipeline. Documents are chunked, embedded and stored in a [`DocumentTable`]. Questions are answered by retrieving the nearest chunks and prompting a model with the chunks as numbered passages, which the model cites with markers like `[1]`.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+/// use kalosm::*;
+/// use std::path::PathBuf;
+/// use surrealdb::{engine::local::RocksDb, Surreal};
+///
+/// #[tokio::main]
+/// async fn main() -> anyhow::Result<()> {
+///     let db = Surreal::new::<RocksDb>("./db/temp.db").await?;
+///     db.use_ns("test").use_db("test").await?;
+///     let table = db
+///         .document_table_builder("documents")
+///         .at("./db/embeddings.db")
+///         .build()?;
+///
+///     let pipeline = RagPipeline::builder(table, Llama::new_chat())
+///         .with_top_k(5)
+///         .build();
+///     pipeline
+///         .add_documents(DocumentFolder::try_from(PathBuf::from("./documents"))?)
+///         .await?;
+///
+///     let mut answer = pipeline.answer("What is the refund policy?").await?;
+///     let mut text = String::new();
+///     while let Some(token) = answer.next().await {
+///         print!("{token}");
+///         text.push_str(&token);
+///     }
+///     for citation in answer.cited(&text) {
+///         println!("\n[{}] {}", citation.marker, citation.location);
+///     }
+///     Ok(())
+/// }
+/// ```
+pub struct RagPipeline<C: Connection, M: Model, E: Embedder, K: Chunker> {
+    table: DocumentTable<C, Document, E, K>,
+    model: M,
+    task: Task,
+    top_k: usize,
+    context_tokens: usize,
+}
+
+impl<C: Connection, M: Model, E: Embedder, K: Chunker> RagPipeline<C, M, E, K> {
+    /// Create a builder for a pipeline that retrieves from the table and answers with the model.
+    pub fn builder(
+        table: DocumentTable<C, Document, E, K>,
+        model: M,
+    ) -> RagPipelineBuilder<C, M, E, K> {
+        RagPipelineBuilder {
+            table,
+            model,
+            instructions: DEFAULT_INSTRUCTIONS.to_string(),
+            top_k: 4,
+            context_tokens: 2048,
+        }
+    }
+
+    /// Get the document table of the pipeline.
+    pub fn table(&self) -> &DocumentTable<C, Document, E, K> {
+        &self.table
+    }
+
+    /// Get the model of the pipeline.
+    pub fn model(&self) -> &M {
+        &self.model
+    }
+
+    /// Chunk, embed and insert documents into the table. Each document is inserted with its metadata.
+    pub async fn add_documents(&self, documents: impl IntoDocuments) -> anyhow::Result<Vec<Id>> {
+        let mut ids = Vec::new();
+        for document in documents.into_documents().await? {
+            let metadata = document.metadata().clone();
+            ids.push(self.table.insert_with_metadata(document, metadata).await?);
+        }
+        Ok(ids)
+    }
+
+    /// Retrieve the passages that are given to the model as context for a question.
+    pub async fn retrieve(&self, question: &str) -> anyhow::Result<Vec<Citation>> {
+        let results = self.table.select_nearest(question, self.top_k).await?;
+        let tokenizer = self.model.tokenizer();
+        let mut citations: Vec<Citation> = Vec::new();
+        let mut used_tokens = 0;
+        for result in results {
+            let remaining_tokens = self.context_tokens.saturating_sub(used_tokens);
+            if remaining_tokens == 0 {
+                break;
+            }
+            let document = result.record;
+            let body = document.body();
+            let byte_range = result
+                .byte_range
+                .filter(|range| body.get(range.clone()).is_some())
+                .unwrap_or(0..body.len());
+            // Several embeddings of the same chunk can match the question
+            if citations.iter().any(|citation| {
+                citation.record_id == result.record_id && citation.byte_range == byte_range
+            }) {
+                continue;
+            }
+
+            let text = body[byte_range.clone()].trim();
+            let tokens = tokenizer.encode(text, false)?;
+            let text = if tokens.len() > remaining_tokens {
+                tokenizer.decode(&tokens[..remaining_tokens])?.to_string()
+            } else {
+                text.to_string()
+            };
+            used_tokens += tokens.len().min(remaining_tokens);
+
+            citations.push(Citation {
+                marker: citations.len() + 1,
+                record_id: result.record_id,
+                title: document.title().to_string(),
+                location: document.locate(byte_range.start),
+                byte_range,
+                text,
+                score: result.rerank_score.unwrap_or(result.similarity),
+            });
+        }
+        Ok(citations)
+    }
+
+    /// Answer a question with the passages retrieved from the table. The answer is streamed as it is generated.
+    pub async fn answer(&self, question: &str) -> anyhow::Result<RagAnswer>
+    where
+        <<M as Model>::SyncModel as SyncModel>::Session: Send + Sync,
+    {
+        let citations = self.retrieve(question).await?;
+        let prompt = rag_prompt(question, &citations);
+        let stream = self.task.run(prompt, &self.model);
+        Ok(RagAnswer { citations, stream })
+    }
+
+    /// Answer each question and collect the answers with the expected answers as [`TestCases`], which can be scored with any [`crate::Metric`].
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// # use kalosm::language::*;
+    /// # use kalosm::*;
+    /// # async fn evaluate<C: surrealdb::Connection>(pipeline: RagPipeline<C, Llama, Bert, ChunkStrategy>) -> anyhow::Result<()> {
+    /// let mut cases = pipeline
+    ///     .test_cases([("How long is the warranty?", "The warranty lasts two years.")])
+    ///     .await?;
+    /// let result = cases.evaluate(&mut BertDistance::default()).await;
+    /// println!("{}", result);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn test_cases(
+        &self,
+        questions: impl IntoIterator<Item = (impl Into<String>, impl Into<String>)>,
+    ) -> anyhow::Result<TestCases<String>>
+    where
+        <<M as Model>::SyncModel as SyncModel>::Session: Send + Sync,
+    {
+        let mut cases = TestCases::new().with_name("RAG pipeline");
+        for (question, expected) in questions {
+            let answer = self.answer(&question.into()).await?.text().await;
+            cases.push_case(expected.into(), answer);
+        }
+        Ok(cases)
+    }
+}
+
+/// Format the question and the numbered passages as a prompt.
+fn rag_prompt(question: &str, citations: &[Citation]) -> String {
+    let mut prompt = String::from("Context:\n");
+    for citation in citations {
+        let location = citation.location.to_string();
+        prompt += &format!("\n[{}] {}", citation.marker, citation.title);
+        if !location.is_empty() {
+            prompt += &format!(" ({})", location);
+        }
+        prompt += &format!("\n{}\n", citation.text);
+    }
+    prompt += &format!("\nQuestion: {}", question);
+    prompt
+}
+
+/// Find the numbers of the citation markers in an answer, like `[1]` or `[1, 3]`.
+fn citation_markers(answer: &str) -> Vec<usize> {
+    let mut markers = Vec::new();
+    let mut rest = answer;
+    while let Some(start) = rest.find('[') {
+        rest = &rest[start + 1..];
+        let Some(end) = rest.find(']') else {
+            break;
+        };
+        let numbers = rest[..end]
+            .split(',')
+            .map(|number| number.trim().parse::<usize>())
+            .collect::<Result<Vec<_>, _>>();
+        if let Ok(numbers) = numbers {
+            for number in numbers {
+                if !markers.contains(&number) {
+                    markers.push(number);
+                }
+            }
+        }
+        rest = &rest[end..];
+    }
+    markers
+}
+
+#[test]
+fn finds_citation_markers() {
+    assert_eq!(
+        citation_markers("Refunds take 14 days [2]. Store credit is instant [1, 3][2]."),
+        vec![2, 1, 3]
+    );
+    assert_eq!(
+        citation_markers("See [the manual] and [4"),
+        Vec::<usize>::new()
+    );
+}
+
+#[tokio::test]
+async fn retrieve_dedups_truncates_and_locates_passages() {
+    use super::test_support::memory_table;
+    use kalosm_language::kalosm_language_model::UnknownVectorSpace;
+    use std::borrow::Cow;
+    use std::sync::Arc;
+
+    /// Embeds text by the words it mentions.
+    struct Mentions;
+
+    #[async_trait::async_trait]
+    impl Embedder for Mentions {
+        type VectorSpace = UnknownVectorSpace;
+
+        async fn embed(&self, input: &str) -> anyhow::Result<Embedding<UnknownVectorSpace>> {
+            let input = input.to_lowercase();
+            let mentions = |word| input.contains(word) as u8 as f32;
+            Ok(vec![mentions("refund"), mentions("card"), 0.1].into())
+        }
+    }
+
+    /// Splits documents into lines and embeds every line twice, so each chunk matches a question more than once.
+    struct Lines;
+
+    #[async_trait::async_trait]
+    impl Chunker for Lines {
+        async fn chunk<E: Embedder + Send>(
+            &self,
+            document: &Document,
+            embedder: &E,
+        ) -> anyhow::Result<Vec<Chunk<E::VectorSpace>>> {
+            let mut chunks = Vec::new();
+            let mut start = 0;
+            for line in document.body().split_inclusive('\n') {
+                let embedding = embedder.embed(line.trim()).await?;
+                chunks.push(Chunk {
+                    byte_range: start..start + line.len(),
+                    embeddings: vec![embedding.clone(), embedding],
+                });
+                start += line.len();
+            }
+            Ok(chunks)
+        }
+    }
+
+    /// A tokenizer with one token per byte.
+    struct Bytes;
+
+    impl Tokenizer for Bytes {
+        fn encode(&self, text: &str, _: bool) -> anyhow::Result<Vec<u32>> {
+            Ok(text.bytes().map(u32::from).collect())
+        }
+
+        fn decode(&self, ids: &[u32]) -> anyhow::Result<Cow<'_, str>> {
+            let bytes = ids.iter().map(|id| *id as u8).collect::<Vec<_>>();
+            Ok(Cow::Owned(String::from_utf8_lossy(&bytes).to_string()))
+        }
+
+        fn get_all_tokens(&self) -> anyhow::Result<Cow<'_, [u32]>> {
+            Ok(Cow::Owned((0..256).collect()))
+        }
+    }
+
+    /// A model that is only used for its tokenizer.
+    struct Stub;
+
+    #[async_trait::async_trait]
+    impl Model for Stub {
+        type TextStream = ChannelTextStream<String>;
+        type SyncModel = SyncModelNotSupported;
+
+        fn tokenizer(&self) -> Arc<dyn Tokenizer + Send + Sync> {
+            Arc::new(Bytes)
+        }
+
+        async fn stream_text_inner(
+            &self,
+            _: &str,
+            _: GenerationParameters,
+        ) -> anyhow::Result<Self::TextStream> {
+            anyhow::bail!("Not implemented")
+        }
+    }
+
+    let table = DocumentTable::new(Mentions, memory_table("documents").await, Lines);
+    let body = "Shipping takes five days.\nRefunds take fourteen days after the return arrives.\nRefunds are paid to the original card.\n";
+    let paid = body.find("Refunds are").unwrap();
+    let mut document = Document::from_parts("Policy", body);
+    document.set_pages(vec![0..paid, paid..body.len()]);
+    let id = table.insert(document).await.unwrap();
+
+    // The budget fits the first passage and 7 bytes of the second
+    let pipeline = RagPipeline::builder(table, Stub)
+        .with_top_k(4)
+        .with_context_tokens("Refunds are paid to the original card.".len() + 7)
+        .build();
+    let citations = pipeline
+        .retrieve("Are refunds paid to my card?")
+        .await
+        .unwrap();
+
+    // Both embeddings of each refund line match, but each line is only cited once
+    assert_eq!(citations.len(), 2);
+    assert_eq!(
+        citations
+            .iter()
+            .map(|citation| citation.marker)
+            .collect::<Vec<_>>(),
+        [1, 2]
+    );
+    assert!(citations.iter().all(|citation| citation.record_id == id));
+
+    assert_eq!(citations[0].text, "Refunds are paid to the original card.");
+    assert_eq!(
+        body[citations[0].byte_range.clone()].trim(),
+        "Refunds are paid to the original card."
+    );
+    assert_eq!(citations[0].location.page, Some(2));
+
+    assert_eq!(citations[1].text, "Refunds");
+    assert_eq!(
+        body[citations[1].byte_range.clone()].trim(),
+        "Refunds take fourteen days after the return arrives."
+    );
+    assert_eq!(citations[1].location.page, Some(1));
+}
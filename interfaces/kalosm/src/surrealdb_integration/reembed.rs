@@ -0,0 +1,471 @@
+use super::{DocumentLink, EmbeddingIndexedTable, ObjectWithEmbeddingIds};
+use kalosm_language::kalosm_language_model::VectorSpace;
+use kalosm_language::prelude::*;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use surrealdb::sql::{Id, Thing};
+use surrealdb::Connection;
+
+/// The file in the directory of a vector database that stores the generation of the links table of the vector database.
+const GENERATION_FILE: &str = "kalosm-links-generation";
+
+/// The number of records whose embedding ids are moved into place in one transaction after a swap.
+const FINISH_BATCH_SIZE: usize = 256;
+
+/// A record in the main table with its id.
+#[derive(Deserialize)]
+pub(super) struct StoredRecord<R> {
+    pub(super) id: Thing,
+    #[serde(flatten)]
+    pub(super) object: ObjectWithEmbeddingIds<R>,
+}
+
+/// The new embeddings of a record, with the byte range of the chunk each embedding was created from.
+pub(super) struct ReembeddedRecord {
+    pub(super) id: Id,
+    pub(super) embeddings: Vec<(EmbeddingId, Option<Range<usize>>)>,
+}
+
+/// The embedding ids of a record in the vector database of a re-embedding.
+#[derive(Serialize, Deserialize)]
+pub(super) struct ReembeddedIds {
+    /// The generation of the links table of the re-embedded vector database.
+    pub(super) generation: u64,
+    pub(super) embedding_ids: Vec<EmbeddingId>,
+}
+
+/// A record with embedding ids from a re-embedding that are not moved into place yet.
+#[derive(Deserialize)]
+struct ReembeddedRecordIds {
+    id: Thing,
+    reembedded: ReembeddedIds,
+}
+
+/// The byte range and metadata of an embedding of a record.
+pub(super) struct EmbeddingSource {
+    byte_range: Option<Range<usize>>,
+    metadata: EmbeddingMetadata,
+}
+
+/// Pick the metadata for the new embedding of a chunk of a record. The metadata is copied from the old embedding whose chunk overlaps the new chunk the most. Old embeddings without a byte range are matched by their position in the record instead.
+pub(super) fn metadata_for_chunk(
+    sources: &[EmbeddingSource],
+    index: usize,
+    byte_range: &Range<usize>,
+) -> EmbeddingMetadata {
+    // Search from the end so ties go to the first chunk, because max_by_key keeps the last maximum
+    let overlapping = sources
+        .iter()
+        .rev()
+        .filter_map(|source| {
+            let range = source.byte_range.as_ref()?;
+            let overlap = range
+                .end
+                .min(byte_range.end)
+                .saturating_sub(range.start.max(byte_range.start));
+            (overlap > 0).then_some((overlap, source))
+        })
+        .max_by_key(|(overlap, _)| *overlap);
+    overlapping
+        .map(|(_, source)| source)
+        .or_else(|| sources.get(index))
+        .or_else(|| sources.first())
+        .map(|source| source.metadata.clone())
+        .unwrap_or_default()
+}
+
+/// A vector database with new embeddings for every record of a table. The links to the new embeddings are stored in the links table of the next generation, and the new embedding ids of each record are stored next to the current ones. Neither is used until the vector database is swapped in.
+pub(super) struct StagedVectorDb<T: VectorSpace> {
+    pub(super) vector_db: VectorDB<T>,
+    generation: u64,
+}
+
+/// Get the name of the links table of a generation of the vector database. The first generation uses the links table from before tables could be re-embedded.
+pub(super) fn links_table(table: &str, generation: u64) -> String {
+    match generation {
+        0 => format!("{table}-links"),
+        generation => format!("{table}-links-{generation}"),
+    }
+}
+
+/// The directory a vector database is built in while the table is re-embedded.
+fn staged_location(location: &Path) -> PathBuf {
+    sibling(location, "reembed")
+}
+
+/// The directory the old vector database is moved to while the new vector database is moved into place.
+fn old_location(location: &Path) -> PathBuf {
+    sibling(location, "old")
+}
+
+/// The marker that is written once the staged vector database and its links are complete. While it exists, the staged vector database replaces the current one.
+fn swap_marker(location: &Path) -> PathBuf {
+    sibling(location, "swap")
+}
+
+fn sibling(location: &Path, extension: &str) -> PathBuf {
+    let mut name = location.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(extension);
+    location.with_file_name(name)
+}
+
+/// Write a file and flush it to the disk so it survives a crash.
+fn write_durable(path: &Path, contents: &str) -> anyhow::Result<()> {
+    let mut file = std::fs::File::create(path)?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()?;
+    sync_parent(path)
+}
+
+/// Flush the entries of the folder that contains a path to the disk, so renames and new files in the folder survive a crash.
+fn sync_parent(path: &Path) -> anyhow::Result<()> {
+    // Folders can only be opened and synced on unix
+    if cfg!(unix) {
+        let parent = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        std::fs::File::open(parent)?.sync_all()?;
+    }
+    Ok(())
+}
+
+/// Finish a swap of vector databases that was interrupted, then get the generation of the links table of the vector database at the location.
+pub(super) fn open_generation(location: Option<&Path>) -> anyhow::Result<u64> {
+    let Some(location) = location else {
+        return Ok(0);
+    };
+    recover_swap(location)?;
+    match std::fs::read_to_string(location.join(GENERATION_FILE)) {
+        Ok(generation) => Ok(generation.trim().parse()?),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(0),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Move the staged vector database into place if the swap marker exists.
+///
+/// The marker is only written after the staged vector database and its links are complete, so a swap with a marker is always finished. Each step checks what was already done, so this can run again if it is interrupted itself. Without a marker, a staged vector database is from a re-embedding that was interrupted before it was complete. It is never used and is removed by the next re-embedding.
+pub(super) fn recover_swap(location: &Path) -> anyhow::Result<()> {
+    let staged = staged_location(location);
+    let old = old_location(location);
+    let marker = swap_marker(location);
+    if !marker.exists() {
+        if location.exists() && old.exists() {
+            std::fs::remove_dir_all(&old)?;
+        }
+        return Ok(());
+    }
+
+    if staged.exists() {
+        if location.exists() {
+            std::fs::rename(location, &old)?;
+        }
+        std::fs::rename(&staged, location)?;
+        sync_parent(location)?;
+    }
+    if old.exists() {
+        std::fs::remove_dir_all(&old)?;
+    }
+    std::fs::remove_file(&marker)?;
+    sync_parent(&marker)
+}
+
+impl<C: Connection, R, S: VectorSpace> EmbeddingIndexedTable<C, R, S> {
+    /// Select a page of records from the table, ordered by id.
+    pub(super) async fn select_page(
+        &self,
+        start: usize,
+        limit: usize,
+    ) -> anyhow::Result<Vec<StoredRecord<R>>>
+    where
+        R: DeserializeOwned,
+    {
+        let records = self
+            .db
+            .query("SELECT * FROM type::table($table) ORDER BY id LIMIT $limit START $start")
+            .bind(("table", &self.table))
+            .bind(("limit", limit))
+            .bind(("start", start))
+            .await?
+            .take(0)?;
+        Ok(records)
+    }
+
+    /// Get the byte range and metadata of every embedding of a record, so the metadata can be copied to the new embeddings of the same part of the record.
+    pub(super) async fn embedding_sources(
+        &self,
+        embedding_ids: &[EmbeddingId],
+    ) -> anyhow::Result<Vec<EmbeddingSource>> {
+        let mut sources = Vec::with_capacity(embedding_ids.len());
+        for id in embedding_ids {
+            let link = self
+                .db
+                .select::<Option<DocumentLink>>(Thing {
+                    tb: self.table_links(),
+                    id: Id::Number(id.0 as i64),
+                })
+                .await?;
+            sources.push(EmbeddingSource {
+                byte_range: link.and_then(|link| link.byte_range),
+                metadata: self.vector_db.metadata(*id)?,
+            });
+        }
+        Ok(sources)
+    }
+
+    /// Create an empty vector database for the new embeddings next to the current vector database, and clear the links table of the next generation.
+    ///
+    /// The builder should record the embedding model and chunker of the new embeddings. The distance metric and location are copied from the current vector database.
+    pub(super) async fn stage_vector_db<T: VectorSpace + Sync>(
+        &self,
+        vector_db: VectorDBBuilder<T>,
+    ) -> anyhow::Result<StagedVectorDb<T>> {
+        // Move the embedding ids from the last re-embedding into place before new ids are staged
+        self.finish_swap().await?;
+
+        let generation = self.generation + 1;
+        // The links table and the staged vector database from a re-embedding that was interrupted are never used
+        self.db
+            .query("DELETE type::table($links)")
+            .bind(("links", links_table(&self.table, generation)))
+            .await?
+            .check()?;
+        let mut vector_db = vector_db.with_distance_metric(self.vector_db.distance_metric());
+        if let Some(location) = &self.location {
+            let staged = staged_location(location);
+            if staged.exists() {
+                std::fs::remove_dir_all(&staged)?;
+            }
+            vector_db = vector_db.at(staged);
+        }
+        Ok(StagedVectorDb {
+            vector_db: vector_db.build()?,
+            generation,
+        })
+    }
+
+    /// Store the links to the new embeddings of a batch of records in a single transaction. The links are not used until the staged vector database is swapped in.
+    pub(super) async fn stage_links<T: VectorSpace>(
+        &self,
+        staged: &StagedVectorDb<T>,
+        records: Vec<ReembeddedRecord>,
+    ) -> anyhow::Result<()> {
+        let links = links_table(&self.table, staged.generation);
+        let mut query = self.db.query("BEGIN TRANSACTION");
+        for (i, record) in records.into_iter().enumerate() {
+            let mut embedding_ids = Vec::new();
+            for (j, (embedding_id, byte_range)) in record.embeddings.into_iter().enumerate() {
+                query = query
+                    .query(format!("UPDATE $link_{i}_{j} CONTENT $content_{i}_{j}"))
+                    .bind((
+                        format!("link_{i}_{j}"),
+                        Thing {
+                            tb: links.clone(),
+                            id: Id::Number(embedding_id.0 as i64),
+                        },
+                    ))
+                    .bind((
+                        format!("content_{i}_{j}"),
+                        DocumentLink {
+                            document_id: record.id.clone(),
+                            byte_range,
+                        },
+                    ));
+                embedding_ids.push(embedding_id);
+            }
+            query = query
+                .query(format!(
+                    "UPDATE $record_{i} MERGE {{ reembedded: $reembedded_{i} }}"
+                ))
+                .bind((
+                    format!("record_{i}"),
+                    Thing {
+                        tb: self.table.clone(),
+                        id: record.id,
+                    },
+                ))
+                .bind((
+                    format!("reembedded_{i}"),
+                    ReembeddedIds {
+                        generation: staged.generation,
+                        embedding_ids,
+                    },
+                ));
+        }
+        query.query("COMMIT TRANSACTION").await?.check()?;
+        Ok(())
+    }
+
+    /// Replace the vector database of the table with a staged vector database that contains new embeddings for every record.
+    ///
+    /// The generation of the links table is stored in the staged vector database, and a swap marker is written before the staged vector database is moved into place. If the process stops after the marker is written, [`recover_swap`] finishes the move the next time the table is opened. Searches always use the links table of the generation stored in the vector database, so they never mix the old embeddings with the new links.
+    pub(super) async fn swap_vector_db<T: VectorSpace>(
+        self,
+        staged: StagedVectorDb<T>,
+    ) -> anyhow::Result<EmbeddingIndexedTable<C, R, T>> {
+        let StagedVectorDb {
+            vector_db,
+            generation,
+        } = staged;
+        vector_db.build()?;
+
+        let EmbeddingIndexedTable {
+            table,
+            db,
+            vector_db: old_vector_db,
+            location,
+            ..
+        } = self;
+        let vector_db = match &location {
+            Some(location) => {
+                drop(old_vector_db);
+                drop(vector_db);
+                let staged = staged_location(location);
+                write_durable(&staged.join(GENERATION_FILE), &generation.to_string())?;
+                let old = old_location(location);
+                if old.exists() {
+                    std::fs::remove_dir_all(&old)?;
+                }
+                write_durable(&swap_marker(location), "")?;
+                recover_swap(location)?;
+                VectorDB::builder().at(location).build()?
+            }
+            None => vector_db,
+        };
+
+        let table = EmbeddingIndexedTable {
+            table,
+            db,
+            vector_db,
+            location,
+            generation,
+            phantom: std::marker::PhantomData,
+        };
+        table.finish_swap().await?;
+        Ok(table)
+    }
+
+    /// Move the embedding ids of the records from the last re-embedding into place in batches, then remove the links table of the previous generation.
+    ///
+    /// Until this finishes, the embedding ids are read from the re-embedded ids of each record, so it is safe to stop in the middle. It runs after every swap, and again before the table is repaired or re-embedded.
+    pub(super) async fn finish_swap(&self) -> anyhow::Result<()> {
+        let Some(previous) = self.generation.checked_sub(1) else {
+            return Ok(());
+        };
+        loop {
+            let records: Vec<ReembeddedRecordIds> = self
+                .db
+                .query("SELECT id, reembedded FROM type::table($table) WHERE reembedded.generation = $generation LIMIT $limit")
+                .bind(("table", &self.table))
+                .bind(("generation", self.generation))
+                .bind(("limit", FINISH_BATCH_SIZE))
+                .await?
+                .take(0)?;
+            if records.is_empty() {
+                break;
+            }
+            let mut query = self.db.query("BEGIN TRANSACTION");
+            for (i, record) in records.into_iter().enumerate() {
+                query = query
+                    .query(format!(
+                        "UPDATE $record_{i} SET embedding_ids = $embedding_ids_{i}, reembedded = NONE"
+                    ))
+                    .bind((format!("record_{i}"), record.id))
+                    .bind((
+                        format!("embedding_ids_{i}"),
+                        record.reembedded.embedding_ids,
+                    ));
+            }
+            query.query("COMMIT TRANSACTION").await?.check()?;
+        }
+        self.db
+            .query("DELETE type::table($links)")
+            .bind(("links", links_table(&self.table, previous)))
+            .await?
+            .check()?;
+        Ok(())
+    }
+}
+
+#[test]
+fn recover_swap_finishes_a_swap_from_the_marker() {
+    let dir = tempfile::tempdir().unwrap();
+    let location = dir.path().join("vectors");
+    let write = |path: &Path, contents: &str| {
+        std::fs::create_dir_all(path).unwrap();
+        std::fs::write(path.join("data"), contents).unwrap();
+    };
+    let read = |path: &Path| std::fs::read_to_string(path.join("data")).unwrap();
+
+    // A re-embedding that stopped before it was complete is not swapped in
+    write(&location, "old");
+    write(&staged_location(&location), "new");
+    recover_swap(&location).unwrap();
+    assert_eq!(read(&location), "old");
+
+    // The swap is finished from every step after the marker is written
+    write_durable(&swap_marker(&location), "").unwrap();
+    recover_swap(&location).unwrap();
+    assert_eq!(read(&location), "new");
+    assert!(!staged_location(&location).exists());
+    assert!(!old_location(&location).exists());
+    assert!(!swap_marker(&location).exists());
+
+    write(&staged_location(&location), "newer");
+    std::fs::rename(&location, old_location(&location)).unwrap();
+    write_durable(&swap_marker(&location), "").unwrap();
+    recover_swap(&location).unwrap();
+    assert_eq!(read(&location), "newer");
+    assert!(!old_location(&location).exists());
+    assert!(!swap_marker(&location).exists());
+
+    write(&old_location(&location), "new");
+    write_durable(&swap_marker(&location), "").unwrap();
+    recover_swap(&location).unwrap();
+    assert_eq!(read(&location), "newer");
+    assert!(!old_location(&location).exists());
+    assert!(!swap_marker(&location).exists());
+}
+
+#[test]
+fn metadata_is_copied_from_the_overlapping_chunk() {
+    let source = |byte_range: Option<Range<usize>>, section: &str| EmbeddingSource {
+        byte_range,
+        metadata: EmbeddingMetadata::new().with("section", section),
+    };
+    let section = |metadata: EmbeddingMetadata| metadata.get("section").cloned();
+    let sources = [source(Some(0..10), "intro"), source(Some(10..30), "body")];
+
+    assert_eq!(
+        section(metadata_for_chunk(&sources, 0, &(0..8))),
+        Some("intro".into())
+    );
+    assert_eq!(
+        section(metadata_for_chunk(&sources, 0, &(5..25))),
+        Some("body".into())
+    );
+    // A chunk past the old chunks falls back to the old embedding at the same position
+    assert_eq!(
+        section(metadata_for_chunk(&sources, 1, &(40..50))),
+        Some("body".into())
+    );
+    assert_eq!(
+        section(metadata_for_chunk(&sources, 5, &(40..50))),
+        Some("intro".into())
+    );
+
+    let unranged = [source(None, "first"), source(None, "second")];
+    assert_eq!(
+        section(metadata_for_chunk(&unranged, 1, &(0..8))),
+        Some("second".into())
+    );
+    assert_eq!(
+        metadata_for_chunk(&[], 0, &(0..8)),
+        EmbeddingMetadata::new()
+    );
+}
@@ -0,0 +1,217 @@
+use super::{DocumentLink, EmbeddingIndexedTable};
+use kalosm_language::kalosm_language_model::VectorSpace;
+use kalosm_language::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use surrealdb::sql::{Id, Thing};
+use surrealdb::Connection;
+
+/// The embedding ids of a record in the main table.
+#[derive(Serialize, Deserialize)]
+struct RecordEmbeddings {
+    #[serde(skip_serializing)]
+    id: Option<Thing>,
+    embedding_ids: Vec<EmbeddingId>,
+}
+
+/// A record in the links table.
+#[derive(Deserialize)]
+struct LinkRecord {
+    id: Thing,
+    document_id: Id,
+}
+
+/// The problems found and fixed by [`EmbeddingIndexedTable::repair`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RepairReport {
+    /// Embeddings in the vector database that no record refers to. They were removed from the vector database.
+    pub orphaned_embeddings: Vec<EmbeddingId>,
+    /// Links to records that do not exist, or to embeddings that are not in the vector database. They were removed.
+    pub dangling_links: Vec<EmbeddingId>,
+    /// Embeddings of a record that had no link to the record. The links were recreated without a byte range.
+    pub restored_links: Vec<EmbeddingId>,
+    /// Records that refer to embeddings that are not in the vector database. The missing embeddings were removed from the records, so these records should be inserted again to be fully searchable.
+    pub damaged_records: Vec<Id>,
+}
+
+impl RepairReport {
+    /// Returns true if the table was already consistent.
+    pub fn is_clean(&self) -> bool {
+        self.orphaned_embeddings.is_empty()
+            && self.dangling_links.is_empty()
+            && self.restored_links.is_empty()
+            && self.damaged_records.is_empty()
+    }
+}
+
+impl<C: Connection, R, S: VectorSpace> EmbeddingIndexedTable<C, R, S> {
+    fn link(&self, embedding_id: EmbeddingId) -> Thing {
+        Thing {
+            tb: self.table_links(),
+            id: Id::Number(embedding_id.0 as i64),
+        }
+    }
+
+    /// Make the records, the links and the vector database consistent with each other.
+    ///
+    /// Inserting or deleting a record writes to the vector database and the surreal database separately. If the process stops between those writes, the vector database can contain embeddings that no record refers to, and the links table can contain links to records or embeddings that no longer exist. Orphaned embeddings are never returned by searches, but they take up space, and a dangling link makes a search fail when its embedding is the nearest.
+    ///
+    /// The records in the main table are the source of truth: embeddings and links that do not belong to a record are removed, and missing links are recreated. Moving the embedding ids of a re-embedding into place is finished first if it was interrupted. This should be run when nothing else is writing to the table, for example when the application starts.
+    pub async fn repair(&self) -> anyhow::Result<RepairReport> {
+        self.finish_swap().await?;
+        let mut report = RepairReport::default();
+        let stored = self.vector_db.embedding_ids();
+        let records = self
+            .db
+            .select::<Vec<RecordEmbeddings>>(self.table.clone())
+            .await?;
+        let links = self
+            .db
+            .select::<Vec<LinkRecord>>(self.table_links())
+            .await?
+            .into_iter()
+            .filter_map(|link| match link.id.id {
+                Id::Number(id) => Some((EmbeddingId(id as u32), link.document_id)),
+                _ => None,
+            })
+            .collect::<HashMap<_, _>>();
+
+        // The record each embedding in the vector database belongs to
+        let mut owners = HashMap::new();
+        for record in records {
+            let Some(thing) = record.id else {
+                continue;
+            };
+            let (present, missing): (Vec<_>, Vec<_>) = record
+                .embedding_ids
+                .into_iter()
+                .partition(|id| stored.contains(id.0));
+            for embedding_id in &present {
+                if links.get(embedding_id) != Some(&thing.id) {
+                    self.db
+                        .update::<Option<DocumentLink>>(self.link(*embedding_id))
+                        .content(DocumentLink {
+                            document_id: thing.id.clone(),
+                            byte_range: None,
+                        })
+                        .await?;
+                    report.restored_links.push(*embedding_id);
+                }
+                owners.insert(*embedding_id, thing.id.clone());
+            }
+            if !missing.is_empty() {
+                self.db
+                    .update::<Option<RecordEmbeddings>>(thing.clone())
+                    .merge(RecordEmbeddings {
+                        id: None,
+                        embedding_ids: present,
+                    })
+                    .await?;
+                report.damaged_records.push(thing.id);
+            }
+        }
+
+        for embedding_id in links.keys() {
+            if !owners.contains_key(embedding_id) {
+                self.db
+                    .delete::<Option<DocumentLink>>(self.link(*embedding_id))
+                    .await?;
+                report.dangling_links.push(*embedding_id);
+            }
+        }
+
+        let orphaned = stored
+            .iter()
+            .map(EmbeddingId)
+            .filter(|id| !owners.contains_key(id))
+            .collect::<Vec<_>>();
+        if !orphaned.is_empty() {
+            let mut transaction = self.vector_db.transaction()?;
+            for embedding_id in &orphaned {
+                transaction.remove_embedding(*embedding_id)?;
+            }
+            transaction.commit()?;
+        }
+        report.orphaned_embeddings = orphaned;
+
+        report.dangling_links.sort();
+        report.restored_links.sort();
+        Ok(report)
+    }
+}
+
+#[tokio::test]
+async fn repair_fixes_orphaned_embeddings_and_dangling_links() {
+    use super::test_support::memory_table;
+    use super::ObjectWithEmbeddingIds;
+    use kalosm_language::kalosm_language_model::UnknownVectorSpace;
+
+    #[derive(Serialize, Deserialize)]
+    struct Note {
+        text: String,
+    }
+
+    let table: EmbeddingIndexedTable<_, Note, UnknownVectorSpace> = memory_table("notes").await;
+
+    let kept = table
+        .insert(
+            [vec![1.0, 0.0].into(), vec![0.0, 1.0].into()],
+            Note {
+                text: "kept".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+    let deleted = table
+        .insert(
+            [vec![1.0, 1.0].into()],
+            Note {
+                text: "deleted".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+    assert!(table.repair().await.unwrap().is_clean());
+
+    // An insert that stopped after adding the embedding to the vector database
+    table
+        .vector_db()
+        .add_embedding(vec![0.5, 0.5].into())
+        .unwrap();
+    // A delete that stopped after removing the record
+    table
+        .db()
+        .delete::<Option<ObjectWithEmbeddingIds<Note>>>(Thing {
+            tb: table.table().to_string(),
+            id: deleted,
+        })
+        .await
+        .unwrap();
+    // A lost link and a lost embedding of the record that was kept
+    table
+        .db()
+        .delete::<Option<DocumentLink>>(table.link(EmbeddingId(0)))
+        .await
+        .unwrap();
+    table.vector_db().remove_embedding(EmbeddingId(1)).unwrap();
+
+    let report = table.repair().await.unwrap();
+    assert_eq!(
+        report,
+        RepairReport {
+            orphaned_embeddings: vec![EmbeddingId(2), EmbeddingId(3)],
+            dangling_links: vec![EmbeddingId(1), EmbeddingId(2)],
+            restored_links: vec![EmbeddingId(0)],
+            damaged_records: vec![kept.clone()],
+        }
+    );
+    assert!(table.repair().await.unwrap().is_clean());
+
+    let results = table
+        .select_nearest(vec![1.0, 1.0].into(), 10)
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].record_id, kept);
+    assert_eq!(results[0].record.text, "kept");
+}
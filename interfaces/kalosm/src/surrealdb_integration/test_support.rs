@@ -0,0 +1,45 @@
+use super::{EmbeddingIndexedTable, VectorDbSurrealExt};
+use kalosm_language::kalosm_language_model::{UnknownVectorSpace, VectorSpace};
+use kalosm_language::prelude::*;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use surrealdb::engine::local::{Db, Mem};
+use surrealdb::Surreal;
+
+/// Embeds text as its length.
+pub(crate) struct Length;
+
+#[async_trait::async_trait]
+impl Embedder for Length {
+    type VectorSpace = UnknownVectorSpace;
+
+    async fn embed(&self, input: &str) -> anyhow::Result<Embedding<UnknownVectorSpace>> {
+        Ok(vec![input.len() as f32].into())
+    }
+
+    fn model_id(&self) -> Option<String> {
+        Some("length".to_string())
+    }
+
+    fn dimensions(&self) -> Option<usize> {
+        Some(1)
+    }
+}
+
+/// Open an empty in-memory database.
+pub(crate) async fn memory_db() -> Surreal<Db> {
+    let db = Surreal::new::<Mem>(()).await.unwrap();
+    db.use_ns("test").use_db("test").await.unwrap();
+    db
+}
+
+/// Create a vector indexed table in an empty in-memory database.
+pub(crate) async fn memory_table<R: Serialize + DeserializeOwned, S: VectorSpace>(
+    table: &str,
+) -> EmbeddingIndexedTable<Db, R, S> {
+    memory_db()
+        .await
+        .vector_indexed_table_builder(table)
+        .build()
+        .unwrap()
+}
@@ -62,6 +62,21 @@ pub trait Embedder: Send + Sync + 'static {
         Ok(embeddings)
     }
 
+    /// Get the tokenizer the embedder uses to split text into tokens, if it is available. Chunkers can use the tokenizer to keep chunks within the number of tokens the embedder can read.
+    fn tokenizer(&self) -> Option<Arc<dyn Tokenizer + Send + Sync>> {
+        None
+    }
+
+    /// A stable identifier for the model and weights the embedder uses, if it is known. Embeddings from different models are not comparable, so this is stored with the embeddings to detect when they are searched with a different model.
+    fn model_id(&self) -> Option<String> {
+        None
+    }
+
+    /// The number of dimensions in the embeddings this embedder creates, if it is known before embedding any text.
+    fn dimensions(&self) -> Option<usize> {
+        None
+    }
+
     /// Convert this embedder into an embedder trait object.
     fn into_any_embedder(self) -> DynEmbedder
     where
@@ -93,6 +108,18 @@ impl<E: Embedder + Send + Sync + 'static> Embedder for AnyEmbedder<E> {
             .await
             .map(|e| e.into_iter().map(|e| e.cast()).collect())
     }
+
+    fn tokenizer(&self) -> Option<Arc<dyn Tokenizer + Send + Sync>> {
+        self.0.tokenizer()
+    }
+
+    fn model_id(&self) -> Option<String> {
+        self.0.model_id()
+    }
+
+    fn dimensions(&self) -> Option<usize> {
+        self.0.dimensions()
+    }
 }
 
 /// A model that can be created asynchronously.
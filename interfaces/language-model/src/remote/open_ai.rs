@@ -238,4 +238,12 @@ impl Embedder for AdaEmbedder {
 
         Ok(embedding)
     }
+
+    fn model_id(&self) -> Option<String> {
+        Some("openai/text-embedding-ada-002".to_string())
+    }
+
+    fn dimensions(&self) -> Option<usize> {
+        Some(1536)
+    }
 }
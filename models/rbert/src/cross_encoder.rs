@@ -0,0 +1,139 @@
+use std::sync::RwLock;
+
+use candle_core::{Module, Tensor, D};
+use candle_nn::{Linear, VarBuilder};
+use candle_transformers::models::bert::{BertModel, Config, DTYPE};
+use kalosm_common::accelerated_device_if_available;
+use tokenizers::Tokenizer;
+
+use crate::{fetch_model_files, BertSource};
+
+/// A builder for a [`BertCrossEncoder`]
+pub struct BertCrossEncoderBuilder {
+    source: BertSource,
+}
+
+impl Default for BertCrossEncoderBuilder {
+    fn default() -> Self {
+        Self {
+            source: BertSource::ms_marco_mini_lm_l6_v2_cross_encoder(),
+        }
+    }
+}
+
+impl BertCrossEncoderBuilder {
+    /// Set the source of the model. The model must be a bert model with a sequence classification head that outputs a single relevance score, check out available models: <https://huggingface.co/cross-encoder>
+    pub fn with_source(mut self, source: BertSource) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Build the model
+    pub fn build(self) -> anyhow::Result<BertCrossEncoder> {
+        BertCrossEncoder::new(self)
+    }
+}
+
+/// A bert cross encoder that scores how relevant a document is to a query.
+///
+/// Unlike a [`crate::Bert`] embedding model which embeds the query and document separately, a cross encoder reads the query and document together. This makes it slower, but more accurate. It is typically used to rerank a small number of results from a faster search.
+///
+/// ```rust, no_run
+/// use rbert::*;
+///
+/// fn main() -> anyhow::Result<()> {
+///     let cross_encoder = BertCrossEncoder::builder().build()?;
+///     let scores = cross_encoder.score(
+///         "How many people live in Berlin?",
+///         &[
+///             "Berlin has a population of 3,520,031 registered inhabitants.",
+///             "Berlin is well known for its museums.",
+///         ],
+///     )?;
+///     println!("scores {:?}", scores);
+///     Ok(())
+/// }
+/// ```
+pub struct BertCrossEncoder {
+    model: BertModel,
+    pooler: Linear,
+    classifier: Linear,
+    tokenizer: RwLock<Tokenizer>,
+}
+
+impl Default for BertCrossEncoder {
+    fn default() -> Self {
+        Self::builder().build().unwrap()
+    }
+}
+
+impl BertCrossEncoder {
+    /// Create a new [`BertCrossEncoderBuilder`]
+    pub fn builder() -> BertCrossEncoderBuilder {
+        BertCrossEncoderBuilder::default()
+    }
+
+    fn new(builder: BertCrossEncoderBuilder) -> anyhow::Result<Self> {
+        let BertCrossEncoderBuilder { source } = builder;
+        let BertSource { model_id, revision } = source;
+
+        let (config_filename, tokenizer_filename, weights_filename) =
+            fetch_model_files(model_id, revision)?;
+        let config = std::fs::read_to_string(config_filename)?;
+        let hidden_size = serde_json::from_str::<serde_json::Value>(&config)?["hidden_size"]
+            .as_u64()
+            .ok_or_else(|| anyhow::anyhow!("Missing hidden_size in config"))?
+            as usize;
+        let config: Config = serde_json::from_str(&config)?;
+
+        let device = accelerated_device_if_available()?;
+        let vb =
+            unsafe { VarBuilder::from_mmaped_safetensors(&[&weights_filename], DTYPE, &device)? };
+        let model = BertModel::load(vb.clone(), &config)?;
+        let pooler = candle_nn::linear(hidden_size, hidden_size, vb.pp("bert.pooler.dense"))?;
+        let classifier = candle_nn::linear(hidden_size, 1, vb.pp("classifier"))?;
+        let tokenizer = Tokenizer::from_file(&tokenizer_filename).map_err(anyhow::Error::msg)?;
+
+        Ok(Self {
+            model,
+            pooler,
+            classifier,
+            tokenizer: RwLock::new(tokenizer),
+        })
+    }
+
+    /// Score how relevant each document is to the query. Scores are between 0 and 1, higher scores are more relevant.
+    pub fn score(&self, query: &str, documents: &[&str]) -> anyhow::Result<Vec<f32>> {
+        documents
+            .iter()
+            .map(|document| self.score_pair(query, document))
+            .collect()
+    }
+
+    fn score_pair(&self, query: &str, document: &str) -> anyhow::Result<f32> {
+        let device = &self.model.device;
+
+        // Pairs are scored one at a time so padding doesn't change the scores
+        let encoding = {
+            let mut tokenizer = self.tokenizer.write().unwrap();
+            tokenizer.with_padding(None);
+            tokenizer
+                .encode((query, document), true)
+                .map_err(anyhow::Error::msg)?
+        };
+        let token_ids = Tensor::new(encoding.get_ids(), device)?.unsqueeze(0)?;
+        let token_type_ids = Tensor::new(encoding.get_type_ids(), device)?.unsqueeze(0)?;
+
+        let hidden_states = self.model.forward(&token_ids, &token_type_ids)?;
+        // The classification head reads the pooled embedding of the [CLS] token
+        let cls = hidden_states.narrow(1, 0, 1)?.squeeze(1)?;
+        let pooled = self.pooler.forward(&cls)?.tanh()?;
+        let logit = self
+            .classifier
+            .forward(&pooled)?
+            .squeeze(D::Minus1)?
+            .to_vec1::<f32>()?[0];
+
+        Ok(1. / (1. + (-logit).exp()))
+    }
+}
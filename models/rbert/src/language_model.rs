@@ -1,7 +1,9 @@
 pub use crate::Bert;
+use kalosm_language_model::kalosm_sample::Tokenizer;
 use kalosm_language_model::Embedding;
 use kalosm_language_model::VectorSpace;
 use kalosm_language_model::{CreateModel, Embedder};
+use std::sync::Arc;
 
 #[async_trait::async_trait]
 impl CreateModel for Bert {
@@ -33,6 +35,18 @@ impl Embedder for Bert {
 
         Ok(embeddings)
     }
+
+    fn tokenizer(&self) -> Option<Arc<dyn Tokenizer + Send + Sync>> {
+        Some(self.shared_tokenizer())
+    }
+
+    fn model_id(&self) -> Option<String> {
+        Some(Bert::model_id(self).to_string())
+    }
+
+    fn dimensions(&self) -> Option<usize> {
+        Some(self.hidden_size())
+    }
 }
 
 /// A vector space for BERT sentence embeddings.
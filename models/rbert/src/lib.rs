@@ -48,11 +48,16 @@ extern crate intel_mkl_src;
 #[cfg(feature = "accelerate")]
 extern crate accelerate_src;
 
+mod cross_encoder;
+pub use cross_encoder::*;
 mod language_model;
 pub use language_model::*;
 
 use kalosm_common::accelerated_device_if_available;
-use std::{path::PathBuf, sync::RwLock};
+use std::{
+    path::PathBuf,
+    sync::{Arc, RwLock},
+};
 
 use anyhow::anyhow;
 use candle_core::Tensor;
@@ -71,6 +76,35 @@ fn try_fetch(repo: Repo) -> anyhow::Result<(PathBuf, PathBuf, PathBuf)> {
     ))
 }
 
+/// Fetch the config, tokenizer and weights of a model from the hub, falling back to the local cache if the hub is unavailable.
+fn fetch_model_files(
+    model_id: String,
+    revision: String,
+) -> anyhow::Result<(PathBuf, PathBuf, PathBuf)> {
+    let repo = Repo::with_revision(model_id, RepoType::Model, revision);
+    match try_fetch(repo.clone()) {
+        Ok(filenames) => Ok(filenames),
+        Err(err) => {
+            tracing::warn!(
+                "Failed to fetch model from hub, falling back to local cache: {}",
+                err
+            );
+            let cache = Cache::default().repo(repo);
+            Ok((
+                cache
+                    .get("config.json")
+                    .ok_or(anyhow!("Missing config file in cache"))?,
+                cache
+                    .get("tokenizer.json")
+                    .ok_or(anyhow!("Missing tokenizer file in cache"))?,
+                cache
+                    .get("model.safetensors")
+                    .ok_or(anyhow!("Missing weights file in cache"))?,
+            ))
+        }
+    }
+}
+
 /// A the source of a [`Bert`] model
 pub struct BertSource {
     model_id: String,
@@ -111,6 +145,13 @@ impl BertSource {
             .with_revision("refs/pr/3".to_string())
     }
 
+    /// Create a new [`BertSource`] with the MS MARCO MiniLM-L-6-v2 cross encoder preset. This model can only be used with a [`BertCrossEncoder`].
+    pub fn ms_marco_mini_lm_l6_v2_cross_encoder() -> Self {
+        Self::default()
+            .with_model_id("cross-encoder/ms-marco-MiniLM-L-6-v2".to_string())
+            .with_revision("main".to_string())
+    }
+
     /// Create a new [`BertSource`] with the MiniLM-L6-v2 preset
     pub fn mini_lm_l6_v2() -> Self {
         Self::default()
@@ -151,6 +192,9 @@ impl BertBuilder {
 pub struct Bert {
     model: BertModel,
     tokenizer: RwLock<Tokenizer>,
+    shared_tokenizer: Arc<Tokenizer>,
+    model_id: String,
+    hidden_size: usize,
 }
 
 impl Default for Bert {
@@ -168,31 +212,15 @@ impl Bert {
     fn new(builder: BertBuilder) -> anyhow::Result<Self> {
         let BertBuilder { source } = builder;
         let BertSource { model_id, revision } = source;
+        let id = format!("{model_id}@{revision}");
 
-        let repo = Repo::with_revision(model_id, RepoType::Model, revision);
-        let (config_filename, tokenizer_filename, weights_filename) = match try_fetch(repo.clone())
-        {
-            Ok(filenames) => filenames,
-            Err(err) => {
-                tracing::warn!(
-                    "Failed to fetch model from hub, falling back to local cache: {}",
-                    err
-                );
-                let cache = Cache::default().repo(repo);
-                (
-                    cache
-                        .get("config.json")
-                        .ok_or(anyhow!("Missing config file in cache"))?,
-                    cache
-                        .get("tokenizer.json")
-                        .ok_or(anyhow!("Missing tokenizer file in cache"))?,
-                    cache
-                        .get("model.safetensors")
-                        .ok_or(anyhow!("Missing weights file in cache"))?,
-                )
-            }
-        };
+        let (config_filename, tokenizer_filename, weights_filename) =
+            fetch_model_files(model_id, revision)?;
         let config = std::fs::read_to_string(config_filename)?;
+        let hidden_size = serde_json::from_str::<serde_json::Value>(&config)?["hidden_size"]
+            .as_u64()
+            .ok_or_else(|| anyhow::anyhow!("Missing hidden_size in config"))?
+            as usize;
         let config: Config = serde_json::from_str(&config)?;
 
         let device = accelerated_device_if_available()?;
@@ -202,11 +230,29 @@ impl Bert {
         let tokenizer = Tokenizer::from_file(&tokenizer_filename).map_err(anyhow::Error::msg)?;
 
         Ok(Bert {
+            shared_tokenizer: Arc::new(tokenizer.clone()),
             tokenizer: RwLock::new(tokenizer),
             model,
+            model_id: id,
+            hidden_size,
         })
     }
 
+    /// Get the id and revision of the model on the hugging face hub.
+    pub fn model_id(&self) -> &str {
+        &self.model_id
+    }
+
+    /// Get the number of dimensions in the embeddings the model creates.
+    pub fn hidden_size(&self) -> usize {
+        self.hidden_size
+    }
+
+    /// Get the tokenizer the model uses.
+    pub(crate) fn shared_tokenizer(&self) -> Arc<Tokenizer> {
+        self.shared_tokenizer.clone()
+    }
+
     /// Embed a batch of sentences
     pub(crate) fn embed_batch_raw(&self, sentences: &[&str]) -> anyhow::Result<Vec<Tensor>> {
         let mut combined = Vec::new();